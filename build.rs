@@ -0,0 +1,62 @@
+//! Embed build information served by `GET /version` (see `src/routes/version.rs`).
+//!
+//! - `BUILD_GIT_SHA` - the `GIT_SHA` env var when set (for builds outside the
+//!   repository, e.g. `docker build --build-arg GIT_SHA=...`), else
+//!   `git rev-parse HEAD`, else `unknown`
+//! - `BUILD_TIMESTAMP` - Unix seconds: `SOURCE_DATE_EPOCH` when set, for
+//!   reproducible builds, else when this script ran
+//! - `BUILD_FEATURES` - comma-separated Cargo features enabled for the build
+use std::{
+    env,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    // ---
+    let sha = env::var("GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| git(&["rev-parse", "HEAD"]))
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=BUILD_GIT_SHA={sha}");
+
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={timestamp}");
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|f| f.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+
+    // Rerun on commits and checkouts only, so the timestamp doesn't force a
+    // rebuild every time
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Trimmed stdout of a successful `git` command.
+fn git(args: &[&str]) -> Option<String> {
+    // ---
+    let output = Command::new("git").args(args).output().ok()?;
+    let out = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !out.trim().is_empty()).then(|| out.trim().to_string())
+}
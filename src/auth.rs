@@ -0,0 +1,698 @@
+//! Request authentication for the API routes.
+//!
+//! When JWT validation, API keys or client certificates are configured,
+//! [`authenticate`] requires every API request (health checks stay open) to
+//! carry a valid `Authorization: Bearer <jwt>` (with JWT validation), a known
+//! `x-api-key` or a known client certificate. HS256 tokens are
+//! verified with a shared secret; RS256 tokens with a static public key or a
+//! key from the configured JWKS endpoint, selected by the token's `kid`.
+//! Verified [`Claims`] are inserted into the request extensions for
+//! downstream authorization.
+//!
+//! Every authenticated request also gets a [`Principal`] carrying its
+//! [`Role`]: from the token's `roles` (or `role`) claim, or from the API key's
+//! configured role. Routes enforce a minimum role with [`require_role`],
+//! which answers 403 when the principal's role is too low. A principal may
+//! also be limited to specific meshes (`meshes` claim or `API_KEY_<N>_MESHES`);
+//! routes apply that scope to their queries and writes.
+//!
+//! On HTTPS connections with mutual TLS, a verified client certificate whose
+//! subject CN matches a `CLIENT_CERT_<N>_CN` authenticates the request as that
+//! identity, ahead of any headers; with identities configured, a certificate
+//! matching none of them is refused (403). Certificate identities may
+//! additionally be limited to specific devices when pushing readings.
+//!
+//! Otherwise, with no JWT configuration, API keys or certificate identities the
+//! middleware lets everything through as an `admin` principal.
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{ApiError, ApiKeyConfig, ClientCertConfig, Config, JwtConfig, PeerCertificate};
+
+/// Minimum time between JWKS refetches triggered by unknown `kid`s.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+// ---
+
+/// Verified JWT claims, available to handlers as `Extension<Claims>`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    // ---
+    /// Subject (user or service identity).
+    pub sub: String,
+
+    /// Issuer, if present.
+    pub iss: Option<String>,
+
+    /// All remaining claims, untyped.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Access level, ordered: each role includes everything the lower ones may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // ---
+    /// Query readings and summaries.
+    Reader,
+
+    /// Also ingest and modify data.
+    Writer,
+
+    /// Also use `/admin/*` routes.
+    Admin,
+}
+
+impl Role {
+    // ---
+    fn as_str(self) -> &'static str {
+        // ---
+        match self {
+            Role::Reader => "reader",
+            Role::Writer => "writer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    // ---
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // ---
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reader" => Ok(Role::Reader),
+            "writer" => Ok(Role::Writer),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!(
+                "unknown role {other:?} (expected reader, writer or admin)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    // ---
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // ---
+        f.write_str(self.as_str())
+    }
+}
+
+/// The authenticated caller, available to handlers as `Extension<Principal>`.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    // ---
+    /// JWT subject, API key name, client certificate CN, or `anonymous` when
+    /// auth is disabled.
+    pub name: String,
+
+    /// Highest role granted to the caller.
+    pub role: Role,
+
+    /// Meshes the caller may read and write; `None` means all meshes.
+    pub meshes: Option<Vec<String>>,
+
+    /// Devices the caller may push readings for; `None` means all devices.
+    pub devices: Option<Vec<String>>,
+}
+
+impl Principal {
+    // ---
+    /// Whether the caller may read or write data for `mesh_id`.
+    pub fn can_access_mesh(&self, mesh_id: &str) -> bool {
+        // ---
+        self.meshes
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|m| m == mesh_id))
+    }
+
+    /// Whether the caller may push readings for `device_id`.
+    pub fn can_push_device(&self, device_id: &str) -> bool {
+        // ---
+        self.devices
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|d| d == device_id))
+    }
+}
+
+impl Claims {
+    // ---
+    /// Highest known role in the `roles` (array) or `role` (string) claim;
+    /// `reader` when neither names a known role.
+    fn role(&self) -> Role {
+        // ---
+        let listed = match (self.extra.get("roles"), self.extra.get("role")) {
+            (Some(serde_json::Value::Array(roles)), _) => {
+                roles.iter().filter_map(|r| r.as_str()).collect()
+            }
+            (_, Some(serde_json::Value::String(role))) => vec![role.as_str()],
+            _ => Vec::new(),
+        };
+        listed
+            .into_iter()
+            .filter_map(|r| r.parse().ok())
+            .max()
+            .unwrap_or(Role::Reader)
+    }
+
+    /// Mesh scope from a `meshes` array claim; `None` (all meshes) when absent.
+    fn meshes(&self) -> Option<Vec<String>> {
+        // ---
+        let serde_json::Value::Array(meshes) = self.extra.get("meshes")? else {
+            // A malformed claim must not widen access
+            return Some(Vec::new());
+        };
+        Some(
+            meshes
+                .iter()
+                .filter_map(|m| m.as_str().map(str::to_owned))
+                .collect(),
+        )
+    }
+}
+
+/// Authentication settings and key material shared by all requests.
+pub struct Authenticator {
+    // ---
+    jwt: Option<JwtVerifier>,
+    api_keys: Vec<ApiKeyConfig>,
+    client_certs: Vec<ClientCertConfig>,
+}
+
+impl Authenticator {
+    // ---
+    /// Build from configuration; fails on an unparsable RS256 public key.
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        // ---
+        Ok(Self {
+            jwt: cfg.jwt.as_ref().map(JwtVerifier::new).transpose()?,
+            api_keys: cfg.api_keys.clone(),
+            client_certs: cfg.client_certs.clone(),
+        })
+    }
+
+    /// Principal for a request with a verified TLS client certificate, while
+    /// certificate identities are configured: `Ok` when its CN is one of them,
+    /// `Err` with the CN when it isn't. `None` without identities, or without
+    /// a certificate naming a CN (left to header authentication).
+    fn client_cert_principal(&self, req: &Request) -> Option<Result<Principal, String>> {
+        // ---
+        if self.client_certs.is_empty() {
+            return None;
+        }
+        let cn = req
+            .extensions()
+            .get::<PeerCertificate>()?
+            .common_name
+            .as_deref()?;
+        let Some(cert) = self.client_certs.iter().find(|c| c.common_name == cn) else {
+            return Some(Err(cn.to_owned()));
+        };
+        Some(Ok(Principal {
+            name: cert.common_name.clone(),
+            role: cert.role,
+            meshes: cert.meshes.clone(),
+            devices: cert.devices.clone(),
+        }))
+    }
+
+    /// Whether no way to authenticate is configured, leaving the API open.
+    fn is_open(&self) -> bool {
+        // ---
+        self.jwt.is_none() && self.api_keys.is_empty() && self.client_certs.is_empty()
+    }
+}
+
+/// Middleware: authenticate the request or reject it with 401.
+pub async fn authenticate(
+    State(auth): State<Arc<Authenticator>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    // ---
+    match auth.client_cert_principal(&req) {
+        Some(Ok(principal)) => {
+            tracing::debug!(
+                "Authenticated client certificate {} as {}",
+                principal.name,
+                principal.role
+            );
+            req.extensions_mut().insert(principal);
+            return next.run(req).await;
+        }
+        Some(Err(cn)) => {
+            tracing::info!(
+                "Rejected client certificate CN {:?}: no CLIENT_CERT_<N>_CN entry",
+                cn
+            );
+            return ApiError::Forbidden {
+                error: "forbidden",
+                hint: "this client certificate's CN has no CLIENT_CERT_<N>_CN entry",
+            }
+            .into_response();
+        }
+        None => {}
+    }
+
+    if auth.is_open() {
+        req.extensions_mut().insert(Principal {
+            name: "anonymous".into(),
+            role: Role::Admin,
+            meshes: None,
+            devices: None,
+        });
+        return next.run(req).await;
+    }
+
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_owned);
+
+    if let (Some(jwt), Some(token)) = (&auth.jwt, bearer) {
+        return match jwt.verify(token.trim()).await {
+            Ok(claims) => {
+                let principal = Principal {
+                    name: claims.sub.clone(),
+                    role: claims.role(),
+                    meshes: claims.meshes(),
+                    devices: None,
+                };
+                tracing::debug!(
+                    "Authenticated subject {} as {} (iss={:?}, {} other claims)",
+                    claims.sub,
+                    principal.role,
+                    claims.iss,
+                    claims.extra.len()
+                );
+                req.extensions_mut().insert(principal);
+                req.extensions_mut().insert(claims);
+                next.run(req).await
+            }
+            Err(e) => {
+                tracing::info!("Rejected bearer token: {}", e);
+                unauthorized(
+                    "invalid bearer token; obtain a fresh token from your identity provider",
+                )
+            }
+        };
+    }
+
+    let api_key = req.headers().get("x-api-key").and_then(|v| v.to_str().ok());
+    if let Some(key) = auth
+        .api_keys
+        .iter()
+        .find(|k| Some(k.key.as_str()) == api_key)
+    {
+        tracing::debug!("Authenticated API key {} as {}", key.name, key.role);
+        req.extensions_mut().insert(Principal {
+            name: key.name.clone(),
+            role: key.role,
+            meshes: key.meshes.clone(),
+            devices: None,
+        });
+        return next.run(req).await;
+    }
+
+    if auth.jwt.is_some() {
+        unauthorized("send Authorization: Bearer <jwt> or a known x-api-key header")
+    } else if !auth.api_keys.is_empty() {
+        unauthorized("send a known x-api-key header")
+    } else {
+        unauthorized("connect with a client certificate whose CN has a CLIENT_CERT_<N>_CN entry")
+    }
+}
+
+/// Middleware: reject with 403 unless the [`Principal`] has at least `required`.
+///
+/// Layer it per route with `middleware::from_fn_with_state(Role::Writer, require_role)`;
+/// it must run inside [`authenticate`].
+pub async fn require_role(State(required): State<Role>, req: Request, next: Next) -> Response {
+    // ---
+    let Some(principal) = req.extensions().get::<Principal>() else {
+        tracing::error!("require_role used without the authenticate middleware");
+        return ApiError::RoleRequired {
+            required,
+            role: None,
+        }
+        .into_response();
+    };
+
+    if principal.role >= required {
+        return next.run(req).await;
+    }
+
+    tracing::info!(
+        "Denied {} {} to {} ({} < {})",
+        req.method(),
+        req.uri().path(),
+        principal.name,
+        principal.role,
+        required
+    );
+    ApiError::RoleRequired {
+        required,
+        role: Some(principal.role),
+    }
+    .into_response()
+}
+
+/// 403 response for a mesh outside the caller's scope.
+pub fn mesh_forbidden() -> Response {
+    // ---
+    ApiError::Forbidden {
+        error: "forbidden",
+        hint: "these credentials are not allowed to access this mesh",
+    }
+    .into_response()
+}
+
+fn unauthorized(hint: &'static str) -> Response {
+    // ---
+    ApiError::Unauthorized {
+        error: "unauthorized",
+        hint,
+    }
+    .into_response()
+}
+
+// ---
+
+/// JWT signature and claim verification.
+struct JwtVerifier {
+    // ---
+    hs256: Option<DecodingKey>,
+    rs256: Option<DecodingKey>,
+    jwks_url: Option<String>,
+    jwks: RwLock<JwksCache>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Default)]
+struct JwksCache {
+    keys: HashMap<String, DecodingKey>,
+    fetched_at: Option<Instant>,
+}
+
+impl JwtVerifier {
+    // ---
+    fn new(cfg: &JwtConfig) -> Result<Self> {
+        // ---
+        let rs256 = cfg
+            .rs256_public_key_pem
+            .as_deref()
+            .map(|pem| DecodingKey::from_rsa_pem(pem.as_bytes()))
+            .transpose()
+            .map_err(|e| anyhow!("Invalid JWT_RS256_PUBLIC_KEY_FILE: {}", e))?;
+
+        Ok(Self {
+            hs256: cfg
+                .hs256_secret
+                .as_deref()
+                .map(|s| DecodingKey::from_secret(s.as_bytes())),
+            rs256,
+            jwks_url: cfg.jwks_url.clone(),
+            jwks: RwLock::new(JwksCache::default()),
+            issuer: cfg.issuer.clone(),
+            audience: cfg.audience.clone(),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Verify signature, expiry, and configured `iss`/`aud`; return the claims.
+    async fn verify(&self, token: &str) -> Result<Claims> {
+        // ---
+        let head = decode_header(token)?;
+        let key = match head.alg {
+            Algorithm::HS256 => self.hs256.clone(),
+            Algorithm::RS256 => self.rs256_key(head.kid.as_deref()).await,
+            other => return Err(anyhow!("unsupported algorithm {:?}", other)),
+        }
+        .ok_or_else(|| anyhow!("no key configured for {:?} (kid={:?})", head.alg, head.kid))?;
+
+        let mut validation = Validation::new(head.alg);
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        if let Some(iss) = &self.issuer {
+            validation.set_issuer(&[iss]);
+        }
+        match &self.audience {
+            Some(aud) => validation.set_audience(&[aud]),
+            None => validation.validate_aud = false,
+        }
+
+        Ok(decode::<Claims>(token, &key, &validation)?.claims)
+    }
+
+    /// Find the RS256 key for `kid`, refetching the JWKS (rate-limited) when
+    /// the `kid` is unknown; falls back to the static public key.
+    async fn rs256_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        // ---
+        let (Some(kid), Some(url)) = (kid, &self.jwks_url) else {
+            return self.rs256.clone();
+        };
+
+        if let Some(key) = self.jwks.read().await.keys.get(kid) {
+            return Some(key.clone());
+        }
+
+        let mut cache = self.jwks.write().await;
+        let stale = cache
+            .fetched_at
+            .is_none_or(|t| t.elapsed() >= JWKS_REFRESH_INTERVAL);
+        if stale {
+            cache.fetched_at = Some(Instant::now());
+            match self.fetch_jwks(url).await {
+                Ok(keys) => cache.keys = keys,
+                Err(e) => tracing::warn!("Failed to fetch JWKS from {}: {}", url, e),
+            }
+        }
+
+        cache.keys.get(kid).cloned().or_else(|| self.rs256.clone())
+    }
+
+    async fn fetch_jwks(&self, url: &str) -> Result<HashMap<String, DecodingKey>> {
+        // ---
+        let set: JwkSet = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let keys = set
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let kid = jwk.common.key_id.clone()?;
+                DecodingKey::from_jwk(jwk).ok().map(|k| (kid, k))
+            })
+            .collect::<HashMap<_, _>>();
+
+        tracing::info!("Loaded {} JWKS keys from {}", keys.len(), url);
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn verifier(issuer: Option<&str>, audience: Option<&str>) -> JwtVerifier {
+        // ---
+        JwtVerifier::new(&JwtConfig {
+            hs256_secret: Some("s3cret".into()),
+            rs256_public_key_pem: None,
+            jwks_url: None,
+            issuer: issuer.map(Into::into),
+            audience: audience.map(Into::into),
+        })
+        .unwrap()
+    }
+
+    fn token(claims: serde_json::Value, secret: &str) -> String {
+        // ---
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn exp() -> i64 {
+        // ---
+        chrono::Utc::now().timestamp() + 600
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_hs256_and_keeps_extra_claims() {
+        // ---
+        let t = token(
+            json!({"sub": "alice", "iss": "sso", "exp": exp(), "team": "ops"}),
+            "s3cret",
+        );
+        let claims = verifier(Some("sso"), None).verify(&t).await.unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert_eq!(claims.extra.get("team"), Some(&json!("ops")));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signature_expiry_issuer_and_audience() {
+        // ---
+        let v = verifier(Some("sso"), Some("sensorflow"));
+
+        let wrong_key = token(
+            json!({"sub": "a", "iss": "sso", "aud": "sensorflow", "exp": exp()}),
+            "other",
+        );
+        assert!(v.verify(&wrong_key).await.is_err());
+
+        let expired = token(
+            json!({"sub": "a", "iss": "sso", "aud": "sensorflow", "exp": 1}),
+            "s3cret",
+        );
+        assert!(v.verify(&expired).await.is_err());
+
+        let wrong_iss = token(
+            json!({"sub": "a", "iss": "evil", "aud": "sensorflow", "exp": exp()}),
+            "s3cret",
+        );
+        assert!(v.verify(&wrong_iss).await.is_err());
+
+        let wrong_aud = token(
+            json!({"sub": "a", "iss": "sso", "aud": "other", "exp": exp()}),
+            "s3cret",
+        );
+        assert!(v.verify(&wrong_aud).await.is_err());
+    }
+
+    #[test]
+    fn roles_are_ordered_and_parsed_from_claims() {
+        // ---
+        assert!(Role::Admin > Role::Writer && Role::Writer > Role::Reader);
+        assert_eq!("Writer".parse::<Role>(), Ok(Role::Writer));
+        assert!("root".parse::<Role>().is_err());
+
+        let claims = |extra: serde_json::Value| Claims {
+            sub: "a".into(),
+            iss: None,
+            extra: extra.as_object().unwrap().clone(),
+        };
+        assert_eq!(claims(json!({})).role(), Role::Reader);
+        assert_eq!(claims(json!({"role": "writer"})).role(), Role::Writer);
+        assert_eq!(
+            claims(json!({"roles": ["reader", "bogus", "admin"]})).role(),
+            Role::Admin
+        );
+    }
+
+    #[test]
+    fn mesh_scope_from_claims() {
+        // ---
+        let claims = |extra: serde_json::Value| Claims {
+            sub: "a".into(),
+            iss: None,
+            extra: extra.as_object().unwrap().clone(),
+        };
+        assert_eq!(claims(json!({})).meshes(), None);
+        assert_eq!(
+            claims(json!({"meshes": ["mesh-001", 7]})).meshes(),
+            Some(vec!["mesh-001".to_string()])
+        );
+        assert_eq!(claims(json!({"meshes": "mesh-001"})).meshes(), Some(vec![]));
+
+        let p = Principal {
+            name: "a".into(),
+            role: Role::Reader,
+            meshes: Some(vec!["mesh-001".into()]),
+            devices: None,
+        };
+        assert!(p.can_access_mesh("mesh-001"));
+        assert!(!p.can_access_mesh("mesh-002"));
+        assert!(p.can_push_device("device-009"));
+
+        let gateway = Principal {
+            devices: Some(vec!["device-001".into()]),
+            ..p
+        };
+        assert!(gateway.can_push_device("device-001"));
+        assert!(!gateway.can_push_device("device-002"));
+    }
+
+    #[tokio::test]
+    async fn rejects_algorithm_without_key() {
+        // ---
+        let t = encode(
+            &Header::new(Algorithm::HS384),
+            &json!({"sub": "a", "exp": exp()}),
+            &EncodingKey::from_secret(b"s3cret"),
+        )
+        .unwrap();
+        assert!(verifier(None, None).verify(&t).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn client_certificates_need_a_configured_cn() {
+        // ---
+        use axum::{body::Body, http::StatusCode, routing::get, Extension, Router};
+        use tower::ServiceExt;
+
+        let auth = Arc::new(Authenticator {
+            jwt: None,
+            api_keys: Vec::new(),
+            client_certs: vec![ClientCertConfig {
+                common_name: "gateway-a".into(),
+                role: Role::Writer,
+                meshes: None,
+                devices: None,
+            }],
+        });
+        let app = Router::new()
+            .route(
+                "/",
+                get(|Extension(p): Extension<Principal>| async move { p.name }),
+            )
+            .layer(axum::middleware::from_fn_with_state(auth, authenticate));
+        let status = |cn: Option<&str>| {
+            let mut req = Request::new(Body::empty());
+            req.extensions_mut().insert(PeerCertificate {
+                common_name: cn.map(str::to_owned),
+            });
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+
+        assert_eq!(status(Some("gateway-a")).await, StatusCode::OK);
+        assert_eq!(status(Some("gateway-b")).await, StatusCode::FORBIDDEN);
+        // Connected without one: header authentication, and no headers here
+        assert_eq!(status(None).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,231 @@
+//! Command-line interface of the binary, parsed with `clap`.
+//!
+//! Without a command the binary serves the API, as it always has; the other
+//! commands run one operational task against the configured database and
+//! exit, without starting the server. Global options override the environment
+//! (and `.env`) for this run, so a one-off task needs no edited env files;
+//! each falls back to its environment variable, so `--help` shows what is in
+//! effect. `--config` names a configuration file beneath both (see
+//! `config_file.rs`).
+use std::path::PathBuf;
+
+use chrono::Duration;
+use clap::{Args, Parser, Subcommand};
+
+use crate::parse_duration;
+
+/// `--version` text: the package version and the commit it was built from.
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("BUILD_GIT_SHA"), ")");
+
+// ---
+
+/// A parsed command line.
+#[derive(Debug, Parser)]
+#[command(name = "sensorflow-data-pipeline", version = VERSION, about)]
+pub struct Cli {
+    // ---
+    /// What to run; serving the API when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+/// Options accepted before or after any command, each overriding the
+/// environment variable it names.
+#[derive(Debug, Args)]
+#[command(next_help_heading = "Options (override the environment and .env)")]
+pub struct GlobalArgs {
+    // ---
+    /// TOML, YAML or JSON settings beneath the environment
+    #[arg(long, global = true, value_name = "FILE", env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// PostgreSQL (or sqlite:/mysql:) connection string
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        env = "DATABASE_URL",
+        hide_env_values = true
+    )]
+    pub database_url: Option<String>,
+
+    /// Upstream sensor API
+    #[arg(long, global = true, value_name = "URL", env = "SENSOR_API_URL")]
+    pub sensor_api_url: Option<String>,
+
+    /// Log level or filter
+    #[arg(long, global = true, value_name = "LEVEL", env = "AXUM_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Log format
+    #[arg(long, global = true, value_name = "FORMAT", env = "LOG_FORMAT")]
+    pub log_format: Option<String>,
+
+    /// Any other setting; repeatable
+    #[arg(long = "env", global = true, value_name = "KEY=VALUE", value_parser = env_pair)]
+    pub env: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    // ---
+    /// Run the API server (default)
+    Serve,
+
+    /// Create or upgrade the database schema, then exit
+    Migrate,
+
+    /// Ingest every upstream source (or the named ones) once
+    Ingest {
+        /// Required: scheduled ingest runs under serve (SENSOR_API_INTERVAL_SECS)
+        #[arg(long, required = true)]
+        once: bool,
+
+        /// Only this source; repeatable
+        #[arg(long = "source", value_name = "NAME")]
+        sources: Vec<String>,
+    },
+
+    /// Store readings from a JSON file in the upstream wire format (an array,
+    /// or a page with "results")
+    Import {
+        /// The file; - for stdin
+        #[arg(value_name = "FILE")]
+        path: String,
+
+        /// Source to tag the readings with; defaults to import:<file name>
+        #[arg(long, value_name = "NAME")]
+        source: Option<String>,
+    },
+
+    /// Permanently remove soft-deleted readings
+    Purge {
+        /// Only those deleted at least this long ago, e.g. 30d
+        #[arg(long, value_name = "DURATION", value_parser = deleted_before)]
+        deleted_before: Option<Duration>,
+    },
+}
+
+impl Cli {
+    // ---
+    /// The command to run.
+    pub fn command(&self) -> Command {
+        // ---
+        self.command.clone().unwrap_or(Command::Serve)
+    }
+
+    /// Environment overrides from the global options: `--env` pairs first,
+    /// so a named option wins over `--env` for the same variable.
+    pub fn env(&self) -> Vec<(String, String)> {
+        // ---
+        let g = &self.global;
+        let named = [
+            (
+                "CONFIG_FILE",
+                g.config.as_ref().map(|p| p.display().to_string()),
+            ),
+            ("DATABASE_URL", g.database_url.clone()),
+            ("SENSOR_API_URL", g.sensor_api_url.clone()),
+            ("AXUM_LOG_LEVEL", g.log_level.clone()),
+            ("LOG_FORMAT", g.log_format.clone()),
+        ];
+        let named = named
+            .into_iter()
+            .filter_map(|(var, value)| Some((var.to_string(), value?)));
+        g.env.iter().cloned().chain(named).collect()
+    }
+}
+
+fn env_pair(pair: &str) -> Result<(String, String), String> {
+    // ---
+    pair.split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {pair:?}"))
+}
+
+fn deleted_before(raw: &str) -> Result<Duration, String> {
+    // ---
+    parse_duration(raw).ok_or_else(|| format!("expected <n>s, <n>m, <n>h or <n>d, got {raw:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use clap::error::ErrorKind;
+
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Cli, clap::Error> {
+        // ---
+        Cli::try_parse_from(
+            std::iter::once("sensorflow-data-pipeline").chain(args.split_whitespace()),
+        )
+    }
+
+    #[test]
+    fn commands_and_their_flags_parse() {
+        // ---
+        assert_eq!(parse_str("").unwrap().command(), Command::Serve);
+        assert_eq!(parse_str("migrate").unwrap().command(), Command::Migrate);
+        assert_eq!(
+            parse_str("ingest --once --source a --source=b")
+                .unwrap()
+                .command(),
+            Command::Ingest {
+                once: true,
+                sources: vec!["a".into(), "b".into()]
+            }
+        );
+        assert_eq!(
+            parse_str("import - --source backfill").unwrap().command(),
+            Command::Import {
+                path: "-".into(),
+                source: Some("backfill".into())
+            }
+        );
+        assert_eq!(
+            parse_str("purge --deleted-before 30d").unwrap().command(),
+            Command::Purge {
+                deleted_before: Some(Duration::days(30))
+            }
+        );
+        let help = parse_str("serve --help").unwrap_err();
+        assert_eq!(help.kind(), ErrorKind::DisplayHelp);
+        assert_eq!(
+            parse_str("-V").unwrap_err().kind(),
+            ErrorKind::DisplayVersion
+        );
+    }
+
+    #[test]
+    fn global_options_become_env_overrides_anywhere() {
+        // ---
+        let cli = parse_str(
+            "--database-url postgres://db migrate --env RETENTION_MONTHS=6 --config=prod.toml",
+        )
+        .unwrap();
+        assert_eq!(cli.command(), Command::Migrate);
+        // Other variables may come from the test's own environment
+        let env = cli.env();
+        assert_eq!(env[0], ("RETENTION_MONTHS".into(), "6".into()));
+        assert!(env.contains(&("DATABASE_URL".into(), "postgres://db".into())));
+        assert!(env.contains(&("CONFIG_FILE".into(), "prod.toml".into())));
+    }
+
+    #[test]
+    fn mistakes_are_usage_errors() {
+        // ---
+        assert!(parse_str("ingest").is_err());
+        assert!(parse_str("import").is_err());
+        assert!(parse_str("purge --deleted-before soon").is_err());
+        assert!(parse_str("serve --once").is_err());
+        assert!(parse_str("migrate extra").is_err());
+        assert!(parse_str("frobnicate").is_err());
+        assert!(parse_str("--env =x").is_err());
+        assert!(parse_str("--database-url").is_err());
+    }
+}
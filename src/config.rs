@@ -6,14 +6,31 @@
 //! avoid scattering `env::var` calls throughout the codebase, improving
 //! maintainability
 //!
-use std::env;
+use std::{cell::RefCell, collections::HashMap, env, time::Duration};
 
 use anyhow::{anyhow, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+
+use url::Url;
+
+use crate::{secrets::Secrets, Role, DEMO_RATE_LIMIT, REGISTRY_COLUMNS};
+
+thread_local! {
+    /// Variables [`load_with_overlay`] sees in place of the environment's:
+    /// `Some` replaces a value, `None` unsets it.
+    static OVERLAY: RefCell<HashMap<String, Option<String>>> = RefCell::default();
+
+    /// The first secret file [`env_var`] failed to read during a load.
+    static SECRET_FILE_ERROR: RefCell<Option<anyhow::Error>> = RefCell::default();
+}
 
 /// Parse an optional integer environment variable with a default value.
 macro_rules! parse_env_u32 {
     ($var_name:expr, $default:expr) => {
-        env::var($var_name)
+        env_var($var_name)
             .ok()
             .map(|v| v.parse::<u32>())
             .transpose()
@@ -22,86 +39,1525 @@ macro_rules! parse_env_u32 {
     };
 }
 
+/// Parse an optional environment variable into `$ty`, yielding `None` when unset.
+macro_rules! parse_env_opt {
+    ($var_name:expr, $ty:ty) => {
+        env_var(&$var_name)
+            .ok()
+            .map(|v| v.parse::<$ty>())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid {}: {}", $var_name, e))?
+    };
+}
+
 /// Parse a required string environment variable.
 macro_rules! require_env {
     ($var_name:expr) => {
-        env::var($var_name)
-            .map_err(|_| anyhow!("{} must be set in .env or environment", $var_name))?
+        env_var($var_name).map_err(|_| {
+            anyhow!(
+                "{} (or {}_FILE) must be set in .env or environment",
+                $var_name,
+                $var_name
+            )
+        })?
     };
 }
 
 /// Strongly typed application configuration.
 ///
 /// All fields are immutable after loading, ensuring a consistent configuration
-/// snapshot for the lifetime of the application.
+/// snapshot for the lifetime of the application. The few settings a reload
+/// may change are read from [`LiveConfig`](crate::LiveConfig) instead.
 #[derive(Debug, Clone)]
 pub struct Config {
     // ---
+    /// Deployment profile, whose defaults apply to settings left unset.
+    pub app_env: AppEnv,
+
     /// PostgreSQL connection string.
     pub db_url: String,
 
+    /// Read replica connection string; read-only queries prefer it when set.
+    pub replica_url: Option<String>,
+
+    /// How often the read replica's health is checked.
+    pub replica_check_secs: u64,
+
     /// Maximum number of database connections in the pool.
     pub db_pool_max: u32,
 
+    /// Connection attempts retried at startup while the database is unavailable.
+    pub db_connect_retries: u32,
+
+    /// Wait before the first connection retry; doubled for each next one.
+    pub db_connect_backoff_ms: u64,
+
+    /// Longest a query waits for a free pooled connection.
+    pub db_acquire_timeout_ms: u64,
+
+    /// Connections the pool keeps open even when idle.
+    pub db_pool_min: u32,
+
+    /// Idle time after which a pooled connection is closed; `None` keeps it.
+    pub db_idle_timeout_secs: Option<u64>,
+
+    /// Age at which a pooled connection is replaced; `None` keeps it.
+    pub db_max_lifetime_secs: Option<u64>,
+
+    /// Postgres `statement_timeout` set on every connection; `None` leaves it off.
+    pub db_statement_timeout_ms: Option<u64>,
+
+    /// How often pool statistics are sampled for `/admin/pool` and `/metrics`.
+    pub pool_sample_secs: u64,
+
+    /// How often the hourly and daily rollups catch up with new readings.
+    pub rollup_refresh_secs: u64,
+
+    /// Upstream sensor APIs to ingest from (at least one, none in demo mode).
+    pub sources: Vec<SourceConfig>,
+
+    /// HTTP lookups that attach extra fields to readings before storage.
+    pub enrichers: Vec<EnricherConfig>,
+
+    /// Field-level encryption of sensitive attributes; `None` stores them in plain text.
+    pub encryption: Option<EncryptionConfig>,
+
+    /// Source names (or `prefix*` patterns), most preferred first, deciding
+    /// which copy of a reading stored by several sources counts.
+    pub source_priority: Vec<String>,
+
+    /// HMAC key for signing pagination cursors handed to clients.
+    pub cursor_secret: String,
+
+    /// Row limit applied when a request doesn't specify `limit`.
+    pub default_limit: u32,
+
+    /// Known API clients, identified by the `x-api-key` request header.
+    pub api_keys: Vec<ApiKeyConfig>,
+
+    /// Known TLS client certificates, identified by subject common name.
+    pub client_certs: Vec<ClientCertConfig>,
+
+    /// JWT bearer-token validation; `None` (and no `api_keys`) leaves the API
+    /// unauthenticated.
+    pub jwt: Option<JwtConfig>,
+
+    /// HTTPS certificate and key; `None` serves plain HTTP.
+    pub tls: Option<TlsConfig>,
+
+    /// Cross-origin access for browser clients; `None` disables CORS.
+    pub cors: Option<CorsConfig>,
+
+    /// Per-client token-bucket rate limit; `None` disables limiting.
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Log and count queries taking at least this long; `None` disables it.
+    pub slow_query_ms: Option<u64>,
+
+    /// How long hot readings and summary results are served from memory;
+    /// `None` disables the response cache (see `response_cache.rs`).
+    pub response_cache_secs: Option<u64>,
+
+    /// Keep `/ready` at 503 until `sensor_data` has readings, and ingest in
+    /// the background at startup instead of on the first request.
+    pub ready_requires_data: bool,
+
+    /// How long a readings request waits for a running initial ingest before
+    /// answering 503 with `Retry-After`.
+    pub ingest_wait_secs: u64,
+
+    /// Public read-only playground on synthetic data (see `demo.rs`).
+    pub demo_mode: bool,
+
+    /// Build `sensor_data` indexes with `CREATE INDEX CONCURRENTLY` after the
+    /// schema migration rather than inside it.
+    pub schema_indexes_concurrently: bool,
+
+    /// Monthly partitioning of `sensor_data`; `None` keeps one plain table.
+    pub partitioning: Option<PartitionConfig>,
+
+    /// `AXUM_LOG_LEVEL`, else the profile's; `RUST_LOG`, when set, takes precedence.
+    pub log_level: Option<String>,
+}
+
+/// Deployment profile (`APP_ENV`): a bundle of defaults, so the env files of
+/// each deployment need only what differs from it.
+///
+/// | Setting                    | `dev`   | `staging` | `prod` |
+/// |----------------------------|---------|-----------|--------|
+/// | `AXUM_LOG_LEVEL`           | `debug` | `info`    | `info` |
+/// | `DB_POOL_MAX`              | 5       | 10        | 20     |
+/// | `SENSOR_API_INTERVAL_SECS` | unset   | 900       | 300    |
+///
+/// `dev`, the default, keeps the defaults the service has always had.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AppEnv {
+    // ---
+    #[default]
+    Dev,
+    Staging,
+    Prod,
+}
+
+/// Native monthly partitioning of `sensor_data` (see `partitions.rs`).
+#[derive(Debug, Clone)]
+pub struct PartitionConfig {
+    // ---
+    /// Months after the current one whose partitions are created ahead of time.
+    pub premake_months: u32,
+
+    /// Months kept before the current one; older partitions are dropped.
+    /// `None` keeps everything.
+    pub retention_months: Option<u32>,
+}
+
+/// PEM files for native HTTPS serving, re-read on `SIGHUP`.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    // ---
+    /// Certificate chain, leaf first.
+    pub cert_path: String,
+
+    /// Private key for the leaf certificate.
+    pub key_path: String,
+
+    /// CA bundle for verifying client certificates; `None` disables mutual TLS.
+    pub client_ca_path: Option<String>,
+
+    /// Reject handshakes without a client certificate (when `client_ca_path` is set).
+    pub client_cert_required: bool,
+}
+
+/// A client certificate identity, e.g. a device gateway pushing readings.
+///
+/// Matched on the verified certificate's subject CN.
+#[derive(Debug, Clone)]
+pub struct ClientCertConfig {
+    // ---
+    /// Subject common name the certificate must carry.
+    pub common_name: String,
+
+    /// Access level granted.
+    pub role: Role,
+
+    /// Meshes the client may access; `None` means all meshes.
+    pub meshes: Option<Vec<String>>,
+
+    /// Devices the client may push readings for; `None` means all devices.
+    pub devices: Option<Vec<String>>,
+}
+
+/// CORS policy for browser dashboards calling the API cross-origin.
+///
+/// Values are validated at load time, so building the layer can't fail.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    // ---
+    /// Allowed `Origin`s; `["*"]` allows any origin.
+    pub allowed_origins: Vec<String>,
+
+    /// Allowed request methods for preflighted requests.
+    pub allowed_methods: Vec<String>,
+
+    /// Allowed request headers for preflighted requests.
+    pub allowed_headers: Vec<String>,
+}
+
+/// Token-bucket rate limit applied per client (API key or IP).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    // ---
+    /// Sustained requests per second.
+    pub per_sec: f64,
+
+    /// Bucket size: requests a client may make at once after idling.
+    pub burst: u32,
+}
+
+/// JWT validation settings. At least one key source is set.
+///
+/// HS256 tokens are checked against `hs256_secret`; RS256 tokens against
+/// `rs256_public_key_pem` or, by `kid`, the keys published at `jwks_url`.
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    // ---
+    /// Shared secret for HS256 tokens.
+    pub hs256_secret: Option<String>,
+
+    /// PEM-encoded RSA public key for RS256 tokens.
+    pub rs256_public_key_pem: Option<String>,
+
+    /// JWKS endpoint publishing RS256 keys (e.g. the SSO provider's).
+    pub jwks_url: Option<String>,
+
+    /// Required `iss` claim, if set.
+    pub issuer: Option<String>,
+
+    /// Required `aud` claim, if set.
+    pub audience: Option<String>,
+}
+
+/// Application-level encryption of designated attribute fields.
+#[derive(Debug, Clone)]
+pub struct EncryptionConfig {
+    // ---
+    /// AES-256-GCM key.
+    pub key: [u8; 32],
+
+    /// Short label stored with every ciphertext, identifying `key`.
+    pub key_id: String,
+
+    /// Attribute fields to encrypt, as `<enricher name>.<field>`, and
+    /// [`REGISTRY_COLUMNS`] as `<table>.<column>`.
+    pub fields: Vec<String>,
+
+    /// Lowest role that reads the fields decrypted; others see them redacted.
+    pub decrypt_role: Role,
+}
+
+/// Fallback `default_limit` when `DEFAULT_LIMIT` is unset.
+pub const DEFAULT_LIMIT: u32 = 1000;
+
+/// A known API client, identified by the `x-api-key` request header.
+#[derive(Debug, Clone)]
+pub struct ApiKeyConfig {
+    // ---
+    /// Human-readable client name (used in logs, never the key itself).
+    pub name: String,
+
+    /// Secret value the client sends in `x-api-key`.
+    pub key: String,
+
+    /// Overrides `Config::default_limit` for this client.
+    pub default_limit: Option<u32>,
+
+    /// Access level granted to this client.
+    pub role: Role,
+
+    /// Meshes this client may access; `None` means all meshes.
+    pub meshes: Option<Vec<String>>,
+}
+
+/// Configuration for a single upstream sensor API.
+///
+/// Each stored reading is tagged with the `name` of the source it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceConfig {
+    // ---
+    /// Short name stored in `sensor_data.source` for traceability.
+    pub name: String,
+
     /// Sensor data API base URL.
-    pub api_url: String,
+    pub url: String,
+
+    /// Maximum number of API pages to fetch per ingest run (safety limit).
+    pub max_pages: u32,
+
+    /// Value sent in the `x-api-key` header, if the source requires one.
+    pub api_key: Option<String>,
+
+    /// Access token sent with every upstream request, if the source requires one.
+    pub token: Option<String>,
 
-    /// Maximum number of API pages to fetch (safety limit).
-    pub api_max_pages: u32,
+    /// Header carrying `token` verbatim; when `None` the token is sent as
+    /// `Authorization: Bearer <token>`.
+    pub auth_header: Option<String>,
+
+    /// Re-ingest interval in seconds; `None` means ingest once when the
+    /// source has no stored data.
+    pub interval_secs: Option<u64>,
+}
+
+/// An HTTP enrichment lookup, e.g. an asset-management API for device locations.
+#[derive(Debug, Clone)]
+pub struct EnricherConfig {
+    // ---
+    /// Key under which the looked-up fields are stored in `attributes`.
+    pub name: String,
+
+    /// URL template; `{device_id}` and `{mesh_id}` are filled in per reading.
+    pub url: String,
+
+    /// Bearer token sent with every lookup, if the API requires one.
+    pub token: Option<String>,
+
+    /// Response fields to keep; `None` keeps the whole object.
+    pub fields: Option<Vec<String>>,
+
+    /// Per-lookup timeout in milliseconds.
+    pub timeout_ms: u64,
+
+    /// How long a lookup result is reused, in seconds.
+    pub cache_secs: u64,
 }
 
 /// Load configuration from environment variables with defaults.
 ///
+/// A configuration file, when given, has already filled in the variables the
+/// environment leaves unset (see `config_file.rs`).
+///
 /// Required:
-/// - `DATABASE_URL` – PostgreSQL connection string
-/// - `SENSOR_API_URL` – Sensor data API base URL
+/// - `DATABASE_URL` – PostgreSQL connection string, or a `sqlite:`, `mysql:`
+///   or `mariadb:` URL (see `sqlite.rs` and `mysql.rs`)
+/// - `SENSOR_API_URL` – Sensor data API base URL, **or** numbered sources
+///   `SENSOR_API_1_URL`, `SENSOR_API_2_URL`, ... (see [`load_sources`])
 ///
 /// Optional:
-/// - `DB_POOL_MAX` – max DB connections (default: 5)
+/// - `APP_ENV` – `dev`, `staging` or `prod`, selecting the defaults marked
+///   "per profile" (see [`AppEnv`]; default: `dev`)
+/// - `DATABASE_USER`, `DATABASE_PASSWORD` – replace the credentials in
+///   `DATABASE_URL`, e.g. with dynamic ones from Vault (see `secrets.rs`)
+/// - `DB_POOL_MAX` – max DB connections (default per profile: 5)
+/// - `DATABASE_REPLICA_URL` – read replica for read-only queries (default:
+///   unset, everything on the primary)
+/// - `REPLICA_CHECK_SECS` – replica health check interval (default: 5)
+/// - `DB_CONNECT_RETRIES` – startup connection retries while the database is
+///   unavailable (default: 5)
+/// - `DB_CONNECT_BACKOFF_MS` – wait before the first retry, doubled for each
+///   next one up to 30s (default: 500)
+/// - `DB_ACQUIRE_TIMEOUT_MS` – longest wait for a pooled connection (default: 30000)
+/// - `DB_POOL_MIN` – connections kept open even when idle (default: 0)
+/// - `DB_IDLE_TIMEOUT_SECS` – close pooled connections idle this long; `0`
+///   never (default: 600)
+/// - `DB_MAX_LIFETIME_SECS` – replace pooled connections this old; `0` never
+///   (default: 1800)
+/// - `DB_STATEMENT_TIMEOUT_MS` – Postgres `statement_timeout` for every
+///   connection (default: unset, no limit)
+/// - `POOL_SAMPLE_SECS` – pool statistics sampling interval (default: 10)
+/// - `ROLLUP_REFRESH_SECS` – hourly/daily rollup refresh interval (default: 60)
 /// - `API_MAX_PAGES` – max API pages to fetch (default: 100)
+/// - `CURSOR_SECRET` – HMAC key for pagination cursors (default: random per
+///   process, so cursors don't survive restarts or work across replicas)
+/// - `DEFAULT_LIMIT` – rows returned when a request has no `limit` (default: 1000)
+/// - `API_KEY_<N>` – known client keys (see [`load_api_keys`])
+/// - `ENRICH_<N>_URL` – enrichment lookups (see [`load_enrichers`])
+/// - `ENCRYPTED_FIELDS` – attribute fields and registry columns encrypted at rest
+///   (see [`load_encryption`])
+/// - `SOURCE_PRIORITY` – comma-separated source names or `prefix*` patterns,
+///   most preferred first, for reconciling readings stored by several sources
+///   (default: empty, so the earliest stored copy wins)
+/// - `JWT_*` – bearer-token validation (see [`load_jwt`])
+/// - `SLOW_QUERY_MS` – log and count queries taking at least this many
+///   milliseconds (default: unset, disabled)
+/// - `RESPONSE_CACHE_SECS` – serve repeated readings and summary queries from
+///   memory for this many seconds (default: unset, disabled)
+/// - `READY_REQUIRES_DATA` – `true` gates `/ready` on stored data (default: false)
+/// - `INGEST_WAIT_SECS` – how long a readings request waits for the initial
+///   ingest before answering 503 (default: 5)
+/// - `DEMO_MODE` – `true` serves synthetic data read-only, rate limited by
+///   default; no upstream source is needed or used (default: false)
+/// - `SCHEMA_INDEXES_CONCURRENTLY` – `true` builds missing `sensor_data`
+///   indexes without blocking writes (default: false)
+/// - `PARTITION_BY_MONTH` – monthly partitioning of `sensor_data` (see
+///   [`load_partitioning`])
+/// - `TLS_CERT_PATH`, `TLS_KEY_PATH` – PEM files; serve HTTPS when both are set
+/// - `TLS_CLIENT_CA_PATH` – enables client certificates (see [`load_client_certs`])
+/// - `CORS_ALLOWED_ORIGINS` – enables CORS (see [`load_cors`])
+/// - `RATE_LIMIT_PER_SEC` – per-client request rate; enables rate limiting
+/// - `RATE_LIMIT_BURST` – per-client burst size (default: `RATE_LIMIT_PER_SEC`
+///   rounded up)
+///
+/// Any variable except those ending in `_FILE` above can instead be given as
+/// `<NAME>_FILE`, the path of a file holding the value, e.g. a mounted
+/// Docker or Kubernetes secret (see [`env_var`]), and any variable can refer
+/// to a secret in a backend of `secrets` (see `secrets.rs`).
 ///
-/// Returns an error if any required variable is missing or invalid.
-pub fn load_from_env() -> Result<Config> {
+/// Returns the configuration with the shortest lease of the secrets read, or
+/// an error if any required variable is missing or invalid.
+pub async fn load_from_env(secrets: &Secrets) -> Result<(Config, Option<Duration>)> {
+    // ---
+    let (overlay, lease) = secrets.resolve(HashMap::new()).await?;
+    Ok((load_with_overlay(overlay)?, lease))
+}
+
+/// [`load_from_env`] through the overlay set up by [`load_with_overlay`].
+fn load() -> Result<Config> {
     // ---
-    let db_url = require_env!("DATABASE_URL");
-    let api_url = require_env!("SENSOR_API_URL");
-    let db_pool_max = parse_env_u32!("DB_POOL_MAX", 5);
-    let api_max_pages = parse_env_u32!("API_MAX_PAGES", 100);
+    let db_url = with_credentials(
+        &require_env!("DATABASE_URL"),
+        env_var("DATABASE_USER").ok().as_deref(),
+        env_var("DATABASE_PASSWORD").ok().as_deref(),
+    );
+    let app_env = AppEnv::from_env()?;
+    let db_pool_max = parse_env_u32!("DB_POOL_MAX", app_env.db_pool_max());
+    let replica_url = env_var("DATABASE_REPLICA_URL")
+        .ok()
+        .filter(|v| !v.trim().is_empty());
+    let replica_check_secs = match parse_env_opt!("REPLICA_CHECK_SECS", u64) {
+        Some(0) => return Err(anyhow!("Invalid REPLICA_CHECK_SECS: must be > 0")),
+        Some(secs) => secs,
+        None => 5,
+    };
+    let db_connect_retries = parse_env_u32!("DB_CONNECT_RETRIES", 5);
+    let db_connect_backoff_ms = parse_env_opt!("DB_CONNECT_BACKOFF_MS", u64).unwrap_or(500);
+    let db_acquire_timeout_ms = parse_env_opt!("DB_ACQUIRE_TIMEOUT_MS", u64).unwrap_or(30_000);
+    let db_pool_min = parse_env_u32!("DB_POOL_MIN", 0);
+    if db_pool_min > db_pool_max {
+        return Err(anyhow!(
+            "Invalid DB_POOL_MIN: {db_pool_min} is more than DB_POOL_MAX ({db_pool_max})"
+        ));
+    }
+    // 0 turns either limit off
+    let db_idle_timeout_secs =
+        Some(parse_env_opt!("DB_IDLE_TIMEOUT_SECS", u64).unwrap_or(600)).filter(|s| *s > 0);
+    let db_max_lifetime_secs =
+        Some(parse_env_opt!("DB_MAX_LIFETIME_SECS", u64).unwrap_or(1800)).filter(|s| *s > 0);
+    let db_statement_timeout_ms =
+        parse_env_opt!("DB_STATEMENT_TIMEOUT_MS", u64).filter(|ms| *ms > 0);
+    let pool_sample_secs = match parse_env_opt!("POOL_SAMPLE_SECS", u64) {
+        Some(0) => return Err(anyhow!("Invalid POOL_SAMPLE_SECS: must be > 0")),
+        Some(secs) => secs,
+        None => 10,
+    };
+    let rollup_refresh_secs = match parse_env_opt!("ROLLUP_REFRESH_SECS", u64) {
+        Some(0) => return Err(anyhow!("Invalid ROLLUP_REFRESH_SECS: must be > 0")),
+        Some(secs) => secs,
+        None => 60,
+    };
+    let demo_mode = parse_env_opt!("DEMO_MODE", bool).unwrap_or(false);
+    let sources = if demo_mode {
+        if env_var("SENSOR_API_URL").is_ok() || env_var("SENSOR_API_1_URL").is_ok() {
+            tracing::warn!("DEMO_MODE is set; ignoring the configured upstream sources");
+        }
+        Vec::new()
+    } else {
+        load_sources(app_env)?
+    };
+    let enrichers = load_enrichers()?;
+    let encryption = load_encryption()?;
+    let source_priority = env_list("SOURCE_PRIORITY").unwrap_or_default();
+
+    let cursor_secret = env_var("CURSOR_SECRET").unwrap_or_else(|_| {
+        tracing::warn!("CURSOR_SECRET not set; using a random key (cursors reset on restart)");
+        URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+    });
+
+    let default_limit = parse_env_u32!("DEFAULT_LIMIT", DEFAULT_LIMIT);
+    let api_keys = load_api_keys()?;
+    let jwt = load_jwt()?;
+    let tls = match (env_var("TLS_CERT_PATH"), env_var("TLS_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => Some(TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: env_var("TLS_CLIENT_CA_PATH").ok(),
+            client_cert_required: parse_env_opt!("TLS_CLIENT_CERT_REQUIRED", bool).unwrap_or(true),
+        }),
+        (Err(_), Err(_)) => None,
+        _ => {
+            return Err(anyhow!(
+                "TLS_CERT_PATH and TLS_KEY_PATH must be set together"
+            ))
+        }
+    };
+    let client_certs = load_client_certs()?;
+    if !client_certs.is_empty() && tls.as_ref().is_none_or(|t| t.client_ca_path.is_none()) {
+        return Err(anyhow!(
+            "CLIENT_CERT_<N>_CN requires TLS_CERT_PATH, TLS_KEY_PATH and TLS_CLIENT_CA_PATH"
+        ));
+    }
+    let cors = load_cors()?;
+    let ready_requires_data = parse_env_opt!("READY_REQUIRES_DATA", bool).unwrap_or(false);
+    let ingest_wait_secs = parse_env_opt!("INGEST_WAIT_SECS", u64).unwrap_or(5);
+    let slow_query_ms = parse_env_opt!("SLOW_QUERY_MS", u64);
+    let response_cache_secs = parse_env_opt!("RESPONSE_CACHE_SECS", u64).filter(|s| *s > 0);
+    let schema_indexes_concurrently =
+        parse_env_opt!("SCHEMA_INDEXES_CONCURRENTLY", bool).unwrap_or(false);
+    let partitioning = load_partitioning()?;
+
+    let rate_limit = match parse_env_opt!("RATE_LIMIT_PER_SEC", f64) {
+        None if demo_mode => Some(DEMO_RATE_LIMIT),
+        None => None,
+        Some(per_sec) if !(per_sec > 0.0 && per_sec.is_finite()) => {
+            return Err(anyhow!("Invalid RATE_LIMIT_PER_SEC: must be > 0"));
+        }
+        Some(per_sec) => Some(RateLimitConfig {
+            per_sec,
+            burst: parse_env_u32!("RATE_LIMIT_BURST", per_sec.ceil() as u32).max(1),
+        }),
+    };
 
     Ok(Config {
+        app_env,
         db_url,
-        api_url,
+        replica_url,
+        replica_check_secs,
         db_pool_max,
-        api_max_pages,
+        db_connect_retries,
+        db_connect_backoff_ms,
+        db_acquire_timeout_ms,
+        db_pool_min,
+        db_idle_timeout_secs,
+        db_max_lifetime_secs,
+        db_statement_timeout_ms,
+        pool_sample_secs,
+        rollup_refresh_secs,
+        sources,
+        enrichers,
+        encryption,
+        source_priority,
+        cursor_secret,
+        default_limit,
+        api_keys,
+        client_certs,
+        jwt,
+        tls,
+        cors,
+        rate_limit,
+        slow_query_ms,
+        response_cache_secs,
+        ready_requires_data,
+        ingest_wait_secs,
+        demo_mode,
+        schema_indexes_concurrently,
+        partitioning,
+        log_level: Some(
+            env_var("AXUM_LOG_LEVEL").unwrap_or_else(|_| app_env.log_level().to_string()),
+        ),
+    })
+}
+
+/// [`load_from_env`] as if the environment had the `overlay` applied, without
+/// changing it; how a reload reads the configuration files again.
+pub fn load_with_overlay(overlay: HashMap<String, Option<String>>) -> Result<Config> {
+    // ---
+    OVERLAY.with(|o| *o.borrow_mut() = overlay);
+    let loaded = load();
+    OVERLAY.with(|o| o.borrow_mut().clear());
+
+    // A secret file that couldn't be read is the real problem, even when
+    // loading failed on the variable it left unset
+    match SECRET_FILE_ERROR.with(|slot| slot.borrow_mut().take()) {
+        Some(e) => Err(e),
+        None => loaded,
+    }
+}
+
+/// Variables ending in `_FILE` that are settings of their own, not secret files.
+const NOT_SECRET_FILES: &[&str] = &[
+    "CONFIG_FILE",
+    "ENCRYPTION_KEY_FILE",
+    "JWT_RS256_PUBLIC_KEY_FILE",
+];
+
+/// `env::var`, seeing the overlay of a [`load_with_overlay`] in progress.
+///
+/// When `name` is unset, `<name>_FILE` may name a file holding its value
+/// (e.g. a mounted Docker or Kubernetes secret), without its trailing
+/// newline. Setting both, or naming an unreadable file, fails the load that
+/// is in progress (see [`SECRET_FILE_ERROR`]).
+fn env_var(name: impl AsRef<str>) -> Result<String, env::VarError> {
+    // ---
+    let name = name.as_ref();
+    let file_var = format!("{name}_FILE");
+    let value = raw_env_var(name);
+    if NOT_SECRET_FILES.contains(&file_var.as_str()) {
+        return value;
+    }
+    let Ok(path) = raw_env_var(&file_var) else {
+        return value;
+    };
+
+    let read = match value {
+        Ok(_) => Err(anyhow!("Set only one of {name} and {file_var}")),
+        Err(_) => std::fs::read_to_string(&path)
+            .map(|v| v.trim_end_matches(['\n', '\r']).to_string())
+            .map_err(|e| anyhow!("Invalid {file_var} {path}: {e}")),
+    };
+    read.map_err(|e| {
+        SECRET_FILE_ERROR.with(|slot| {
+            slot.borrow_mut().get_or_insert(e);
+        });
+        env::VarError::NotPresent
     })
 }
 
+/// [`env_var`] outside a load, e.g. `VAULT_TOKEN` for the secrets backend;
+/// `None` when unset.
+pub fn setting(name: &str) -> Result<Option<String>> {
+    // ---
+    let value = env_var(name).ok();
+    match SECRET_FILE_ERROR.with(|slot| slot.borrow_mut().take()) {
+        Some(e) => Err(e),
+        None => Ok(value),
+    }
+}
+
+/// `env::var` through the overlay, without secret files.
+fn raw_env_var(name: &str) -> Result<String, env::VarError> {
+    // ---
+    match OVERLAY.with(|o| o.borrow().get(name).cloned()) {
+        Some(Some(value)) => Ok(value),
+        Some(None) => Err(env::VarError::NotPresent),
+        None => env::var(name),
+    }
+}
+
+/// Load monthly partitioning settings; enabled by `PARTITION_BY_MONTH=true`:
+/// - `PARTITION_PREMAKE_MONTHS` – partitions created ahead of the current
+///   month (default: 3)
+/// - `RETENTION_MONTHS` – months kept before the current one; older
+///   partitions are dropped (default: unset, keep everything)
+///
+/// `RETENTION_MONTHS` without `PARTITION_BY_MONTH` is an error.
+fn load_partitioning() -> Result<Option<PartitionConfig>> {
+    // ---
+    let retention_months = match parse_env_opt!("RETENTION_MONTHS", u32) {
+        Some(0) => return Err(anyhow!("Invalid RETENTION_MONTHS: must be > 0")),
+        other => other,
+    };
+    if !parse_env_opt!("PARTITION_BY_MONTH", bool).unwrap_or(false) {
+        if retention_months.is_some() {
+            return Err(anyhow!("RETENTION_MONTHS requires PARTITION_BY_MONTH=true"));
+        }
+        return Ok(None);
+    }
+    Ok(Some(PartitionConfig {
+        premake_months: parse_env_u32!("PARTITION_PREMAKE_MONTHS", 3),
+        retention_months,
+    }))
+}
+
+/// Load JWT validation settings; enabled when any key source is set:
+/// - `JWT_HS256_SECRET` – shared secret for HS256
+/// - `JWT_RS256_PUBLIC_KEY_FILE` – path to a PEM RSA public key for RS256
+/// - `JWT_JWKS_URL` – JWKS endpoint for RS256 keys selected by `kid`
+///
+/// Optional: `JWT_ISSUER`, `JWT_AUDIENCE` – required `iss` / `aud` claims.
+fn load_jwt() -> Result<Option<JwtConfig>> {
+    // ---
+    let hs256_secret = env_var("JWT_HS256_SECRET").ok();
+    let rs256_public_key_pem = env_var("JWT_RS256_PUBLIC_KEY_FILE")
+        .ok()
+        .map(|path| {
+            std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Invalid JWT_RS256_PUBLIC_KEY_FILE {}: {}", path, e))
+        })
+        .transpose()?;
+    let jwks_url = env_var("JWT_JWKS_URL").ok();
+
+    if hs256_secret.is_none() && rs256_public_key_pem.is_none() && jwks_url.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(JwtConfig {
+        hs256_secret,
+        rs256_public_key_pem,
+        jwks_url,
+        issuer: env_var("JWT_ISSUER").ok(),
+        audience: env_var("JWT_AUDIENCE").ok(),
+    }))
+}
+
+/// Load the CORS policy; enabled by `CORS_ALLOWED_ORIGINS`, a comma-separated
+/// list of origins (e.g. `https://dash.example.com`) or `*` for any origin:
+/// - `CORS_ALLOWED_METHODS` – default: `GET,POST,PUT`
+/// - `CORS_ALLOWED_HEADERS` – default: `authorization,content-type,x-api-key,x-request-id,idempotency-key`
+fn load_cors() -> Result<Option<CorsConfig>> {
+    // ---
+    let list = |var: &str, default: &str| -> Vec<String> {
+        env_var(var)
+            .unwrap_or(default.into())
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect()
+    };
+
+    let allowed_origins = list("CORS_ALLOWED_ORIGINS", "");
+    if allowed_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let cfg = CorsConfig {
+        allowed_origins,
+        allowed_methods: list("CORS_ALLOWED_METHODS", "GET,POST,PUT"),
+        allowed_headers: list(
+            "CORS_ALLOWED_HEADERS",
+            "authorization,content-type,x-api-key,x-request-id,idempotency-key",
+        ),
+    };
+
+    for origin in &cfg.allowed_origins {
+        if origin != "*" {
+            reqwest::header::HeaderValue::from_str(origin)
+                .map_err(|e| anyhow!("Invalid CORS_ALLOWED_ORIGINS entry {}: {}", origin, e))?;
+        }
+    }
+    for method in &cfg.allowed_methods {
+        reqwest::Method::from_bytes(method.as_bytes())
+            .map_err(|e| anyhow!("Invalid CORS_ALLOWED_METHODS entry {}: {}", method, e))?;
+    }
+    for header in &cfg.allowed_headers {
+        reqwest::header::HeaderName::from_bytes(header.as_bytes())
+            .map_err(|e| anyhow!("Invalid CORS_ALLOWED_HEADERS entry {}: {}", header, e))?;
+    }
+
+    Ok(Some(cfg))
+}
+
+/// Load known API clients from numbered env vars, `N = 1, 2, ...`, stopping
+/// at the first `N` without an `API_KEY_<N>`:
+/// - `API_KEY_<N>` – the secret clients send in `x-api-key`
+/// - `API_KEY_<N>_NAME` – client name for logs (default: `key-<N>`)
+/// - `API_KEY_<N>_DEFAULT_LIMIT` – per-client default row limit
+/// - `API_KEY_<N>_ROLE` – `reader`, `writer` or `admin` (default: `reader`)
+/// - `API_KEY_<N>_MESHES` – comma-separated mesh IDs the client may access
+///   (default: all meshes)
+fn load_api_keys() -> Result<Vec<ApiKeyConfig>> {
+    // ---
+    let mut keys = Vec::new();
+
+    for n in 1.. {
+        let Ok(key) = env_var(format!("API_KEY_{n}")) else {
+            break;
+        };
+        keys.push(ApiKeyConfig {
+            name: env_var(format!("API_KEY_{n}_NAME")).unwrap_or(format!("key-{n}")),
+            key,
+            default_limit: parse_env_opt!(format!("API_KEY_{n}_DEFAULT_LIMIT"), u32),
+            role: parse_env_opt!(format!("API_KEY_{n}_ROLE"), Role).unwrap_or(Role::Reader),
+            meshes: env_list(&format!("API_KEY_{n}_MESHES")),
+        });
+    }
+
+    Ok(keys)
+}
+
+/// Load client certificate identities from numbered env vars, `N = 1, 2, ...`,
+/// stopping at the first `N` without a `CLIENT_CERT_<N>_CN`.
+///
+/// Certificates are verified against `TLS_CLIENT_CA_PATH` during the TLS
+/// handshake (required unless `TLS_CLIENT_CERT_REQUIRED=false`); the verified
+/// subject CN then selects the identity, and a CN without one is refused:
+/// - `CLIENT_CERT_<N>_CN` – subject common name
+/// - `CLIENT_CERT_<N>_ROLE` – `reader`, `writer` or `admin` (default: `writer`)
+/// - `CLIENT_CERT_<N>_MESHES` – comma-separated mesh IDs (default: all meshes)
+/// - `CLIENT_CERT_<N>_DEVICES` – comma-separated device IDs the client may push
+///   readings for (default: all devices)
+fn load_client_certs() -> Result<Vec<ClientCertConfig>> {
+    // ---
+    let mut certs = Vec::new();
+
+    for n in 1.. {
+        let Ok(common_name) = env_var(format!("CLIENT_CERT_{n}_CN")) else {
+            break;
+        };
+        certs.push(ClientCertConfig {
+            common_name,
+            role: parse_env_opt!(format!("CLIENT_CERT_{n}_ROLE"), Role).unwrap_or(Role::Writer),
+            meshes: env_list(&format!("CLIENT_CERT_{n}_MESHES")),
+            devices: env_list(&format!("CLIENT_CERT_{n}_DEVICES")),
+        });
+    }
+
+    Ok(certs)
+}
+
+/// Load enrichment lookups, read as `ENRICH_<N>_*` for `N = 1, 2, ...` up to
+/// the first `N` without a URL:
+/// - `ENRICH_<N>_URL` – URL template with `{device_id}` / `{mesh_id}` placeholders
+/// - `ENRICH_<N>_NAME` – attribute key (default: `enrich-<N>`)
+/// - `ENRICH_<N>_TOKEN` – bearer token
+/// - `ENRICH_<N>_FIELDS` – comma-separated response fields to keep (default: all)
+/// - `ENRICH_<N>_TIMEOUT_MS` – per-lookup timeout (default: 500)
+/// - `ENRICH_<N>_CACHE_SECS` – result cache lifetime (default: 300)
+fn load_enrichers() -> Result<Vec<EnricherConfig>> {
+    // ---
+    let mut enrichers = Vec::new();
+
+    for n in 1.. {
+        let Ok(url) = env_var(format!("ENRICH_{n}_URL")) else {
+            break;
+        };
+        enrichers.push(EnricherConfig {
+            name: env_var(format!("ENRICH_{n}_NAME")).unwrap_or_else(|_| format!("enrich-{n}")),
+            url,
+            token: env_var(format!("ENRICH_{n}_TOKEN")).ok(),
+            fields: env_list(&format!("ENRICH_{n}_FIELDS")),
+            timeout_ms: parse_env_opt!(format!("ENRICH_{n}_TIMEOUT_MS"), u64).unwrap_or(500),
+            cache_secs: parse_env_opt!(format!("ENRICH_{n}_CACHE_SECS"), u64).unwrap_or(300),
+        });
+    }
+
+    Ok(enrichers)
+}
+
+/// Load field encryption; enabled by `ENCRYPTED_FIELDS`, a comma-separated list
+/// of `<enricher name>.<field>` attributes (e.g. `assets.location`) and
+/// [`REGISTRY_COLUMNS`] (e.g. `devices.notes`):
+/// - `ENCRYPTION_KEY` – base64-encoded 32-byte AES-256-GCM key, **or**
+/// - `ENCRYPTION_KEY_FILE` – file holding it, e.g. written by a KMS or secrets agent
+/// - `ENCRYPTION_KEY_ID` – label stored with each ciphertext (default: `1`)
+/// - `DECRYPT_ROLE` – lowest role reading the fields decrypted (default: `admin`)
+fn load_encryption() -> Result<Option<EncryptionConfig>> {
+    // ---
+    let Some(fields) = env_list("ENCRYPTED_FIELDS").filter(|f| !f.is_empty()) else {
+        return Ok(None);
+    };
+    if let Some(bad) = fields.iter().find(|f| {
+        f.split_once('.')
+            .is_none_or(|(ns, field)| ns.is_empty() || field.is_empty())
+    }) {
+        return Err(anyhow!(
+            "Invalid ENCRYPTED_FIELDS entry {bad:?}: expected <enricher name>.<field>"
+        ));
+    }
+    if let Some(bad) = fields.iter().find(|f| {
+        (f.starts_with("devices.") || f.starts_with("meshes."))
+            && !REGISTRY_COLUMNS.contains(&f.as_str())
+    }) {
+        return Err(anyhow!(
+            "Invalid ENCRYPTED_FIELDS entry {bad:?}: registry columns that can be encrypted are {}",
+            REGISTRY_COLUMNS.join(", ")
+        ));
+    }
+
+    let encoded = match (env_var("ENCRYPTION_KEY"), env_var("ENCRYPTION_KEY_FILE")) {
+        (Ok(key), Err(_)) => key,
+        (Err(_), Ok(path)) => std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Invalid ENCRYPTION_KEY_FILE {}: {}", path, e))?,
+        (Ok(_), Ok(_)) => {
+            return Err(anyhow!(
+                "Set only one of ENCRYPTION_KEY and ENCRYPTION_KEY_FILE"
+            ))
+        }
+        (Err(_), Err(_)) => {
+            return Err(anyhow!(
+                "ENCRYPTED_FIELDS requires ENCRYPTION_KEY or ENCRYPTION_KEY_FILE"
+            ))
+        }
+    };
+    let key: [u8; 32] = STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid encryption key: expected 32 bytes, base64-encoded"))?;
+
+    let key_id = env_var("ENCRYPTION_KEY_ID").unwrap_or_else(|_| "1".into());
+    if key_id.is_empty() || key_id.contains(':') {
+        return Err(anyhow!(
+            "Invalid ENCRYPTION_KEY_ID {key_id:?}: must be non-empty without ':'"
+        ));
+    }
+
+    Ok(Some(EncryptionConfig {
+        key,
+        key_id,
+        fields,
+        decrypt_role: parse_env_opt!("DECRYPT_ROLE", Role).unwrap_or(Role::Admin),
+    }))
+}
+
+/// Comma-separated list from `var`, ignoring blanks; `None` when unset.
+fn env_list(var: &str) -> Option<Vec<String>> {
+    // ---
+    env_var(var).ok().map(|v| {
+        v.split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// Load upstream source definitions.
+///
+/// Numbered sources are read as `SENSOR_API_<N>_*` for `N = 1, 2, ...`,
+/// stopping at the first `N` without a `SENSOR_API_<N>_URL`:
+/// - `SENSOR_API_<N>_URL` – source URL
+/// - `SENSOR_API_<N>_NAME` – source tag (default: `source-<N>`)
+/// - `SENSOR_API_<N>_MAX_PAGES` – page limit (default: `API_MAX_PAGES`)
+/// - `SENSOR_API_<N>_KEY` – `x-api-key` header value
+/// - `SENSOR_API_<N>_TOKEN` – access token (default: `SENSOR_API_TOKEN`)
+/// - `SENSOR_API_<N>_AUTH_HEADER` – header for the token (default:
+///   `SENSOR_API_AUTH_HEADER`, else `Authorization: Bearer`)
+/// - `SENSOR_API_<N>_INTERVAL_SECS` – re-ingest interval (default per
+///   profile, see [`AppEnv`]; `0` turns it off)
+///
+/// If no numbered sources exist, a single source is built from the
+/// unnumbered `SENSOR_API_URL`, `SENSOR_API_NAME` (default: `default`),
+/// `SENSOR_API_KEY`, `SENSOR_API_TOKEN`, `SENSOR_API_AUTH_HEADER` and
+/// `SENSOR_API_INTERVAL_SECS`.
+fn load_sources(app_env: AppEnv) -> Result<Vec<SourceConfig>> {
+    // ---
+    let interval = |var: String| -> Result<Option<u64>> {
+        // ---
+        let secs = parse_env_opt!(var, u64).or(app_env.ingest_interval_secs());
+        Ok(secs.filter(|secs| *secs > 0))
+    };
+    let default_max_pages = parse_env_u32!("API_MAX_PAGES", 100);
+    let default_token = env_var("SENSOR_API_TOKEN").ok();
+    let default_auth_header = env_var("SENSOR_API_AUTH_HEADER").ok();
+    let mut sources = Vec::new();
+
+    for n in 1.. {
+        let var = format!("SENSOR_API_{n}_URL");
+        let Ok(url) = env_var(&var) else {
+            break;
+        };
+        let url = source_url(&var, &url)?;
+        sources.push(SourceConfig {
+            name: env_var(format!("SENSOR_API_{n}_NAME")).unwrap_or(format!("source-{n}")),
+            url,
+            max_pages: parse_env_opt!(format!("SENSOR_API_{n}_MAX_PAGES"), u32)
+                .unwrap_or(default_max_pages),
+            api_key: env_var(format!("SENSOR_API_{n}_KEY")).ok(),
+            token: env_var(format!("SENSOR_API_{n}_TOKEN"))
+                .ok()
+                .or(default_token.clone()),
+            auth_header: env_var(format!("SENSOR_API_{n}_AUTH_HEADER"))
+                .ok()
+                .or(default_auth_header.clone()),
+            interval_secs: interval(format!("SENSOR_API_{n}_INTERVAL_SECS"))?,
+        });
+    }
+
+    if sources.is_empty() {
+        let url = env_var("SENSOR_API_URL").map_err(|_| {
+            anyhow!("SENSOR_API_URL or SENSOR_API_1_URL must be set in .env or environment")
+        })?;
+        let url = source_url("SENSOR_API_URL", &url)?;
+        sources.push(SourceConfig {
+            name: env_var("SENSOR_API_NAME").unwrap_or("default".into()),
+            url,
+            max_pages: default_max_pages,
+            api_key: env_var("SENSOR_API_KEY").ok(),
+            token: default_token,
+            auth_header: default_auth_header,
+            interval_secs: interval("SENSOR_API_INTERVAL_SECS".into())?,
+        });
+    }
+
+    for src in &sources {
+        if let Some(header) = &src.auth_header {
+            reqwest::header::HeaderName::from_bytes(header.as_bytes())
+                .map_err(|e| anyhow!("Invalid auth header for source {}: {}", src.name, e))?;
+        }
+    }
+
+    let mut names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    names.sort_unstable();
+    if let Some(dup) = names.windows(2).find(|w| w[0] == w[1]) {
+        return Err(anyhow!("Duplicate sensor API source name: {}", dup[0]));
+    }
+
+    Ok(sources)
+}
+
+impl AppEnv {
+    // ---
+    /// From `APP_ENV`; `dev` when unset.
+    fn from_env() -> Result<Self> {
+        // ---
+        match env_var("APP_ENV") {
+            Err(_) => Ok(AppEnv::Dev),
+            Ok(value) => AppEnv::parse(&value).ok_or_else(|| {
+                anyhow!("Invalid APP_ENV: expected dev, staging or prod, got {value:?}")
+            }),
+        }
+    }
+
+    /// `dev`, `staging` or `prod`; `development` and `production` also do.
+    pub fn parse(value: &str) -> Option<Self> {
+        // ---
+        match value.trim().to_ascii_lowercase().as_str() {
+            "dev" | "development" => Some(AppEnv::Dev),
+            "staging" => Some(AppEnv::Staging),
+            "prod" | "production" => Some(AppEnv::Prod),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        // ---
+        match self {
+            AppEnv::Dev => "dev",
+            AppEnv::Staging => "staging",
+            AppEnv::Prod => "prod",
+        }
+    }
+
+    pub fn log_level(self) -> &'static str {
+        // ---
+        match self {
+            AppEnv::Dev => "debug",
+            AppEnv::Staging | AppEnv::Prod => "info",
+        }
+    }
+
+    fn db_pool_max(self) -> u32 {
+        // ---
+        match self {
+            AppEnv::Dev => 5,
+            AppEnv::Staging => 10,
+            AppEnv::Prod => 20,
+        }
+    }
+
+    fn ingest_interval_secs(self) -> Option<u64> {
+        // ---
+        match self {
+            AppEnv::Dev => None,
+            AppEnv::Staging => Some(900),
+            AppEnv::Prod => Some(300),
+        }
+    }
+}
+
 impl Config {
+    /// Look up the client configuration for an `x-api-key` header value.
+    pub fn api_key(&self, key: &str) -> Option<&ApiKeyConfig> {
+        // ---
+        self.api_keys.iter().find(|k| k.key == key)
+    }
+
+    /// Default row limit for a request carrying `api_key` (if any).
+    ///
+    /// Falls back to the global `default_limit` for anonymous or unknown keys.
+    pub fn default_limit_for(&self, api_key: Option<&str>) -> u32 {
+        // ---
+        api_key
+            .and_then(|key| self.api_key(key))
+            .and_then(|k| k.default_limit)
+            .unwrap_or(self.default_limit)
+    }
+
+    /// `db_url` with the password masked, for logs and errors.
+    pub fn masked_db_url(&self) -> String {
+        // ---
+        mask_password(&self.db_url)
+    }
+
     /// Log the loaded configuration for debugging purposes.
     ///
     /// Masks sensitive information like database passwords while showing
     /// all configuration values that were loaded.
     pub fn log_config(&self) {
         // ---
-        // Mask the password in the database URL for security
-        let masked_db_url = if let Some(at_pos) = self.db_url.rfind('@') {
-            if let Some(colon_pos) = self.db_url[..at_pos].rfind(':') {
-                format!(
-                    "{}:****{}",
-                    &self.db_url[..colon_pos],
-                    &self.db_url[at_pos..]
-                )
-            } else {
-                self.db_url.clone()
+        tracing::info!("Configuration loaded:");
+        tracing::info!("  APP_ENV        : {}", self.app_env.name());
+        tracing::info!("  DATABASE_URL   : {}", self.masked_db_url());
+        match &self.replica_url {
+            None => tracing::info!("  REPLICA        : disabled"),
+            Some(url) => tracing::info!(
+                "  REPLICA        : {} (checked every {}s)",
+                mask_password(url),
+                self.replica_check_secs
+            ),
+        }
+        tracing::info!(
+            "  DB_POOL        : min={} max={} idle_timeout={} max_lifetime={}",
+            self.db_pool_min,
+            self.db_pool_max,
+            self.db_idle_timeout_secs
+                .map_or("off".to_string(), |s| format!("{s}s")),
+            self.db_max_lifetime_secs
+                .map_or("off".to_string(), |s| format!("{s}s"))
+        );
+        tracing::info!(
+            "  DB_CONNECT     : retries={} backoff={}ms",
+            self.db_connect_retries,
+            self.db_connect_backoff_ms
+        );
+        tracing::info!(
+            "  DB_TIMEOUTS    : acquire={}ms statement={}",
+            self.db_acquire_timeout_ms,
+            self.db_statement_timeout_ms
+                .map_or("off".to_string(), |ms| format!("{ms}ms"))
+        );
+        tracing::info!("  POOL_SAMPLE_SECS: {}", self.pool_sample_secs);
+        tracing::info!("  ROLLUP_REFRESH_SECS: {}", self.rollup_refresh_secs);
+        tracing::info!("  DEFAULT_LIMIT  : {}", self.default_limit);
+        tracing::info!("  READY_REQUIRES_DATA: {}", self.ready_requires_data);
+        tracing::info!("  INGEST_WAIT_SECS: {}", self.ingest_wait_secs);
+        tracing::info!("  DEMO_MODE      : {}", self.demo_mode);
+        tracing::info!(
+            "  SCHEMA_INDEXES_CONCURRENTLY: {}",
+            self.schema_indexes_concurrently
+        );
+        match &self.partitioning {
+            None => tracing::info!("  PARTITIONING   : disabled"),
+            Some(p) => tracing::info!(
+                "  PARTITIONING   : monthly, premake={} retention={}",
+                p.premake_months,
+                p.retention_months
+                    .map_or("unlimited".to_string(), |m| format!("{m} months"))
+            ),
+        }
+        tracing::info!("  SOURCE_PRIORITY: {:?}", self.source_priority);
+        match &self.encryption {
+            None => tracing::info!("  ENCRYPTION     : disabled"),
+            Some(enc) => tracing::info!(
+                "  ENCRYPTION     : fields={:?} key_id={} key=**** decrypt_role={}",
+                enc.fields,
+                enc.key_id,
+                enc.decrypt_role
+            ),
+        }
+        match self.slow_query_ms {
+            None => tracing::info!("  SLOW_QUERY_MS  : disabled"),
+            Some(ms) => tracing::info!("  SLOW_QUERY_MS  : {}", ms),
+        }
+        match self.response_cache_secs {
+            None => tracing::info!("  RESPONSE_CACHE : disabled"),
+            Some(secs) => tracing::info!("  RESPONSE_CACHE : {}s", secs),
+        }
+        match &self.tls {
+            None => tracing::info!("  TLS            : disabled (plain HTTP)"),
+            Some(tls) => tracing::info!(
+                "  TLS            : cert={} key={} client_ca={:?} client_cert_required={}",
+                tls.cert_path,
+                tls.key_path,
+                tls.client_ca_path,
+                tls.client_cert_required
+            ),
+        }
+        for c in &self.client_certs {
+            tracing::info!(
+                "  CLIENT_CERT    : cn={} role={} meshes={:?} devices={:?}",
+                c.common_name,
+                c.role,
+                c.meshes,
+                c.devices
+            );
+        }
+        match &self.cors {
+            None => tracing::info!("  CORS           : disabled"),
+            Some(cors) => tracing::info!(
+                "  CORS           : origins={:?} methods={:?} headers={:?}",
+                cors.allowed_origins,
+                cors.allowed_methods,
+                cors.allowed_headers
+            ),
+        }
+        match &self.rate_limit {
+            None => tracing::info!("  RATE_LIMIT     : disabled"),
+            Some(rl) => tracing::info!("  RATE_LIMIT     : {}/s, burst {}", rl.per_sec, rl.burst),
+        }
+        match &self.jwt {
+            None => tracing::info!("  JWT            : disabled"),
+            Some(jwt) => tracing::info!(
+                "  JWT            : hs256_secret={} rs256_key={} jwks_url={} iss={:?} aud={:?}",
+                mask(&jwt.hs256_secret),
+                if jwt.rs256_public_key_pem.is_some() {
+                    "set"
+                } else {
+                    "none"
+                },
+                jwt.jwks_url.as_deref().unwrap_or("none"),
+                jwt.issuer,
+                jwt.audience,
+            ),
+        }
+        for k in &self.api_keys {
+            tracing::info!(
+                "  API_KEY        : {} (role={}, default_limit={:?}, meshes={:?})",
+                k.name,
+                k.role,
+                k.default_limit,
+                k.meshes
+            );
+        }
+        for e in &self.enrichers {
+            tracing::info!(
+                "  ENRICH         : {} -> {} (fields={:?}, timeout_ms={}, cache_secs={}, token={})",
+                e.name,
+                e.url,
+                e.fields,
+                e.timeout_ms,
+                e.cache_secs,
+                mask(&e.token)
+            );
+        }
+        for src in &self.sources {
+            tracing::info!(
+                "  SOURCE         : {} -> {} (max_pages={}, interval_secs={:?})",
+                src.name,
+                src.url,
+                src.max_pages,
+                src.interval_secs,
+            );
+            tracing::info!(
+                "                   api_key={} token={} auth_header={}",
+                mask(&src.api_key),
+                mask(&src.token),
+                src.auth_header
+                    .as_deref()
+                    .unwrap_or("Authorization: Bearer"),
+            );
+        }
+    }
+}
+
+/// A connection string with its password replaced by `****`.
+fn mask_password(url: &str) -> String {
+    // ---
+    if let Some(at_pos) = url.rfind('@') {
+        if let Some(colon_pos) = url[..at_pos].rfind(':') {
+            return format!("{}:****{}", &url[..colon_pos], &url[at_pos..]);
+        }
+    }
+    url.to_string()
+}
+
+/// Upstream URL `raw` from `var`, checked to be http(s) and without trailing
+/// slashes, so a typo fails at startup rather than at the first ingest.
+fn source_url(var: &str, raw: &str) -> Result<String> {
+    // ---
+    let mut url = Url::parse(raw.trim())
+        .map_err(|e| anyhow!("Invalid {var} {:?}: {e}", mask_password(raw)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!(
+            "Invalid {var} {:?}: must be an http:// or https:// URL",
+            mask_password(raw)
+        ));
+    }
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&path);
+
+    // A bare host keeps its `/` path, which `Url` always prints
+    let url = url.to_string();
+    Ok(url.strip_suffix('/').unwrap_or(&url).to_string())
+}
+
+/// `url` with its user and password replaced by those given.
+fn with_credentials(url: &str, user: Option<&str>, password: Option<&str>) -> String {
+    // ---
+    if user.is_none() && password.is_none() {
+        return url.to_string();
+    }
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(authority_end);
+    let (userinfo, host) = match authority.rfind('@') {
+        Some(at) => (Some(&authority[..at]), &authority[at + 1..]),
+        None => (None, authority),
+    };
+    let (old_user, old_password) = match userinfo {
+        Some(info) => match info.split_once(':') {
+            Some((u, p)) => (Some(u.to_string()), Some(p.to_string())),
+            None => (Some(info.to_string()), None),
+        },
+        None => (None, None),
+    };
+
+    let user = user.map(percent_encode).or(old_user).unwrap_or_default();
+    let password = password.map(percent_encode).or(old_password);
+    match password {
+        Some(password) => format!("{scheme}://{user}:{password}@{host}{tail}"),
+        None => format!("{scheme}://{user}@{host}{tail}"),
+    }
+}
+
+/// Percent-encode everything but URL-unreserved characters.
+fn percent_encode(s: &str) -> String {
+    // ---
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
             }
-        } else {
-            self.db_url.clone()
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Render a secret for logging without revealing it.
+fn mask(secret: &Option<String>) -> &'static str {
+    // ---
+    if secret.is_some() {
+        "****"
+    } else {
+        "none"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn secret_files_set_their_variable() {
+        // ---
+        let path = env::temp_dir().join(format!("sensorflow-secret-{}", std::process::id()));
+        std::fs::write(&path, "s3cret\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let overlay = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), Some(v.to_string())));
+            OVERLAY.with(|o| *o.borrow_mut() = vars.collect());
         };
+        let failure = || SECRET_FILE_ERROR.with(|slot| slot.borrow_mut().take());
 
-        tracing::info!("Configuration loaded:");
-        tracing::info!("  DATABASE_URL   : {}", masked_db_url);
-        tracing::info!("  SENSOR_API_URL : {}", self.api_url);
-        tracing::info!("  DB_POOL_MAX    : {}", self.db_pool_max);
-        tracing::info!("  API_MAX_PAGES  : {}", self.api_max_pages);
+        overlay(&[("SECRET_TEST_TOKEN_FILE", &path)]);
+        assert_eq!(env_var("SECRET_TEST_TOKEN").unwrap(), "s3cret");
+        assert!(failure().is_none());
+
+        overlay(&[
+            ("SECRET_TEST_TOKEN_FILE", &path),
+            ("SECRET_TEST_TOKEN", "x"),
+        ]);
+        assert!(env_var("SECRET_TEST_TOKEN").is_err());
+        assert!(failure().is_some());
+
+        overlay(&[("SECRET_TEST_TOKEN_FILE", "/nonexistent/secret")]);
+        assert!(env_var("SECRET_TEST_TOKEN").is_err());
+        assert!(failure().is_some());
+
+        // Settings whose value is a path stay paths
+        overlay(&[("ENCRYPTION_KEY_FILE", &path)]);
+        assert!(env_var("ENCRYPTION_KEY").is_err());
+        assert_eq!(env_var("ENCRYPTION_KEY_FILE").unwrap(), path);
+        assert!(failure().is_none());
+
+        OVERLAY.with(|o| o.borrow_mut().clear());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Load a minimal configuration with `vars` set (or unset, for `None`).
+    fn load(vars: &[(&str, Option<&str>)]) -> Result<Config> {
+        // ---
+        let overlay = [
+            ("DATABASE_URL", Some("postgres://db/sensors")),
+            ("SENSOR_API_URL", Some("http://upstream/sensor-data")),
+            ("SENSOR_API_1_URL", None),
+            ("APP_ENV", None),
+            ("DB_POOL_MAX", None),
+            ("DB_POOL_MIN", None),
+            ("DB_IDLE_TIMEOUT_SECS", None),
+            ("DB_MAX_LIFETIME_SECS", None),
+            ("AXUM_LOG_LEVEL", None),
+            ("SENSOR_API_INTERVAL_SECS", None),
+            ("DEMO_MODE", None),
+        ]
+        .into_iter()
+        .chain(vars.iter().copied())
+        .map(|(k, v)| (k.to_string(), v.map(String::from)))
+        .collect();
+        load_with_overlay(overlay)
+    }
+
+    #[test]
+    fn profiles_fill_in_unset_settings() {
+        // ---
+
+        let dev = load(&[]).unwrap();
+        assert_eq!(dev.app_env, AppEnv::Dev);
+        assert_eq!(dev.db_pool_max, 5);
+        assert_eq!(dev.log_level.as_deref(), Some("debug"));
+        assert_eq!(dev.sources[0].interval_secs, None);
+
+        let prod = load(&[("APP_ENV", Some("production"))]).unwrap();
+        assert_eq!(prod.app_env, AppEnv::Prod);
+        assert_eq!(prod.db_pool_max, 20);
+        assert_eq!(prod.log_level.as_deref(), Some("info"));
+        assert_eq!(prod.sources[0].interval_secs, Some(300));
+
+        // What is set wins, and 0 turns the profile's re-ingest off
+        let tuned = load(&[
+            ("APP_ENV", Some("prod")),
+            ("DB_POOL_MAX", Some("8")),
+            ("SENSOR_API_INTERVAL_SECS", Some("0")),
+        ])
+        .unwrap();
+        assert_eq!(tuned.db_pool_max, 8);
+        assert_eq!(tuned.sources[0].interval_secs, None);
+
+        assert!(load(&[("APP_ENV", Some("qa"))]).is_err());
+    }
+
+    #[test]
+    fn pool_limits_default_and_turn_off_at_zero() {
+        // ---
+        let cfg = load(&[]).unwrap();
+        assert_eq!(cfg.db_pool_min, 0);
+        assert_eq!(cfg.db_idle_timeout_secs, Some(600));
+        assert_eq!(cfg.db_max_lifetime_secs, Some(1800));
+
+        let cfg = load(&[
+            ("DB_POOL_MIN", Some("2")),
+            ("DB_IDLE_TIMEOUT_SECS", Some("0")),
+            ("DB_MAX_LIFETIME_SECS", Some("300")),
+        ])
+        .unwrap();
+        assert_eq!(cfg.db_pool_min, 2);
+        assert_eq!(cfg.db_idle_timeout_secs, None);
+        assert_eq!(cfg.db_max_lifetime_secs, Some(300));
+
+        assert!(load(&[("DB_POOL_MIN", Some("6"))]).is_err());
+    }
+
+    #[test]
+    fn encrypted_fields_accept_attributes_and_registry_columns() {
+        // ---
+        let key = STANDARD.encode([7u8; 32]);
+        let encrypt = |fields| {
+            load(&[
+                ("ENCRYPTED_FIELDS", Some(fields)),
+                ("ENCRYPTION_KEY", Some(&key)),
+                ("ENCRYPTION_KEY_FILE", None),
+            ])
+        };
+        let cfg = encrypt("assets.location,devices.notes,meshes.contact").unwrap();
+        assert_eq!(cfg.encryption.unwrap().fields.len(), 3);
+
+        let err = encrypt("devices.display_name").unwrap_err().to_string();
+        assert!(err.contains("devices.notes"), "{err}");
+        assert!(encrypt("notes").is_err());
+    }
+
+    #[test]
+    fn source_urls_are_checked_and_normalized() {
+        // ---
+        let url = |raw| source_url("SENSOR_API_URL", raw);
+        assert_eq!(
+            url(" http://sensor-api:8080/sensor-data/ ").unwrap(),
+            "http://sensor-api:8080/sensor-data"
+        );
+        assert_eq!(
+            url("HTTPS://Upstream.example").unwrap(),
+            "https://upstream.example"
+        );
+        assert_eq!(
+            url("http://upstream/v2//?site=a").unwrap(),
+            "http://upstream/v2?site=a"
+        );
+
+        for bad in [
+            "sensor-api:8080/sensor-data",
+            "ftp://upstream/data",
+            "http://",
+            "localhost",
+        ] {
+            assert!(url(bad).is_err(), "{bad} accepted");
+        }
+        let err = url("htp//user:secret@upstream").unwrap_err().to_string();
+        assert!(err.starts_with("Invalid SENSOR_API_URL"), "{err}");
+        assert!(!err.contains("secret"), "{err}");
+    }
+
+    #[test]
+    fn credentials_replace_those_in_the_url() {
+        // ---
+        let url = "postgres://app:old@db:5432/sensors?sslmode=require";
+        assert_eq!(with_credentials(url, None, None), url);
+        assert_eq!(
+            with_credentials(url, Some("v-token-x1"), Some("p@ss/w:rd")),
+            "postgres://v-token-x1:p%40ss%2Fw%3Ard@db:5432/sensors?sslmode=require"
+        );
+        assert_eq!(
+            with_credentials("postgres://db/sensors", None, Some("pw")),
+            "postgres://:pw@db/sensors"
+        );
+        assert_eq!(
+            with_credentials("postgres://app:old@db", Some("other"), None),
+            "postgres://other:old@db"
+        );
     }
 }
@@ -0,0 +1,493 @@
+//! Configuration files.
+//!
+//! Settings can also come from a TOML, YAML or JSON file named by `--config`
+//! or `CONFIG_FILE`, layered under the environment: file, then environment
+//! (and `.env`), then command-line options, each overriding the one before.
+//! The file is deserialized into [`FileConfig`], whose typed sections hold
+//! what is numbered or nested in the environment:
+//!
+//! - `[[sources]]`: upstream sources, the Nth entry `SENSOR_API_<N>_*`
+//! - `[[enrichers]]`: enrichment lookups, `ENRICH_<N>_*`
+//! - `[auth]`: `[auth.jwt]` (`JWT_*`), `[[auth.api_keys]]` (`API_KEY_<N>`,
+//!   `API_KEY_<N>_*`) and `[[auth.client_certs]]` (`CLIENT_CERT_<N>_*`)
+//!
+//! Any other key names a variable by its path, joined with `_` and
+//! uppercased: `[db] pool_max = 5` is `DB_POOL_MAX`, and an array of values
+//! is joined with commas, as list variables expect. The sections reject
+//! unknown fields and mistyped values, naming the line.
+//!
+//! Either way the file comes out as the environment variables
+//! [`load_from_env`] reads, so every setting works in any layer and keeps the
+//! one documented name. [`apply`] only sets variables that aren't set yet,
+//! which is what puts the file below the environment.
+//!
+//! A reload (see `reload.rs`) reads `.env` and the file again through
+//! [`Layers::reread`], leaving the process environment untouched.
+//!
+//! [`load_from_env`]: crate::config::load_from_env
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Display,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::Role;
+
+// ---
+
+/// The variables set by the process environment or the command line, as
+/// opposed to `.env` and the configuration file; only the latter two can
+/// change on a reload.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    // ---
+    pinned: HashSet<String>,
+}
+
+impl Layers {
+    // ---
+    /// Record the variables set so far; call before `.env` and the file are applied.
+    pub fn pin_environment() -> Self {
+        // ---
+        Layers {
+            pinned: env_names().collect(),
+        }
+    }
+
+    /// An overlay (see [`load_with_overlay`]) making the environment look as
+    /// if `.env` and the configuration file were applied just now: what they
+    /// set before is unset unless they still set it.
+    ///
+    /// [`load_with_overlay`]: crate::config::load_with_overlay
+    pub fn reread(&self) -> Result<HashMap<String, Option<String>>> {
+        // ---
+        let mut overlay: HashMap<String, Option<String>> = env_names()
+            .filter(|name| !self.pinned.contains(name))
+            .map(|name| (name, None))
+            .collect();
+
+        match dotenvy::dotenv_iter() {
+            Ok(vars) => {
+                for var in vars {
+                    let (key, value) = var.context("reading .env")?;
+                    if !self.pinned.contains(&key) {
+                        overlay.insert(key, Some(value));
+                    }
+                }
+            }
+            Err(e) if e.not_found() => {}
+            Err(e) => return Err(e).context("reading .env"),
+        }
+
+        let path = match overlay.get("CONFIG_FILE") {
+            Some(path) => path.clone(),
+            None => env::var("CONFIG_FILE").ok(),
+        };
+        if let Some(path) = path {
+            for (key, value) in load(Path::new(&path))? {
+                let set = self.pinned.contains(&key) || matches!(overlay.get(&key), Some(Some(_)));
+                if !set {
+                    overlay.insert(key, Some(value));
+                }
+            }
+        }
+        Ok(overlay)
+    }
+}
+
+/// Set every variable `path` defines that isn't set already; returns how many
+/// were set.
+pub fn apply(path: &Path) -> Result<usize> {
+    // ---
+    let pairs = load(path)?;
+    let mut set = 0;
+    for (key, value) in pairs {
+        if env::var_os(&key).is_none() {
+            env::set_var(key, value);
+            set += 1;
+        }
+    }
+    Ok(set)
+}
+
+/// The variables the file at `path` defines; JSON for a `.json` file, YAML
+/// for `.yaml` or `.yml`, TOML otherwise.
+pub fn load(path: &Path) -> Result<Vec<(String, String)>> {
+    // ---
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading config file {}", path.display()))?;
+    let ext = path.extension().and_then(|ext| ext.to_str());
+    let parsed = match ext {
+        Some("json") => serde_json::from_str::<FileConfig>(&text).map_err(anyhow::Error::from),
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(anyhow::Error::from),
+        _ => toml::from_str(&text).map_err(anyhow::Error::from),
+    };
+    parsed
+        .and_then(FileConfig::into_vars)
+        .with_context(|| format!("parsing config file {}", path.display()))
+}
+
+fn env_names() -> impl Iterator<Item = String> {
+    // ---
+    env::vars_os().filter_map(|(name, _)| name.into_string().ok())
+}
+
+/// A configuration file.
+#[derive(Debug, Default, Deserialize)]
+pub struct FileConfig {
+    // ---
+    /// Upstream sources, in priority of their numbering.
+    #[serde(default)]
+    pub sources: Vec<SourceSection>,
+
+    #[serde(default)]
+    pub enrichers: Vec<EnricherSection>,
+
+    #[serde(default)]
+    pub auth: AuthSection,
+
+    /// Every other setting, by its key path.
+    #[serde(flatten)]
+    pub settings: Map<String, Value>,
+}
+
+/// One `[[sources]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSection {
+    // ---
+    pub url: String,
+    pub name: Option<String>,
+
+    /// Sent as `x-api-key`.
+    pub api_key: Option<String>,
+    pub token: Option<String>,
+    pub auth_header: Option<String>,
+    pub interval_secs: Option<u64>,
+    pub max_pages: Option<u32>,
+}
+
+/// One `[[enrichers]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnricherSection {
+    // ---
+    pub url: String,
+    pub name: Option<String>,
+    pub token: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<String>,
+    pub timeout_ms: Option<u64>,
+    pub cache_secs: Option<u64>,
+}
+
+/// The `[auth]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthSection {
+    // ---
+    pub jwt: Option<JwtSection>,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeySection>,
+    #[serde(default)]
+    pub client_certs: Vec<ClientCertSection>,
+}
+
+/// `[auth.jwt]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtSection {
+    // ---
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub jwks_url: Option<String>,
+    pub hs256_secret: Option<String>,
+    pub rs256_public_key_file: Option<String>,
+}
+
+/// One `[[auth.api_keys]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeySection {
+    // ---
+    /// The secret clients send.
+    pub key: String,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "role")]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub meshes: Vec<String>,
+    pub default_limit: Option<u32>,
+}
+
+/// One `[[auth.client_certs]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientCertSection {
+    // ---
+    pub cn: String,
+    #[serde(default, deserialize_with = "role")]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub meshes: Vec<String>,
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
+impl FileConfig {
+    // ---
+    /// The environment variables this file sets, sections first.
+    pub fn into_vars(self) -> Result<Vec<(String, String)>> {
+        // ---
+        let mut vars = Vars::default();
+        for (n, s) in (1..).zip(self.sources) {
+            let p = format!("SENSOR_API_{n}");
+            vars.set(format!("{p}_URL"), Some(s.url));
+            vars.set(format!("{p}_NAME"), s.name);
+            vars.set(format!("{p}_KEY"), s.api_key);
+            vars.set(format!("{p}_TOKEN"), s.token);
+            vars.set(format!("{p}_AUTH_HEADER"), s.auth_header);
+            vars.set(format!("{p}_INTERVAL_SECS"), s.interval_secs);
+            vars.set(format!("{p}_MAX_PAGES"), s.max_pages);
+        }
+        for (n, e) in (1..).zip(self.enrichers) {
+            let p = format!("ENRICH_{n}");
+            vars.set(format!("{p}_URL"), Some(e.url));
+            vars.set(format!("{p}_NAME"), e.name);
+            vars.set(format!("{p}_TOKEN"), e.token);
+            vars.list(format!("{p}_FIELDS"), &e.fields);
+            vars.set(format!("{p}_TIMEOUT_MS"), e.timeout_ms);
+            vars.set(format!("{p}_CACHE_SECS"), e.cache_secs);
+        }
+        if let Some(jwt) = self.auth.jwt {
+            vars.set("JWT_ISSUER".into(), jwt.issuer);
+            vars.set("JWT_AUDIENCE".into(), jwt.audience);
+            vars.set("JWT_JWKS_URL".into(), jwt.jwks_url);
+            vars.set("JWT_HS256_SECRET".into(), jwt.hs256_secret);
+            vars.set(
+                "JWT_RS256_PUBLIC_KEY_FILE".into(),
+                jwt.rs256_public_key_file,
+            );
+        }
+        for (n, k) in (1..).zip(self.auth.api_keys) {
+            let p = format!("API_KEY_{n}");
+            vars.set(p.clone(), Some(k.key));
+            vars.set(format!("{p}_NAME"), k.name);
+            vars.set(format!("{p}_ROLE"), k.role);
+            vars.list(format!("{p}_MESHES"), &k.meshes);
+            vars.set(format!("{p}_DEFAULT_LIMIT"), k.default_limit);
+        }
+        for (n, c) in (1..).zip(self.auth.client_certs) {
+            let p = format!("CLIENT_CERT_{n}");
+            vars.set(format!("{p}_CN"), Some(c.cn));
+            vars.set(format!("{p}_ROLE"), c.role);
+            vars.list(format!("{p}_MESHES"), &c.meshes);
+            vars.list(format!("{p}_DEVICES"), &c.devices);
+        }
+        for (key, value) in &self.settings {
+            vars.walk(&mut vec![key.clone()], value)?;
+        }
+        vars.into_unique()
+    }
+}
+
+/// Variables collected from a file, in order.
+#[derive(Default)]
+struct Vars(Vec<(String, String)>);
+
+impl Vars {
+    // ---
+    fn set(&mut self, var: String, value: Option<impl Display>) {
+        // ---
+        if let Some(value) = value {
+            self.0.push((var, value.to_string()));
+        }
+    }
+
+    fn list(&mut self, var: String, values: &[String]) {
+        // ---
+        if !values.is_empty() {
+            self.0.push((var, values.join(",")));
+        }
+    }
+
+    /// Add the variables of the untyped setting at `path`.
+    fn walk(&mut self, path: &mut Vec<String>, value: &Value) -> Result<()> {
+        // ---
+        let var = || path.join("_").replace('-', "_").to_ascii_uppercase();
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    path.push(key.clone());
+                    self.walk(path, value)?;
+                    path.pop();
+                }
+            }
+            Value::Array(items) => {
+                let values = items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => Ok(s.clone()),
+                        Value::Number(_) | Value::Bool(_) => Ok(item.to_string()),
+                        _ => bail!(
+                            "{}: only sources, enrichers and auth take lists of tables; \
+                             other lists hold strings, numbers or booleans",
+                            path.join(".")
+                        ),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.0.push((var(), values.join(",")));
+            }
+            Value::String(s) => self.0.push((var(), s.clone())),
+            Value::Number(_) | Value::Bool(_) => self.0.push((var(), value.to_string())),
+            Value::Null => {}
+        }
+        Ok(())
+    }
+
+    /// The variables, refusing one set twice (e.g. by a section and a key).
+    fn into_unique(self) -> Result<Vec<(String, String)>> {
+        // ---
+        let mut seen = HashSet::new();
+        if let Some((var, _)) = self.0.iter().find(|(var, _)| !seen.insert(var.as_str())) {
+            bail!("{var} is set twice");
+        }
+        Ok(self.0)
+    }
+}
+
+/// A role, by the names `API_KEY_<N>_ROLE` takes.
+fn role<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Role>, D::Error> {
+    // ---
+    let raw = Option::<String>::deserialize(d)?;
+    raw.map(|r| r.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        // ---
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn toml_sections_become_env_names() {
+        // ---
+        let file: FileConfig = toml::from_str(
+            r#"
+            database_url = "postgres://db/sensors"  # primary
+            source_priority = ["default", "push:*"]
+
+            [db]
+            pool_max = 10
+
+            [[sources]]
+            name = "east"
+            url = 'https://east.example/sensor-data'
+            interval_secs = 3_600
+
+            [[sources]]
+            name = "west#2"
+            url = "https://west.example/sensor-data"
+
+            [auth.jwt]
+            audience = "sensorflow \"api\""
+
+            [[auth.api_keys]]
+            key = "s3cret"
+            role = "Writer"
+            meshes = [
+                "mesh-001",
+                "mesh-002",
+            ]
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.into_vars().unwrap(),
+            pairs(&[
+                ("SENSOR_API_1_URL", "https://east.example/sensor-data"),
+                ("SENSOR_API_1_NAME", "east"),
+                ("SENSOR_API_1_INTERVAL_SECS", "3600"),
+                ("SENSOR_API_2_URL", "https://west.example/sensor-data"),
+                ("SENSOR_API_2_NAME", "west#2"),
+                ("JWT_AUDIENCE", "sensorflow \"api\""),
+                ("API_KEY_1", "s3cret"),
+                ("API_KEY_1_ROLE", "writer"),
+                ("API_KEY_1_MESHES", "mesh-001,mesh-002"),
+                ("DATABASE_URL", "postgres://db/sensors"),
+                ("DB_POOL_MAX", "10"),
+                ("SOURCE_PRIORITY", "default,push:*"),
+            ])
+        );
+    }
+
+    #[test]
+    fn yaml_and_json_read_like_toml() {
+        // ---
+        let yaml: FileConfig = serde_yaml::from_str(
+            "
+            database_url: postgres://db/sensors
+            cors:
+              allowed_origins: [https://a.example, https://b.example]
+            sources:
+              - url: https://east.example
+            auth:
+              client_certs:
+                - cn: gateway-a
+                  devices: [device-001]
+            ",
+        )
+        .unwrap();
+        let json: FileConfig = serde_json::from_str(
+            r#"{
+                "database_url": "postgres://db/sensors",
+                "cors": { "allowed_origins": ["https://a.example", "https://b.example"] },
+                "sources": [{ "url": "https://east.example" }],
+                "auth": { "client_certs": [{ "cn": "gateway-a", "devices": ["device-001"] }] }
+            }"#,
+        )
+        .unwrap();
+        let expected = pairs(&[
+            ("SENSOR_API_1_URL", "https://east.example"),
+            ("CLIENT_CERT_1_CN", "gateway-a"),
+            ("CLIENT_CERT_1_DEVICES", "device-001"),
+            (
+                "CORS_ALLOWED_ORIGINS",
+                "https://a.example,https://b.example",
+            ),
+            ("DATABASE_URL", "postgres://db/sensors"),
+        ]);
+        assert_eq!(yaml.into_vars().unwrap(), expected);
+        assert_eq!(json.into_vars().unwrap(), expected);
+    }
+
+    #[test]
+    fn mistakes_are_errors() {
+        // ---
+        let toml = |text| toml::from_str::<FileConfig>(text).map_err(anyhow::Error::from);
+        let vars = |text| toml(text).and_then(FileConfig::into_vars);
+
+        // Typed sections check their fields, naming the line
+        let err = toml("[[sources]]\nurl = \"http://a\"\ninterval_secs = \"soon\"").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{err}");
+        assert!(toml("[[sources]]\nurl = \"http://a\"\nurl_typo = 1").is_err());
+        assert!(toml("[[auth.api_keys]]\nkey = \"k\"\nrole = \"root\"").is_err());
+        assert!(toml("[db]\npool_max = 1\n[db]\npool_max = 2").is_err());
+
+        assert!(vars("[[partners]]\nurl = \"http://a\"").is_err());
+        assert!(vars("sensor_api_1_url = \"a\"\n[[sources]]\nurl = \"b\"").is_err());
+        assert!(vars("jwt = { issuer = \"x\" }").is_ok());
+    }
+}
@@ -0,0 +1,256 @@
+//! Opaque, signed pagination cursors.
+//!
+//! Cursors handed to clients are `v1.<payload>.<signature>` where `payload` is
+//! base64url JSON describing the keyset position and `signature` is an
+//! HMAC-SHA256 over `v1.<payload>` keyed by `CURSOR_SECRET`. Each payload is
+//! tagged with its kind, so a cursor minted for one endpoint can't be replayed
+//! against another. Clients can't forge or edit cursors into arbitrary
+//! queries; anything that fails verification is a [`CursorError`].
+use std::fmt;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Current cursor format version; bump when the payload layout changes.
+const VERSION: &str = "v1";
+
+// ---
+
+/// Keyset position for `GET /sql/readings` (ordered by `timestamp_utc DESC, id DESC`
+/// unless `sort` says otherwise).
+///
+/// Points at the last row of the previous page; the next page starts strictly
+/// after it in the same order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadingsCursor {
+    // ---
+    /// Timestamp of the last row returned.
+    pub timestamp_utc: DateTime<Utc>,
+
+    /// Primary key of the last row returned (tie-breaker for equal timestamps).
+    pub id: i32,
+
+    /// `sort` the page was ordered by; absent for the default newest-first order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+
+    /// Sort key of the last row returned, for orders by a measurement or device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_c: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+impl ReadingsCursor {
+    // ---
+    const KIND: &'static str = "readings";
+
+    /// Encode and sign this cursor.
+    pub fn encode(&self, secret: &[u8]) -> String {
+        // ---
+        let envelope = Envelope {
+            k: Self::KIND.to_string(),
+            pos: self.clone(),
+        };
+        let json = serde_json::to_vec(&envelope).expect("cursor payload serializes");
+        let payload = URL_SAFE_NO_PAD.encode(json);
+        let signed = format!("{VERSION}.{payload}");
+        let sig = URL_SAFE_NO_PAD.encode(sign(secret, &signed));
+        format!("{signed}.{sig}")
+    }
+
+    /// Verify and decode a cursor produced by [`ReadingsCursor::encode`].
+    pub fn decode(secret: &[u8], s: &str) -> Result<Self, CursorError> {
+        // ---
+        let mut parts = s.trim().splitn(3, '.');
+        let (Some(version), Some(payload), Some(sig)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(CursorError::Malformed);
+        };
+
+        if version != VERSION {
+            return Err(CursorError::UnsupportedVersion);
+        }
+
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig)
+            .map_err(|_| CursorError::Malformed)?;
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+        mac.update(format!("{version}.{payload}").as_bytes());
+        // Constant-time comparison
+        mac.verify_slice(&sig)
+            .map_err(|_| CursorError::BadSignature)?;
+
+        let json = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| CursorError::Malformed)?;
+        let envelope: Envelope<Self> =
+            serde_json::from_slice(&json).map_err(|_| CursorError::Malformed)?;
+
+        if envelope.k != Self::KIND {
+            return Err(CursorError::WrongKind);
+        }
+        Ok(envelope.pos)
+    }
+}
+
+/// Why a client-supplied cursor was rejected.
+#[derive(Debug, PartialEq)]
+pub enum CursorError {
+    // ---
+    /// Not of the form `version.payload.signature`, or undecodable.
+    Malformed,
+
+    /// Produced by an unknown cursor format version.
+    UnsupportedVersion,
+
+    /// Signature doesn't match (forged, edited, or signed with another secret).
+    BadSignature,
+
+    /// Valid cursor, but minted for a different endpoint.
+    WrongKind,
+}
+
+impl CursorError {
+    // ---
+    /// Client-facing explanation, suitable for an error body `hint`.
+    pub fn hint(&self) -> &'static str {
+        // ---
+        match self {
+            CursorError::Malformed => {
+                "cursor is malformed; pass the X-Next-Cursor value back unchanged"
+            }
+            CursorError::UnsupportedVersion => {
+                "cursor format is no longer supported; restart pagination without a cursor"
+            }
+            CursorError::BadSignature => {
+                "cursor signature mismatch; cursors can't be edited and expire on key rotation"
+            }
+            CursorError::WrongKind => "cursor was issued by a different endpoint",
+        }
+    }
+}
+
+impl fmt::Display for CursorError {
+    // ---
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // ---
+        f.write_str(self.hint())
+    }
+}
+
+/// Wire payload: the position plus its kind tag.
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    k: String,
+    #[serde(flatten)]
+    pos: T,
+}
+
+fn sign(secret: &[u8], data: &str) -> Vec<u8> {
+    // ---
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use chrono::TimeZone;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn sample() -> ReadingsCursor {
+        // ---
+        ReadingsCursor {
+            timestamp_utc: Utc.with_ymd_and_hms(2025, 3, 21, 12, 0, 0).unwrap(),
+            id: 42,
+            sort: None,
+            temperature_c: None,
+            humidity: None,
+            device_id: None,
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        // ---
+        let token = sample().encode(SECRET);
+        assert!(token.starts_with("v1."));
+        assert_eq!(ReadingsCursor::decode(SECRET, &token), Ok(sample()));
+    }
+
+    #[test]
+    fn round_trips_sort_keys() {
+        // ---
+        let cursor = ReadingsCursor {
+            sort: Some("temperature_desc".into()),
+            temperature_c: Some(21.3),
+            ..sample()
+        };
+        let token = cursor.encode(SECRET);
+        assert_eq!(ReadingsCursor::decode(SECRET, &token), Ok(cursor));
+    }
+
+    #[test]
+    fn rejects_other_secret() {
+        // ---
+        let token = sample().encode(SECRET);
+        assert_eq!(
+            ReadingsCursor::decode(b"other-secret", &token),
+            Err(CursorError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_edited_payload() {
+        // ---
+        let token = sample().encode(SECRET);
+        let (_, rest) = token.split_once('.').unwrap();
+        let (_, sig) = rest.split_once('.').unwrap();
+
+        let forged = URL_SAFE_NO_PAD
+            .encode(r#"{"k":"readings","timestamp_utc":"1970-01-01T00:00:00Z","id":0}"#);
+        assert_eq!(
+            ReadingsCursor::decode(SECRET, &format!("v1.{forged}.{sig}")),
+            Err(CursorError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_version_and_garbage() {
+        // ---
+        let token = sample().encode(SECRET);
+        let v2 = token.replacen("v1.", "v2.", 1);
+        assert_eq!(
+            ReadingsCursor::decode(SECRET, &v2),
+            Err(CursorError::UnsupportedVersion)
+        );
+        assert_eq!(
+            ReadingsCursor::decode(SECRET, "not-a-cursor"),
+            Err(CursorError::Malformed)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_kind() {
+        // ---
+        let payload = URL_SAFE_NO_PAD
+            .encode(r#"{"k":"changes","timestamp_utc":"2025-03-21T12:00:00Z","id":42}"#);
+        let signed = format!("v1.{payload}");
+        let sig = URL_SAFE_NO_PAD.encode(sign(SECRET, &signed));
+        assert_eq!(
+            ReadingsCursor::decode(SECRET, &format!("{signed}.{sig}")),
+            Err(CursorError::WrongKind)
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Database connection pool setup.
+//!
+//! [`connect`] opens the pool and, when Postgres isn't reachable yet (e.g.
+//! its container is still starting), retries up to `DB_CONNECT_RETRIES`
+//! times, waiting `DB_CONNECT_BACKOFF_MS` before the first retry and twice
+//! as long before each next one, up to [`MAX_BACKOFF`]. Errors that waiting
+//! can't fix, such as a bad password or unknown database, fail at once.
+//!
+//! Each attempt opens a single connection first: the pool itself keeps
+//! retrying a refused connection until its acquire timeout, which would turn
+//! every attempt into a 30 second wait.
+//!
+//! The pool keeps `DB_POOL_MIN` to `DB_POOL_MAX` connections, closing those
+//! idle for `DB_IDLE_TIMEOUT_SECS` and replacing those older than
+//! `DB_MAX_LIFETIME_SECS` (e.g. to stay under a PgBouncer or load balancer
+//! timeout). Pooled connections wait at most `DB_ACQUIRE_TIMEOUT_MS` for a free slot,
+//! and with `DB_STATEMENT_TIMEOUT_MS` set, every connection runs
+//! `SET statement_timeout` when opened, so a runaway query is cancelled
+//! instead of holding its connection. Handlers pass database errors through
+//! [`db_error_response`], which answers either timeout with 503.
+//!
+//! With `DATABASE_REPLICA_URL` set, read-only queries go through
+//! [`ReadPool`] to a second pool on the replica while everything else uses
+//! the primary. The replica is checked every `REPLICA_CHECK_SECS`; reads
+//! fall back to the primary while it is unreachable, immediately when a read
+//! fails to reach it, and until its first successful check. Reads from the
+//! replica may trail writes by its replication lag.
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use axum::response::{IntoResponse, Response};
+use sqlx::{
+    postgres::{PgConnection, PgPoolOptions},
+    Connection, Executor, PgPool,
+};
+
+use crate::{ApiError, Config, REPLICA_HEALTHY};
+
+/// Longest wait between two connection attempts.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// SQLSTATE `cannot_connect_now`: the server is starting up or shutting down.
+const CANNOT_CONNECT_NOW: &str = "57P03";
+
+/// SQLSTATE `query_canceled`, raised when `statement_timeout` expires.
+const QUERY_CANCELED: &str = "57014";
+
+// ---
+
+/// Open the pool, retrying while the database is unavailable.
+pub async fn connect(cfg: &Config) -> Result<PgPool> {
+    // ---
+    let base = Duration::from_millis(cfg.db_connect_backoff_ms);
+    let mut attempt = 0;
+    loop {
+        let err = match PgConnection::connect(&cfg.db_url).await {
+            Ok(probe) => {
+                let _ = probe.close().await;
+                match pool_options(cfg).connect(&cfg.db_url).await {
+                    Ok(pool) => return Ok(pool),
+                    Err(e) => e,
+                }
+            }
+            Err(e) => e,
+        };
+
+        if attempt >= cfg.db_connect_retries || !is_transient(&err) {
+            return Err(anyhow!(
+                "Failed to connect to database '{}': {}",
+                cfg.masked_db_url(),
+                err
+            ));
+        }
+        let delay = retry_delay(base, attempt);
+        attempt += 1;
+        tracing::warn!(
+            "Database not available ({}); retry {}/{} in {:?}",
+            err,
+            attempt,
+            cfg.db_connect_retries,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Pool on `DATABASE_REPLICA_URL`, if set.
+///
+/// Connects lazily: an unreachable replica must not hold up startup, and
+/// [`ReadPool`] keeps reads on the primary until it answers.
+pub fn connect_replica(cfg: &Config) -> Result<Option<PgPool>> {
+    // ---
+    let Some(url) = &cfg.replica_url else {
+        return Ok(None);
+    };
+    let pool = pool_options(cfg)
+        .connect_lazy(url)
+        .map_err(|e| anyhow!("Invalid DATABASE_REPLICA_URL: {e}"))?;
+    Ok(Some(pool))
+}
+
+/// Where read-only queries run: the replica while healthy, else the primary.
+#[derive(Clone)]
+pub struct ReadPool {
+    // ---
+    primary: PgPool,
+    replica: Option<(PgPool, Arc<AtomicU8>)>,
+}
+
+/// Replica health states kept in the `AtomicU8`.
+const UNCHECKED: u8 = 0;
+const HEALTHY: u8 = 1;
+const UNAVAILABLE: u8 = 2;
+
+impl ReadPool {
+    // ---
+    /// Route reads to `replica` (when given), checking it every `check_every`.
+    pub fn spawn(primary: PgPool, replica: Option<PgPool>, check_every: Duration) -> Self {
+        // ---
+        let Some(replica) = replica else {
+            return Self {
+                primary,
+                replica: None,
+            };
+        };
+        let health = Arc::new(AtomicU8::new(UNCHECKED));
+
+        let (pool, state) = (replica.clone(), health.clone());
+        tokio::spawn(async move {
+            // ---
+            let mut ticker = tokio::time::interval(check_every);
+            loop {
+                ticker.tick().await;
+                let ok = matches!(
+                    tokio::time::timeout(check_every, sqlx::query("SELECT 1").execute(&pool)).await,
+                    Ok(Ok(_))
+                );
+                set_health(&state, ok);
+            }
+        });
+        Self {
+            primary,
+            replica: Some((replica, health)),
+        }
+    }
+
+    /// The pool a read should use now.
+    pub fn pool(&self) -> &PgPool {
+        // ---
+        match &self.replica {
+            Some((replica, health)) if health.load(Ordering::Relaxed) == HEALTHY => replica,
+            _ => &self.primary,
+        }
+    }
+
+    /// Run `query` on [`pool`](Self::pool), retrying on the primary when the
+    /// replica can't be reached.
+    pub async fn read<T, Fut>(&self, query: impl Fn(PgPool) -> Fut) -> Result<T, sqlx::Error>
+    where
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        // ---
+        let Some((replica, health)) = &self.replica else {
+            return query(self.primary.clone()).await;
+        };
+        if health.load(Ordering::Relaxed) != HEALTHY {
+            return query(self.primary.clone()).await;
+        }
+        match query(replica.clone()).await {
+            Err(e) if is_transient(&e) => {
+                tracing::warn!("Read replica query failed ({}); retrying on primary", e);
+                set_health(health, false);
+                query(self.primary.clone()).await
+            }
+            out => out,
+        }
+    }
+}
+
+/// Record a replica health check, logging changes.
+fn set_health(state: &AtomicU8, ok: bool) {
+    // ---
+    let new = if ok { HEALTHY } else { UNAVAILABLE };
+    if state.swap(new, Ordering::Relaxed) != new {
+        if ok {
+            tracing::info!("Read replica healthy; routing reads to it");
+        } else {
+            tracing::warn!("Read replica unavailable; routing reads to the primary");
+        }
+    }
+    metrics::gauge!(REPLICA_HEALTHY).set(if ok { 1.0 } else { 0.0 });
+}
+
+fn pool_options(cfg: &Config) -> PgPoolOptions {
+    // ---
+    let statement_timeout_ms = cfg.db_statement_timeout_ms;
+    PgPoolOptions::new()
+        .max_connections(cfg.db_pool_max)
+        .min_connections(cfg.db_pool_min)
+        .acquire_timeout(Duration::from_millis(cfg.db_acquire_timeout_ms))
+        .idle_timeout(cfg.db_idle_timeout_secs.map(Duration::from_secs))
+        .max_lifetime(cfg.db_max_lifetime_secs.map(Duration::from_secs))
+        .after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                // ---
+                if let Some(ms) = statement_timeout_ms {
+                    conn.execute(format!("SET statement_timeout = {ms}").as_str())
+                        .await?;
+                }
+                Ok(())
+            })
+        })
+}
+
+/// Response for a failed database call: 503 when the pool or the statement
+/// timed out, 500 with `error` (e.g. `load failed`) otherwise; see
+/// [`ApiError::database`].
+///
+/// Callers log the error themselves, with their own context.
+pub fn db_error_response(err: &sqlx::Error, error: &'static str) -> Response {
+    // ---
+    ApiError::database(err, error).into_response()
+}
+
+/// The `error` and `hint` of a 503 for `err`, if it is a timeout.
+pub(crate) fn timeout_hint(err: &sqlx::Error) -> Option<(&'static str, &'static str)> {
+    // ---
+    match timeout_kind(err)? {
+        Timeout::Acquire => Some((
+            "database busy",
+            "no database connection became free within DB_ACQUIRE_TIMEOUT_MS; retry shortly",
+        )),
+        Timeout::Statement => Some((
+            "query timed out",
+            "the query ran longer than DB_STATEMENT_TIMEOUT_MS; narrow the filters or retry",
+        )),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Timeout {
+    Acquire,
+    Statement,
+}
+
+fn timeout_kind(err: &sqlx::Error) -> Option<Timeout> {
+    // ---
+    match err {
+        sqlx::Error::PoolTimedOut => Some(Timeout::Acquire),
+        sqlx::Error::Database(db) if db.code().as_deref() == Some(QUERY_CANCELED) => {
+            Some(Timeout::Statement)
+        }
+        _ => None,
+    }
+}
+
+/// Wait before retry number `attempt + 1`: `base` doubled per earlier retry.
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    // ---
+    base.saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+/// Whether waiting might fix `err`: the server is unreachable or still starting.
+fn is_transient(err: &sqlx::Error) -> bool {
+    // ---
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db) => db.code().as_deref() == Some(CANNOT_CONNECT_NOW),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use axum::http::{header, StatusCode};
+
+    #[test]
+    fn retry_delays_double_up_to_the_cap() {
+        // ---
+        let base = Duration::from_millis(500);
+        let delays: Vec<_> = (0..4).map(|n| retry_delay(base, n).as_millis()).collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000]);
+        assert_eq!(retry_delay(base, 10), MAX_BACKOFF);
+        assert_eq!(retry_delay(base, u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn only_unavailable_servers_are_retried() {
+        // ---
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(is_transient(&sqlx::Error::Io(refused)));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+    }
+
+    #[test]
+    fn timeouts_are_503_and_other_errors_500() {
+        // ---
+        let resp = db_error_response(&sqlx::Error::PoolTimedOut, "load failed");
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+
+        let resp = db_error_response(&sqlx::Error::RowNotFound, "load failed");
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
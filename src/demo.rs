@@ -0,0 +1,181 @@
+//! Public demo mode (`DEMO_MODE=true`).
+//!
+//! Lets the API run as a public playground without upstream access or any
+//! way to change it:
+//! - no upstream sources are ingested; [`seed`] stores a day of synthetic
+//!   readings ([`synthetic_readings`]) under the `demo` source at startup
+//! - [`demo_guard`] answers every mutating request and every `/admin` or
+//!   `/metrics` request with 403, whatever the caller's role
+//! - rate limiting is on with demo defaults unless `RATE_LIMIT_*` is set
+//!   (see [`DEMO_RATE_LIMIT`])
+//! - [`demo_watermark`] marks every response with [`DEMO_HEADER`]
+//!
+//! The synthetic data are deterministic apart from their timestamps: three
+//! meshes of two devices each, every 15 minutes over the 24 hours before
+//! startup, with a daily temperature and humidity cycle and a few
+//! out-of-range readings so alerts have something to show.
+use std::f32::consts::TAU;
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use sqlx::PgPool;
+
+use crate::{store_pushed, ApiError, Enrichment, RateLimitConfig, RawSensorReading};
+
+/// Source name of the synthetic readings.
+pub const DEMO_SOURCE: &str = "demo";
+
+/// Response header marking demo responses.
+pub const DEMO_HEADER: HeaderName = HeaderName::from_static("x-sensorflow-demo");
+
+/// Rate limit applied in demo mode when `RATE_LIMIT_PER_SEC` is unset.
+pub const DEMO_RATE_LIMIT: RateLimitConfig = RateLimitConfig {
+    per_sec: 1.0,
+    burst: 10,
+};
+
+const WATERMARK: &str = "synthetic data; read-only public demo";
+
+/// Minutes between synthetic readings of one device.
+const STEP_MINUTES: i64 = 15;
+
+/// Hours of history generated.
+const HISTORY_HOURS: i64 = 24;
+
+// ---
+
+/// A day of synthetic readings ending at `now`, oldest first.
+pub fn synthetic_readings(now: DateTime<Utc>) -> Vec<RawSensorReading> {
+    // ---
+    let end = now
+        .duration_trunc(Duration::minutes(STEP_MINUTES))
+        .unwrap_or(now);
+    let steps = HISTORY_HOURS * 60 / STEP_MINUTES;
+
+    let mut out = Vec::new();
+    for step in (0..steps).rev() {
+        let timestamp = end - Duration::minutes(step * STEP_MINUTES);
+        let day_fraction = (steps - step) as f32 / steps as f32;
+        for mesh in 1..=3 {
+            for n in 1..=2 {
+                let device = (mesh - 1) * 2 + n;
+                let phase = day_fraction * TAU + device as f32 * 0.4;
+                let mut temperature_c = 21.0 + mesh as f32 + 4.0 * phase.sin();
+                let mut humidity = 45.0 + 12.0 * phase.cos();
+                let mut status = "ok";
+
+                // A few excursions per day, on fixed devices
+                match (device, step % 32) {
+                    (2, 0) => temperature_c = 64.5,
+                    (5, 8) => humidity = 93.0,
+                    (6, 16) => status = "battery_low",
+                    _ => {}
+                }
+                out.push(RawSensorReading {
+                    mesh_id: format!("mesh-{mesh:03}"),
+                    device_id: format!("device-{device:03}"),
+                    timestamp,
+                    temperature_c: (temperature_c * 10.0).round() / 10.0,
+                    humidity: (humidity * 10.0).round() / 10.0,
+                    status: status.into(),
+                    latitude: None,
+                    longitude: None,
+                    unrecognized: serde_json::Map::new(),
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Store the synthetic readings unless demo data is already present.
+pub async fn seed(
+    pool: &PgPool,
+    priority: &[String],
+    enrichment: &Enrichment,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let has_data: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sensor_data WHERE source = $1)")
+            .bind(DEMO_SOURCE)
+            .fetch_one(pool)
+            .await?;
+    if has_data {
+        return Ok(0);
+    }
+    let readings = synthetic_readings(Utc::now());
+    store_pushed(pool, DEMO_SOURCE, &readings, priority, enrichment).await
+}
+
+/// Middleware: reject everything but reads of the public routes.
+pub async fn demo_guard(req: Request, next: Next) -> Response {
+    // ---
+    if allowed(req.method(), req.uri().path()) {
+        return next.run(req).await;
+    }
+    ApiError::Forbidden {
+        error: "not available in demo mode",
+        hint: "this demo instance is read-only; run your own to ingest or administer data",
+    }
+    .into_response()
+}
+
+/// Middleware: add [`DEMO_HEADER`] to every response.
+pub async fn demo_watermark(req: Request, next: Next) -> Response {
+    // ---
+    let mut resp = next.run(req).await;
+    resp.headers_mut()
+        .insert(DEMO_HEADER, HeaderValue::from_static(WATERMARK));
+    resp
+}
+
+fn allowed(method: &Method, path: &str) -> bool {
+    // ---
+    let read = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let restricted = ["/admin", "/metrics"]
+        .iter()
+        .any(|p| path == *p || path.starts_with(&format!("{p}/")));
+    read && !restricted
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn synthetic_day_covers_every_device_and_some_alerts() {
+        // ---
+        let now = Utc::now();
+        let readings = synthetic_readings(now);
+        assert_eq!(readings.len(), 96 * 6);
+        assert!(readings.iter().all(|r| r.timestamp <= now));
+        assert!(readings
+            .windows(2)
+            .all(|w| w[0].timestamp <= w[1].timestamp));
+
+        let alerts = readings
+            .iter()
+            .map(RawSensorReading::to_transformed)
+            .filter(|t| t.temperature_alert || t.humidity_alert)
+            .count();
+        assert_eq!(alerts, 6);
+    }
+
+    #[test]
+    fn only_public_reads_are_allowed() {
+        // ---
+        assert!(allowed(&Method::GET, "/v1/readings"));
+        assert!(allowed(&Method::GET, "/sql/devices/device-001/mesh"));
+        assert!(!allowed(&Method::POST, "/v1/readings"));
+        assert!(!allowed(&Method::PUT, "/sql/devices/device-001/mesh"));
+        assert!(!allowed(&Method::GET, "/admin/sources"));
+        assert!(!allowed(&Method::GET, "/metrics"));
+        assert!(allowed(&Method::GET, "/metricsfoo"));
+    }
+}
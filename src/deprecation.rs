@@ -0,0 +1,192 @@
+//! Client-visible deprecation signalling, driven by per-route metadata.
+//!
+//! A route opts in by attaching the [`deprecated`] middleware with a static
+//! [`DeprecationPolicy`] describing what is being retired: the route itself
+//! (replaced by a successor path) and/or individual query parameters. When a
+//! request uses anything deprecated, the response carries:
+//! - `Deprecation: @<unix time>` (RFC 9745), the earliest `since` among the
+//!   deprecated features used
+//! - `Sunset: <HTTP-date>` (RFC 8594), the earliest planned removal, if any
+//! - `Link: <successor>; rel="successor-version"` for a deprecated route
+//!
+//! Handlers that return an envelope also get the human-readable notices as
+//! `Extension<DeprecationWarnings>`, to include alongside the data.
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{NaiveDate, NaiveTime};
+
+use crate::Principal;
+
+// ---
+
+/// One deprecated feature: when it was deprecated, when it goes, what replaces it.
+#[derive(Debug)]
+pub struct Deprecated {
+    // ---
+    pub since: NaiveDate,
+
+    /// Planned removal date; `None` while undecided.
+    pub sunset: Option<NaiveDate>,
+
+    /// Successor route path or parameter name.
+    pub replacement: &'static str,
+}
+
+/// Deprecation metadata for one route, attached with [`deprecated`].
+#[derive(Debug)]
+pub struct DeprecationPolicy {
+    // ---
+    /// Set when the whole route is deprecated in favour of `replacement`.
+    pub route: Option<Deprecated>,
+
+    /// Deprecated query parameters on this route, by exact name.
+    pub params: &'static [(&'static str, Deprecated)],
+}
+
+/// Deprecation notices for the current request, for inclusion in envelopes.
+#[derive(Debug, Clone, Default)]
+pub struct DeprecationWarnings(pub Vec<String>);
+
+/// `NaiveDate` for route metadata constants; panics at compile time on invalid dates.
+pub const fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    // ---
+    match NaiveDate::from_ymd_opt(year, month, day) {
+        Some(d) => d,
+        None => panic!("invalid date"),
+    }
+}
+
+/// Middleware: signal use of the deprecated features described by `policy`.
+pub async fn deprecated(
+    State(policy): State<&'static DeprecationPolicy>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    // ---
+    let params: Vec<(String, String)> = Query::try_from_uri(req.uri())
+        .map(|Query(q)| q)
+        .unwrap_or_default();
+    let used = used_features(policy, params.iter().map(|(k, _)| k.as_str()));
+    if used.is_empty() {
+        return next.run(req).await;
+    }
+
+    let warnings: Vec<String> = used
+        .iter()
+        .map(|(what, dep)| notice(*what, dep, req.uri().path()))
+        .collect();
+    let caller = req
+        .extensions()
+        .get::<Principal>()
+        .map(|p| p.name.clone())
+        .unwrap_or_default();
+    for w in &warnings {
+        tracing::info!("Deprecated usage by {:?}: {}", caller, w);
+    }
+    req.extensions_mut().insert(DeprecationWarnings(warnings));
+
+    let mut resp = next.run(req).await;
+    let headers = resp.headers_mut();
+    if let Some(since) = used.iter().map(|(_, d)| d.since).min() {
+        let at = since.and_time(NaiveTime::MIN).and_utc().timestamp();
+        if let Ok(v) = HeaderValue::from_str(&format!("@{at}")) {
+            headers.insert("deprecation", v);
+        }
+    }
+    if let Some(sunset) = used.iter().filter_map(|(_, d)| d.sunset).min() {
+        let date = sunset
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+            .format("%a, %d %b %Y %H:%M:%S GMT");
+        if let Ok(v) = HeaderValue::from_str(&date.to_string()) {
+            headers.insert("sunset", v);
+        }
+    }
+    if let Some(route) = &policy.route {
+        if let Ok(v) = HeaderValue::from_str(&format!(
+            "<{}>; rel=\"successor-version\"",
+            route.replacement
+        )) {
+            headers.append(header::LINK, v);
+        }
+    }
+    resp
+}
+
+/// Deprecated features a request uses: `None` for the route, `Some(name)` per parameter.
+fn used_features<'p>(
+    policy: &'static DeprecationPolicy,
+    param_names: impl Iterator<Item = &'p str>,
+) -> Vec<(Option<&'static str>, &'static Deprecated)> {
+    // ---
+    let mut used: Vec<(Option<&'static str>, &'static Deprecated)> = Vec::new();
+    if let Some(route) = &policy.route {
+        used.push((None, route));
+    }
+    for name in param_names {
+        if let Some((param, dep)) = policy.params.iter().find(|(p, _)| *p == name) {
+            if !used.iter().any(|(n, _)| *n == Some(*param)) {
+                used.push((Some(param), dep));
+            }
+        }
+    }
+    used
+}
+
+fn notice(what: Option<&str>, dep: &Deprecated, path: &str) -> String {
+    // ---
+    let subject = match what {
+        None => format!("{path} is deprecated; use {}", dep.replacement),
+        Some(param) => format!(
+            "parameter '{param}' is deprecated; use '{}'",
+            dep.replacement
+        ),
+    };
+    match dep.sunset {
+        Some(sunset) => format!("{subject} (removal planned {sunset})"),
+        None => subject,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    static POLICY: DeprecationPolicy = DeprecationPolicy {
+        route: Some(Deprecated {
+            since: date(2026, 10, 1),
+            sunset: Some(date(2027, 6, 30)),
+            replacement: "/v1/readings",
+        }),
+        params: &[(
+            "deviceId",
+            Deprecated {
+                since: date(2026, 10, 1),
+                sunset: None,
+                replacement: "device_id",
+            },
+        )],
+    };
+
+    #[test]
+    fn reports_route_and_each_used_param_once() {
+        // ---
+        let used = used_features(&POLICY, ["deviceId", "limit", "deviceId"].into_iter());
+        let names: Vec<_> = used.iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, [None, Some("deviceId")]);
+
+        assert_eq!(
+            notice(None, used[0].1, "/sql/readings"),
+            "/sql/readings is deprecated; use /v1/readings (removal planned 2027-06-30)"
+        );
+        assert_eq!(
+            notice(Some("deviceId"), used[1].1, "/sql/readings"),
+            "parameter 'deviceId' is deprecated; use 'device_id'"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Compact duration strings for query parameters (`90s`, `30m`, `2h`, `7d`).
+//!
+//! Shared by endpoints that take a time window so they all accept the same
+//! syntax: a positive integer followed by one unit suffix.
+use chrono::Duration;
+
+// ---
+
+/// Parse `"<n><unit>"` where unit is `s`, `m`, `h` or `d`.
+///
+/// Returns `None` for zero, negative, unit-less, or overflowing values.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    // ---
+    let s = s.trim();
+    let unit = s.chars().last()?;
+    let n: i64 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+    if n <= 0 {
+        return None;
+    }
+
+    match unit {
+        's' => Duration::try_seconds(n),
+        'm' => Duration::try_minutes(n),
+        'h' => Duration::try_hours(n),
+        'd' => Duration::try_days(n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn parses_each_unit() {
+        // ---
+        assert_eq!(parse_duration("90s"), Some(Duration::seconds(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_duration(" 2h "), Some(Duration::hours(2)));
+        assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        // ---
+        for bad in [
+            "",
+            "m",
+            "30",
+            "0m",
+            "-5m",
+            "1.5h",
+            "10w",
+            "99999999999999999d",
+        ] {
+            assert_eq!(parse_duration(bad), None, "{bad:?}");
+        }
+    }
+}
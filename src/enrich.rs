@@ -0,0 +1,457 @@
+//! Enrichment hooks applied to readings before they are stored.
+//!
+//! An [`Enricher`] looks up extra fields for a transformed reading, e.g. the
+//! location name of its device from an asset-management API. Registered
+//! enrichers run in order for every reading ingested or pushed; each one's
+//! result is stored under its name in `sensor_data.attributes`:
+//!
+//! ```json
+//! { "assets": { "location": "Plant A, line 3" } }
+//! ```
+//!
+//! Enrichment is best-effort and must never stall ingest:
+//! - every lookup is bounded by the enricher's timeout
+//! - results are cached per [`Enricher::cache_key`] (the device by default)
+//! - after a failed or timed-out lookup the enricher is skipped for
+//!   [`FAILURE_BACKOFF`], so a down source costs one timeout, not one per reading
+//!
+//! Readings are stored without that enricher's fields when it fails.
+//! [`HttpLookup`] is the built-in enricher configured by `ENRICH_<N>_*`.
+//!
+//! Fields designated sensitive (`ENCRYPTED_FIELDS`) are encrypted once all
+//! enrichers have run, and must be passed through [`Enrichment::reveal`]
+//! before being returned to a caller (see `field_crypto.rs`). The registry
+//! columns it names go through [`Enrichment::encrypt_column`] and
+//! [`Enrichment::reveal_column`] the same way.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+
+use crate::{DeviceInfo, EncryptionConfig, EnricherConfig, FieldCipher, Role, SensorReading};
+
+/// Extra fields attached to a reading.
+pub type Attributes = serde_json::Map<String, Value>;
+
+/// How long an enricher is skipped after a failed lookup.
+pub const FAILURE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Cached lookups kept per enricher before the cache is reset.
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+// ---
+
+/// A source of extra fields for readings.
+pub trait Enricher: Send + Sync {
+    // ---
+    /// Key under which this enricher's fields are stored; also used in logs.
+    fn name(&self) -> &str;
+
+    /// Readings with the same key share one cached lookup.
+    fn cache_key(&self, reading: &SensorReading) -> String {
+        // ---
+        reading.device_id.clone()
+    }
+
+    /// Fetch the fields for `reading`; an empty map means "nothing known".
+    fn lookup<'a>(
+        &'a self,
+        reading: &'a SensorReading,
+    ) -> BoxFuture<'a, Result<Attributes, String>>;
+}
+
+/// Per-enricher limits.
+#[derive(Debug, Clone, Copy)]
+pub struct EnrichOptions {
+    // ---
+    /// Longest a single lookup may take.
+    pub timeout: Duration,
+
+    /// How long a successful lookup is reused.
+    pub cache_ttl: Duration,
+}
+
+struct Registered {
+    enricher: Arc<dyn Enricher>,
+    options: EnrichOptions,
+    cache: Mutex<HashMap<String, (Instant, Attributes)>>,
+    skip_until: Mutex<Option<Instant>>,
+}
+
+/// The registered enrichers, shared by every ingest path.
+#[derive(Default)]
+pub struct Enrichment {
+    // ---
+    enrichers: Vec<Registered>,
+
+    /// Encrypts sensitive fields before storage, when configured.
+    cipher: Option<FieldCipher>,
+}
+
+impl Enrichment {
+    // ---
+    /// Register an [`HttpLookup`] for every configured `ENRICH_<N>_URL`,
+    /// encrypting fields per `encryption` when set.
+    pub fn from_config(
+        configs: &[EnricherConfig],
+        encryption: Option<&EncryptionConfig>,
+    ) -> anyhow::Result<Self> {
+        // ---
+        let mut enrichment = Self {
+            cipher: encryption.map(FieldCipher::new),
+            ..Self::default()
+        };
+        for cfg in configs {
+            let options = EnrichOptions {
+                timeout: Duration::from_millis(cfg.timeout_ms),
+                cache_ttl: Duration::from_secs(cfg.cache_secs),
+            };
+            enrichment.register(Arc::new(HttpLookup::new(cfg)?), options);
+        }
+        Ok(enrichment)
+    }
+
+    /// Add `enricher`; it runs after those registered before it.
+    pub fn register(&mut self, enricher: Arc<dyn Enricher>, options: EnrichOptions) {
+        // ---
+        self.enrichers.push(Registered {
+            enricher,
+            options,
+            cache: Mutex::new(HashMap::new()),
+            skip_until: Mutex::new(None),
+        });
+    }
+
+    /// Run every enricher on `reading`, storing results in its `attributes`,
+    /// then encrypt the fields designated sensitive.
+    pub async fn apply(&self, reading: &mut SensorReading) {
+        // ---
+        for reg in &self.enrichers {
+            if let Some(attrs) = reg.attributes_for(reading).await {
+                if !attrs.is_empty() {
+                    let name = reg.enricher.name().to_string();
+                    reading.attributes.insert(name, Value::Object(attrs));
+                }
+            }
+        }
+        if let Some(cipher) = &self.cipher {
+            cipher.encrypt(reading);
+        }
+    }
+
+    /// Prepare a stored reading's `attributes` for a caller with `role`:
+    /// encrypted fields are decrypted if the role allows it, redacted otherwise.
+    pub fn reveal(&self, reading: &mut SensorReading, role: Role) {
+        // ---
+        if let Some(cipher) = &self.cipher {
+            cipher.reveal(reading, role);
+        }
+    }
+
+    /// Encrypt `value`, the `table.column` of the registry row `id`, before
+    /// it is stored, if it is designated sensitive.
+    pub fn encrypt_column(&self, table: &str, column: &str, id: &str, value: &mut Option<String>) {
+        // ---
+        if let Some(cipher) = &self.cipher {
+            cipher.encrypt_column(table, column, id, value);
+        }
+    }
+
+    /// Prepare a stored registry column for a caller with `role`, as
+    /// [`reveal`](Self::reveal) does attributes.
+    pub fn reveal_column(
+        &self,
+        table: &str,
+        column: &str,
+        id: &str,
+        value: &mut Option<String>,
+        role: Role,
+    ) {
+        // ---
+        if let Some(cipher) = &self.cipher {
+            cipher.reveal_column(table, column, id, value, role);
+        }
+    }
+
+    /// [`reveal_column`](Self::reveal_column) for a device's registry entry.
+    pub fn reveal_device(&self, device: &mut DeviceInfo, role: Role) {
+        // ---
+        let id = &device.device_id;
+        self.reveal_column("devices", "location", id, &mut device.location, role);
+        self.reveal_column("devices", "notes", id, &mut device.notes, role);
+    }
+}
+
+impl Registered {
+    // ---
+    /// Cached or freshly looked-up fields; `None` while backing off or on failure.
+    async fn attributes_for(&self, reading: &SensorReading) -> Option<Attributes> {
+        // ---
+        let now = Instant::now();
+        if lock(&self.skip_until).is_some_and(|until| now < until) {
+            return None;
+        }
+
+        let key = self.enricher.cache_key(reading);
+        if let Some((at, attrs)) = lock(&self.cache).get(&key) {
+            if now.duration_since(*at) < self.options.cache_ttl {
+                return Some(attrs.clone());
+            }
+        }
+
+        let name = self.enricher.name();
+        let failure =
+            match tokio::time::timeout(self.options.timeout, self.enricher.lookup(reading)).await {
+                Ok(Ok(attrs)) => {
+                    let mut cache = lock(&self.cache);
+                    if cache.len() >= MAX_CACHE_ENTRIES {
+                        cache.clear();
+                    }
+                    cache.insert(key, (Instant::now(), attrs.clone()));
+                    return Some(attrs);
+                }
+                Ok(Err(e)) => e,
+                Err(_) => format!("timed out after {:?}", self.options.timeout),
+            };
+
+        tracing::warn!(
+            "Enricher {} failed ({}); skipping it for {:?}",
+            name,
+            failure,
+            FAILURE_BACKOFF
+        );
+        *lock(&self.skip_until) = Some(Instant::now() + FAILURE_BACKOFF);
+        None
+    }
+}
+
+/// Lock ignoring poisoning; the guarded data is a plain cache.
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // ---
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Enricher fetching a JSON object per device from an HTTP API.
+///
+/// `{device_id}` and `{mesh_id}` in the URL are replaced with the reading's
+/// (percent-encoded) values. A 404 means the device is unknown and yields no
+/// fields; other non-2xx statuses and non-object bodies are failures.
+pub struct HttpLookup {
+    // ---
+    name: String,
+    url: String,
+    token: Option<String>,
+    fields: Option<Vec<String>>,
+    client: reqwest::Client,
+}
+
+impl HttpLookup {
+    // ---
+    pub fn new(cfg: &EnricherConfig) -> anyhow::Result<Self> {
+        // ---
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .build()?;
+        Ok(Self {
+            name: cfg.name.clone(),
+            url: cfg.url.clone(),
+            token: cfg.token.clone(),
+            fields: cfg.fields.clone(),
+            client,
+        })
+    }
+
+    fn url_for(&self, reading: &SensorReading) -> String {
+        // ---
+        self.url
+            .replace("{device_id}", &encode(&reading.device_id))
+            .replace("{mesh_id}", &encode(&reading.mesh_id))
+    }
+}
+
+impl Enricher for HttpLookup {
+    // ---
+    fn name(&self) -> &str {
+        // ---
+        &self.name
+    }
+
+    fn cache_key(&self, reading: &SensorReading) -> String {
+        // ---
+        self.url_for(reading)
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        reading: &'a SensorReading,
+    ) -> BoxFuture<'a, Result<Attributes, String>> {
+        // ---
+        Box::pin(async move {
+            // ---
+            let mut request = self.client.get(self.url_for(reading));
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(Attributes::new());
+            }
+            let body: Value = response
+                .error_for_status()
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            let Value::Object(mut attrs) = body else {
+                return Err("response is not a JSON object".into());
+            };
+            if let Some(fields) = &self.fields {
+                attrs.retain(|k, _| fields.contains(k));
+            }
+            Ok(attrs)
+        })
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn encode(value: &str) -> String {
+    // ---
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::Utc;
+
+    use super::*;
+
+    struct Counting {
+        calls: AtomicUsize,
+        delay: Duration,
+    }
+
+    impl Enricher for Counting {
+        // ---
+        fn name(&self) -> &str {
+            // ---
+            "assets"
+        }
+
+        fn lookup<'a>(
+            &'a self,
+            reading: &'a SensorReading,
+        ) -> BoxFuture<'a, Result<Attributes, String>> {
+            // ---
+            Box::pin(async move {
+                // ---
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(self.delay).await;
+                let mut attrs = Attributes::new();
+                attrs.insert(
+                    "location".into(),
+                    format!("site of {}", reading.device_id).into(),
+                );
+                Ok(attrs)
+            })
+        }
+    }
+
+    fn reading(device_id: &str) -> SensorReading {
+        // ---
+        SensorReading {
+            id: None,
+            mesh_id: "mesh-001".into(),
+            device_id: device_id.into(),
+            timestamp_utc: Utc::now(),
+            timestamp_local: None,
+            temperature_c: 20.0,
+            humidity: 50.0,
+            raw_temperature_c: None,
+            raw_humidity: None,
+            status: "ok".into(),
+            temperature_alert: false,
+            humidity_alert: false,
+            latitude: None,
+            longitude: None,
+            extra: serde_json::Map::new(),
+            quality: None,
+            quality_flags: Vec::new(),
+            attributes: Attributes::new(),
+            invalid: false,
+            corrections: Vec::new(),
+            ingest_batch_id: None,
+            smoothed: None,
+            rolling_avg: None,
+            device: None,
+        }
+    }
+
+    fn enrichment(delay: Duration) -> (Enrichment, Arc<Counting>) {
+        // ---
+        let counting = Arc::new(Counting {
+            calls: AtomicUsize::new(0),
+            delay,
+        });
+        let mut enrichment = Enrichment::default();
+        enrichment.register(
+            counting.clone(),
+            EnrichOptions {
+                timeout: Duration::from_millis(50),
+                cache_ttl: Duration::from_secs(60),
+            },
+        );
+        (enrichment, counting)
+    }
+
+    #[tokio::test]
+    async fn lookups_are_cached_per_device() {
+        // ---
+        let (enrichment, counting) = enrichment(Duration::ZERO);
+        for device in ["device-001", "device-001", "device-002"] {
+            let mut r = reading(device);
+            enrichment.apply(&mut r).await;
+            assert_eq!(
+                r.attributes["assets"]["location"],
+                format!("site of {device}")
+            );
+        }
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn slow_enrichers_time_out_and_back_off() {
+        // ---
+        let (enrichment, counting) = enrichment(Duration::from_secs(5));
+        let started = Instant::now();
+        for device in ["device-001", "device-002", "device-003"] {
+            let mut r = reading(device);
+            enrichment.apply(&mut r).await;
+            assert!(r.attributes.is_empty());
+        }
+        // One timed-out lookup, then skipped for the backoff period
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn url_values_are_percent_encoded() {
+        // ---
+        assert_eq!(encode("device-001"), "device-001");
+        assert_eq!(encode("a/b c"), "a%2Fb%20c");
+    }
+}
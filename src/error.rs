@@ -0,0 +1,344 @@
+//! Errors handlers answer with.
+//!
+//! Every [`ApiError`] becomes a JSON body of the same shape,
+//! `{"error": "...", "hint": "..."}`: `error` is a short, stable description
+//! clients can match on and `hint` says what to do about it. The variant picks
+//! the status code, so a handler states what went wrong rather than how to
+//! answer it:
+//!
+//! ```ignore
+//! return ApiError::Validation {
+//!     error: "invalid sort",
+//!     hint: "use sort=asc or sort=desc",
+//! }
+//! .into_response();
+//! ```
+//!
+//! A few variants add fields of their own next to those two, e.g. the role a
+//! route requires. Details of upstream and database failures go to the log
+//! (handlers log them with their own context), never into the body.
+//!
+//! Requests the extractors can't parse are answered the same way: see
+//! `extract.rs`.
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{IngestError, Role};
+
+// ---
+
+/// A failed request; see the module docs.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    // ---
+    /// 400: the request can't be parsed at all.
+    #[error("{error}")]
+    BadRequest {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 422: a parameter or body field has an invalid value.
+    #[error("{error}")]
+    Validation {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 404: what the path names doesn't exist (or isn't visible to the caller).
+    #[error("{error}")]
+    NotFound {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 401 with `WWW-Authenticate`: the request has no credentials, or none
+    /// the service accepts.
+    #[error("{error}")]
+    Unauthorized {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 403: the caller may not do this.
+    #[error("{error}")]
+    Forbidden {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 403 naming the `required_role` and the caller's `role` (`null` when
+    /// the caller has none).
+    #[error("forbidden")]
+    RoleRequired { required: Role, role: Option<Role> },
+
+    /// 409: the request conflicts with one still running.
+    #[error("{error}")]
+    Conflict {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 413: the request asks for or sends more than a limit allows.
+    #[error("{error}")]
+    TooLarge {
+        error: &'static str,
+        hint: &'static str,
+    },
+
+    /// 422 with the reason as `detail`: reloaded configuration was invalid.
+    #[error("invalid configuration: {0}")]
+    InvalidConfiguration(String),
+
+    /// 429 with `Retry-After`: the caller is over its rate limit.
+    #[error("{error}")]
+    TooManyRequests {
+        error: &'static str,
+        hint: &'static str,
+        retry_after_secs: u64,
+    },
+
+    /// 502: an upstream sensor API failed.
+    #[error("upstream failed: {0}")]
+    Upstream(String),
+
+    /// 503 with `Retry-After`: the service can't answer yet, e.g. the
+    /// database timed out (see [`ApiError::database`]).
+    #[error("{error}")]
+    Unavailable {
+        error: &'static str,
+        hint: &'static str,
+        retry_after_secs: u64,
+    },
+
+    /// 503 with `Retry-After` during maintenance, with its `message` and
+    /// `since`.
+    #[error("under maintenance")]
+    Maintenance {
+        message: Option<String>,
+        since: DateTime<Utc>,
+        retry_after_secs: u64,
+    },
+
+    /// 500: anything else; `error` describes the operation that failed.
+    #[error("{0}")]
+    Internal(&'static str),
+}
+
+#[derive(Serialize)]
+struct Body {
+    error: &'static str,
+    hint: &'static str,
+
+    /// The fields a variant adds.
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl ApiError {
+    // ---
+    /// A failed database call during `error`, e.g. `ApiError::database(&e, "load failed")`:
+    /// [`Unavailable`](Self::Unavailable) when the pool or the statement timed
+    /// out (retrying may succeed), else [`Internal`](Self::Internal).
+    pub fn database(err: &sqlx::Error, error: &'static str) -> Self {
+        // ---
+        match crate::db::timeout_hint(err) {
+            Some((error, hint)) => ApiError::Unavailable {
+                error,
+                hint,
+                retry_after_secs: 1,
+            },
+            None => ApiError::Internal(error),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    // ---
+    fn into_response(self) -> Response {
+        // ---
+        let mut extra = Map::new();
+        let mut retry_after = None;
+        let (status, error, hint) = match self {
+            ApiError::BadRequest { error, hint } => (StatusCode::BAD_REQUEST, error, hint),
+            ApiError::Validation { error, hint } => (StatusCode::UNPROCESSABLE_ENTITY, error, hint),
+            ApiError::NotFound { error, hint } => (StatusCode::NOT_FOUND, error, hint),
+            ApiError::Unauthorized { error, hint } => {
+                let body = Json(Body { error, hint, extra });
+                let challenge = [(header::WWW_AUTHENTICATE, "Bearer")];
+                return (StatusCode::UNAUTHORIZED, challenge, body).into_response();
+            }
+            ApiError::Forbidden { error, hint } => (StatusCode::FORBIDDEN, error, hint),
+            ApiError::RoleRequired { required, role } => {
+                extra.insert("required_role".into(), json!(required));
+                extra.insert("role".into(), json!(role));
+                (
+                    StatusCode::FORBIDDEN,
+                    "forbidden",
+                    "use credentials granted a higher role (JWT `roles` claim or API_KEY_<N>_ROLE)",
+                )
+            }
+            ApiError::Conflict { error, hint } => (StatusCode::CONFLICT, error, hint),
+            ApiError::TooLarge { error, hint } => (StatusCode::PAYLOAD_TOO_LARGE, error, hint),
+            ApiError::InvalidConfiguration(detail) => {
+                extra.insert("detail".into(), json!(detail));
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "invalid configuration",
+                    "fix the configuration and reload again; the current settings stay in effect",
+                )
+            }
+            ApiError::TooManyRequests {
+                error,
+                hint,
+                retry_after_secs,
+            } => {
+                retry_after = Some(retry_after_secs);
+                (StatusCode::TOO_MANY_REQUESTS, error, hint)
+            }
+            ApiError::Upstream(_) => (
+                StatusCode::BAD_GATEWAY,
+                "upstream failed",
+                "an upstream sensor API failed or sent an invalid response; retry later",
+            ),
+            ApiError::Unavailable {
+                error,
+                hint,
+                retry_after_secs,
+            } => {
+                retry_after = Some(retry_after_secs);
+                (StatusCode::SERVICE_UNAVAILABLE, error, hint)
+            }
+            ApiError::Maintenance {
+                message,
+                since,
+                retry_after_secs,
+            } => {
+                retry_after = Some(retry_after_secs);
+                extra.insert("message".into(), json!(message));
+                extra.insert("since".into(), json!(since));
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "under maintenance",
+                    "the service is down for maintenance; retry after the Retry-After delay",
+                )
+            }
+            ApiError::Internal(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                error,
+                "an internal error occurred; see the service logs",
+            ),
+        };
+        let body = Json(Body { error, hint, extra });
+        match retry_after {
+            Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+impl From<IngestError> for ApiError {
+    // ---
+    fn from(e: IngestError) -> Self {
+        // ---
+        match e {
+            IngestError::Upstream(e) => ApiError::Upstream(e),
+            IngestError::Internal(_) => ApiError::Internal("ingest failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    async fn body(resp: Response) -> serde_json::Value {
+        // ---
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_error_has_a_status_and_an_error_with_a_hint() {
+        // ---
+        let resp = ApiError::Validation {
+            error: "invalid sort",
+            hint: "use sort=asc or sort=desc",
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body(resp).await,
+            serde_json::json!({ "error": "invalid sort", "hint": "use sort=asc or sort=desc" })
+        );
+
+        let resp =
+            ApiError::from(IngestError::Upstream("connection refused".into())).into_response();
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        let json = body(resp).await;
+        assert_eq!(json["error"], "upstream failed");
+        assert!(!json["hint"].as_str().unwrap().contains("refused"));
+
+        let resp = ApiError::from(IngestError::Internal("disk full".into())).into_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body(resp).await["error"], "ingest failed");
+    }
+
+    #[tokio::test]
+    async fn variants_add_their_headers_and_fields() {
+        // ---
+        let resp = ApiError::Unauthorized {
+            error: "unauthorized",
+            hint: "send a known x-api-key header",
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let resp = ApiError::TooManyRequests {
+            error: "rate_limited",
+            hint: "retry later",
+            retry_after_secs: 7,
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "7");
+
+        let resp = ApiError::RoleRequired {
+            required: Role::Writer,
+            role: Some(Role::Reader),
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let json = body(resp).await;
+        assert_eq!(json["required_role"], "writer");
+        assert_eq!(json["role"], "reader");
+        assert!(json["hint"].is_string());
+
+        let resp = ApiError::InvalidConfiguration("bad RATE_LIMIT_PER_SEC".into()).into_response();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body(resp).await["detail"], "bad RATE_LIMIT_PER_SEC");
+    }
+
+    #[tokio::test]
+    async fn database_timeouts_are_retryable() {
+        // ---
+        let resp = ApiError::database(&sqlx::Error::PoolTimedOut, "load failed").into_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(body(resp).await["error"], "database busy");
+
+        let resp = ApiError::database(&sqlx::Error::RowNotFound, "load failed").into_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body(resp).await["error"], "load failed");
+    }
+}
@@ -0,0 +1,137 @@
+//! Optional error reporting to Sentry.
+//!
+//! With `SENTRY_DSN` set, [`init`] starts a Sentry client and every request
+//! runs under its own Sentry hub tagged with `request_id`, method and path
+//! (see [`report_errors`]). Reported to Sentry:
+//! - panics, anywhere in the process; inside a handler they carry the
+//!   request's tags
+//! - every 5xx response, as an error event named after the route
+//!
+//! Warnings and errors logged while handling a request are attached to its
+//! events as breadcrumbs, so an event shows the error text behind a generic
+//! `"load failed"` body. `SENTRY_ENVIRONMENT` and `SENTRY_RELEASE` are
+//! honoured; the release defaults to the crate version. Without `SENTRY_DSN`
+//! nothing is sent and the middleware passes requests straight through.
+use std::{env, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
+use sentry::{
+    integrations::tracing::EventFilter, types::Dsn, ClientInitGuard, ClientOptions, Hub, Level,
+    SentryFutureExt as _,
+};
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::RequestId;
+
+// ---
+
+/// Start the Sentry client when `SENTRY_DSN` is set; `None` otherwise.
+///
+/// The returned guard flushes pending events when dropped at exit.
+pub fn init() -> Result<Option<ClientInitGuard>> {
+    // ---
+    let Some(dsn) = env::var("SENTRY_DSN").ok().filter(|v| !v.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let dsn: Dsn = dsn
+        .trim()
+        .parse()
+        .map_err(|e| anyhow!("Invalid SENTRY_DSN: {e}"))?;
+
+    // The reporting client uses rustls without a built-in crypto provider
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let mut options = ClientOptions::new();
+    options.dsn = Some(dsn);
+    options.release = sentry::release_name!();
+    Ok(Some(sentry::init(options)))
+}
+
+/// Tracing layer turning warnings and errors into breadcrumbs on the current hub.
+pub fn breadcrumb_layer<S>() -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    // ---
+    sentry::integrations::tracing::layer()
+        .event_filter(|meta| match *meta.level() {
+            tracing::Level::ERROR | tracing::Level::WARN => EventFilter::Breadcrumb,
+            _ => EventFilter::Ignore,
+        })
+        // Tracing spans go to OTLP, not Sentry performance monitoring
+        .span_filter(|_| false)
+}
+
+/// Middleware: run the request under its own hub and report 5xx responses.
+///
+/// Sits inside [`request_id`](crate::request_id) so the ID is known.
+pub async fn report_errors(req: Request, next: Next) -> Response {
+    // ---
+    if Hub::current().client().is_none() {
+        return next.run(req).await;
+    }
+
+    let method = req.method().to_string();
+    let path = req.uri().path().to_owned();
+    let request_id = req.extensions().get::<RequestId>().map(|r| r.0.as_str());
+    let hub = request_hub(&method, &path, request_id);
+
+    let resp = next.run(req).bind_hub(hub.clone()).await;
+    if resp.status().is_server_error() {
+        capture_server_error(&hub, &method, &path, resp.status());
+    }
+    resp
+}
+
+/// A hub inheriting the global client, scoped to one request.
+fn request_hub(method: &str, path: &str, request_id: Option<&str>) -> Arc<Hub> {
+    // ---
+    let hub = Arc::new(Hub::new_from_top(Hub::current()));
+    hub.configure_scope(|scope| {
+        scope.set_transaction(Some(&format!("{method} {path}")));
+        scope.set_tag("http.method", method);
+        scope.set_tag("http.path", path);
+        if let Some(id) = request_id {
+            scope.set_tag("request_id", id);
+        }
+    });
+    hub
+}
+
+fn capture_server_error(hub: &Hub, method: &str, path: &str, status: StatusCode) {
+    // ---
+    hub.configure_scope(|scope| scope.set_tag("http.status_code", status.as_u16()));
+    hub.capture_message(&format!("{method} {path} returned {status}"), Level::Error);
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn server_errors_carry_request_context() {
+        // ---
+        let events = sentry::test::with_captured_events(|| {
+            let hub = request_hub("GET", "/v1/readings", Some("req-42"));
+            capture_server_error(
+                &hub,
+                "GET",
+                "/v1/readings",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        });
+
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(
+            event.message.as_deref(),
+            Some("GET /v1/readings returned 500 Internal Server Error")
+        );
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.transaction.as_deref(), Some("GET /v1/readings"));
+        assert_eq!(event.tags["request_id"], "req-42");
+        assert_eq!(event.tags["http.status_code"], "500");
+    }
+}
@@ -0,0 +1,88 @@
+//! Pipeline lifecycle event log.
+//!
+//! Notable lifecycle moments (startup, schema migrations, ingest runs) are
+//! written to the `events` table as structured rows, giving operators a
+//! queryable timeline via `GET /admin/events` that outlives process logs.
+//!
+//! Recording is best-effort: a failed insert is logged and never fails the
+//! operation being recorded.
+use serde_json::Value;
+use sqlx::PgPool;
+
+// ---
+
+/// Kind of lifecycle event, stored as its snake_case name in `events.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    // ---
+    /// Process started serving.
+    Startup,
+
+    /// A schema migration changed the database.
+    MigrationApplied,
+
+    /// An ingest run for one source began.
+    IngestStarted,
+
+    /// An ingest run for one source completed.
+    IngestFinished,
+
+    /// An ingest run for one source failed.
+    IngestFailed,
+
+    /// Expired readings were dropped by retention (see `partitions.rs`).
+    PartitionDropped,
+
+    /// Readings matching a filter were soft-deleted via `DELETE /v1/readings`.
+    ReadingsDeleted,
+
+    /// Soft-deleted readings were restored via `POST /admin/readings/restore`.
+    ReadingsRestored,
+
+    /// Soft-deleted readings were removed for good by the `purge` command.
+    ReadingsPurged,
+
+    /// A reading was corrected or marked invalid via `PATCH /v1/readings/{id}`.
+    ReadingCorrected,
+
+    /// Maintenance mode was turned on, updated or off via `PUT /admin/maintenance`.
+    MaintenanceChanged,
+
+    /// The configuration was reloaded on `SIGHUP` or via `POST /admin/reload`.
+    ConfigReloaded,
+}
+
+impl EventKind {
+    // ---
+    pub fn as_str(self) -> &'static str {
+        // ---
+        match self {
+            EventKind::Startup => "startup",
+            EventKind::MigrationApplied => "migration_applied",
+            EventKind::IngestStarted => "ingest_started",
+            EventKind::IngestFinished => "ingest_finished",
+            EventKind::IngestFailed => "ingest_failed",
+            EventKind::PartitionDropped => "partition_dropped",
+            EventKind::ReadingsDeleted => "readings_deleted",
+            EventKind::ReadingsRestored => "readings_restored",
+            EventKind::ReadingsPurged => "readings_purged",
+            EventKind::ReadingCorrected => "reading_corrected",
+            EventKind::MaintenanceChanged => "maintenance_changed",
+            EventKind::ConfigReloaded => "config_reloaded",
+        }
+    }
+}
+
+/// Append an event with a JSON `detail` payload to the `events` table.
+pub async fn record_event(pool: &PgPool, kind: EventKind, detail: Value) {
+    // ---
+    let result = sqlx::query("INSERT INTO events (kind, detail) VALUES ($1, $2)")
+        .bind(kind.as_str())
+        .bind(&detail)
+        .execute(pool)
+        .await;
+
+    if let Err(e) = result {
+        tracing::warn!("Failed to record {} event: {}", kind.as_str(), e);
+    }
+}
@@ -0,0 +1,221 @@
+//! Extractors that reject with [`ApiError`] bodies.
+//!
+//! axum's `Query`, `Path` and `Json` answer a request they can't parse with a
+//! plain-text body. These wrap them and answer with the JSON body of every
+//! other error instead: a query string or path that doesn't deserialize is a
+//! 400, as is a body that isn't JSON; a JSON body with missing or mistyped
+//! fields is a 422 and one over the body limit a 413. The parser's message
+//! goes to the log at `debug`, as it may echo the request.
+//!
+//! Routes import these from the crate instead of `axum::extract`. [`Json`]
+//! also responds, as axum's does, so a route needn't import both.
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection},
+        FromRequest, FromRequestParts, Request,
+    },
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::ApiError;
+
+// ---
+
+/// `axum::extract::Query`, rejecting with a 400 [`ApiError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+/// `axum::extract::Path`, rejecting with a 400 [`ApiError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+/// `axum::Json`, rejecting with a 400, 413 or 422 [`ApiError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    // ---
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // ---
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Query(value)),
+            Err(rejection) => Err(query_error(&rejection.body_text())),
+        }
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    // ---
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // ---
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(rejection) => Err(path_error(&rejection)),
+        }
+    }
+}
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    // ---
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        // ---
+        match axum::Json::<T>::from_request(req, state).await {
+            Ok(axum::Json(value)) => Ok(Json(value)),
+            Err(rejection) => Err(json_error(&rejection)),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    // ---
+    fn into_response(self) -> Response {
+        // ---
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// The error for a query string that doesn't deserialize; shared with
+/// extractors parsing the query string themselves.
+pub fn query_error(detail: &impl std::fmt::Display) -> ApiError {
+    // ---
+    tracing::debug!("Rejected query string: {}", detail);
+    ApiError::BadRequest {
+        error: "invalid query string",
+        hint: "check the parameter names and values against the API docs",
+    }
+}
+
+fn path_error(rejection: &PathRejection) -> ApiError {
+    // ---
+    tracing::debug!("Rejected path: {}", rejection.body_text());
+    ApiError::BadRequest {
+        error: "invalid path",
+        hint: "check the IDs in the path against the API docs",
+    }
+}
+
+fn json_error(rejection: &JsonRejection) -> ApiError {
+    // ---
+    tracing::debug!("Rejected body: {}", rejection.body_text());
+    match rejection {
+        JsonRejection::JsonDataError(_) => ApiError::Validation {
+            error: "invalid body",
+            hint: "check the body's field names, types and required fields against the API docs",
+        },
+        JsonRejection::MissingJsonContentType(_) => ApiError::BadRequest {
+            error: "unsupported content type",
+            hint: "send the body as JSON with content-type: application/json",
+        },
+        _ if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => ApiError::TooLarge {
+            error: "request too large",
+            hint: "request bodies are limited to 2 MiB",
+        },
+        _ => ApiError::BadRequest {
+            error: "malformed body",
+            hint: "send a valid JSON body",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use std::collections::HashMap;
+
+    use axum::{body::Body, routing::post, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn handler(
+        Path(id): Path<i32>,
+        Query(query): Query<HashMap<String, u32>>,
+        Json(body): Json<HashMap<String, String>>,
+    ) -> Json<(i32, usize, usize)> {
+        // ---
+        Json((id, query.len(), body.len()))
+    }
+
+    async fn send(uri: &str, content_type: &str, body: &'static str) -> (StatusCode, String) {
+        // ---
+        let app = Router::new().route("/items/{id}", post(handler));
+        let req = Request::post(uri)
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        (
+            status,
+            json["error"].as_str().unwrap_or_default().to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn rejections_are_json_errors() {
+        // ---
+        let json = "application/json";
+        let (status, _) = send("/items/7?n=1", json, r#"{"a": "b"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+
+        for (uri, content_type, body, expected) in [
+            (
+                "/items/x?n=1",
+                json,
+                "{}",
+                (StatusCode::BAD_REQUEST, "invalid path"),
+            ),
+            (
+                "/items/7?n=many",
+                json,
+                "{}",
+                (StatusCode::BAD_REQUEST, "invalid query string"),
+            ),
+            (
+                "/items/7",
+                json,
+                "{",
+                (StatusCode::BAD_REQUEST, "malformed body"),
+            ),
+            (
+                "/items/7",
+                json,
+                r#"{"a": 1}"#,
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid body"),
+            ),
+            (
+                "/items/7",
+                "text/plain",
+                "{}",
+                (StatusCode::BAD_REQUEST, "unsupported content type"),
+            ),
+        ] {
+            let (status, error) = send(uri, content_type, body).await;
+            assert_eq!((status, error.as_str()), expected, "{uri} {body}");
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Application-level encryption of sensitive attribute fields.
+//!
+//! Attribute fields named in `ENCRYPTED_FIELDS` (`<enricher name>.<field>`)
+//! are encrypted with AES-256-GCM after enrichment and before storage, so the
+//! database, its backups and replicas only hold ciphertext:
+//!
+//! ```json
+//! { "assets": { "location": "enc:v1:1:3q2+7w...", "site": "Plant A" } }
+//! ```
+//!
+//! Each value is serialized to JSON, encrypted under a fresh random nonce and
+//! bound to the reading's device and field path, so a ciphertext copied to
+//! another device or field fails to decrypt. [`FieldCipher::reveal`] decrypts
+//! for callers with at least `DECRYPT_ROLE`; everyone else sees [`REDACTED`].
+//!
+//! Entries naming one of the [`REGISTRY_COLUMNS`] (`devices.notes`,
+//! `devices.location`, `meshes.contact`) encrypt that registry column instead:
+//! the registry routes store it sealed to the row's device or mesh ID and
+//! reveal it on reads the same way.
+//!
+//! The key ID stored with each value names the key that wrote it. Values that
+//! don't decrypt with the configured key (e.g. written before a key change)
+//! are redacted and logged rather than failing the read.
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+
+use crate::{EncryptionConfig, Role, SensorReading};
+
+/// Shown in place of encrypted values to callers below `DECRYPT_ROLE`.
+pub const REDACTED: &str = "[encrypted]";
+
+/// Marks an encrypted value: `enc:v1:<key id>:<base64 nonce + ciphertext>`.
+const PREFIX: &str = "enc:v1:";
+
+/// AES-GCM nonce length in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Registry columns `ENCRYPTED_FIELDS` may name, as `<table>.<column>`.
+pub const REGISTRY_COLUMNS: &[&str] = &["devices.location", "devices.notes", "meshes.contact"];
+
+// ---
+
+/// Encrypts and decrypts the configured attribute fields.
+pub struct FieldCipher {
+    // ---
+    cipher: Aes256Gcm,
+    key_id: String,
+
+    /// `(enricher name, field)` pairs to encrypt.
+    fields: Vec<(String, String)>,
+
+    /// `(table, column)` pairs of the registry to encrypt.
+    columns: Vec<(String, String)>,
+    decrypt_role: Role,
+}
+
+impl FieldCipher {
+    // ---
+    pub fn new(cfg: &EncryptionConfig) -> Self {
+        // ---
+        let (columns, fields): (Vec<_>, Vec<_>) = cfg
+            .fields
+            .iter()
+            .partition(|f| REGISTRY_COLUMNS.contains(&f.as_str()));
+        let split = |names: Vec<&String>| {
+            names
+                .into_iter()
+                .filter_map(|f| f.split_once('.'))
+                .map(|(ns, field)| (ns.to_string(), field.to_string()))
+                .collect()
+        };
+        Self {
+            cipher: Aes256Gcm::new(&cfg.key.into()),
+            key_id: cfg.key_id.clone(),
+            fields: split(fields),
+            columns: split(columns),
+            decrypt_role: cfg.decrypt_role,
+        }
+    }
+
+    /// Encrypt the configured fields present in `reading.attributes`.
+    ///
+    /// A field that can't be encrypted is dropped; plain text is never stored.
+    pub fn encrypt(&self, reading: &mut SensorReading) {
+        // ---
+        for (ns, field) in &self.fields {
+            let Some(Value::Object(attrs)) = reading.attributes.get_mut(ns) else {
+                continue;
+            };
+            let Some(value) = attrs.get_mut(field) else {
+                continue;
+            };
+            if is_encrypted(value) {
+                continue;
+            }
+            match self.seal(&reading.device_id, ns, field, value) {
+                Ok(sealed) => *value = Value::String(sealed),
+                Err(e) => {
+                    tracing::warn!("Dropping {}.{}: encryption failed ({})", ns, field, e);
+                    attrs.remove(field);
+                }
+            }
+        }
+    }
+
+    /// Decrypt every encrypted field for callers with at least `DECRYPT_ROLE`;
+    /// replace them with [`REDACTED`] for everyone else.
+    pub fn reveal(&self, reading: &mut SensorReading, role: Role) {
+        // ---
+        let authorized = role >= self.decrypt_role;
+        for (ns, attrs) in reading.attributes.iter_mut() {
+            let Value::Object(attrs) = attrs else {
+                continue;
+            };
+            for (field, value) in attrs.iter_mut() {
+                let Value::String(sealed) = value else {
+                    continue;
+                };
+                if !sealed.starts_with(PREFIX) {
+                    continue;
+                }
+                *value = if authorized {
+                    self.open(&reading.device_id, ns, field, sealed)
+                        .unwrap_or_else(|e| {
+                            tracing::warn!(
+                                "Cannot decrypt {}.{} for device {}: {}",
+                                ns,
+                                field,
+                                reading.device_id,
+                                e
+                            );
+                            Value::String(REDACTED.into())
+                        })
+                } else {
+                    Value::String(REDACTED.into())
+                };
+            }
+        }
+    }
+
+    /// Encrypt `value`, the `table.column` of the registry row `id`, when
+    /// that column is designated sensitive.
+    ///
+    /// A value that can't be encrypted is dropped; plain text is never stored.
+    pub fn encrypt_column(&self, table: &str, column: &str, id: &str, value: &mut Option<String>) {
+        // ---
+        let designated = self.columns.iter().any(|(t, c)| t == table && c == column);
+        let Some(plain) = value.as_deref().filter(|_| designated) else {
+            return;
+        };
+        if plain.starts_with(PREFIX) {
+            return;
+        }
+        match self.seal(id, table, column, &Value::String(plain.to_string())) {
+            Ok(sealed) => *value = Some(sealed),
+            Err(e) => {
+                tracing::warn!("Dropping {}.{}: encryption failed ({})", table, column, e);
+                *value = None;
+            }
+        }
+    }
+
+    /// Decrypt an encrypted registry column (see [`encrypt_column`](Self::encrypt_column))
+    /// for callers with at least `DECRYPT_ROLE`; replace it with [`REDACTED`]
+    /// for everyone else.
+    pub fn reveal_column(
+        &self,
+        table: &str,
+        column: &str,
+        id: &str,
+        value: &mut Option<String>,
+        role: Role,
+    ) {
+        // ---
+        let Some(sealed) = value.as_deref().filter(|v| v.starts_with(PREFIX)) else {
+            return;
+        };
+        let revealed = if role >= self.decrypt_role {
+            match self.open(id, table, column, sealed) {
+                Ok(Value::String(plain)) => plain,
+                Ok(other) => other.to_string(),
+                Err(e) => {
+                    tracing::warn!("Cannot decrypt {}.{} of {}: {}", table, column, id, e);
+                    REDACTED.into()
+                }
+            }
+        } else {
+            REDACTED.into()
+        };
+        *value = Some(revealed);
+    }
+
+    fn seal(
+        &self,
+        device_id: &str,
+        ns: &str,
+        field: &str,
+        value: &Value,
+    ) -> Result<String, String> {
+        // ---
+        let plaintext = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: aad(device_id, ns, field).as_bytes(),
+                },
+            )
+            .map_err(|e| e.to_string())?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!(
+            "{PREFIX}{}:{}",
+            self.key_id,
+            STANDARD.encode(sealed)
+        ))
+    }
+
+    fn open(&self, device_id: &str, ns: &str, field: &str, sealed: &str) -> Result<Value, String> {
+        // ---
+        let (key_id, data) = sealed
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or("malformed ciphertext")?;
+        if key_id != self.key_id {
+            return Err(format!("written with key {key_id:?}"));
+        }
+        let data = STANDARD.decode(data).map_err(|e| e.to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("malformed ciphertext".into());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad(device_id, ns, field).as_bytes(),
+                },
+            )
+            .map_err(|_| "authentication failed")?;
+        serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+    }
+}
+
+fn is_encrypted(value: &Value) -> bool {
+    // ---
+    value.as_str().is_some_and(|s| s.starts_with(PREFIX))
+}
+
+/// Associated data binding a ciphertext to its device and field.
+fn aad(device_id: &str, ns: &str, field: &str) -> String {
+    // ---
+    format!("{device_id}\0{ns}.{field}")
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+
+    fn cipher() -> FieldCipher {
+        // ---
+        FieldCipher::new(&EncryptionConfig {
+            key: [7; 32],
+            key_id: "test".into(),
+            fields: vec![
+                "assets.location".into(),
+                "assets.contact".into(),
+                "meshes.contact".into(),
+            ],
+            decrypt_role: Role::Admin,
+        })
+    }
+
+    fn reading(device_id: &str) -> SensorReading {
+        // ---
+        let attributes = json!({
+            "assets": { "location": "Plant A", "contact": { "phone": "555-0100" }, "site": 3 }
+        });
+        SensorReading {
+            id: None,
+            mesh_id: "mesh-001".into(),
+            device_id: device_id.into(),
+            timestamp_utc: Utc::now(),
+            timestamp_local: None,
+            temperature_c: 20.0,
+            humidity: 50.0,
+            raw_temperature_c: None,
+            raw_humidity: None,
+            status: "ok".into(),
+            temperature_alert: false,
+            humidity_alert: false,
+            latitude: None,
+            longitude: None,
+            extra: serde_json::Map::new(),
+            quality: None,
+            quality_flags: Vec::new(),
+            attributes: attributes.as_object().unwrap().clone(),
+            invalid: false,
+            corrections: Vec::new(),
+            ingest_batch_id: None,
+            smoothed: None,
+            rolling_avg: None,
+            device: None,
+        }
+    }
+
+    #[test]
+    fn designated_fields_round_trip_for_authorized_roles() {
+        // ---
+        let cipher = cipher();
+        let original = reading("device-001");
+        let mut r = reading("device-001");
+        cipher.encrypt(&mut r);
+
+        let assets = &r.attributes["assets"];
+        assert!(is_encrypted(&assets["location"]));
+        assert!(is_encrypted(&assets["contact"]));
+        assert_eq!(assets["site"], 3);
+
+        // Encrypting again leaves ciphertext alone
+        let sealed = r.attributes.clone();
+        cipher.encrypt(&mut r);
+        assert_eq!(r.attributes, sealed);
+
+        cipher.reveal(&mut r, Role::Admin);
+        assert_eq!(r.attributes, original.attributes);
+    }
+
+    #[test]
+    fn other_roles_and_moved_ciphertexts_are_redacted() {
+        // ---
+        let cipher = cipher();
+        let mut r = reading("device-001");
+        cipher.encrypt(&mut r);
+
+        let mut reader_view = reading("device-001");
+        reader_view.attributes = r.attributes.clone();
+        cipher.reveal(&mut reader_view, Role::Reader);
+        assert_eq!(reader_view.attributes["assets"]["location"], REDACTED);
+
+        // Same ciphertext attached to another device fails authentication
+        let mut moved = reading("device-002");
+        moved.attributes = r.attributes.clone();
+        cipher.reveal(&mut moved, Role::Admin);
+        assert_eq!(moved.attributes["assets"]["contact"], REDACTED);
+    }
+
+    #[test]
+    fn designated_registry_columns_are_sealed_to_their_row() {
+        // ---
+        let cipher = cipher();
+        let mut contact = Some("ops@example.com".to_string());
+        cipher.encrypt_column("meshes", "contact", "mesh-001", &mut contact);
+        let sealed = contact.clone().unwrap();
+        assert!(sealed.starts_with(PREFIX) && !sealed.contains("example.com"));
+
+        // Columns not named in ENCRYPTED_FIELDS are stored as sent
+        let mut notes = Some("aisle 4".to_string());
+        cipher.encrypt_column("devices", "notes", "device-001", &mut notes);
+        assert_eq!(notes.as_deref(), Some("aisle 4"));
+
+        let mut reader_view = contact.clone();
+        cipher.reveal_column(
+            "meshes",
+            "contact",
+            "mesh-001",
+            &mut reader_view,
+            Role::Reader,
+        );
+        assert_eq!(reader_view.as_deref(), Some(REDACTED));
+
+        let mut moved = contact.clone();
+        cipher.reveal_column("meshes", "contact", "mesh-002", &mut moved, Role::Admin);
+        assert_eq!(moved.as_deref(), Some(REDACTED));
+
+        cipher.reveal_column("meshes", "contact", "mesh-001", &mut contact, Role::Admin);
+        assert_eq!(contact.as_deref(), Some("ops@example.com"));
+    }
+}
@@ -0,0 +1,337 @@
+//! `Idempotency-Key` support for write endpoints.
+//!
+//! Gateways retrying a push after a network failure can't tell whether the
+//! first attempt was stored. With an `Idempotency-Key` header (up to 255
+//! printable ASCII characters) on a `POST`, `PUT`, `PATCH` or `DELETE`,
+//! [`idempotency`] runs the request once per caller and key: the successful
+//! response is kept in `idempotency_keys` alongside a digest of the request,
+//! and a retry with the same key is answered from there, marked with
+//! [`REPLAYED_HEADER`], without reaching the handler. Keys are kept for
+//! [`KEY_TTL_HOURS`].
+//!
+//! - A key reused for a different request (method, path, query or body) is
+//!   rejected with 422
+//! - A retry arriving while the first attempt still runs gets 409
+//! - Unsuccessful responses aren't kept, so the request can be retried with
+//!   the same key once the cause is fixed
+//! - A claim whose request never finished (e.g. the process died) is given up
+//!   after [`ABANDONED_AFTER_SECS`]
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::{db_error_response, ApiError, Principal};
+
+/// Request header carrying the client's key.
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Response header marking a replayed response.
+pub const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest key accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// Largest request body buffered for the digest, as axum's default body limit.
+const MAX_REQUEST_BYTES: usize = 2 * 1024 * 1024;
+
+/// How long a key answers retries.
+const KEY_TTL_HOURS: i32 = 24;
+
+/// How long a claim may stay unanswered before another attempt takes it over.
+const ABANDONED_AFTER_SECS: f64 = 300.0;
+
+// ---
+
+/// What `idempotency_keys` says about a request.
+enum Claim {
+    // ---
+    /// First attempt; run it.
+    Claimed,
+
+    /// Answered before with this response.
+    Stored {
+        status: i16,
+        content_type: Option<String>,
+        body: Vec<u8>,
+    },
+
+    /// Another attempt with this key is still running.
+    InFlight,
+
+    /// The key was used for a different request.
+    Mismatch,
+}
+
+/// A row of `idempotency_keys`.
+#[derive(sqlx::FromRow)]
+struct HeldKey {
+    // ---
+    request_digest: String,
+    status: Option<i16>,
+    content_type: Option<String>,
+    body: Option<Vec<u8>>,
+}
+
+/// Middleware: run keyed write requests at most once per caller and key.
+///
+/// Layer it inside [`authenticate`](crate::authenticate), so keys are scoped to the
+/// caller.
+pub async fn idempotency(State(pool): State<PgPool>, req: Request, next: Next) -> Response {
+    // ---
+    let writes = matches!(
+        *req.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    let Some(key) = req
+        .headers()
+        .get(&IDEMPOTENCY_KEY_HEADER)
+        .filter(|_| writes)
+    else {
+        return next.run(req).await;
+    };
+    let Some(key) = key.to_str().ok().filter(|k| is_valid(k)).map(str::to_owned) else {
+        return ApiError::Validation {
+            error: "invalid idempotency key",
+            hint: "use 1 to 255 printable ASCII characters, e.g. a UUID",
+        }
+        .into_response();
+    };
+    let caller = req
+        .extensions()
+        .get::<Principal>()
+        .map(|p| p.name.clone())
+        .unwrap_or_default();
+
+    let (parts, body) = req.into_parts();
+    let Ok(body) = to_bytes(body, MAX_REQUEST_BYTES).await else {
+        return ApiError::TooLarge {
+            error: "request too large",
+            hint: "keyed requests are limited to 2 MiB",
+        }
+        .into_response();
+    };
+    let digest = request_digest(&parts.method, &parts.uri.to_string(), &body);
+
+    match claim(&pool, &caller, &key, &digest).await {
+        Ok(Claim::Claimed) => {}
+        Ok(Claim::Stored {
+            status,
+            content_type,
+            body,
+        }) => {
+            tracing::info!("Replaying response for idempotency key {}", key);
+            let status = StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK);
+            let mut resp = (status, body).into_response();
+            if let Some(value) = content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+                resp.headers_mut().insert(header::CONTENT_TYPE, value);
+            }
+            resp.headers_mut()
+                .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+            return resp;
+        }
+        Ok(Claim::InFlight) => {
+            return ApiError::Conflict {
+                error: "request in progress",
+                hint: "a request with this idempotency key is still running; retry shortly",
+            }
+            .into_response();
+        }
+        Ok(Claim::Mismatch) => {
+            return ApiError::Validation {
+                error: "idempotency key reused",
+                hint: "this key was used for a different request; use a new key per request",
+            }
+            .into_response();
+        }
+        Err(e) => {
+            tracing::error!("Idempotency key lookup failed: {}", e);
+            return db_error_response(&e, "idempotency check failed");
+        }
+    }
+
+    let resp = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !resp.status().is_success() {
+        if let Err(e) = release(&pool, &caller, &key).await {
+            tracing::warn!("Failed to release idempotency key {}: {}", key, e);
+        }
+        return resp;
+    }
+
+    let (parts, body) = resp.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!(
+                "Failed to buffer response for idempotency key {}: {}",
+                key,
+                e
+            );
+            if let Err(e) = release(&pool, &caller, &key).await {
+                tracing::warn!("Failed to release idempotency key {}: {}", key, e);
+            }
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if let Err(e) = store(&pool, &caller, &key, parts.status, content_type, &body).await {
+        tracing::warn!(
+            "Failed to store response for idempotency key {}: {}",
+            key,
+            e
+        );
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn is_valid(key: &str) -> bool {
+    // ---
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Hex SHA-256 of everything that makes two requests the same request.
+fn request_digest(method: &Method, uri: &str, body: &[u8]) -> String {
+    // ---
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{method} {uri}\n"));
+    hasher.update(body);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Expire old keys, then claim `key` for `caller` or report what holds it.
+#[tracing::instrument(name = "db.claim_idempotency_key", skip_all)]
+async fn claim(pool: &PgPool, caller: &str, key: &str, digest: &str) -> Result<Claim, sqlx::Error> {
+    // ---
+    sqlx::query(
+        "DELETE FROM idempotency_keys WHERE created_at < now() - make_interval(hours => $1)",
+    )
+    .bind(KEY_TTL_HOURS)
+    .execute(pool)
+    .await?;
+
+    let claimed = sqlx::query_scalar::<_, bool>(
+        r#"
+        INSERT INTO idempotency_keys (caller, key, request_digest)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (caller, key) DO UPDATE SET
+            request_digest = EXCLUDED.request_digest,
+            created_at     = now()
+        WHERE idempotency_keys.status IS NULL
+          AND idempotency_keys.created_at < now() - make_interval(secs => $4)
+        RETURNING true
+        "#,
+    )
+    .bind(caller)
+    .bind(key)
+    .bind(digest)
+    .bind(ABANDONED_AFTER_SECS)
+    .fetch_optional(pool)
+    .await?;
+    if claimed.is_some() {
+        return Ok(Claim::Claimed);
+    }
+
+    let held = sqlx::query_as::<_, HeldKey>(
+        r#"
+        SELECT request_digest, status, content_type, body
+        FROM idempotency_keys
+        WHERE caller = $1 AND key = $2
+        "#,
+    )
+    .bind(caller)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(match held {
+        Some(held) if held.request_digest != digest => Claim::Mismatch,
+        Some(HeldKey {
+            status: Some(status),
+            content_type,
+            body,
+            ..
+        }) => Claim::Stored {
+            status,
+            content_type,
+            body: body.unwrap_or_default(),
+        },
+        // Still running, or released since the claim failed: retry
+        _ => Claim::InFlight,
+    })
+}
+
+/// Keep the response to the request that claimed `key`.
+async fn store(
+    pool: &PgPool,
+    caller: &str,
+    key: &str,
+    status: StatusCode,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<(), sqlx::Error> {
+    // ---
+    sqlx::query(
+        r#"
+        UPDATE idempotency_keys SET status = $3, content_type = $4, body = $5
+        WHERE caller = $1 AND key = $2
+        "#,
+    )
+    .bind(caller)
+    .bind(key)
+    .bind(status.as_u16() as i16)
+    .bind(content_type)
+    .bind(body)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Give up the claim on `key`, so the request may be retried.
+async fn release(pool: &PgPool, caller: &str, key: &str) -> Result<(), sqlx::Error> {
+    // ---
+    sqlx::query("DELETE FROM idempotency_keys WHERE caller = $1 AND key = $2 AND status IS NULL")
+        .bind(caller)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn keys_are_printable_and_digests_cover_the_request() {
+        // ---
+        assert!(is_valid("3f2c9a4e-1b7d-4c1e-9a61-0c5f6b2d8e11"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has space"));
+        assert!(!is_valid(&"k".repeat(MAX_KEY_LEN + 1)));
+
+        let digest = request_digest(&Method::POST, "/v1/readings", b"[]");
+        assert_eq!(digest, request_digest(&Method::POST, "/v1/readings", b"[]"));
+        assert_ne!(
+            digest,
+            request_digest(&Method::POST, "/v1/readings", b"[{}]")
+        );
+        assert_ne!(digest, request_digest(&Method::PUT, "/v1/readings", b"[]"));
+        assert_ne!(
+            digest,
+            request_digest(&Method::POST, "/sql/readings", b"[]")
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Index advisor for `sensor_data` queries.
+//!
+//! `GET /sql/readings` reports the filter columns each query used, and how
+//! long it took, to a shared [`FilterStats`] (through `PgReadings`, so only
+//! queries that reached Postgres count). The advisor turns each observed
+//! combination into the composite index that would serve it (equality columns
+//! first, then `timestamp_utc` for ranges and the `ORDER BY`), checks the
+//! table's existing indexes, and ranks the missing ones by the query time they
+//! could have saved. Admins can then create a suggestion explicitly.
+//!
+//! Stats are in-memory and per process; they reset on restart.
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use serde::Serialize;
+use sqlx::{Connection, Executor, PgPool};
+
+use crate::create_index_concurrently;
+
+/// Columns `/sql/readings` can filter on by equality, in index order.
+const EQUALITY_COLUMNS: [&str; 2] = ["device_id", "mesh_id"];
+
+/// Range and ordering column; always last in a suggested index.
+const RANGE_COLUMN: &str = "timestamp_utc";
+
+// ---
+
+/// Filter usage observed since startup, keyed by the sorted filter columns.
+#[derive(Default)]
+pub struct FilterStats {
+    // ---
+    usage: Mutex<HashMap<Vec<&'static str>, Usage>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Usage {
+    count: u64,
+    total: Duration,
+}
+
+impl FilterStats {
+    // ---
+    /// Record one query that filtered on `columns` and took `elapsed`.
+    pub fn record(&self, columns: &[&'static str], elapsed: Duration) {
+        // ---
+        let mut key = columns.to_vec();
+        key.sort_unstable();
+        key.dedup();
+
+        let mut usage = self.usage.lock().expect("filter stats lock poisoned");
+        let entry = usage.entry(key).or_default();
+        entry.count += 1;
+        entry.total += elapsed;
+    }
+
+    fn snapshot(&self) -> Vec<(Vec<&'static str>, Usage)> {
+        // ---
+        let usage = self.usage.lock().expect("filter stats lock poisoned");
+        usage.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+}
+
+/// One observed filter combination and the index that would serve it.
+#[derive(Debug, Serialize)]
+pub struct Advice {
+    // ---
+    /// Filter columns used together, sorted.
+    pub filters: Vec<&'static str>,
+
+    /// Queries seen with exactly this combination.
+    pub uses: u64,
+
+    /// Mean observed query time.
+    pub avg_ms: f64,
+
+    /// Composite index serving this combination.
+    pub suggested_index: Vec<&'static str>,
+
+    /// Existing index already serving it, if any.
+    pub covered_by: Option<String>,
+
+    /// Observed query time for uncovered combinations: an upper bound on what
+    /// the index could have saved. Zero when already covered.
+    pub estimated_benefit_ms: f64,
+
+    /// DDL to create the index (only when not covered).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_sql: Option<String>,
+}
+
+/// Advisor report for `GET /admin/index-advisor`.
+#[derive(Debug, Serialize)]
+pub struct AdvisorReport {
+    /// Planner's row estimate for `sensor_data`.
+    pub table_rows: i64,
+
+    /// Most valuable first.
+    pub advice: Vec<Advice>,
+}
+
+/// Build the advisor report from observed usage and the current indexes.
+#[tracing::instrument(name = "db.index_advisor", skip_all)]
+pub async fn advise(pool: &PgPool, stats: &FilterStats) -> Result<AdvisorReport, sqlx::Error> {
+    // ---
+    let indexes = existing_indexes(pool).await?;
+    // A partitioned table's own estimate is 0; its partitions hold the rows
+    let table_rows: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(GREATEST(reltuples, 0)), 0)::BIGINT
+        FROM pg_class
+        WHERE oid = 'sensor_data'::regclass
+           OR oid IN (SELECT inhrelid FROM pg_inherits WHERE inhparent = 'sensor_data'::regclass)
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let mut advice: Vec<Advice> = stats
+        .snapshot()
+        .into_iter()
+        .map(|(filters, usage)| {
+            let suggested_index = suggested_index(&filters);
+            let covered_by = indexes
+                .iter()
+                .find(|(_, cols)| covers(cols, &suggested_index))
+                .map(|(name, _)| name.clone());
+            let total_ms = usage.total.as_secs_f64() * 1000.0;
+
+            Advice {
+                uses: usage.count,
+                avg_ms: total_ms / usage.count as f64,
+                estimated_benefit_ms: if covered_by.is_some() { 0.0 } else { total_ms },
+                create_sql: covered_by
+                    .is_none()
+                    .then(|| create_index_sql(&suggested_index)),
+                covered_by,
+                suggested_index,
+                filters,
+            }
+        })
+        .collect();
+
+    advice.sort_by(|a, b| {
+        b.estimated_benefit_ms
+            .total_cmp(&a.estimated_benefit_ms)
+            .then(b.uses.cmp(&a.uses))
+    });
+    Ok(AdvisorReport { table_rows, advice })
+}
+
+/// Create the suggested index for `filters`, unless one already covers it.
+///
+/// `filters` must only name columns `/sql/readings` filters on; anything else
+/// is rejected before any SQL is built. Returns the created index name, or
+/// `None` if the combination was already covered.
+pub async fn create_index(
+    pool: &PgPool,
+    filters: &[String],
+) -> Result<Option<String>, AdvisorError> {
+    // ---
+    let mut cols: Vec<&'static str> = Vec::with_capacity(filters.len());
+    for f in filters {
+        let known = EQUALITY_COLUMNS
+            .iter()
+            .chain([&RANGE_COLUMN])
+            .find(|c| **c == f.as_str())
+            .ok_or(AdvisorError::UnknownColumn)?;
+        cols.push(known);
+    }
+    if cols.is_empty() {
+        return Err(AdvisorError::UnknownColumn);
+    }
+    cols.sort_unstable();
+    cols.dedup();
+
+    let suggested = suggested_index(&cols);
+    let indexes = existing_indexes(pool).await?;
+    if indexes
+        .iter()
+        .any(|(_, existing)| covers(existing, &suggested))
+    {
+        return Ok(None);
+    }
+
+    // CONCURRENTLY can't run in a transaction; unbound SQL is sent on its own.
+    // Index builds outlast DB_STATEMENT_TIMEOUT_MS, so this runs on a
+    // connection of its own with the timeout lifted, closed afterwards.
+    tracing::info!("Creating advisor index: {}", create_index_sql(&suggested));
+    let mut conn = pool.acquire().await?.detach();
+    conn.execute("SET statement_timeout = 0").await?;
+    let key = format!("({})", suggested.join(", "));
+    let created = create_index_concurrently(&mut conn, &index_name(&suggested), &key).await;
+    let _ = conn.close().await;
+    created?;
+    Ok(Some(index_name(&suggested)))
+}
+
+/// Why [`create_index`] refused or failed.
+#[derive(Debug)]
+pub enum AdvisorError {
+    // ---
+    /// Empty list, or a column `/sql/readings` doesn't filter on.
+    UnknownColumn,
+
+    /// The database rejected the statement.
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AdvisorError {
+    // ---
+    fn from(e: sqlx::Error) -> Self {
+        // ---
+        AdvisorError::Database(e)
+    }
+}
+
+/// Equality filters first, then the range/ordering column.
+fn suggested_index(filters: &[&'static str]) -> Vec<&'static str> {
+    // ---
+    let mut cols: Vec<&'static str> = EQUALITY_COLUMNS
+        .into_iter()
+        .filter(|c| filters.contains(c))
+        .collect();
+    cols.push(RANGE_COLUMN);
+    cols
+}
+
+/// Whether an index on `existing` serves `wanted`: the equality columns lead
+/// (in any order), followed by the range column.
+fn covers(existing: &[String], wanted: &[&'static str]) -> bool {
+    // ---
+    if existing.len() < wanted.len() {
+        return false;
+    }
+    let (eq, range) = wanted.split_at(wanted.len() - 1);
+    let lead = &existing[..eq.len()];
+
+    eq.iter().all(|c| lead.iter().any(|l| l == c)) && existing[eq.len()] == range[0]
+}
+
+fn index_name(cols: &[&str]) -> String {
+    // ---
+    format!("idx_sensor_data_adv_{}", cols.join("_"))
+}
+
+fn create_index_sql(cols: &[&str]) -> String {
+    // ---
+    format!(
+        "CREATE INDEX CONCURRENTLY IF NOT EXISTS {} ON sensor_data ({})",
+        index_name(cols),
+        cols.join(", ")
+    )
+}
+
+/// `(index name, column names in key order)` for every `sensor_data` index.
+async fn existing_indexes(pool: &PgPool) -> Result<Vec<(String, Vec<String>)>, sqlx::Error> {
+    // ---
+    sqlx::query_as(
+        r#"
+        SELECT i.relname::TEXT, array_agg(a.attname::TEXT ORDER BY k.ord)
+        FROM pg_index x
+        JOIN pg_class i ON i.oid = x.indexrelid
+        CROSS JOIN LATERAL unnest(x.indkey::INT2[]) WITH ORDINALITY AS k (attnum, ord)
+        JOIN pg_attribute a ON a.attrelid = x.indrelid AND a.attnum = k.attnum
+        WHERE x.indrelid = 'sensor_data'::regclass AND x.indisvalid
+        GROUP BY i.relname
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn cols(names: &[&str]) -> Vec<String> {
+        // ---
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn suggests_equality_columns_then_timestamp() {
+        // ---
+        assert_eq!(suggested_index(&[]), ["timestamp_utc"]);
+        assert_eq!(
+            suggested_index(&["mesh_id", "timestamp_utc", "device_id"]),
+            ["device_id", "mesh_id", "timestamp_utc"]
+        );
+    }
+
+    #[test]
+    fn coverage_requires_leading_equality_then_range() {
+        // ---
+        let wanted = ["device_id", "mesh_id", "timestamp_utc"];
+        assert!(covers(
+            &cols(&["mesh_id", "device_id", "timestamp_utc"]),
+            &wanted
+        ));
+        assert!(!covers(&cols(&["device_id", "timestamp_utc"]), &wanted));
+        assert!(!covers(&cols(&["device_id", "mesh_id"]), &wanted));
+        assert!(covers(
+            &cols(&["device_id", "timestamp_utc", "id"]),
+            &["device_id", "timestamp_utc"]
+        ));
+    }
+
+    #[test]
+    fn record_merges_combinations_in_any_order() {
+        // ---
+        let stats = FilterStats::default();
+        stats.record(&["mesh_id", "device_id"], Duration::from_millis(4));
+        stats.record(&["device_id", "mesh_id"], Duration::from_millis(6));
+
+        let snap = stats.snapshot();
+        assert_eq!(snap.len(), 1);
+        assert_eq!(snap[0].0, ["device_id", "mesh_id"]);
+        assert_eq!(snap[0].1.count, 2);
+        assert_eq!(snap[0].1.total, Duration::from_millis(10));
+    }
+}
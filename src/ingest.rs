@@ -0,0 +1,978 @@
+//! Upstream ingestion for the sensor pipeline.
+//!
+//! Fetches readings from every configured upstream source (see
+//! `upstream.rs`), transforms them (applying each device's
+//! `device_calibration` offsets, if any), scores their quality (see
+//! `quality.rs`) and enriches them (see `enrich.rs`), stores them in
+//! `sensor_data` tagged with the source name, and refreshes the
+//! `mesh_summary` aggregates. Ingest runs once per source when that source has
+//! no stored data (triggered from `GET /sql/readings`), and optionally on a
+//! per-source schedule via [`spawn_scheduled_ingest`] or on demand via
+//! [`ingest_all`]. Readings pushed by clients are stored via [`store_pushed`].
+//! Deployments storing readings in SQLite or MySQL ingest through
+//! [`spawn_repository_ingest`] instead.
+//!
+//! A device reporting through several sources yields one stored copy of each
+//! reading per source; [`reconcile_sources`] keeps one copy per device and
+//! timestamp counting toward summaries and alerts, and records the rest in
+//! `source_conflicts`.
+//!
+//! Every run is an `ingest_batches` row tagged with what triggered it, whose
+//! counts are brought up to date after each upstream page and every
+//! [`PROGRESS_EVERY`] stored readings, so `GET /admin/ingest/status` can tell a
+//! long ingest that is progressing from one that is wedged.
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::sync::watch;
+use tracing::Instrument;
+
+use crate::{
+    assess_batch, record_event, refresh_rollups, source_for, Calibration, Enrichment, EventKind,
+    RawSensorReading, ReadingsRepository, ResponseCache, SensorReading, SensorSource, SourceConfig,
+};
+
+/// Stored readings between progress updates of a batch.
+const PROGRESS_EVERY: u64 = 500;
+
+// ---
+
+/// Why an ingest failed, as far as a client is concerned.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum IngestError {
+    // ---
+    /// Fetching from a source failed or it sent something unusable.
+    #[error("{0}")]
+    Upstream(String),
+
+    /// Reading or writing our own database failed.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl From<sqlx::Error> for IngestError {
+    // ---
+    fn from(e: sqlx::Error) -> Self {
+        // ---
+        IngestError::Internal(e.to_string())
+    }
+}
+
+/// What started an ingest batch, as recorded in `ingest_batches.trigger`.
+#[derive(Debug, Clone, Copy)]
+enum Trigger {
+    // ---
+    /// First ingest of a source without stored data.
+    Initial,
+
+    /// A source's re-ingest interval elapsed.
+    Scheduled,
+
+    /// `POST /sql/ingest`.
+    Manual,
+
+    /// Readings pushed by a client.
+    Push,
+}
+
+impl Trigger {
+    // ---
+    fn as_str(self) -> &'static str {
+        // ---
+        match self {
+            Trigger::Initial => "initial",
+            Trigger::Scheduled => "scheduled",
+            Trigger::Manual => "manual",
+            Trigger::Push => "push",
+        }
+    }
+}
+
+/// Ensure data exists: for each source with no rows in `sensor_data`, fetch
+/// from its API, transform, persist, and update summaries; otherwise no-op.
+/// Used to avoid re-ingesting on every GET.
+pub async fn ensure_data_loaded(
+    pool: &PgPool,
+    sources: &[SourceConfig],
+    priority: &[String],
+    enrichment: &Enrichment,
+) -> Result<(), IngestError> {
+    // ---
+    let mut ingested = false;
+
+    for source in sources {
+        // Quick query of posgres then skip ingest if we already have data
+        let has_data: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sensor_data WHERE source = $1)")
+                .bind(&source.name)
+                .fetch_one(pool)
+                .await?;
+
+        if has_data {
+            tracing::debug!("Data present for source {}; skipping ingest", source.name);
+            continue;
+        }
+
+        tracing::info!(
+            "No data present for source {}; performing initial ingest",
+            source.name
+        );
+        ingest_source(pool, source, priority, enrichment, Trigger::Initial).await?;
+        ingested = true;
+    }
+
+    if ingested {
+        update_mesh_summaries(pool).await?;
+    }
+    Ok(())
+}
+
+/// Whether any of `sources` has no rows in `sensor_data` yet, i.e. still
+/// needs its initial ingest.
+pub async fn sources_without_data(
+    pool: &PgPool,
+    sources: &[SourceConfig],
+) -> Result<bool, sqlx::Error> {
+    // ---
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM unnest($1::TEXT[]) AS s (name)
+            WHERE NOT EXISTS (SELECT 1 FROM sensor_data d WHERE d.source = s.name)
+        )
+        "#,
+    )
+    .bind(names)
+    .fetch_one(pool)
+    .await
+}
+
+/// Re-ingest every source now, regardless of stored data, then refresh summaries.
+///
+/// Returns `(source name, newly inserted rows)` per source. Used by the
+/// on-demand `POST /sql/ingest` route.
+pub async fn ingest_all(
+    pool: &PgPool,
+    sources: &[SourceConfig],
+    priority: &[String],
+    enrichment: &Enrichment,
+) -> Result<Vec<(String, u64)>, IngestError> {
+    // ---
+    let mut counts = Vec::with_capacity(sources.len());
+    for source in sources {
+        counts.push((
+            source.name.clone(),
+            ingest_source(pool, source, priority, enrichment, Trigger::Manual).await?,
+        ));
+    }
+
+    update_mesh_summaries(pool).await?;
+    Ok(counts)
+}
+
+/// Enrich and store readings pushed by a client, tagged with `source` and a
+/// new ingest batch, then reconcile duplicates, link alerts and refresh
+/// summaries.
+///
+/// Returns the number of newly inserted rows; readings the source already
+/// stored (same device and timestamp) are skipped, so retried pushes are safe.
+#[tracing::instrument(name = "db.store_pushed", skip_all, fields(source = %source, readings = readings.len()))]
+pub async fn store_pushed(
+    pool: &PgPool,
+    source: &str,
+    readings: &[RawSensorReading],
+    priority: &[String],
+    enrichment: &Enrichment,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let calibrations = load_calibrations(pool).await?;
+    let mut transformed: Vec<_> = readings
+        .iter()
+        .map(|r| r.to_calibrated(calibrations.get(&r.device_id)))
+        .collect();
+    assess_batch(readings, &mut transformed, Utc::now());
+
+    let batch = start_batch(pool, source, Trigger::Push).await?;
+    let mut stats = BatchStats {
+        fetched: readings.len(),
+        ..BatchStats::default()
+    };
+    for (i, mut t) in transformed.into_iter().enumerate() {
+        enrichment.apply(&mut t).await;
+        match store_sensor_reading(pool, source, batch, &t).await {
+            Ok(n) => {
+                stats.rows += n;
+                if (i as u64 + 1).is_multiple_of(PROGRESS_EVERY) {
+                    report_progress(pool, batch, &stats).await;
+                }
+            }
+            Err(e) => {
+                stats.errors += 1;
+                stats.error = Some(e.to_string());
+                finish_batch(pool, batch, &stats).await?;
+                return Err(e);
+            }
+        }
+    }
+    finish_batch(pool, batch, &stats).await?;
+
+    let inserted = stats.rows;
+    if inserted > 0 {
+        reconcile_sources(pool, priority).await?;
+        link_alert_events(pool).await?;
+        update_mesh_summaries(pool).await?;
+    }
+    Ok(inserted)
+}
+
+/// The re-ingest loops started by [`spawn_scheduled_ingest`]; dropping it
+/// stops them, each after the ingest it may be running.
+pub struct ScheduledIngest {
+    // ---
+    _stop: watch::Sender<()>,
+}
+
+/// Spawn a background re-ingest loop for every source with `interval_secs` set.
+///
+/// Each loop waits one interval, ingests the source, and refreshes summaries.
+/// Rows already stored for the source (same device and timestamp) are skipped,
+/// so re-fetching an unchanged upstream is harmless. The loops run until the
+/// returned handle is dropped.
+#[must_use = "dropping the handle stops the loops"]
+pub fn spawn_scheduled_ingest(
+    pool: PgPool,
+    sources: &[SourceConfig],
+    priority: &[String],
+    enrichment: Arc<Enrichment>,
+) -> ScheduledIngest {
+    // ---
+    let (stop, stopped) = watch::channel(());
+    for source in sources {
+        let Some(secs) = source.interval_secs else {
+            continue;
+        };
+        let pool = pool.clone();
+        let source = source.clone();
+        let priority = priority.to_vec();
+        let enrichment = enrichment.clone();
+        let mut stopped = stopped.clone();
+
+        tracing::info!(
+            "Scheduling ingest for source {} every {}s",
+            source.name,
+            secs
+        );
+        tokio::spawn(async move {
+            // ---
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs.max(1)));
+            ticker.tick().await; // first tick completes immediately
+
+            loop {
+                // Only errors, once the handle is dropped
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = stopped.changed() => break,
+                }
+                let ingested =
+                    ingest_source(&pool, &source, &priority, &enrichment, Trigger::Scheduled).await;
+                if let Err(e) = ingested {
+                    tracing::error!("Scheduled ingest for source {} failed: {}", source.name, e);
+                    continue;
+                }
+                if let Err(e) = update_mesh_summaries(&pool).await {
+                    tracing::error!("Summary update after ingest failed: {}", e);
+                }
+            }
+            tracing::info!("Stopped scheduled ingest for source {}", source.name);
+        });
+    }
+    ScheduledIngest { _stop: stop }
+}
+
+/// Ingest every source into `repo` now, then again every `interval_secs` for
+/// those with one, clearing `cache` whenever readings were stored.
+///
+/// This is the ingest of deployments keeping readings outside Postgres (see
+/// `sqlite.rs`): the repository scores and enriches them, but there are no
+/// calibrations, ingest batches, events or source reconciliation. The loops
+/// run for the life of the process.
+pub fn spawn_repository_ingest(
+    repo: Arc<dyn ReadingsRepository>,
+    cache: Arc<ResponseCache>,
+    sources: &[SourceConfig],
+) {
+    // ---
+    for source in sources {
+        let (repo, cache, source) = (repo.clone(), cache.clone(), source.clone());
+        tokio::spawn(async move {
+            // ---
+            loop {
+                match ingest_into(repo.as_ref(), &source).await {
+                    Ok(inserted) => {
+                        tracing::info!(
+                            "Ingested {} new readings from source {}",
+                            inserted,
+                            source.name
+                        );
+                        if inserted > 0 {
+                            cache.clear();
+                        }
+                    }
+                    Err(e) => tracing::error!("Ingest for source {} failed: {}", source.name, e),
+                }
+                let Some(secs) = source.interval_secs else {
+                    break;
+                };
+                tokio::time::sleep(Duration::from_secs(secs.max(1))).await;
+            }
+        });
+    }
+}
+
+/// Fetch one source and store its readings in `repo`, returning how many were new.
+async fn ingest_into(repo: &dyn ReadingsRepository, source: &SourceConfig) -> Result<u64, String> {
+    // ---
+    let fetched = fetch_sensor_data(source, source_for(source).as_ref(), None).await?;
+    repo.insert_batch(&source.name, &fetched.readings)
+        .await
+        .map_err(|e| format!("storing readings failed: {e}"))
+}
+
+/// Fetch, transform, enrich, and store all readings from one source, then
+/// reconcile duplicates and link alerts.
+///
+/// Returns the number of newly inserted rows. Each run is an `ingest_batches`
+/// row that its readings reference, and is recorded as `ingest_started`
+/// followed by `ingest_finished` or `ingest_failed` events.
+#[tracing::instrument(name = "ingest", skip_all, fields(source = %source.name))]
+async fn ingest_source(
+    pool: &PgPool,
+    source: &SourceConfig,
+    priority: &[String],
+    enrichment: &Enrichment,
+    trigger: Trigger,
+) -> Result<u64, IngestError> {
+    // ---
+    let started = Instant::now();
+    let batch = match start_batch(pool, &source.name, trigger).await {
+        Ok(batch) => batch,
+        Err(e) => {
+            let e = IngestError::Internal(format!("opening ingest batch failed: {e}"));
+            record_event(
+                pool,
+                EventKind::IngestFailed,
+                json!({ "source": source.name, "error": e.to_string() }),
+            )
+            .await;
+            return Err(e);
+        }
+    };
+    record_event(
+        pool,
+        EventKind::IngestStarted,
+        json!({ "source": source.name, "batch": batch, "trigger": trigger.as_str() }),
+    )
+    .await;
+
+    let mut stats = BatchStats::default();
+    let fail = |mut stats: BatchStats, e: IngestError| async move {
+        stats.error = Some(e.to_string());
+        if let Err(e) = finish_batch(pool, batch, &stats).await {
+            tracing::error!("Closing ingest batch {} failed: {}", batch, e);
+        }
+        record_event(
+            pool,
+            EventKind::IngestFailed,
+            json!({ "source": source.name, "batch": batch, "error": e.to_string() }),
+        )
+        .await;
+        Err::<u64, _>(e)
+    };
+
+    // Expensive call to ingest data and store in DB
+    let upstream = source_for(source);
+    let fetched = fetch_sensor_data(source, upstream.as_ref(), Some((pool, batch))).await;
+    let raw = match fetched.map_err(IngestError::Upstream) {
+        Ok(fetched) => {
+            stats.pages = fetched.pages;
+            stats.errors = fetched.skipped;
+            fetched.readings
+        }
+        Err(e) => return fail(stats, e).await,
+    };
+    stats.fetched = raw.len();
+
+    let calibrations = match load_calibrations(pool).await {
+        Ok(c) => c,
+        Err(e) => {
+            let e = IngestError::Internal(format!("loading calibrations failed: {e}"));
+            return fail(stats, e).await;
+        }
+    };
+    let mut transformed: Vec<_> = raw
+        .iter()
+        .map(|r| r.to_calibrated(calibrations.get(&r.device_id)))
+        .collect();
+    assess_batch(&raw, &mut transformed, Utc::now());
+
+    for (i, mut t) in transformed.into_iter().enumerate() {
+        enrichment.apply(&mut t).await;
+        match store_sensor_reading(pool, &source.name, batch, &t).await {
+            Ok(n) => stats.rows += n,
+            Err(e) => {
+                tracing::error!("store failed: {e}");
+                stats.errors += 1;
+            }
+        }
+        if (i as u64 + 1).is_multiple_of(PROGRESS_EVERY) {
+            report_progress(pool, batch, &stats).await;
+        }
+    }
+    if let Err(e) = finish_batch(pool, batch, &stats).await {
+        tracing::error!("Closing ingest batch {} failed: {}", batch, e);
+    }
+
+    let inserted = stats.rows;
+    tracing::info!("Source {}: inserted {} new readings", source.name, inserted);
+    if let Err(e) = reconcile_sources(pool, priority).await {
+        tracing::error!("Source reconciliation failed: {}", e);
+    }
+    if let Err(e) = link_alert_events(pool).await {
+        tracing::error!("Linking alert events failed: {}", e);
+    }
+    record_event(
+        pool,
+        EventKind::IngestFinished,
+        json!({
+            "source": source.name,
+            "batch": batch,
+            "fetched": stats.fetched,
+            "inserted": inserted,
+            "duration_ms": started.elapsed().as_millis() as u64,
+        }),
+    )
+    .await;
+    Ok(inserted)
+}
+
+/// What one ingest batch did, recorded in `ingest_batches` as it progresses and
+/// when it finishes.
+#[derive(Debug, Default)]
+struct BatchStats {
+    // ---
+    /// Upstream pages fetched; 0 for pushes.
+    pages: u32,
+
+    /// Readings received.
+    fetched: usize,
+
+    /// Readings newly stored.
+    rows: u64,
+
+    /// Items that couldn't be parsed or stored.
+    errors: u32,
+
+    /// Why the batch failed, if it did.
+    error: Option<String>,
+}
+
+/// Open an `ingest_batches` row for a fetch or push from `source`; returns its ID.
+async fn start_batch(pool: &PgPool, source: &str, trigger: Trigger) -> Result<i64, sqlx::Error> {
+    // ---
+    sqlx::query_scalar("INSERT INTO ingest_batches (source, trigger) VALUES ($1, $2) RETURNING id")
+        .bind(source)
+        .bind(trigger.as_str())
+        .fetch_one(pool)
+        .await
+}
+
+/// Record the counts of the running batch `id` so far. Failures are only
+/// logged; the batch goes on.
+async fn report_progress(pool: &PgPool, id: i64, stats: &BatchStats) {
+    // ---
+    let reported = sqlx::query(
+        r#"
+        UPDATE ingest_batches
+        SET progressed_at = now(), pages = $2, fetched = $3, rows = $4, errors = $5
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(stats.pages as i32)
+    .bind(stats.fetched as i32)
+    .bind(stats.rows as i32)
+    .bind(stats.errors as i32)
+    .execute(pool)
+    .await;
+    if let Err(e) = reported {
+        tracing::warn!("Recording progress of ingest batch {} failed: {}", id, e);
+    }
+}
+
+/// Record the outcome of batch `id`.
+async fn finish_batch(pool: &PgPool, id: i64, stats: &BatchStats) -> Result<(), sqlx::Error> {
+    // ---
+    sqlx::query(
+        r#"
+        UPDATE ingest_batches
+        SET finished_at = now(), progressed_at = now(), pages = $2, fetched = $3, rows = $4, errors = $5, error = $6
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(stats.pages as i32)
+    .bind(stats.fetched as i32)
+    .bind(stats.rows as i32)
+    .bind(stats.errors as i32)
+    .bind(&stats.error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetch all pages from `upstream`, the source `source` configures.
+///
+/// Starts at the first page, follows each page's `next_cursor` until
+/// exhausted or the source's `max_pages` is reached, and returns the
+/// concatenated `RawSensorReading` list with the number of pages fetched and
+/// items skipped. Logs each page at `debug` level and, given `batch` (its pool
+/// and ID), reports it as the batch's progress.
+#[tracing::instrument(name = "upstream.fetch", skip_all, fields(source = %source.name, url = %source.url))]
+async fn fetch_sensor_data(
+    source: &SourceConfig,
+    upstream: &dyn SensorSource,
+    batch: Option<(&PgPool, i64)>,
+) -> Result<Fetched, String> {
+    // ---
+    let max_pages = source.max_pages;
+
+    let mut all_data = Vec::new();
+    let mut cursor: Option<String> = None;
+    let mut page_count = 0;
+    let mut skipped = 0;
+
+    // https://www.postgresql.org/docs/current/queries-limit.html
+    // Above is interesting by we actually use CURSOR-BASED pagination pattern instead,
+    // keep fetching pages until max_pages or no more data
+    loop {
+        // Guardrail: don’t hammer upstream forever.
+        if page_count >= max_pages {
+            tracing::debug!(
+                "Hit page limit of {}, stopping pagination. Fetched {} records so far.",
+                max_pages,
+                all_data.len()
+            );
+            break;
+        }
+        page_count += 1;
+
+        // HTTP sources record their status code on the page span
+        let page_span = tracing::info_span!(
+            "upstream.page",
+            page = page_count,
+            otel.kind = "client",
+            http.response.status_code = tracing::field::Empty,
+        );
+        let page = upstream
+            .fetch_page(cursor.as_deref())
+            .instrument(page_span)
+            .await?;
+        tracing::debug!(
+            "Page {} has {} readings ({} skipped)",
+            page_count,
+            page.readings.len(),
+            page.skipped
+        );
+        all_data.extend(page.readings);
+        skipped += page.skipped;
+
+        // Advance pagination; stop when there is no next cursor.
+        cursor = page.next_cursor;
+
+        tracing::debug!("Page {} next_cursor: {:?}", page_count, cursor);
+        let progress = BatchStats {
+            pages: page_count,
+            fetched: all_data.len(),
+            errors: skipped,
+            ..BatchStats::default()
+        };
+        if let Some((pool, batch)) = batch {
+            report_progress(pool, batch, &progress).await;
+        }
+
+        if cursor.is_none() {
+            tracing::info!(
+                "No more pages, stopping. Total records fetched: {}",
+                all_data.len()
+            );
+            break;
+        }
+    }
+
+    tracing::info!(
+        "Finished fetching {} total records from {} pages of source {}",
+        all_data.len(),
+        page_count,
+        source.name
+    );
+    Ok(Fetched {
+        readings: all_data,
+        pages: page_count,
+        skipped,
+    })
+}
+
+/// Result of [`fetch_sensor_data`].
+struct Fetched {
+    // ---
+    readings: Vec<RawSensorReading>,
+    pages: u32,
+
+    /// Items that failed to parse.
+    skipped: u32,
+}
+
+/// Calibration offsets of every device that has one, by device ID.
+async fn load_calibrations(pool: &PgPool) -> Result<HashMap<String, Calibration>, sqlx::Error> {
+    // ---
+    let rows: Vec<(String, f32, f32)> = sqlx::query_as(
+        "SELECT device_id, temperature_offset, humidity_offset FROM device_calibration",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(device_id, temperature_offset, humidity_offset)| {
+            let calibration = Calibration {
+                temperature_offset,
+                humidity_offset,
+            };
+            (device_id, calibration)
+        })
+        .collect())
+}
+
+/// Insert one normalized reading into `sensor_data`, tagged with `source` and
+/// ingest batch `batch`.
+///
+/// - Uses a parameterized `INSERT ... SELECT ... WHERE NOT EXISTS`
+/// - No string interpolation → safe from SQL injection; `sqlx` handles quoting & types.
+/// - Skips the row if `source` already stored a reading for the same device and
+///   timestamp, so scheduled re-ingests don't duplicate data.
+/// - Returns the number of rows inserted (0 or 1).
+async fn store_sensor_reading(
+    pool: &PgPool,
+    source: &str,
+    batch: i64,
+    reading: &SensorReading,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let result = sqlx::query(
+        r#"
+        INSERT INTO sensor_data (
+            source, mesh_id, device_id, timestamp_utc,
+            temperature_c, humidity, status,
+            temperature_alert, humidity_alert, attributes,
+            latitude, longitude, extra, raw_temperature_c, raw_humidity,
+            quality, quality_flags, ingest_batch_id
+        )
+        SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
+        WHERE NOT EXISTS (
+            SELECT 1 FROM sensor_data
+            WHERE source = $1 AND device_id = $3 AND timestamp_utc = $4
+        )
+        "#,
+    )
+    .bind(source)
+    .bind(&reading.mesh_id)
+    .bind(&reading.device_id)
+    .bind(reading.timestamp_utc)
+    .bind(reading.temperature_c)
+    .bind(reading.humidity)
+    .bind(&reading.status)
+    .bind(reading.temperature_alert)
+    .bind(reading.humidity_alert)
+    .bind(sqlx::types::Json(&reading.attributes))
+    .bind(reading.latitude)
+    .bind(reading.longitude)
+    .bind(sqlx::types::Json(&reading.extra))
+    .bind(reading.raw_temperature_c)
+    .bind(reading.raw_humidity)
+    .bind(reading.quality)
+    .bind(&reading.quality_flags)
+    .bind(batch)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Mark all but one copy of each reading stored by several sources as a
+/// duplicate, and record the others in `source_conflicts`.
+///
+/// Copies share a device and timestamp. The preferred copy comes from the
+/// source matching the earliest `priority` entry (an exact name, or a prefix
+/// ending in `*` such as `push:*`); unlisted sources rank last, and ties go to
+/// the copy stored first, and soft-deleted copies only take preference when
+/// no live one is left. Other copies get `duplicate_of` set to the preferred
+/// row's ID, so they are left out of summaries and alert events, and get a
+/// `source_conflicts` row noting whether their values differ.
+///
+/// Re-run after every ingest, deletion and restore, so a copy arriving from a
+/// higher-priority source takes over from one stored earlier and a deleted
+/// preferred copy hands over to a live one. Returns the number of readings whose
+/// duplicate status changed.
+#[tracing::instrument(name = "db.reconcile_sources", skip_all)]
+pub async fn reconcile_sources(pool: &PgPool, priority: &[String]) -> Result<u64, sqlx::Error> {
+    // ---
+    let patterns: Vec<String> = priority.iter().map(|p| like_pattern(p)).collect();
+    let mut tx = pool.begin().await?;
+
+    let changed = sqlx::query(
+        r#"
+        WITH copies AS (
+            SELECT device_id, timestamp_utc
+            FROM sensor_data
+            GROUP BY device_id, timestamp_utc
+            HAVING COUNT(*) > 1
+        ),
+        ranked AS (
+            SELECT s.id,
+                   FIRST_VALUE(s.id) OVER (
+                       PARTITION BY s.device_id, s.timestamp_utc
+                       ORDER BY s.deleted_at IS NOT NULL, r.rank NULLS LAST, s.id
+                   ) AS preferred_id
+            FROM sensor_data s
+            JOIN copies c ON c.device_id = s.device_id AND c.timestamp_utc = s.timestamp_utc
+            LEFT JOIN LATERAL (
+                SELECT MIN(p.ord) AS rank
+                FROM unnest($1::text[]) WITH ORDINALITY AS p (pattern, ord)
+                WHERE s.source LIKE p.pattern
+            ) r ON true
+        )
+        UPDATE sensor_data s
+        SET duplicate_of = NULLIF(r.preferred_id, s.id)
+        FROM ranked r
+        WHERE s.id = r.id
+          AND s.duplicate_of IS DISTINCT FROM NULLIF(r.preferred_id, s.id)
+        "#,
+    )
+    .bind(&patterns)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if changed > 0 {
+        sqlx::query(
+            r#"
+            INSERT INTO source_conflicts (
+                reading_id, preferred_id, device_id, timestamp_utc,
+                source, preferred_source, values_differ
+            )
+            SELECT d.id, p.id, d.device_id, d.timestamp_utc, d.source, p.source,
+                   (d.temperature_c, d.humidity, d.status)
+                       IS DISTINCT FROM (p.temperature_c, p.humidity, p.status)
+            FROM sensor_data d
+            JOIN sensor_data p ON p.id = d.duplicate_of
+            ON CONFLICT (reading_id) DO UPDATE SET
+                preferred_id     = EXCLUDED.preferred_id,
+                preferred_source = EXCLUDED.preferred_source,
+                values_differ    = EXCLUDED.values_differ,
+                detected_at      = now()
+            WHERE source_conflicts.preferred_id <> EXCLUDED.preferred_id
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // A copy that became preferred is no longer in conflict
+        sqlx::query(
+            r#"
+            DELETE FROM source_conflicts c
+            USING sensor_data s
+            WHERE s.id = c.reading_id AND s.duplicate_of IS NULL
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Alerts already linked to a copy that lost preference
+        sqlx::query(
+            r#"
+            DELETE FROM alert_events e
+            USING sensor_data s
+            WHERE s.id = e.reading_id AND s.duplicate_of IS NOT NULL
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tracing::info!("Reconciled {} readings stored by several sources", changed);
+    }
+
+    tx.commit().await?;
+    Ok(changed)
+}
+
+/// `LIKE` pattern for a `SOURCE_PRIORITY` entry: literal, except a trailing `*`.
+fn like_pattern(entry: &str) -> String {
+    // ---
+    let (literal, wildcard) = match entry.strip_suffix('*') {
+        Some(prefix) => (prefix, "%"),
+        None => (entry, ""),
+    };
+    let mut pattern = String::with_capacity(entry.len() + 1);
+    for c in literal.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push_str(wildcard);
+    pattern
+}
+
+/// Create an `alert_events` row for every alert flag on a stored reading that
+/// doesn't have one yet; returns the number created.
+///
+/// Duplicate copies of a reading (see [`reconcile_sources`]), and readings
+/// marked invalid, are skipped.
+///
+/// Runs after each ingest, and once at startup to backfill readings stored
+/// before alert events existed.
+#[tracing::instrument(name = "db.link_alert_events", skip_all)]
+pub async fn link_alert_events(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    // ---
+    let result = sqlx::query(
+        r#"
+        INSERT INTO alert_events (reading_id, kind, device_id, mesh_id, occurred_at)
+        SELECT s.id, k.kind, s.device_id, s.mesh_id, s.timestamp_utc
+        FROM sensor_data s
+        CROSS JOIN LATERAL (
+            VALUES ('temperature', s.temperature_alert), ('humidity', s.humidity_alert)
+        ) AS k (kind, flagged)
+        WHERE k.flagged AND s.duplicate_of IS NULL AND s.deleted_at IS NULL AND NOT s.invalid
+        ON CONFLICT (reading_id, kind) DO NOTHING
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Bring `mesh_summary` up to date with the readings stored so far.
+///
+/// Each reading counts toward the mesh its device was assigned to at the
+/// reading's timestamp (`device_mesh_assignments`), falling back to the
+/// `mesh_id` the device reported when no assignment applies. Duplicate copies
+/// of a reading stored by several sources (see [`reconcile_sources`]) are not
+/// counted.
+///
+/// Recomputes only the hours marked since the last refresh (see
+/// `rollups.rs`) and adjusts the per-mesh running totals behind
+/// `mesh_summary` by their difference, so the cost follows the newly stored
+/// readings rather than the size of `sensor_data`. Meshes whose aggregates
+/// changed are announced on [`SUMMARY_CHANNEL`](crate::SUMMARY_CHANNEL) for
+/// live aggregate streams.
+#[tracing::instrument(name = "db.update_mesh_summaries", skip_all)]
+pub async fn update_mesh_summaries(pool: &PgPool) -> Result<(), sqlx::Error> {
+    // ---
+    refresh_rollups(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use crate::SourcePage;
+    use futures_util::future::BoxFuture;
+
+    /// Serves a reading per device ID in `pages`, each cursor the index of
+    /// the next page.
+    struct FakeSource {
+        pages: Vec<Vec<&'static str>>,
+    }
+
+    impl SensorSource for FakeSource {
+        // ---
+        fn fetch_page<'a>(
+            &'a self,
+            cursor: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<SourcePage, String>> {
+            // ---
+            let at: usize = cursor.map_or(0, |c| c.parse().unwrap());
+            Box::pin(async move {
+                let readings = self.pages.get(at).ok_or("no such page")?;
+                Ok(SourcePage {
+                    readings: readings.iter().map(|id| raw(id)).collect(),
+                    skipped: 1,
+                    next_cursor: (at + 1 < self.pages.len()).then(|| (at + 1).to_string()),
+                })
+            })
+        }
+    }
+
+    fn raw(device_id: &str) -> RawSensorReading {
+        // ---
+        RawSensorReading {
+            mesh_id: "mesh-001".into(),
+            device_id: device_id.into(),
+            timestamp: Utc::now(),
+            temperature_c: 21.0,
+            humidity: 40.0,
+            status: "ok".into(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_follow_cursors_up_to_max_pages() {
+        // ---
+        let upstream = FakeSource {
+            pages: vec![vec!["a", "b"], vec!["c"], vec!["d"]],
+        };
+        let mut source = SourceConfig {
+            name: "fake".into(),
+            url: "fake://".into(),
+            max_pages: 10,
+            api_key: None,
+            token: None,
+            auth_header: None,
+            interval_secs: None,
+        };
+
+        let fetched = fetch_sensor_data(&source, &upstream, None).await.unwrap();
+        assert_eq!((fetched.readings.len(), fetched.pages), (4, 3));
+        assert_eq!(fetched.skipped, 3);
+
+        source.max_pages = 2;
+        let fetched = fetch_sensor_data(&source, &upstream, None).await.unwrap();
+        let devices: Vec<_> = fetched
+            .readings
+            .iter()
+            .map(|r| r.device_id.as_str())
+            .collect();
+        assert_eq!(devices, ["a", "b", "c"]);
+        assert_eq!(fetched.pages, 2);
+    }
+
+    #[test]
+    fn priority_entries_become_like_patterns() {
+        // ---
+        assert_eq!(like_pattern("default"), "default");
+        assert_eq!(like_pattern("push:*"), "push:%");
+        assert_eq!(like_pattern("site_a%"), "site\\_a\\%");
+        assert_eq!(like_pattern("*"), "%");
+    }
+}
@@ -0,0 +1,191 @@
+//! Rolling log files for deployments without a log collector.
+//!
+//! With `LOG_DIR` set, `main.rs` writes every log line to a file in that
+//! directory as well as to stdout, in the same format (`LOG_FORMAT`), never
+//! colored. Files rotate per `LOG_ROTATION`:
+//! - `daily` (default), `hourly`: a new `<prefix>.<date>.log` per period
+//! - `size`: `<prefix>.log` is renamed to `<prefix>.log.1` (shifting older
+//!   files up) once it reaches `LOG_MAX_SIZE_MB`
+//! - `never`: a single `<prefix>.log`
+//!
+//! At most `LOG_MAX_FILES` files (including the current one) are kept. Writes
+//! happen on a background thread, so a slow disk never blocks request handling;
+//! the returned [`WorkerGuard`] flushes pending lines when dropped at exit.
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use tracing_appender::{
+    non_blocking::{NonBlocking, WorkerGuard},
+    rolling::{RollingFileAppender, Rotation},
+};
+
+/// Default `LOG_MAX_SIZE_MB` for size-based rotation.
+const DEFAULT_MAX_SIZE_MB: u64 = 100;
+
+/// Default `LOG_MAX_FILES`.
+const DEFAULT_MAX_FILES: usize = 7;
+
+// ---
+
+/// Open the log file writer when `LOG_DIR` is set; `None` otherwise.
+pub fn from_env() -> Result<Option<(NonBlocking, WorkerGuard)>> {
+    // ---
+    let Ok(dir) = env::var("LOG_DIR") else {
+        return Ok(None);
+    };
+    let prefix = env::var("LOG_FILE_PREFIX").unwrap_or_else(|_| env!("CARGO_PKG_NAME").into());
+    let max_files = match env::var("LOG_MAX_FILES") {
+        Ok(v) => v
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("Invalid LOG_MAX_FILES: {v:?}"))?,
+        Err(_) => DEFAULT_MAX_FILES,
+    };
+    fs::create_dir_all(&dir).map_err(|e| anyhow!("Failed to create LOG_DIR {dir}: {e}"))?;
+
+    let rotation = env::var("LOG_ROTATION").unwrap_or_else(|_| "daily".into());
+    let writer = match rotation.to_ascii_lowercase().as_str() {
+        "size" => {
+            let max_mb = match env::var("LOG_MAX_SIZE_MB") {
+                Ok(v) => v
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow!("Invalid LOG_MAX_SIZE_MB: {v:?}"))?,
+                Err(_) => DEFAULT_MAX_SIZE_MB,
+            };
+            let path = PathBuf::from(&dir).join(format!("{prefix}.log"));
+            let file = SizeRotatingFile::open(path, max_mb * 1024 * 1024, max_files)?;
+            tracing_appender::non_blocking(file)
+        }
+        period => {
+            let rotation = match period {
+                "daily" => Rotation::DAILY,
+                "hourly" => Rotation::HOURLY,
+                "never" => Rotation::NEVER,
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid LOG_ROTATION {rotation:?} (expected daily, hourly, size or never)"
+                    ))
+                }
+            };
+            let appender = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(&prefix)
+                .filename_suffix("log")
+                .max_log_files(max_files)
+                .build(&dir)
+                .map_err(|e| anyhow!("Failed to open log file in {dir}: {e}"))?;
+            tracing_appender::non_blocking(appender)
+        }
+    };
+    Ok(Some(writer))
+}
+
+/// Append-only file that rotates itself once it reaches `max_bytes`.
+struct SizeRotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl SizeRotatingFile {
+    // ---
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> Result<Self> {
+        // ---
+        let file = append(&path).map_err(|e| anyhow!("Failed to open {}: {e}", path.display()))?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    /// Shift `log.N` to `log.N+1` (dropping the oldest) and start a fresh file.
+    fn rotate(&mut self) -> io::Result<()> {
+        // ---
+        self.file.flush()?;
+        let numbered = |n: usize| {
+            let mut p = self.path.clone().into_os_string();
+            p.push(format!(".{n}"));
+            PathBuf::from(p)
+        };
+        // `max_files` counts the live file too
+        let keep = self.max_files - 1;
+        if keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(numbered(keep));
+            for n in (1..keep).rev() {
+                let _ = fs::rename(numbered(n), numbered(n + 1));
+            }
+            fs::rename(&self.path, numbered(1))?;
+        }
+        self.file = append(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    // ---
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // ---
+        // Rotate between lines: each call carries one whole formatted event
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // ---
+        self.file.flush()
+    }
+}
+
+fn append(path: &PathBuf) -> io::Result<File> {
+    // ---
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn size_rotation_keeps_newest_files() {
+        // ---
+        let dir = env::temp_dir().join(format!("sensorflow-logs-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut file = SizeRotatingFile::open(path.clone(), 10, 3).unwrap();
+        for line in ["line-one\n", "line-two\n", "line-three\n", "line-four\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("app.log"), "line-four\n");
+        assert_eq!(read("app.log.1"), "line-three\n");
+        assert_eq!(read("app.log.2"), "line-two\n");
+        assert!(!dir.join("app.log.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,85 +1,665 @@
 //! Application entry point for the `sensorflow-data-pipeline` backend service.
 //!
-//! This binary orchestrates the full startup sequence for the sensor data
+//! The command line (see `cli.rs`) picks what the binary does: `serve` (the
+//! default) runs the API; `migrate`, `ingest --once`, `import` and `purge` run
+//! one operational task against the configured database and exit. Global
+//! options override environment variables for the run.
+//!
+//! Serving orchestrates the full startup sequence for the sensor data
 //! pipeline API, including:
-//! - Loading configuration from environment variables or `.env`
+//! - Loading configuration from environment variables, `.env`, or a
+//!   configuration file (see `config_file.rs`)
 //! - Initializing structured logging/tracing
-//! - Establishing a PostgreSQL connection pool
+//! - Establishing a PostgreSQL connection pool, retrying while the database starts
 //! - Creating the database schema if it does not exist
+//! - Maintaining monthly `sensor_data` partitions when `PARTITION_BY_MONTH` is set
+//! - Recording a `startup` event in the lifecycle log
+//! - Scheduling periodic ingest for sources with an interval configured
+//! - Starting the initial ingest in the background when `READY_REQUIRES_DATA` is set
+//! - Reloading sources, rate limit and log level on `SIGHUP` (see `reload.rs`)
 //! - Mounting all API routes via the `routes` gateway (EMBP pattern)
-//! - Binding the Axum HTTP server and serving requests
+//! - Binding the Axum HTTP server (or HTTPS, when TLS is configured) and serving requests
 //!
 //! # Environment Variables
-//! - `DATABASE_URL` (**required**) – PostgreSQL connection string
+//! - `CONFIG_FILE` (optional) – TOML, YAML or JSON file of settings beneath the
+//!   environment, also `--config`; see `config_file.rs`
+//! - `<NAME>_FILE` (optional) – read setting `<NAME>` from a file, e.g. a mounted
+//!   secret; see [`config::load_from_env`]
+//! - `VAULT_ADDR`, `VAULT_TOKEN` (optional) – resolve `vault:<path>#<field>`
+//!   settings from HashiCorp Vault, see `secrets.rs`
+//! - `DATABASE_URL` (**required**) – PostgreSQL connection string, or a
+//!   `sqlite:` or `mysql:` URL for the reduced edge deployment, see `sqlite.rs`
+//!   and `mysql.rs`
+//! - `DATABASE_USER`, `DATABASE_PASSWORD` (optional) – credentials replacing those
+//!   in `DATABASE_URL`
+//! - `SENSOR_API_URL` or `SENSOR_API_<N>_URL` (**required**) – upstream source(s),
+//!   see [`config::load_from_env`]
 //! - `DB_POOL_MAX` (optional) – maximum number of DB connections (default: 5)
-//! - `AXUM_LOG_LEVEL` (optional) – log verbosity (default: `debug`)
+//! - `DATABASE_REPLICA_URL` (optional) – read replica for read-only queries, see `db.rs`
+//! - `DB_CONNECT_RETRIES`, `DB_CONNECT_BACKOFF_MS` (optional) – startup connection
+//!   retry, see `db.rs`
+//! - `DB_ACQUIRE_TIMEOUT_MS`, `DB_STATEMENT_TIMEOUT_MS` (optional) – query timeouts,
+//!   answered with 503, see `db.rs`
+//! - `DB_POOL_MIN`, `DB_IDLE_TIMEOUT_SECS`, `DB_MAX_LIFETIME_SECS` (optional) – pool
+//!   sizing and connection recycling, see `db.rs`
+//! - `ROLLUP_REFRESH_SECS` (optional) – hourly/daily rollup refresh interval, see `rollups.rs`
+//! - `POOL_SAMPLE_SECS` (optional) – pool statistics interval, see `pool_stats.rs`
+//! - `APP_ENV` (optional) – `dev`, `staging` or `prod` defaults for log level, pool
+//!   size and ingest interval, see [`config::AppEnv`]
+//! - `AXUM_LOG_LEVEL` (optional) – log verbosity (default: `debug`, or per `APP_ENV`)
 //! - `AXUM_SPAN_EVENTS` (optional) – span event mode for tracing
+//! - `WORKER_THREADS`, `BLOCKING_THREADS` (optional) – Tokio runtime sizing, e.g.
+//!   on small edge machines (default: one worker per CPU, 512 blocking)
+//! - `LOG_FORMAT` (optional) – `json` for one JSON object per log line (default: compact)
+//! - `LOG_DIR` (optional) – also write logs to rotating files there, see `log_file.rs`
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT` (optional) – export traces over OTLP/HTTP
+//! - `SENTRY_DSN` (optional) – report panics and 5xx responses, see `error_report.rs`
+//! - `SLOW_QUERY_MS` (optional) – log and count slower queries, see `slow_query.rs`
+//! - `JWT_*` (optional) – bearer-token authentication, see [`config::load_from_env`]
+//! - `READY_REQUIRES_DATA` (optional) – hold `/ready` at 503 until data is stored
+//! - `DEMO_MODE` (optional) – read-only public demo on synthetic data, see `demo.rs`
+//! - `SCHEMA_INDEXES_CONCURRENTLY` (optional) – build `sensor_data` indexes without
+//!   blocking writes, see `schema.rs`
+//! - `PARTITION_BY_MONTH`, `RETENTION_MONTHS` (optional) – monthly partitions of
+//!   `sensor_data`, dropped once expired, see `partitions.rs`
+//! - `RATE_LIMIT_PER_SEC`, `RATE_LIMIT_BURST` (optional) – per-client rate limiting
+//! - `CORS_ALLOWED_ORIGINS` (optional) – enables CORS for browser clients
+//! - `TLS_CERT_PATH`, `TLS_KEY_PATH` (optional) – serve HTTPS; `SIGHUP` reloads the certificate
+//! - `TLS_CLIENT_CA_PATH`, `CLIENT_CERT_<N>_*` (optional) – mutual TLS for device gateways
 //!
 //! This module follows the Explicit Module Boundary Pattern (EMBP) by
 //! delegating schema setup to `schema`, configuration parsing to `config`,
 //! and route registration to `routes`.
-use std::{env, io::IsTerminal, net::SocketAddr};
+use std::{env, io::IsTerminal, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use axum::Router;
 use dotenvy::dotenv;
-use sqlx::postgres::PgPoolOptions;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::filter::EnvFilter;
 use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::{
+    fmt::{writer::MakeWriterExt as _, MakeWriter},
+    layer::SubscriberExt as _,
+    registry::LookupSpan,
+    util::SubscriberInitExt as _,
+    Layer,
+};
 
 use anyhow::Result;
 
+use clap::Parser;
+use cli::{Cli, Command};
+use config_file::Layers;
+use reload::LogFilter;
+use secrets::Secrets;
+
+mod auth;
+mod cli;
 mod config;
+mod config_file;
+mod cursor;
+mod db;
+mod demo;
+mod deprecation;
+mod duration;
+mod enrich;
+mod error;
+mod error_report;
+mod events;
+mod export;
+mod extract;
+mod field_crypto;
+mod idempotency;
+mod index_advisor;
+mod ingest;
+mod log_file;
+mod maintenance;
 mod models;
+mod mysql;
+mod partitions;
+mod pool_stats;
+mod prometheus;
+mod purge;
+mod quality;
+mod rate_limit;
+mod reload;
+mod repository;
+mod request_id;
+mod response_cache;
+mod rollups;
 mod routes;
 mod schema;
+mod secrets;
+mod slow_query;
+mod smoothing;
+mod sqlite;
+mod state;
+mod summary_feed;
+mod tls;
+mod units;
+mod upstream;
+mod warmup;
 
-pub use config::Config;
+pub use auth::{authenticate, mesh_forbidden, require_role, Authenticator, Principal, Role};
+pub use config::{
+    ApiKeyConfig, AppEnv, ClientCertConfig, Config, CorsConfig, EncryptionConfig, EnricherConfig,
+    JwtConfig, PartitionConfig, RateLimitConfig, SourceConfig, TlsConfig, DEFAULT_LIMIT,
+};
+pub use cursor::{CursorError, ReadingsCursor};
+pub use db::{db_error_response, ReadPool};
+pub use demo::{demo_guard, demo_watermark, DEMO_HEADER, DEMO_RATE_LIMIT};
+pub use deprecation::{date, deprecated, Deprecated, DeprecationPolicy, DeprecationWarnings};
+pub use duration::parse_duration;
+pub use enrich::{Attributes, EnrichOptions, Enricher, Enrichment, HttpLookup};
+pub use error::ApiError;
+pub use error_report::report_errors;
+pub use events::{record_event, EventKind};
+pub use export::{csv_record, write_parquet, Column, ExportFormat};
+pub use extract::{query_error, Json, Path, Query};
+pub use field_crypto::{FieldCipher, REGISTRY_COLUMNS};
+pub use idempotency::{idempotency, IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER};
+pub use index_advisor::{advise, create_index, AdvisorError, FilterStats};
 
 // These are not used here but they are imported to be used by routes/*.rs, that way
 // refactoring is eaiser since router/*.rs do not have knowledge of config.rs, only
 // of their parent module (main.rs)
-pub use models::{RawSensorReading, SensorReading};
+pub use ingest::{
+    ensure_data_loaded, ingest_all, link_alert_events, reconcile_sources, sources_without_data,
+    spawn_repository_ingest, spawn_scheduled_ingest, store_pushed, update_mesh_summaries,
+    IngestError, ScheduledIngest,
+};
+pub use maintenance::{maintenance_guard, Maintenance, MaintenanceWindow};
+pub use models::{
+    valid_position, Calibration, DeviceInfo, RawSensorReading, SensorReading, Smoothed,
+};
+pub use mysql::{is_mysql_url, MySqlReadings};
+pub use partitions::{create_partitioned_table, is_partitioned, list_partitions};
+pub use pool_stats::PoolMonitor;
+pub use prometheus::{
+    POOL_ACQUIRE_SECONDS, POOL_CONNECTIONS, POOL_IDLE, POOL_MAX_CONNECTIONS, POOL_SATURATED,
+    REPLICA_HEALTHY, RESPONSE_CACHE_TOTAL, SLOW_QUERIES_TOTAL,
+};
+pub use purge::purge_deleted;
+pub use quality::assess_batch;
+pub use rate_limit::{rate_limit, RateLimiter};
+pub use reload::{LiveConfig, Reloadable, Reloader};
+pub use repository::{
+    last_ingested, load_devices, load_reading, AlertFilter, BoundingBox, PgReadings, ReadingsCount,
+    ReadingsFilter, ReadingsPage, ReadingsRepository, Sort,
+};
+pub use request_id::{request_id, RequestId, REQUEST_ID_HEADER};
+pub use response_cache::{ResponseCache, READINGS_CHANNEL};
+pub use rollups::{
+    expire_rollups_before, mark_device_dirty, refresh_rollups, Rollup, BUCKET_ORIGIN,
+};
+pub use schema::create_index_concurrently;
+pub use slow_query::{filter_shape, timed};
+pub use smoothing::{ewma_alpha, smooth_readings, Ewma};
+pub use sqlite::{is_sqlite_url, SqliteReadings};
+pub use state::{AppState, EdgeState};
+pub use summary_feed::{
+    load_aggregates, notify_payload, MeshAggregate, SummaryFeed, SummaryUpdate, SUMMARY_CHANNEL,
+};
+pub use tls::PeerCertificate;
+pub use units::UnitSystem;
+pub use upstream::{source_for, HttpSource, SensorSource, SourcePage};
+pub use warmup::{warming_up, Warmup, WarmupStatus};
+
+/// Readings stored per batch by `import`.
+const IMPORT_BATCH: usize = 1000;
 
 // ---
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // ---
+    // Prints help, the version or a usage error and exits as asked
+    let cli = Cli::parse();
+
+    // Before .env, which never overrides a variable already set; and before
+    // the runtime starts any thread
+    for (key, value) in &cli.env() {
+        env::set_var(key, value);
+    }
+    // What a reload must keep; everything else came from the files
+    let layers = Layers::pin_environment();
+    dotenv().ok();
+    // Last, filling in only what neither set
+    let config_file = match env::var_os("CONFIG_FILE").map(PathBuf::from) {
+        Some(path) => {
+            let set = config_file::apply(&path)?;
+            Some((path, set))
+        }
+        None => None,
+    };
+
+    runtime()?.block_on(run(cli.command(), layers, config_file))
+}
+
+/// The Tokio runtime, sized by `WORKER_THREADS` (default: one per CPU, or
+/// `TOKIO_WORKER_THREADS`) and `BLOCKING_THREADS` (default: 512), read here
+/// rather than in `config` because the runtime exists before it loads.
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    // ---
+    let threads = |var: &str| -> Result<Option<usize>> {
+        // ---
+        match env::var(var) {
+            Err(_) => Ok(None),
+            Ok(v) => match v.trim().parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid {var}: expected a thread count > 0, got {v:?}"
+                )),
+            },
+        }
+    };
+
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(n) = threads("WORKER_THREADS")? {
+        builder.worker_threads(n);
+    }
+    if let Some(n) = threads("BLOCKING_THREADS")? {
+        builder.max_blocking_threads(n);
+    }
+    Ok(builder.enable_all().build()?)
+}
+
+/// Load the configuration and run `command`; `config_file` is the file
+/// applied, if any, and how many settings came from it.
+async fn run(
+    command: Command,
+    layers: Layers,
+    config_file: Option<(PathBuf, usize)>,
+) -> Result<()> {
     // ---
-    init_tracing();
+    let telemetry = init_tracing()?;
 
     tracing::info!(
-        "{} v{} - {}",
+        "{} v{} ({}) - {}",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION"),
+        env!("BUILD_GIT_SHA"),
         env!("CARGO_PKG_DESCRIPTION")
     );
+    tracing::info!(
+        "Runtime: {} worker thread(s)",
+        tokio::runtime::Handle::current().metrics().num_workers()
+    );
+    if let Some((path, set)) = config_file {
+        tracing::info!("{set} setting(s) from config file {}", path.display());
+    }
 
-    dotenv().ok();
-
-    let cfg = config::load_from_env()?;
+    let secrets = Secrets::from_env()?;
+    let (cfg, lease) = config::load_from_env(&secrets).await?;
     cfg.log_config();
+    if let Some(ms) = cfg.slow_query_ms {
+        slow_query::set_threshold(std::time::Duration::from_millis(ms));
+    }
 
-    tracing::info!("Attempting to connect to database: {}", cfg.db_url);
-
-    let pool = PgPoolOptions::new()
-        .max_connections(cfg.db_pool_max)
-        .connect(&cfg.db_url)
+    let result = match command {
+        Command::Serve if outside_postgres(&cfg) => serve_edge(cfg).await,
+        Command::Serve => serve(cfg, layers, telemetry.log_filter.clone(), secrets, lease).await,
+        // Nothing is stored, so no enrichers are needed
+        Command::Migrate if outside_postgres(&cfg) => open_repository(
+            &cfg,
+            Arc::new(Enrichment::default()),
+            SummaryFeed::default(),
+        )
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to connect to database '{}': {}", cfg.db_url, e))?;
+        .map(|_| println!("Schema is up to date")),
+        Command::Migrate => open_database(&cfg).await.map(|_| {
+            println!("Schema is up to date");
+        }),
+        Command::Ingest { .. } | Command::Purge { .. } if outside_postgres(&cfg) => {
+            Err(anyhow::anyhow!(
+                "this command needs PostgreSQL; SQLite and MySQL deployments ingest while serving"
+            ))
+        }
+        Command::Ingest { sources, .. } => ingest_once(&cfg, &sources).await,
+        Command::Import { path, source } => import(&cfg, &path, source).await,
+        Command::Purge { deleted_before } => purge(&cfg, deleted_before).await,
+    };
+
+    telemetry.shutdown();
+    result
+}
+
+/// Whether `DATABASE_URL` keeps readings in SQLite or MySQL rather than Postgres.
+fn outside_postgres(cfg: &Config) -> bool {
+    // ---
+    is_sqlite_url(&cfg.db_url) || is_mysql_url(&cfg.db_url)
+}
+
+/// The repository on a SQLite or MySQL `DATABASE_URL` (see
+/// [`outside_postgres`]), with its schema brought up to date, publishing the
+/// meshes it writes to `summaries`.
+async fn open_repository(
+    cfg: &Config,
+    enrichment: Arc<Enrichment>,
+    summaries: SummaryFeed,
+) -> Result<Arc<dyn ReadingsRepository>> {
+    // ---
+    tracing::info!("Attempting to connect to database: {}", cfg.masked_db_url());
+    let repo: Arc<dyn ReadingsRepository> = if is_sqlite_url(&cfg.db_url) {
+        let pool = sqlite::open(&cfg.db_url, cfg.db_pool_max).await?;
+        Arc::new(SqliteReadings::new(pool, enrichment, summaries))
+    } else {
+        let pool = mysql::open(&cfg.db_url, cfg.db_pool_max)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to '{}': {e}", cfg.masked_db_url()))?;
+        Arc::new(MySqlReadings::new(pool, enrichment, summaries))
+    };
+    tracing::info!("Successfully connected to database");
+    Ok(repo)
+}
+
+/// Connect to the database and bring its schema up to date.
+async fn open_database(cfg: &Config) -> Result<sqlx::PgPool> {
+    // ---
+    tracing::info!("Attempting to connect to database: {}", cfg.masked_db_url());
+
+    let pool = db::connect(cfg).await?;
 
     tracing::info!("Successfully connected to database");
 
-    schema::create_schema(&pool).await?;
+    schema::create_schema(&pool, cfg).await?;
+    Ok(pool)
+}
+
+/// `serve`: run the API server until it fails; `layers` and `log_filter` are
+/// what configuration reloads need.
+async fn serve(
+    cfg: Config,
+    layers: Layers,
+    log_filter: LogFilter,
+    secrets: Secrets,
+    lease: Option<std::time::Duration>,
+) -> Result<()> {
+    // ---
+    let metrics = prometheus::install()?;
+    let pool = open_database(&cfg).await?;
+    let replica = db::connect_replica(&cfg)?;
+
+    // Partitions for this month on are in place before anything is ingested
+    if let Some(partitioning) = &cfg.partitioning {
+        if let Err(e) = partitions::maintain(&pool, partitioning).await {
+            tracing::warn!("Partition maintenance failed: {}", e);
+        }
+        partitions::spawn_maintenance(pool.clone(), partitioning.clone());
+    }
+
+    // Apply the current SOURCE_PRIORITY to readings already stored
+    match ingest::reconcile_sources(&pool, &cfg.source_priority).await {
+        Ok(0) => {}
+        Ok(_) => {
+            if let Err(e) = update_mesh_summaries(&pool).await {
+                tracing::warn!("Summary update after reconciliation failed: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Source reconciliation failed: {}", e),
+    }
+
+    // Backfill alert links for readings stored before alert events existed
+    match ingest::link_alert_events(&pool).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("Linked {} alert events to existing readings", n),
+        Err(e) => tracing::warn!("Alert event backfill failed: {}", e),
+    }
+
+    let enrichment = Arc::new(Enrichment::from_config(
+        &cfg.enrichers,
+        cfg.encryption.as_ref(),
+    )?);
+    if cfg.demo_mode {
+        match demo::seed(&pool, &cfg.source_priority, &enrichment).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Demo mode: stored {} synthetic readings", n),
+            Err(e) => tracing::error!("Demo mode: storing synthetic readings failed: {}", e),
+        }
+    }
+    let scheduled = spawn_scheduled_ingest(
+        pool.clone(),
+        &cfg.sources,
+        &cfg.source_priority,
+        enrichment.clone(),
+    );
+
+    // Warm up before /ready reports success rather than on the first request
+    let warmup = Warmup::default();
+    if cfg.ready_requires_data {
+        warmup.start(&pool, &cfg.sources, &cfg.source_priority, &enrichment);
+    }
+
+    let auth = Arc::new(Authenticator::from_config(&cfg)?);
+    let limiter = Arc::new(RateLimiter::new(&cfg.api_keys));
+
+    // Sources, rate limit and log level, replaced on SIGHUP or POST /admin/reload
+    let live = LiveConfig::new(&cfg);
+    let reloader = Arc::new(Reloader::new(
+        layers,
+        live.clone(),
+        log_filter,
+        pool.clone(),
+        &cfg,
+        enrichment.clone(),
+        scheduled,
+        secrets,
+        lease,
+    ));
+    reload::spawn_reload_on_sighup(reloader.clone())?;
+    reload::spawn_lease_refresh(reloader.clone());
+
+    record_event(
+        &pool,
+        EventKind::Startup,
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "sources": cfg.sources.iter().map(|s| &s.name).collect::<Vec<_>>(),
+        }),
+    )
+    .await;
+
+    let tls = cfg.tls.clone();
 
     // Build app from routes gateway (EMBP)
-    let app: Router = routes::router(pool.clone(), cfg);
+    // The feed stays on the primary: NOTIFY isn't replicated, and its re-reads
+    // must see the change it was told about
+    let summaries = SummaryFeed::spawn(pool.clone());
+    let reads = ReadPool::spawn(
+        pool.clone(),
+        replica,
+        std::time::Duration::from_secs(cfg.replica_check_secs),
+    );
+    rollups::spawn_refresher(
+        pool.clone(),
+        std::time::Duration::from_secs(cfg.rollup_refresh_secs),
+    );
+    let pool_monitor = PoolMonitor::spawn(
+        pool.clone(),
+        std::time::Duration::from_secs(cfg.pool_sample_secs),
+    );
+    // Like the feed, invalidated by NOTIFY on the primary
+    let cache = ResponseCache::spawn(
+        pool.clone(),
+        cfg.response_cache_secs.map(std::time::Duration::from_secs),
+    );
+    let filter_stats = Arc::new(FilterStats::default());
+    let readings = Arc::new(PgReadings::new(
+        pool.clone(),
+        reads.clone(),
+        cfg.source_priority.clone(),
+        enrichment.clone(),
+        filter_stats.clone(),
+    ));
+    let app: Router = routes::router(AppState {
+        pool: pool.clone(),
+        config: Arc::new(cfg),
+        reads,
+        auth,
+        limiter,
+        summaries,
+        enrichment,
+        metrics,
+        pool_monitor,
+        cache,
+        warmup,
+        maintenance: Maintenance::default(),
+        live,
+        reloader,
+        filter_stats,
+        readings,
+    });
+    listen(app, tls).await
+}
+
+/// `serve` on a SQLite or MySQL database: the edge router over
+/// [`open_repository`], ingesting every source in the background (see
+/// `sqlite.rs` and `mysql.rs`).
+async fn serve_edge(cfg: Config) -> Result<()> {
+    // ---
+    if cfg.demo_mode || cfg.replica_url.is_some() || cfg.partitioning.is_some() {
+        return Err(anyhow::anyhow!(
+            "DEMO_MODE, DATABASE_REPLICA_URL and partitioning need PostgreSQL"
+        ));
+    }
+    let metrics = prometheus::install()?;
+    let enrichment = Arc::new(Enrichment::from_config(
+        &cfg.enrichers,
+        cfg.encryption.as_ref(),
+    )?);
+    let summaries = SummaryFeed::default();
+    let readings = open_repository(&cfg, enrichment.clone(), summaries.clone()).await?;
+    let cache = Arc::new(ResponseCache::new(
+        cfg.response_cache_secs.map(std::time::Duration::from_secs),
+    ));
+    spawn_repository_ingest(readings.clone(), cache.clone(), &cfg.sources);
+
+    let tls = cfg.tls.clone();
+    let app = routes::edge_router(EdgeState {
+        auth: Arc::new(Authenticator::from_config(&cfg)?),
+        limiter: Arc::new(RateLimiter::new(&cfg.api_keys)),
+        live: LiveConfig::new(&cfg),
+        config: Arc::new(cfg),
+        enrichment,
+        metrics,
+        cache,
+        readings,
+        summaries,
+    });
+    listen(app, tls).await
+}
+
+/// Serve `app` on port 8080 until it fails, over HTTPS when `tls` is set.
+async fn listen(app: Router, tls: Option<TlsConfig>) -> Result<()> {
+    // ---
+    // Peer addresses key the rate limiter for clients without an API key
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    tracing::info!("Listening on {}", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    match tls {
+        Some(tls_cfg) => {
+            let rustls = tls::load(&tls_cfg).await?;
+            tls::spawn_reload_on_sighup(rustls.clone(), tls_cfg)?;
+
+            tracing::info!("Listening on {} (HTTPS)", addr);
+            axum_server::bind(addr)
+                .acceptor(tls::ClientCertAcceptor::new(rustls))
+                .serve(make_service)
+                .await?;
+        }
+        None => {
+            tracing::info!("Listening on {}", addr);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, make_service).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `ingest --once`: ingest `names` (every source when empty) and refresh summaries.
+async fn ingest_once(cfg: &Config, names: &[String]) -> Result<()> {
+    // ---
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !cfg.sources.iter().any(|s| &s.name == *name))
+    {
+        return Err(anyhow::anyhow!("unknown source {unknown:?}"));
+    }
+    let sources: Vec<_> = cfg
+        .sources
+        .iter()
+        .filter(|s| names.is_empty() || names.contains(&s.name))
+        .cloned()
+        .collect();
+
+    let pool = open_database(cfg).await?;
+    let enrichment = Enrichment::from_config(&cfg.enrichers, cfg.encryption.as_ref())?;
+    let counts = ingest_all(&pool, &sources, &cfg.source_priority, &enrichment).await?;
+    for (source, inserted) in counts {
+        println!("{source}: {inserted} new readings");
+    }
+    Ok(())
+}
+
+/// `import`: store the readings in the JSON file at `path` (`-` for stdin)
+/// tagged with `source`, in batches of [`IMPORT_BATCH`].
+async fn import(cfg: &Config, path: &str, source: Option<String>) -> Result<()> {
+    // ---
+    let (raw, name) = if path == "-" {
+        (
+            std::io::read_to_string(std::io::stdin())?,
+            "stdin".to_string(),
+        )
+    } else {
+        let name = std::path::Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned());
+        (std::fs::read_to_string(path)?, name)
+    };
+    let source = source.unwrap_or_else(|| format!("import:{name}"));
+
+    // A bare array, or an upstream page {"results": [...]}
+    let mut json: serde_json::Value = serde_json::from_str(&raw)?;
+    let items = match json.get_mut("results") {
+        Some(results) => results.take(),
+        None => json,
+    };
+    let readings: Vec<RawSensorReading> = serde_json::from_value(items)?;
+
+    let enrichment = Enrichment::from_config(&cfg.enrichers, cfg.encryption.as_ref())?;
+    let mut inserted = 0;
+    if outside_postgres(cfg) {
+        let repo = open_repository(cfg, Arc::new(enrichment), SummaryFeed::default()).await?;
+        for batch in readings.chunks(IMPORT_BATCH) {
+            inserted += repo.insert_batch(&source, batch).await?;
+        }
+    } else {
+        let pool = open_database(cfg).await?;
+        for batch in readings.chunks(IMPORT_BATCH) {
+            inserted +=
+                store_pushed(&pool, &source, batch, &cfg.source_priority, &enrichment).await?;
+        }
+    }
+    println!(
+        "{source}: {inserted} of {} readings stored (the rest were already present)",
+        readings.len()
+    );
+    Ok(())
+}
 
+/// `purge`: permanently remove soft-deleted readings.
+async fn purge(cfg: &Config, deleted_before: Option<chrono::Duration>) -> Result<()> {
+    // ---
+    let pool = open_database(cfg).await?;
+    let purged = purge_deleted(&pool, deleted_before, &cfg.source_priority).await?;
+    if purged > 0 {
+        update_mesh_summaries(&pool).await?;
+    }
+    println!("Purged {purged} soft-deleted readings");
     Ok(())
 }
 
@@ -97,12 +677,29 @@ async fn main() -> Result<()> {
 ///   - `"full"`       : emit ENTER, EXIT, and CLOSE events with timing
 ///   - `"enter_exit"` : emit ENTER and EXIT only
 ///   - unset or other values: emit CLOSE events only (default)
-/// - Log level controlled by the `AXUM_LOG_LEVEL` env var
+/// - Log level controlled by the `AXUM_LOG_LEVEL` env var, replaceable through
+///   the returned [`Telemetry`]'s `log_filter` (see `reload.rs`)
+/// - Output format controlled by the `LOG_FORMAT` env var:
+///   - `"json"`: one JSON object per line, with event fields at the top level
+///     and the enclosing spans (e.g. `request_id`) under `span` / `spans`;
+///     never colored
+///   - `"compact"`, unset or other values: human-readable compact lines (default)
+/// - A copy of every line in rotating files under `LOG_DIR`, when set (see
+///   `log_file.rs`; output is then never colored); fails if the directory
+///   can't be written
+/// - Span export over OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+///   (see [`init_otlp`])
+/// - Panic and 5xx reporting to Sentry when `SENTRY_DSN` is set, with
+///   warnings and errors kept as breadcrumbs (see `error_report.rs`); fails
+///   on an invalid DSN
+///
+/// The returned [`Telemetry`] must be shut down on exit to flush buffered
+/// spans, log lines and error reports.
 ///
 /// This should be called once at application startup before any logging
 /// or tracing macros are invoked. It installs the subscriber globally
 /// for the lifetime of the process.
-fn init_tracing() {
+fn init_tracing() -> Result<Telemetry> {
     // ---
     let span_events = match env::var("AXUM_SPAN_EVENTS").as_deref() {
         Ok("full") => FmtSpan::FULL,
@@ -117,28 +714,156 @@ fn init_tracing() {
         _ => std::io::stdout().is_terminal(),
     };
 
-    // Use RUST_LOG if available, otherwise fall back to AXUM_LOG_LEVEL
+    // Use RUST_LOG if available, otherwise fall back to AXUM_LOG_LEVEL, then
+    // the APP_ENV profile's level; replaceable, so a reload can change it
     let env_filter = if env::var("RUST_LOG").is_ok() {
         EnvFilter::from_default_env()
     } else {
-        let level = match env::var("AXUM_LOG_LEVEL").ok().as_deref() {
-            Some("trace") => "trace",
-            Some("debug") => "debug",
-            Some("info") => "info",
-            Some("warn") => "warn",
-            Some("error") => "error",
-            _ => "debug",
-        };
-        EnvFilter::new(format!("{level},sqlx::query=warn"))
+        let profile = env::var("APP_ENV").ok().and_then(|v| AppEnv::parse(&v));
+        let level = env::var("AXUM_LOG_LEVEL")
+            .unwrap_or_else(|_| profile.unwrap_or_default().log_level().to_string());
+        reload::log_filter(Some(&level))
     };
+    let (env_filter, log_filter) = tracing_subscriber::reload::Layer::new(env_filter);
+
+    let provider = init_otlp();
+    let otel_layer = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(env!("CARGO_PKG_NAME"))));
+
+    let error_report = error_report::init()?;
+    let breadcrumbs = error_report
+        .as_ref()
+        .map(|_| error_report::breadcrumb_layer());
 
-    tracing_subscriber::fmt()
+    let log_format = env::var("LOG_FORMAT").unwrap_or_default();
+    let json = log_format.eq_ignore_ascii_case("json");
+    let log_file = log_file::from_env()?;
+
+    // One layer teeing to both outputs: separate fmt layers would each append
+    // recorded span fields to the same per-span buffer, duplicating them.
+    // Files must not get color codes, so stdout goes uncolored too.
+    let (fmt_layer, log_file_guard) = match log_file {
+        Some((writer, guard)) => (
+            fmt_layer(json, false, span_events, std::io::stdout.and(writer)),
+            Some(guard),
+        ),
+        None => (
+            fmt_layer(json, use_color, span_events, std::io::stdout),
+            None,
+        ),
+    };
+
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .with(breadcrumbs)
+        .init();
+
+    if !matches!(
+        log_format.to_ascii_lowercase().as_str(),
+        "" | "json" | "compact"
+    ) {
+        tracing::warn!("Unknown LOG_FORMAT {:?}, using compact", log_format);
+    }
+
+    Ok(Telemetry {
+        log_filter,
+        tracer_provider: provider,
+        _log_file_guard: log_file_guard,
+        _error_report_guard: error_report,
+    })
+}
+
+/// Background exporters and writers that must outlive all logging.
+struct Telemetry {
+    // ---
+    /// Replaces the log filter on reload.
+    log_filter: LogFilter,
+
+    tracer_provider: Option<SdkTracerProvider>,
+
+    /// Flushes buffered log file lines when dropped.
+    _log_file_guard: Option<WorkerGuard>,
+
+    /// Flushes pending Sentry events when dropped.
+    _error_report_guard: Option<sentry::ClientInitGuard>,
+}
+
+impl Telemetry {
+    // ---
+    /// Flush spans still buffered for the OTLP collector, pending log lines
+    /// and error reports.
+    fn shutdown(self) {
+        // ---
+        if let Some(provider) = self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("OpenTelemetry shutdown failed: {e}");
+            }
+        }
+    }
+}
+
+/// One formatted log output (compact or JSON) writing to `writer`.
+fn fmt_layer<S, W>(
+    json: bool,
+    ansi: bool,
+    span_events: FmtSpan,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    // ---
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
         .with_target(true)
         .with_file(true)
         .with_line_number(true)
-        .with_span_events(span_events)
-        .with_env_filter(env_filter)
-        .with_ansi(use_color)
-        .compact()
-        .init();
+        .with_span_events(span_events);
+    if json {
+        layer
+            .with_ansi(false)
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed()
+    } else {
+        layer.with_ansi(ansi).compact().boxed()
+    }
+}
+
+/// Build the OTLP span exporter when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+///
+/// Spans are batched and sent over OTLP/HTTP (protobuf) to
+/// `$OTEL_EXPORTER_OTLP_ENDPOINT/v1/traces`, e.g. `http://localhost:4318` for
+/// Jaeger or Tempo. The exporter honours the other standard `OTEL_EXPORTER_OTLP_*`
+/// variables (headers, timeout); the service name defaults to the crate name
+/// unless `OTEL_SERVICE_NAME` is set. Runs before the subscriber exists, so
+/// problems are reported on stderr and the service starts without export.
+fn init_otlp() -> Option<SdkTracerProvider> {
+    // ---
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|v| !v.trim().is_empty())?;
+
+    let exporter = match SpanExporter::builder().with_http().build() {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            eprintln!("OpenTelemetry exporter disabled: {e}");
+            return None;
+        }
+    };
+
+    let service_name =
+        env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Some(provider)
 }
@@ -0,0 +1,140 @@
+//! Maintenance mode.
+//!
+//! `PUT /admin/maintenance` with `{ "enabled": true }` takes the API out of
+//! service without stopping the process, e.g. around a schema migration or a
+//! bulk backfill: [`maintenance_guard`] answers every request from a caller
+//! below the `admin` role with 503, `Retry-After` and the operator's
+//! `message`, while admins keep full access to run the maintenance itself.
+//! `/health` and `/ready` are unaffected.
+//!
+//! The flag lives in this process: with several replicas, toggle each one.
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{ApiError, Principal, Role};
+
+/// `Retry-After` sent when the operator gave none.
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+// ---
+
+/// The shared maintenance flag; clones share it.
+#[derive(Clone, Default)]
+pub struct Maintenance {
+    // ---
+    window: Arc<RwLock<Option<MaintenanceWindow>>>,
+}
+
+/// A maintenance in progress.
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceWindow {
+    // ---
+    /// Shown to rejected callers, e.g. what is going on and until when.
+    pub message: Option<String>,
+    pub since: DateTime<Utc>,
+
+    /// Who turned maintenance on.
+    pub by: String,
+    pub retry_after_secs: u64,
+}
+
+impl Maintenance {
+    // ---
+    /// The maintenance in progress, if any.
+    pub fn current(&self) -> Option<MaintenanceWindow> {
+        // ---
+        self.window
+            .read()
+            .expect("maintenance lock poisoned")
+            .clone()
+    }
+
+    /// Turn maintenance on, or replace the message and delay of the one in
+    /// progress (keeping its start).
+    pub fn begin(
+        &self,
+        message: Option<String>,
+        by: &str,
+        retry_after_secs: Option<u64>,
+    ) -> MaintenanceWindow {
+        // ---
+        let mut window = self.window.write().expect("maintenance lock poisoned");
+        let started = window.as_ref().map(|w| w.since);
+        let begun = MaintenanceWindow {
+            message,
+            since: started.unwrap_or_else(Utc::now),
+            by: by.to_string(),
+            retry_after_secs: retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+        };
+        *window = Some(begun.clone());
+        begun
+    }
+
+    /// Turn maintenance off; returns the maintenance that ended, if any.
+    pub fn end(&self) -> Option<MaintenanceWindow> {
+        // ---
+        self.window
+            .write()
+            .expect("maintenance lock poisoned")
+            .take()
+    }
+}
+
+/// Middleware: during maintenance, reject callers below the `admin` role.
+///
+/// Layer it inside [`authenticate`](crate::authenticate), which tells admins apart.
+pub async fn maintenance_guard(
+    State(maintenance): State<Maintenance>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // ---
+    let Some(window) = maintenance.current() else {
+        return next.run(req).await;
+    };
+    let admin = req
+        .extensions()
+        .get::<Principal>()
+        .is_some_and(|p| p.role >= Role::Admin);
+    if admin {
+        return next.run(req).await;
+    }
+
+    ApiError::Maintenance {
+        message: window.message,
+        since: window.since,
+        retry_after_secs: window.retry_after_secs,
+    }
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn maintenance_keeps_its_start_until_it_ends() {
+        // ---
+        let maintenance = Maintenance::default();
+        assert!(maintenance.current().is_none());
+
+        let begun = maintenance.begin(Some("migrating".into()), "ops", None);
+        assert_eq!(begun.retry_after_secs, DEFAULT_RETRY_AFTER_SECS);
+
+        let updated = maintenance.clone().begin(None, "ops", Some(300));
+        assert_eq!(updated.since, begun.since);
+        assert_eq!(maintenance.current().unwrap().retry_after_secs, 300);
+
+        assert!(maintenance.end().is_some());
+        assert!(maintenance.current().is_none());
+        assert!(maintenance.end().is_none());
+    }
+}
@@ -1,6 +1,6 @@
 //! Simple data models for the sensor pipeline.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 // ---
@@ -8,11 +8,14 @@ use serde::{Deserialize, Serialize};
 /// Raw reading as returned by the upstream API (wire format).
 ///
 /// - Mirrors the JSON payload 1:1; no normalization or computed fields.
-/// - Use `to_transformed()` to produce a `SensorReading` suitable for storage:
-///   - normalizes `timestamp` to UTC
-///   - computes `temperature_f` from `temperature_c`
+/// - Use `to_transformed()` (or `to_calibrated()` for devices with a
+///   [`Calibration`]) to produce a `SensorReading` suitable for storage:
+///   - normalizes `timestamp` to UTC (no `timestamp_est` or `temperature_f` is
+///     derived or stored; see "Design decisions" in the README)
 ///   - flags anomalies: `temperature_alert` (< -10°C or > 60°C),
 ///     `humidity_alert` (< 10% or > 90%)
+///   - keeps `latitude`/`longitude` only as a valid pair (see [`valid_position`])
+///   - keeps the numeric fields among any unrecognized ones as `extra`
 /// - `status` is preserved verbatim from upstream; consumers may treat non-"ok" as an alert.
 #[derive(Debug, Deserialize)]
 pub struct RawSensorReading {
@@ -32,8 +35,22 @@ pub struct RawSensorReading {
     /// Relative humidity (%) reported by upstream
     pub humidity: f32,
 
-    /// Upstream status string (e.g., "ok"); passed through unchanged
+    /// Upstream status string (e.g., "ok"); passed through unchanged, empty
+    /// when missing
+    #[serde(default)]
     pub status: String,
+
+    /// Position at the time of the reading, in WGS84 degrees; reported by
+    /// mobile sensors only
+    #[serde(default)]
+    pub latitude: Option<f64>,
+
+    #[serde(default)]
+    pub longitude: Option<f64>,
+
+    /// Fields not listed above, e.g. `pressure` from newer sensor types
+    #[serde(flatten)]
+    pub unrecognized: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Normalized sensor reading used for storage and API responses.
@@ -43,10 +60,19 @@ pub struct RawSensorReading {
 /// - `temperature_alert` is true if `temperature_c` < -10.0 **or** > 60.0 (strict).
 /// - `humidity_alert`    is true if `humidity` < 10.0 **or** > 90.0 (strict).
 /// - `status` is copied from upstream; not interpreted here.
+/// - `latitude` and `longitude` are both set or both `None`.
+/// - `raw_temperature_c`/`raw_humidity` hold the reported values when a
+///   [`Calibration`] was applied; alerts use the calibrated ones.
 /// -  Maps 1:1 to the `sensor_data` table and is safe to insert via `store_sensor_reading`.
-#[derive(Debug, Serialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
 pub struct SensorReading {
     // ---
+    /// Row ID in `sensor_data`, a stable reference to the reading (see
+    /// `GET /v1/readings/{id}`); `None` until stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub id: Option<i32>,
+
     /// Natural key of the mesh (from upstream).
     pub mesh_id: String,
 
@@ -57,12 +83,27 @@ pub struct SensorReading {
     /// ingest time).
     pub timestamp_utc: chrono::DateTime<chrono::Utc>,
 
-    /// Temperature in °C as reported/normalized.
+    /// `timestamp_utc` in the requested `tz`, or else the mesh's registered
+    /// timezone, in readings responses; carries its UTC offset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub timestamp_local: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+    /// Temperature in °C as reported/normalized, after calibration.
     pub temperature_c: f32,
 
-    /// Relative humidity in percent.
+    /// Relative humidity in percent, after calibration.
     pub humidity: f32,
 
+    /// Values as reported, for readings whose device had a calibration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub raw_temperature_c: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub raw_humidity: Option<f32>,
+
     /// Upstream status string (e.g., "ok"); preserved verbatim.
     pub status: String,
 
@@ -71,6 +112,135 @@ pub struct SensorReading {
 
     /// Humidity anomaly flag: true if < 10% or > 90%.
     pub humidity_alert: bool,
+
+    /// Position reported with the reading (WGS84 degrees); fixed devices
+    /// leave it to their registry entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub latitude: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub longitude: Option<f64>,
+
+    /// Numeric measurements beyond temperature and humidity, keyed by their
+    /// upstream field name (e.g. `pressure`).
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    #[sqlx(default, json)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+
+    /// Data quality score in [0, 1] computed at ingest (see `quality.rs`);
+    /// `None` for readings stored before scoring existed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub quality: Option<f32>,
+
+    /// Problems lowering `quality`, e.g. `stale_clock`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(default)]
+    pub quality_flags: Vec<String>,
+
+    /// Fields attached by enrichers, keyed by enricher name (see `enrich.rs`).
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    #[sqlx(default, json)]
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+
+    /// Marked invalid by a correction: still listed, but counted toward no
+    /// summary or alert.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[sqlx(default)]
+    pub invalid: bool,
+
+    /// Manual corrections applied to the reading, oldest first, each with the
+    /// values it replaced and who made it (see `routes/corrections.rs`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(default, json)]
+    pub corrections: Vec<serde_json::Value>,
+
+    /// `ingest_batches` row of the fetch or push that stored the reading;
+    /// `None` for readings stored before batches were tracked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub ingest_batch_id: Option<i64>,
+
+    /// EWMA of the device's values up to this reading, in responses with
+    /// `smooth=ewma` (see `smoothing.rs`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub smoothed: Option<Smoothed>,
+
+    /// Average of the device's last `rolling_avg` readings up to this one,
+    /// in responses with `rolling_avg`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub rolling_avg: Option<Smoothed>,
+
+    /// Registry entry of the device, in responses with `include=device`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub device: Option<DeviceInfo>,
+}
+
+/// Smoothed measurements, served alongside the raw ones.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Smoothed {
+    pub temperature_c: f32,
+    pub humidity: f32,
+}
+
+/// Operator-facing metadata for a device, as stored in `devices`.
+///
+/// Every field but the ID is optional: devices report readings whether or
+/// not anyone has registered them.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DeviceInfo {
+    // ---
+    pub device_id: String,
+
+    /// Mesh the device is installed in, as recorded by an operator (readings
+    /// and summaries follow the reported mesh and assignment history instead).
+    pub mesh_id: Option<String>,
+
+    /// Human-readable name, e.g. "Freezer 3".
+    pub display_name: Option<String>,
+
+    /// Free-form location, e.g. "Plant A, aisle 4".
+    pub location: Option<String>,
+
+    /// Where the device is installed, in WGS84 degrees; used for readings
+    /// that report no position of their own.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+
+    pub installed_on: Option<NaiveDate>,
+    pub notes: Option<String>,
+
+    /// When the entry was last written.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Known drift of a device's sensors, added to its reported values at ingest.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Calibration {
+    // ---
+    /// °C added to `temperature_c`.
+    #[serde(default)]
+    pub temperature_offset: f32,
+
+    /// Percentage points added to `humidity`.
+    #[serde(default)]
+    pub humidity_offset: f32,
+}
+
+/// Whether a position is absent, or complete and within WGS84 bounds
+/// (latitude -90..=90, longitude -180..=180).
+pub fn valid_position(latitude: Option<f64>, longitude: Option<f64>) -> bool {
+    // ---
+    match (latitude, longitude) {
+        (None, None) => true,
+        (Some(lat), Some(lon)) => (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon),
+        _ => false,
+    }
 }
 
 /// Simple transformation helpers
@@ -78,16 +248,56 @@ impl RawSensorReading {
     // ---
     pub fn to_transformed(&self) -> SensorReading {
         // ---
+        self.to_calibrated(None)
+    }
+
+    /// Like `to_transformed()`, adding `calibration`'s offsets before alerts
+    /// are evaluated and keeping the reported values as `raw_*`.
+    pub fn to_calibrated(&self, calibration: Option<&Calibration>) -> SensorReading {
+        // ---
+        let (temperature_c, humidity) = match calibration {
+            Some(c) => (
+                self.temperature_c + c.temperature_offset,
+                self.humidity + c.humidity_offset,
+            ),
+            None => (self.temperature_c, self.humidity),
+        };
+        let (latitude, longitude) = if valid_position(self.latitude, self.longitude) {
+            (self.latitude, self.longitude)
+        } else {
+            (None, None)
+        };
 
         SensorReading {
+            id: None,
             mesh_id: self.mesh_id.clone(),
             device_id: self.device_id.clone(),
             timestamp_utc: self.timestamp, // Keep original UTC, UI will map it to local time
-            temperature_c: self.temperature_c,
-            humidity: self.humidity,
+            timestamp_local: None,
+            temperature_c,
+            humidity,
+            raw_temperature_c: calibration.map(|_| self.temperature_c),
+            raw_humidity: calibration.map(|_| self.humidity),
             status: self.status.clone(),
-            temperature_alert: self.temperature_c < -10.0 || self.temperature_c > 60.0,
-            humidity_alert: self.humidity < 10.0 || self.humidity > 90.0,
+            temperature_alert: !(-10.0..=60.0).contains(&temperature_c),
+            humidity_alert: !(10.0..=90.0).contains(&humidity),
+            latitude,
+            longitude,
+            extra: self
+                .unrecognized
+                .iter()
+                .filter(|(_, v)| v.is_number())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            quality: None,
+            quality_flags: Vec::new(),
+            attributes: serde_json::Map::new(),
+            invalid: false,
+            corrections: Vec::new(),
+            ingest_batch_id: None,
+            smoothed: None,
+            rolling_avg: None,
+            device: None,
         }
     }
 }
@@ -107,6 +317,9 @@ mod tests {
             temperature_c: temp_c,
             humidity,
             status: "ok".to_string(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
         }
     }
 
@@ -121,6 +334,9 @@ mod tests {
             temperature_c: 20.0,
             humidity: 50.0,
             status: "ok".to_string(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
         };
 
         let transformed = raw.to_transformed();
@@ -185,6 +401,9 @@ mod tests {
             temperature_c: 20.0,
             humidity: 45.0,
             status: "warning".to_string(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
         };
 
         let transformed = raw.to_transformed();
@@ -196,4 +415,62 @@ mod tests {
         assert_eq!(transformed.temperature_c, 20.0);
         assert_eq!(transformed.humidity, 45.0);
     }
+
+    #[test]
+    fn only_valid_positions_are_kept() {
+        // ---
+        assert!(valid_position(None, None));
+        assert!(valid_position(Some(-90.0), Some(180.0)));
+        assert!(!valid_position(Some(52.5), None));
+        assert!(!valid_position(Some(91.0), Some(13.4)));
+        assert!(!valid_position(Some(52.5), Some(f64::NAN)));
+
+        let mut raw = create_test_raw_reading(20.0, 50.0);
+        (raw.latitude, raw.longitude) = (Some(52.52), Some(13.40));
+        assert_eq!(raw.to_transformed().longitude, Some(13.40));
+        raw.longitude = Some(213.40);
+        let transformed = raw.to_transformed();
+        assert_eq!((transformed.latitude, transformed.longitude), (None, None));
+    }
+
+    #[test]
+    fn unrecognized_numeric_fields_become_extra() {
+        // ---
+        let raw: RawSensorReading = serde_json::from_value(serde_json::json!({
+            "mesh_id": "mesh-001",
+            "device_id": "device-A",
+            "timestamp": "2025-03-26T18:45:00Z",
+            "temperature_c": 20.0,
+            "humidity": 50.0,
+            "status": "ok",
+            "pressure": 1013.2,
+            "co2_ppm": 415,
+            "firmware": "1.4.2"
+        }))
+        .unwrap();
+
+        let extra = raw.to_transformed().extra;
+        assert_eq!(extra.len(), 2);
+        assert_eq!(extra["pressure"], 1013.2);
+        assert_eq!(extra["co2_ppm"], 415);
+    }
+
+    #[test]
+    fn calibration_applies_before_alerts() {
+        // ---
+        let calibration = Calibration {
+            temperature_offset: -1.5,
+            humidity_offset: 2.0,
+        };
+        let drifting = create_test_raw_reading(61.0, 45.0);
+        assert!(drifting.to_transformed().temperature_alert);
+
+        let calibrated = drifting.to_calibrated(Some(&calibration));
+        assert_eq!(calibrated.temperature_c, 59.5);
+        assert_eq!(calibrated.humidity, 47.0);
+        assert!(!calibrated.temperature_alert);
+        assert_eq!(calibrated.raw_temperature_c, Some(61.0));
+        assert_eq!(calibrated.raw_humidity, Some(45.0));
+        assert_eq!(drifting.to_transformed().raw_temperature_c, None);
+    }
 }
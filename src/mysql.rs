@@ -0,0 +1,817 @@
+//! MySQL and MariaDB storage, for sites that standardize on them.
+//!
+//! A `DATABASE_URL` starting with `mysql:` or `mariadb:` (e.g.
+//! `mysql://sensorflow:secret@db:3306/sensorflow`) keeps readings in that
+//! database instead of Postgres: [`MySqlReadings`] is the
+//! [`ReadingsRepository`] on it, and `serve` mounts the same reduced router
+//! as for SQLite (see `sqlite.rs` for what it serves and what needs
+//! Postgres). The database must exist; its tables are created on first start.
+//!
+//! The schema is [`MIGRATIONS`], applied in order and recorded in
+//! `schema_migrations`, under `GET_LOCK` so replicas starting together apply
+//! each once. Queries are built as in `repository.rs`, adapted to MySQL 8 and
+//! MariaDB 10.6 or later:
+//! - Lists of devices, meshes or statuses are `IN (...)` lists rather than arrays.
+//! - Timestamps are `DATETIME(6)` in UTC.
+//! - Pushes skip readings already stored with `INSERT IGNORE`, MySQL's
+//!   `ON CONFLICT DO NOTHING`: with the row counts sqlx asks for, an
+//!   `ON DUPLICATE KEY UPDATE` no-op would count skipped rows as stored.
+//! - Prefixes rely on `\` being `LIKE`'s default escape, so servers running
+//!   with `NO_BACKSLASH_ESCAPES` match `%` and `_` in them as wildcards.
+//! - `extra_key` looks the key up in `JSON_KEYS`.
+//! - `sample` keeps the rows whose ID hashes below the fraction, as on SQLite.
+//! - `bbox` matches by the reading's own position only.
+//! - Mesh summaries are aggregated on read, counting the first copy of each
+//!   reading; `SOURCE_PRIORITY` isn't applied.
+//!
+//! Writes record their meshes' ingest in `mesh_ingest` and publish their new
+//! aggregates to the [`SummaryFeed`], as on SQLite. There is no listener, so a
+//! server's summary streams only follow the readings that server stores.
+use std::{str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use sqlx::{
+    mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlRow},
+    MySql, MySqlPool, QueryBuilder, Row,
+};
+
+use crate::repository::{like_prefix, Readings};
+use crate::{
+    assess_batch, AlertFilter, DeviceInfo, Enrichment, MeshAggregate, RawSensorReading,
+    ReadingsCount, ReadingsCursor, ReadingsFilter, ReadingsPage, ReadingsRepository, SensorReading,
+    Smoothed, Sort, SummaryFeed, SummaryUpdate,
+};
+
+/// Schema changes, oldest first; append new ones, never edit applied ones.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS sensor_data (
+        id                INT          NOT NULL AUTO_INCREMENT PRIMARY KEY,
+        source            VARCHAR(255) NOT NULL DEFAULT 'default',
+        mesh_id           VARCHAR(255) NOT NULL,
+        device_id         VARCHAR(255) NOT NULL,
+        timestamp_utc     DATETIME(6)  NOT NULL,
+        temperature_c     FLOAT        NOT NULL,
+        humidity          FLOAT        NOT NULL,
+        status            VARCHAR(64)  NOT NULL DEFAULT '',
+        temperature_alert BOOLEAN      NOT NULL DEFAULT FALSE,
+        humidity_alert    BOOLEAN      NOT NULL DEFAULT FALSE,
+        attributes        JSON         NOT NULL,
+        raw_temperature_c FLOAT,
+        raw_humidity      FLOAT,
+        latitude          DOUBLE,
+        longitude         DOUBLE,
+        extra             JSON         NOT NULL,
+        quality           FLOAT,
+        quality_flags     JSON         NOT NULL,
+        invalid           BOOLEAN      NOT NULL DEFAULT FALSE,
+        corrections       JSON         NOT NULL,
+        ingest_batch_id   BIGINT,
+        deleted_at        DATETIME(6),
+        UNIQUE KEY uq_sensor_data_source (source, device_id, timestamp_utc),
+        KEY idx_sensor_data_timestamp (timestamp_utc),
+        KEY idx_sensor_data_device_ts (device_id, timestamp_utc),
+        KEY idx_sensor_data_mesh_ts (mesh_id, timestamp_utc)
+    ) CHARACTER SET utf8mb4
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS mesh_ingest (
+        mesh_id          VARCHAR(255) NOT NULL PRIMARY KEY,
+        last_ingested_at DATETIME(6)  NOT NULL
+    ) CHARACTER SET utf8mb4
+    "#,
+    // Meshes stored before ingests were recorded count as ingested now
+    r#"
+    INSERT IGNORE INTO mesh_ingest (mesh_id, last_ingested_at)
+    SELECT DISTINCT mesh_id, UTC_TIMESTAMP(6) FROM sensor_data
+    "#,
+];
+
+/// Every column of a reading, as loaded without `fields`.
+const READING_COLUMNS: &str = "id, mesh_id, device_id, timestamp_utc, \
+     temperature_c, humidity, status, temperature_alert, humidity_alert, attributes, \
+     raw_temperature_c, raw_humidity, latitude, longitude, extra, \
+     quality, quality_flags, invalid, corrections, ingest_batch_id";
+
+/// `sample` keeps rows whose `id * SAMPLE_HASH` modulo [`SAMPLE_BUCKETS`] is
+/// below the fraction of the buckets, as on SQLite.
+const SAMPLE_HASH: i64 = 2_654_435_761;
+const SAMPLE_BUCKETS: i64 = 1_000_000;
+
+/// Seconds a starting server waits for another's migrations.
+const MIGRATION_LOCK_SECS: i64 = 60;
+
+// ---
+
+/// Whether `url` (a `DATABASE_URL`) names a MySQL or MariaDB database.
+pub fn is_mysql_url(url: &str) -> bool {
+    // ---
+    url.starts_with("mysql:") || url.starts_with("mariadb:")
+}
+
+/// Connect to the database at `url` and bring its schema up to date.
+pub async fn open(url: &str, max_connections: u32) -> Result<MySqlPool, sqlx::Error> {
+    // ---
+    let options = MySqlConnectOptions::from_str(url)?.charset("utf8mb4");
+    let pool = MySqlPoolOptions::new()
+        .max_connections(max_connections)
+        .connect_with(options)
+        .await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Apply the [`MIGRATIONS`] the database hasn't had yet; returns how many ran.
+///
+/// MySQL commits DDL as it runs it, so each migration is recorded as soon as
+/// it is applied rather than in one transaction; the named lock keeps two
+/// servers from applying the same one.
+async fn migrate(pool: &MySqlPool) -> Result<usize, sqlx::Error> {
+    // ---
+    // The lock belongs to the connection, so everything runs on this one
+    let mut conn = pool.acquire().await?;
+    let locked: Option<i64> = sqlx::query_scalar("SELECT GET_LOCK('sensorflow_migrate', ?)")
+        .bind(MIGRATION_LOCK_SECS)
+        .fetch_one(&mut *conn)
+        .await?;
+    if locked != Some(1) {
+        return Err(sqlx::Error::Protocol(
+            "timed out waiting for another server's schema migrations".into(),
+        ));
+    }
+
+    let result = async {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS schema_migrations \
+             (version INT NOT NULL PRIMARY KEY, applied_at DATETIME(6) NOT NULL)",
+        )
+        .execute(&mut *conn)
+        .await?;
+        let applied: i64 =
+            sqlx::query_scalar("SELECT CAST(COUNT(*) AS SIGNED) FROM schema_migrations")
+                .fetch_one(&mut *conn)
+                .await?;
+        let pending = MIGRATIONS.get(applied as usize..).unwrap_or_default();
+        for (offset, migration) in pending.iter().enumerate() {
+            sqlx::raw_sql(migration).execute(&mut *conn).await?;
+            sqlx::query("INSERT INTO schema_migrations (version, applied_at) VALUES (?, ?)")
+                .bind(applied + offset as i64 + 1)
+                .bind(Utc::now())
+                .execute(&mut *conn)
+                .await?;
+        }
+        if !pending.is_empty() {
+            tracing::info!(
+                "Applied {} MySQL schema migration(s), now at version {}",
+                pending.len(),
+                MIGRATIONS.len()
+            );
+        }
+        Ok(pending.len())
+    }
+    .await;
+
+    sqlx::query("SELECT RELEASE_LOCK('sensorflow_migrate')")
+        .execute(&mut *conn)
+        .await?;
+    result
+}
+
+/// [`ReadingsRepository`] on MySQL or MariaDB; see the module docs.
+pub struct MySqlReadings {
+    // ---
+    pool: MySqlPool,
+
+    /// Applied to every reading stored.
+    enrichment: Arc<Enrichment>,
+
+    /// Where the aggregates of meshes written to are published.
+    feed: SummaryFeed,
+}
+
+impl MySqlReadings {
+    // ---
+    pub fn new(pool: MySqlPool, enrichment: Arc<Enrichment>, feed: SummaryFeed) -> Self {
+        // ---
+        Self {
+            pool,
+            enrichment,
+            feed,
+        }
+    }
+
+    /// Score, enrich and insert `readings` in one transaction, skipping those
+    /// `source` already stored (same device and timestamp), then record and
+    /// publish the meshes that gained readings.
+    async fn store(&self, source: &str, readings: &[RawSensorReading]) -> Result<u64, sqlx::Error> {
+        // ---
+        let mut transformed: Vec<_> = readings.iter().map(|r| r.to_transformed()).collect();
+        assess_batch(readings, &mut transformed, Utc::now());
+        // Before the transaction: enrichers may call out over HTTP
+        for t in &mut transformed {
+            self.enrichment.apply(t).await;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        let mut meshes: Vec<String> = Vec::new();
+        for t in &transformed {
+            let stored_now = sqlx::query(
+                r#"
+                INSERT IGNORE INTO sensor_data (
+                    source, mesh_id, device_id, timestamp_utc,
+                    temperature_c, humidity, status,
+                    temperature_alert, humidity_alert, attributes,
+                    latitude, longitude, extra, raw_temperature_c, raw_humidity,
+                    quality, quality_flags, corrections
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, '[]')
+                "#,
+            )
+            .bind(source)
+            .bind(&t.mesh_id)
+            .bind(&t.device_id)
+            .bind(t.timestamp_utc)
+            .bind(t.temperature_c)
+            .bind(t.humidity)
+            .bind(&t.status)
+            .bind(t.temperature_alert)
+            .bind(t.humidity_alert)
+            .bind(sqlx::types::Json(&t.attributes))
+            .bind(t.latitude)
+            .bind(t.longitude)
+            .bind(sqlx::types::Json(&t.extra))
+            .bind(t.raw_temperature_c)
+            .bind(t.raw_humidity)
+            .bind(t.quality)
+            .bind(sqlx::types::Json(&t.quality_flags))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if stored_now > 0 && !meshes.contains(&t.mesh_id) {
+                meshes.push(t.mesh_id.clone());
+            }
+            inserted += stored_now;
+        }
+        // GREATEST: another server's ingest may have committed a later one
+        let now = Utc::now();
+        for mesh_id in &meshes {
+            sqlx::query(
+                "INSERT INTO mesh_ingest (mesh_id, last_ingested_at) VALUES (?, ?) \
+                 ON DUPLICATE KEY UPDATE \
+                 last_ingested_at = GREATEST(last_ingested_at, VALUES(last_ingested_at))",
+            )
+            .bind(mesh_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.publish(&meshes).await;
+        Ok(inserted)
+    }
+
+    /// Publish the aggregates of `meshes` when anyone is streaming them; a
+    /// failed load only costs the streams this update.
+    async fn publish(&self, meshes: &[String]) {
+        // ---
+        if meshes.is_empty() || !self.feed.has_subscribers() {
+            return;
+        }
+        match self.summaries(Some(meshes)).await {
+            Ok(rows) => {
+                for row in rows {
+                    self.feed.publish(SummaryUpdate::Updated(row));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load mesh summaries to publish: {}", e),
+        }
+    }
+}
+
+impl ReadingsRepository for MySqlReadings {
+    // ---
+    fn insert_batch<'a>(
+        &'a self,
+        source: &'a str,
+        readings: &'a [RawSensorReading],
+    ) -> BoxFuture<'a, Result<u64, sqlx::Error>> {
+        // ---
+        Box::pin(self.store(source, readings))
+    }
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+        page: &'a ReadingsPage,
+    ) -> BoxFuture<'a, Result<Readings, sqlx::Error>> {
+        // ---
+        Box::pin(load_readings(&self.pool, filter, page))
+    }
+
+    fn count<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+    ) -> BoxFuture<'a, Result<ReadingsCount, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(
+                "SELECT CAST(COUNT(*) AS SIGNED) AS count, MIN(timestamp_utc) AS earliest, \
+                 MAX(timestamp_utc) AS latest FROM sensor_data WHERE 1=1",
+            );
+            push_conditions(&mut query, filter);
+            query.build_query_as().fetch_one(&self.pool).await
+        })
+    }
+
+    fn reading<'a>(
+        &'a self,
+        id: i32,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<SensorReading>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(format!(
+                "SELECT {READING_COLUMNS} FROM sensor_data WHERE deleted_at IS NULL AND id = "
+            ));
+            query.push_bind(id);
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            let row = query.build().fetch_optional(&self.pool).await?;
+            row.map(|row| reading_from_row(&row, false)).transpose()
+        })
+    }
+
+    fn latest<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(
+                "SELECT MAX(timestamp_utc) FROM sensor_data WHERE deleted_at IS NULL",
+            );
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            query.build_query_scalar().fetch_one(&self.pool).await
+        })
+    }
+
+    fn summaries<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Vec<MeshAggregate>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            // The derived table keeps MySQL from re-running the subquery per row
+            let mut query = QueryBuilder::new(
+                r#"
+                SELECT d.mesh_id, AVG(d.temperature_c) AS avg_temperature_c,
+                       AVG(d.humidity) AS avg_humidity,
+                       CAST(COUNT(*) AS SIGNED) AS reading_count,
+                       CAST(NULL AS CHAR) AS site_name
+                FROM sensor_data d
+                JOIN (
+                    SELECT MIN(id) AS id FROM sensor_data
+                    WHERE deleted_at IS NULL AND NOT invalid
+                    GROUP BY device_id, timestamp_utc
+                ) first_copy ON first_copy.id = d.id
+                WHERE 1=1
+                "#,
+            );
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "d.mesh_id", meshes);
+            }
+            query.push(" GROUP BY d.mesh_id ORDER BY d.mesh_id");
+            query.build_query_as().fetch_all(&self.pool).await
+        })
+    }
+
+    fn last_ingested<'a>(
+        &'a self,
+        scope: Option<&'a [String]>,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            last_ingested_query(scope, meshes)
+                .build_query_scalar()
+                .fetch_one(&self.pool)
+                .await
+        })
+    }
+
+    fn timezones<'a>(
+        &'a self,
+        _mesh_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, sqlx::Error>> {
+        // ---
+        // There is no mesh registry
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn devices<'a>(
+        &'a self,
+        _device_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<DeviceInfo>, sqlx::Error>> {
+        // ---
+        // There is no device registry
+        Box::pin(async { Ok(Vec::new()) })
+    }
+}
+
+/// The latest ingest into the meshes both `scope` and `meshes` allow (`None`
+/// allowing all).
+fn last_ingested_query<'a>(
+    scope: Option<&'a [String]>,
+    meshes: Option<&'a [String]>,
+) -> QueryBuilder<'a, MySql> {
+    // ---
+    let mut query = QueryBuilder::new("SELECT MAX(last_ingested_at) FROM mesh_ingest WHERE 1=1");
+    for meshes in [scope, meshes].into_iter().flatten() {
+        push_in(&mut query, "mesh_id", meshes);
+    }
+    query
+}
+
+/// Add ` AND column IN (values...)`; an empty list matches nothing.
+fn push_in<'a>(query: &mut QueryBuilder<'a, MySql>, column: &str, values: &'a [String]) {
+    // ---
+    if values.is_empty() {
+        // `IN ()` is a syntax error in MySQL
+        query.push(" AND FALSE");
+        return;
+    }
+    query.push(format!(" AND {column} IN ("));
+    let mut list = query.separated(", ");
+    for value in values {
+        list.push_bind(value);
+    }
+    list.push_unseparated(")");
+}
+
+/// Add the `WHERE` conditions of `filter`, shared by the readings and their
+/// count; the MySQL counterpart of `ReadingsFilter::push_conditions`.
+fn push_conditions<'a>(query: &mut QueryBuilder<'a, MySql>, filter: &'a ReadingsFilter) {
+    // ---
+    query.push(" AND deleted_at IS NULL");
+
+    for (column, values) in [
+        ("device_id", &filter.device_ids),
+        ("mesh_id", &filter.mesh_ids),
+    ] {
+        if !values.is_empty() {
+            push_in(query, column, values);
+        }
+    }
+
+    for (column, prefix) in [
+        ("device_id", &filter.device_id_prefix),
+        ("mesh_id", &filter.mesh_id_prefix),
+    ] {
+        if let Some(prefix) = prefix {
+            query.push(format!(" AND {column} LIKE "));
+            query.push_bind(like_prefix(prefix));
+        }
+    }
+
+    if let Some(statuses) = &filter.statuses {
+        push_in(query, "status", statuses);
+    }
+    if let Some(statuses) = filter.statuses_not.as_ref().filter(|s| !s.is_empty()) {
+        query.push(" AND status NOT IN (");
+        let mut list = query.separated(", ");
+        for status in statuses {
+            list.push_bind(status);
+        }
+        list.push_unseparated(")");
+    }
+
+    if let Some(alert) = filter.alert {
+        query.push(match alert {
+            AlertFilter::Any => " AND (temperature_alert OR humidity_alert)",
+            AlertFilter::Temperature => " AND temperature_alert",
+            AlertFilter::Humidity => " AND humidity_alert",
+        });
+    }
+
+    for (column, [min, max]) in [
+        ("temperature_c", filter.temperature_c),
+        ("humidity", filter.humidity),
+    ] {
+        if let Some(min) = min {
+            query.push(format!(" AND {column} >= "));
+            query.push_bind(min);
+        }
+        if let Some(max) = max {
+            query.push(format!(" AND {column} <= "));
+            query.push_bind(max);
+        }
+    }
+
+    if let Some(min) = filter.min_quality {
+        query.push(" AND quality >= ");
+        query.push_bind(min);
+    }
+
+    if let Some(key) = &filter.extra_key {
+        query.push(" AND JSON_CONTAINS(JSON_KEYS(extra), JSON_QUOTE(");
+        query.push_bind(key);
+        query.push("))");
+    }
+
+    if let Some(allowed) = &filter.allowed_meshes {
+        push_in(query, "mesh_id", allowed);
+    }
+
+    if let Some(start) = filter.start {
+        query.push(" AND timestamp_utc >= ");
+        query.push_bind(start);
+    }
+    if let Some(end) = filter.end {
+        query.push(" AND timestamp_utc <= ");
+        query.push_bind(end);
+    }
+
+    if let Some(b) = &filter.bbox {
+        query.push(" AND latitude BETWEEN ");
+        query.push_bind(b.min_lat);
+        query.push(" AND ");
+        query.push_bind(b.max_lat);
+        query.push(" AND (longitude >= ");
+        query.push_bind(b.min_lon);
+        // Across the antimeridian either side of it matches
+        query.push(if b.min_lon <= b.max_lon {
+            " AND "
+        } else {
+            " OR "
+        });
+        query.push("longitude <= ");
+        query.push_bind(b.max_lon);
+        query.push(")");
+    }
+}
+
+/// Keep only rows strictly after `c` in `sort`, as `Sort::push_after` does
+/// for Postgres (MySQL compares row values the same way).
+fn push_after<'a>(query: &mut QueryBuilder<'a, MySql>, sort: Sort, c: &'a ReadingsCursor) {
+    // ---
+    let (key, op) = match sort {
+        Sort::TimestampDesc => ("timestamp_utc", "<"),
+        Sort::TimestampAsc => ("timestamp_utc", ">"),
+        Sort::TemperatureDesc => ("temperature_c", "<"),
+        Sort::TemperatureAsc => ("temperature_c", ">"),
+        Sort::HumidityDesc => ("humidity", "<"),
+        Sort::HumidityAsc => ("humidity", ">"),
+        Sort::DeviceId => {
+            let device_id = c.device_id.as_deref().unwrap_or_default();
+            query.push(" AND (device_id > ");
+            query.push_bind(device_id);
+            query.push(" OR (device_id = ");
+            query.push_bind(device_id);
+            query.push(" AND (timestamp_utc, id) < (");
+            query.push_bind(c.timestamp_utc);
+            query.push(", ");
+            query.push_bind(c.id);
+            query.push(")))");
+            return;
+        }
+    };
+    query.push(format!(" AND ({key}, id) {op} ("));
+    match key {
+        "temperature_c" => query.push_bind(c.temperature_c.unwrap_or_default()),
+        "humidity" => query.push_bind(c.humidity.unwrap_or_default()),
+        _ => query.push_bind(c.timestamp_utc),
+    };
+    query.push(", ");
+    query.push_bind(c.id);
+    query.push(")");
+}
+
+/// Build the query for a page of the readings matching `filter`; like the
+/// Postgres one, it fetches one row more than the limit and computes rolling
+/// averages over every matching row.
+fn readings_query<'a>(
+    filter: &'a ReadingsFilter,
+    page: &'a ReadingsPage,
+) -> QueryBuilder<'a, MySql> {
+    // ---
+    let columns = match &page.columns {
+        Some(columns) => columns.join(", "),
+        None => READING_COLUMNS.to_string(),
+    };
+    let mut query = QueryBuilder::new(format!("SELECT {columns}"));
+
+    if let Some(window) = page.rolling_avg {
+        let over = format!(
+            "OVER (PARTITION BY device_id ORDER BY timestamp_utc, id \
+             ROWS BETWEEN {} PRECEDING AND CURRENT ROW)",
+            window.saturating_sub(1)
+        );
+        query.push(format!(
+            ", AVG(temperature_c) {over} AS rolling_temperature_c, \
+             AVG(humidity) {over} AS rolling_humidity"
+        ));
+    }
+    query.push(" FROM sensor_data WHERE 1=1");
+
+    if let Some(fraction) = page.sample {
+        query.push(format!(" AND MOD(id * {SAMPLE_HASH}, {SAMPLE_BUCKETS}) < "));
+        query.push_bind((fraction * SAMPLE_BUCKETS as f64) as i64);
+    }
+    push_conditions(&mut query, filter);
+
+    if let Some(c) = &page.after {
+        push_after(&mut query, page.sort, c);
+    }
+
+    query.push(" ORDER BY ");
+    query.push(page.sort.order_by());
+    query.push(" LIMIT ");
+    query.push_bind(page.limit as i64 + 1);
+    query
+}
+
+/// Load a page of the readings matching `filter`, and the cursor of the next
+/// page: the last row of this one, when more rows follow.
+async fn load_readings(
+    pool: &MySqlPool,
+    filter: &ReadingsFilter,
+    page: &ReadingsPage,
+) -> Result<Readings, sqlx::Error> {
+    // ---
+    let mut rows = readings_query(filter, page).build().fetch_all(pool).await?;
+
+    let (limit, sort) = (page.limit as usize, page.sort);
+    let next = if rows.len() > limit {
+        rows.truncate(limit);
+        match rows.last() {
+            Some(row) => Some(ReadingsCursor {
+                timestamp_utc: row.try_get("timestamp_utc")?,
+                id: row.try_get("id")?,
+                sort: sort.cursor_tag().map(str::to_string),
+                temperature_c: matches!(sort, Sort::TemperatureDesc | Sort::TemperatureAsc)
+                    .then(|| row.try_get("temperature_c"))
+                    .transpose()?,
+                humidity: matches!(sort, Sort::HumidityDesc | Sort::HumidityAsc)
+                    .then(|| row.try_get("humidity"))
+                    .transpose()?,
+                device_id: (sort == Sort::DeviceId)
+                    .then(|| row.try_get("device_id"))
+                    .transpose()?,
+            }),
+            None => None,
+        }
+    } else {
+        None
+    };
+
+    let readings = rows
+        .iter()
+        .map(|row| reading_from_row(row, page.rolling_avg.is_some()))
+        .collect::<Result<_, _>>()?;
+    Ok((readings, next))
+}
+
+/// The reading in `row`, with the columns it lacks at their defaults;
+/// `rolling` when it carries the rolling averages.
+fn reading_from_row(row: &MySqlRow, rolling: bool) -> Result<SensorReading, sqlx::Error> {
+    // ---
+    type JsonMap = sqlx::types::Json<serde_json::Map<String, serde_json::Value>>;
+    type JsonList<T> = sqlx::types::Json<Vec<T>>;
+    Ok(SensorReading {
+        id: Some(row.try_get("id")?),
+        mesh_id: column_or_default(row, "mesh_id")?,
+        device_id: column_or_default(row, "device_id")?,
+        timestamp_utc: row.try_get("timestamp_utc")?,
+        timestamp_local: None,
+        temperature_c: column_or_default(row, "temperature_c")?,
+        humidity: column_or_default(row, "humidity")?,
+        raw_temperature_c: column_or_default(row, "raw_temperature_c")?,
+        raw_humidity: column_or_default(row, "raw_humidity")?,
+        status: column_or_default(row, "status")?,
+        temperature_alert: column_or_default(row, "temperature_alert")?,
+        humidity_alert: column_or_default(row, "humidity_alert")?,
+        attributes: column_or_default::<Option<JsonMap>>(row, "attributes")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        latitude: column_or_default(row, "latitude")?,
+        longitude: column_or_default(row, "longitude")?,
+        extra: column_or_default::<Option<JsonMap>>(row, "extra")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        quality: column_or_default(row, "quality")?,
+        quality_flags: column_or_default::<Option<JsonList<String>>>(row, "quality_flags")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        invalid: column_or_default(row, "invalid")?,
+        corrections: column_or_default::<Option<JsonList<serde_json::Value>>>(row, "corrections")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        ingest_batch_id: column_or_default(row, "ingest_batch_id")?,
+        smoothed: None,
+        rolling_avg: match rolling {
+            true => Some(Smoothed {
+                temperature_c: row.try_get::<f64, _>("rolling_temperature_c")? as f32,
+                humidity: row.try_get::<f64, _>("rolling_humidity")? as f32,
+            }),
+            false => None,
+        },
+        device: None,
+    })
+}
+
+/// `column` of `row`, or its default when the query didn't select it.
+fn column_or_default<'r, T>(row: &'r MySqlRow, column: &str) -> Result<T, sqlx::Error>
+where
+    T: Default + sqlx::Decode<'r, MySql> + sqlx::Type<MySql>,
+{
+    // ---
+    match row.try_get(column) {
+        Err(sqlx::Error::ColumnNotFound(_)) => Ok(T::default()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use chrono::TimeZone;
+
+    fn page(sort: Sort, limit: u32, after: Option<ReadingsCursor>) -> ReadingsPage {
+        // ---
+        ReadingsPage {
+            sort,
+            limit,
+            after,
+            columns: None,
+            sample: None,
+            rolling_avg: None,
+        }
+    }
+
+    #[test]
+    fn mysql_and_mariadb_urls_are_recognized() {
+        // ---
+        assert!(is_mysql_url("mysql://user:pw@db:3306/sensors"));
+        assert!(is_mysql_url("mariadb://db/sensors"));
+        assert!(!is_mysql_url("postgres://db/sensors"));
+        assert!(!is_mysql_url("sqlite:///var/lib/readings.db"));
+    }
+
+    #[test]
+    fn last_ingests_are_read_within_scope() {
+        // ---
+        let scope = ["mesh-001".to_string(), "mesh-002".to_string()];
+        let meshes = ["mesh-002".to_string()];
+        assert_eq!(
+            last_ingested_query(Some(&scope), Some(&meshes)).sql(),
+            "SELECT MAX(last_ingested_at) FROM mesh_ingest WHERE 1=1 \
+             AND mesh_id IN (?, ?) AND mesh_id IN (?)"
+        );
+        assert_eq!(
+            last_ingested_query(None, None).sql(),
+            "SELECT MAX(last_ingested_at) FROM mesh_ingest WHERE 1=1"
+        );
+    }
+
+    #[test]
+    fn filters_become_bound_mysql_conditions() {
+        // ---
+        let filter = ReadingsFilter {
+            mesh_ids: vec!["mesh-001".into(), "mesh-002".into()],
+            device_id_prefix: Some("rack_1".into()),
+            statuses_not: Some(vec!["ok".into()]),
+            extra_key: Some("pressure".into()),
+            allowed_meshes: Some(vec![]),
+            ..ReadingsFilter::default()
+        };
+        let cursor = ReadingsCursor {
+            timestamp_utc: Utc.with_ymd_and_hms(2025, 3, 21, 0, 0, 0).unwrap(),
+            id: 7,
+            sort: None,
+            temperature_c: None,
+            humidity: None,
+            device_id: None,
+        };
+        let page = ReadingsPage {
+            columns: Some(vec!["id", "timestamp_utc"]),
+            sample: Some(0.5),
+            ..page(Sort::TimestampDesc, 50, Some(cursor))
+        };
+        assert_eq!(
+            readings_query(&filter, &page).sql(),
+            "SELECT id, timestamp_utc FROM sensor_data WHERE 1=1 \
+             AND MOD(id * 2654435761, 1000000) < ? \
+             AND deleted_at IS NULL AND mesh_id IN (?, ?) AND device_id LIKE ? \
+             AND status NOT IN (?) \
+             AND JSON_CONTAINS(JSON_KEYS(extra), JSON_QUOTE(?)) AND FALSE \
+             AND (timestamp_utc, id) < (?, ?) \
+             ORDER BY timestamp_utc DESC, id DESC LIMIT ?"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Native monthly partitioning of `sensor_data` (`PARTITION_BY_MONTH=true`).
+//!
+//! For deployments without an extension such as Timescale, `sensor_data` can
+//! be created as a declaratively partitioned table, ranged on `timestamp_utc`
+//! with one partition per UTC month (`sensor_data_y2026m10`) and a default
+//! partition ([`DEFAULT_PARTITION`]) catching readings outside them. Expired
+//! months can then be dropped whole instead of deleted row by row.
+//!
+//! [`maintain`] runs at startup and every [`CHECK_EVERY`]:
+//! - months with rows in the default partition (backfills, late readings) get
+//!   a partition of their own, and the rows move into it
+//! - partitions for the current month and the next `PARTITION_PREMAKE_MONTHS`
+//!   are created ahead of time
+//! - with `RETENTION_MONTHS` set, partitions that ended before the retained
+//!   months are dropped, and older rows in the default partition deleted,
+//!   along with their hourly and daily rollups
+//!
+//! A partitioned table can't be the target of a foreign key, so on such a
+//! database `alert_events` and `source_conflicts` don't reference
+//! `sensor_data`; their rows are deleted here along with the readings they
+//! point to. A reading and its duplicate copies share a timestamp, so they
+//! always expire together.
+//!
+//! Partitioning only applies to a new database: an existing unpartitioned
+//! `sensor_data` is never converted.
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{Datelike, Months, NaiveDate, NaiveTime, Utc};
+use serde_json::json;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+
+use crate::{expire_rollups_before, record_event, EventKind, PartitionConfig};
+
+/// Partition holding readings outside every monthly partition.
+pub const DEFAULT_PARTITION: &str = "sensor_data_default";
+
+/// How often partitions are created and expired.
+pub const CHECK_EVERY: Duration = Duration::from_secs(3600);
+
+// ---
+
+/// Create `sensor_data` as a partitioned table unless it exists already.
+///
+/// Fails if `sensor_data` exists unpartitioned.
+pub async fn create_partitioned_table(conn: &mut PgConnection) -> anyhow::Result<()> {
+    // ---
+    match table_kind(conn).await? {
+        None => {}
+        Some(PARTITIONED) => return Ok(()),
+        Some(_) => {
+            return Err(anyhow!(
+                "PARTITION_BY_MONTH is set but sensor_data already exists unpartitioned; \
+                 partitioning only applies to a new database"
+            ))
+        }
+    }
+
+    // Same columns as the unpartitioned table; the primary key must include
+    // the partition key, and `duplicate_of` can't be a foreign key
+    sqlx::query(
+        r#"
+        CREATE TABLE sensor_data (
+            id                SERIAL,
+            mesh_id           TEXT        NOT NULL,
+            device_id         TEXT        NOT NULL,
+            timestamp_utc     TIMESTAMPTZ NOT NULL,
+            temperature_c     REAL        NOT NULL,
+            humidity          REAL        NOT NULL,
+            status            TEXT,
+            temperature_alert BOOLEAN,
+            humidity_alert    BOOLEAN,
+            source            TEXT        NOT NULL DEFAULT 'default',
+            attributes        JSONB       NOT NULL DEFAULT '{}',
+            duplicate_of      INTEGER,
+            PRIMARY KEY (id, timestamp_utc)
+        ) PARTITION BY RANGE (timestamp_utc);
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(&format!(
+        "CREATE TABLE {DEFAULT_PARTITION} PARTITION OF sensor_data DEFAULT"
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::info!("Created sensor_data partitioned by month");
+    Ok(())
+}
+
+/// Whether `sensor_data` exists as a partitioned table.
+pub async fn is_partitioned(conn: &mut PgConnection) -> Result<bool, sqlx::Error> {
+    // ---
+    Ok(table_kind(conn).await? == Some(PARTITIONED))
+}
+
+/// `pg_class.relkind` of a partitioned table.
+const PARTITIONED: i8 = b'p' as i8;
+
+async fn table_kind(conn: &mut PgConnection) -> Result<Option<i8>, sqlx::Error> {
+    // ---
+    sqlx::query_scalar("SELECT relkind FROM pg_class WHERE oid = to_regclass('sensor_data')")
+        .fetch_optional(conn)
+        .await
+}
+
+/// Names of the partitions of `sensor_data`, the default one included.
+pub async fn list_partitions<'c>(db: impl PgExecutor<'c>) -> Result<Vec<String>, sqlx::Error> {
+    // ---
+    sqlx::query_scalar(
+        r#"
+        SELECT c.relname::TEXT
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = 'sensor_data'::regclass
+        ORDER BY c.relname
+        "#,
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Run [`maintain`] every [`CHECK_EVERY`], after the one at startup.
+pub fn spawn_maintenance(pool: PgPool, cfg: PartitionConfig) {
+    // ---
+    tokio::spawn(async move {
+        // ---
+        let mut ticker = tokio::time::interval(CHECK_EVERY);
+        ticker.tick().await; // first tick completes immediately
+
+        loop {
+            ticker.tick().await;
+            if let Err(e) = maintain(&pool, &cfg).await {
+                tracing::error!("Partition maintenance failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Split months out of the default partition, create upcoming partitions,
+/// and expire old ones per `cfg`.
+pub async fn maintain(pool: &PgPool, cfg: &PartitionConfig) -> Result<(), sqlx::Error> {
+    // ---
+    let this_month = month_start(Utc::now().date_naive());
+    let cutoff = cfg
+        .retention_months
+        .map(|months| this_month - Months::new(months));
+
+    let mut expired = 0;
+    if let Some(cutoff) = cutoff {
+        let purged = purge_default(pool, cutoff).await?;
+        if purged > 0 {
+            tracing::info!(
+                "Deleted {} readings before {} from {}",
+                purged,
+                cutoff,
+                DEFAULT_PARTITION
+            );
+            record_event(
+                pool,
+                EventKind::PartitionDropped,
+                json!({ "partition": DEFAULT_PARTITION, "before": cutoff, "rows": purged }),
+            )
+            .await;
+            expired += purged;
+        }
+    }
+
+    let existing = list_partitions(pool).await?;
+    let mut wanted = months_in_default(pool).await?;
+    wanted.extend((0..=cfg.premake_months).map(|n| this_month + Months::new(n)));
+    wanted.retain(|month| cutoff.is_none_or(|cutoff| *month >= cutoff));
+    wanted.sort_unstable();
+    wanted.dedup();
+    for month in wanted {
+        let name = partition_name(month);
+        if !existing.contains(&name) {
+            let moved = create_partition(pool, month).await?;
+            tracing::info!("Created partition {} ({} readings moved in)", name, moved);
+        }
+    }
+
+    let Some(cutoff) = cutoff else {
+        return Ok(());
+    };
+    for name in existing {
+        let Some(month) = partition_month(&name) else {
+            continue;
+        };
+        if month + Months::new(1) <= cutoff {
+            let rows = drop_partition(pool, &name).await?;
+            tracing::info!("Dropped expired partition {} ({} readings)", name, rows);
+            record_event(
+                pool,
+                EventKind::PartitionDropped,
+                json!({ "partition": name, "before": cutoff, "rows": rows }),
+            )
+            .await;
+            expired += rows;
+        }
+    }
+
+    if expired > 0 {
+        // Also takes the expired readings out of mesh_summary
+        expire_rollups_before(pool, cutoff.and_time(NaiveTime::MIN).and_utc()).await?;
+    }
+    Ok(())
+}
+
+/// Months, as their first day, of the rows in the default partition.
+async fn months_in_default(pool: &PgPool) -> Result<Vec<NaiveDate>, sqlx::Error> {
+    // ---
+    sqlx::query_scalar(&format!(
+        "SELECT DISTINCT date_trunc('month', timestamp_utc AT TIME ZONE 'UTC')::DATE \
+         FROM {DEFAULT_PARTITION}"
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+/// Create and attach the partition for `month`, moving in its rows from the
+/// default partition; returns the number moved.
+async fn create_partition(pool: &PgPool, month: NaiveDate) -> Result<u64, sqlx::Error> {
+    // ---
+    let name = partition_name(month);
+    let (from, to) = bounds(month);
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(&format!(
+        "CREATE TABLE {name} (LIKE sensor_data INCLUDING DEFAULTS)"
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    // Attaching fails while the default partition holds rows in range
+    let moved = sqlx::query(&format!(
+        r#"
+        WITH moved AS (
+            DELETE FROM {DEFAULT_PARTITION}
+            WHERE timestamp_utc >= '{from}' AND timestamp_utc < '{to}'
+            RETURNING *
+        )
+        INSERT INTO {name} SELECT * FROM moved
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    // Indexes and the primary key are created on the new partition to match
+    sqlx::query(&format!(
+        "ALTER TABLE sensor_data ATTACH PARTITION {name} FOR VALUES FROM ('{from}') TO ('{to}')"
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(moved)
+}
+
+/// Drop partition `name` along with the alert events and conflicts of its
+/// readings; returns the number of readings dropped.
+async fn drop_partition(pool: &PgPool, name: &str) -> Result<u64, sqlx::Error> {
+    // ---
+    let mut tx = pool.begin().await?;
+    let rows: i64 = sqlx::query_scalar(&format!(
+        r#"
+        WITH gone AS (SELECT id FROM {name}),
+        alerts AS (
+            DELETE FROM alert_events WHERE reading_id IN (SELECT id FROM gone)
+        ),
+        conflicts AS (
+            DELETE FROM source_conflicts
+            WHERE reading_id IN (SELECT id FROM gone) OR preferred_id IN (SELECT id FROM gone)
+        )
+        SELECT COUNT(*) FROM gone
+        "#
+    ))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!("DROP TABLE {name}"))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(rows as u64)
+}
+
+/// Delete default-partition rows before `cutoff`, with their alert events and
+/// conflicts; returns the number of readings deleted.
+async fn purge_default(pool: &PgPool, cutoff: NaiveDate) -> Result<u64, sqlx::Error> {
+    // ---
+    let (cutoff, _) = bounds(cutoff);
+    let rows: i64 = sqlx::query_scalar(&format!(
+        r#"
+        WITH gone AS (
+            DELETE FROM {DEFAULT_PARTITION} WHERE timestamp_utc < '{cutoff}' RETURNING id
+        ),
+        alerts AS (
+            DELETE FROM alert_events WHERE reading_id IN (SELECT id FROM gone)
+        ),
+        conflicts AS (
+            DELETE FROM source_conflicts
+            WHERE reading_id IN (SELECT id FROM gone) OR preferred_id IN (SELECT id FROM gone)
+        )
+        SELECT COUNT(*) FROM gone
+        "#
+    ))
+    .fetch_one(pool)
+    .await?;
+    Ok(rows as u64)
+}
+
+fn month_start(day: NaiveDate) -> NaiveDate {
+    // ---
+    day.with_day(1).expect("every month has a first day")
+}
+
+/// `sensor_data_y2026m10` for October 2026.
+fn partition_name(month: NaiveDate) -> String {
+    // ---
+    format!("sensor_data_y{:04}m{:02}", month.year(), month.month())
+}
+
+/// The month a partition created by [`maintain`] covers; `None` for others.
+fn partition_month(name: &str) -> Option<NaiveDate> {
+    // ---
+    let rest = name.strip_prefix("sensor_data_y")?;
+    let (year, month) = rest.split_once('m')?;
+    if year.len() != 4 || month.len() != 2 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+}
+
+/// Partition bounds for `month` as UTC timestamp literals: `[from, to)`.
+fn bounds(month: NaiveDate) -> (String, String) {
+    // ---
+    let literal = |day: NaiveDate| format!("{} 00:00:00+00", day.format("%Y-%m-%d"));
+    (literal(month), literal(month + Months::new(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        // ---
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn partition_names_round_trip_to_their_month() {
+        // ---
+        let month = month_start(date(2026, 10, 14));
+        assert_eq!(partition_name(month), "sensor_data_y2026m10");
+        assert_eq!(partition_month("sensor_data_y2026m10"), Some(month));
+        assert_eq!(partition_month(DEFAULT_PARTITION), None);
+        assert_eq!(partition_month("sensor_data_y2026m13"), None);
+        assert_eq!(partition_month("sensor_data_y26m1"), None);
+    }
+
+    #[test]
+    fn bounds_cover_one_utc_month() {
+        // ---
+        let (from, to) = bounds(date(2026, 12, 1));
+        assert_eq!(from, "2026-12-01 00:00:00+00");
+        assert_eq!(to, "2027-01-01 00:00:00+00");
+    }
+}
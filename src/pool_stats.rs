@@ -0,0 +1,246 @@
+//! Database connection pool statistics.
+//!
+//! [`PoolMonitor::spawn`] samples the pool every `POOL_SAMPLE_SECS`:
+//! - `size`: open connections, up to `max` (`DB_POOL_MAX`)
+//! - `idle`: open connections not currently running a query
+//! - `acquire_ms`: how long checking out a connection took; the probe waits
+//!   in line like any query would, so it measures the current wait
+//! - `saturated`: no connection was free when the sample was taken, so a
+//!   query issued at that moment had to wait for one
+//!
+//! sqlx does not expose the number of tasks waiting for a connection, so
+//! waiting shows up as saturated samples and acquire latency instead. Each
+//! sample is published as `sensorflow_db_pool_*` metrics and kept for
+//! `GET /admin/pool`, which also summarizes the last [`WINDOW`] samples: a
+//! pool that is often saturated or slow to hand out connections is too small.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    POOL_ACQUIRE_SECONDS, POOL_CONNECTIONS, POOL_IDLE, POOL_MAX_CONNECTIONS, POOL_SATURATED,
+};
+
+/// Samples summarized by [`PoolReport::recent`].
+pub const WINDOW: usize = 60;
+
+// ---
+
+/// One look at the pool.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolSample {
+    // ---
+    pub sampled_at: DateTime<Utc>,
+    pub max: u32,
+    pub size: u32,
+    pub idle: u32,
+    pub in_use: u32,
+    pub saturated: bool,
+
+    /// Time to check out a connection; `None` if it failed or timed out.
+    pub acquire_ms: Option<f64>,
+}
+
+/// Summary of the most recent samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentSamples {
+    // ---
+    pub samples: usize,
+    pub saturated: usize,
+    pub failed_acquires: usize,
+    pub max_in_use: u32,
+    pub avg_acquire_ms: Option<f64>,
+    pub max_acquire_ms: Option<f64>,
+}
+
+/// Body of `GET /admin/pool`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolReport {
+    // ---
+    pub interval_secs: u64,
+
+    /// `None` until the first sample is taken.
+    pub current: Option<PoolSample>,
+    pub recent: RecentSamples,
+}
+
+/// Handle to the sampler's results; clone freely.
+#[derive(Clone)]
+pub struct PoolMonitor {
+    // ---
+    interval: Duration,
+    samples: Arc<Mutex<VecDeque<PoolSample>>>,
+}
+
+impl PoolMonitor {
+    // ---
+    /// Start sampling `pool` every `interval`.
+    pub fn spawn(pool: PgPool, interval: Duration) -> Self {
+        // ---
+        let monitor = Self {
+            interval,
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(WINDOW))),
+        };
+
+        let sampler = monitor.clone();
+        tokio::spawn(async move {
+            // ---
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let sample = take_sample(&pool, interval).await;
+                publish(&sample);
+                sampler.push(sample);
+            }
+        });
+        monitor
+    }
+
+    /// The latest sample and a summary of the recent ones.
+    pub fn report(&self) -> PoolReport {
+        // ---
+        let samples = lock(&self.samples);
+        PoolReport {
+            interval_secs: self.interval.as_secs(),
+            current: samples.back().cloned(),
+            recent: summarize(samples.iter()),
+        }
+    }
+
+    fn push(&self, sample: PoolSample) {
+        // ---
+        let mut samples = lock(&self.samples);
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+}
+
+/// Read the pool counters, then time checking out a connection.
+///
+/// The checkout gives up after `timeout` so a wedged pool can't stall sampling.
+async fn take_sample(pool: &PgPool, timeout: Duration) -> PoolSample {
+    // ---
+    let max = pool.options().get_max_connections();
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+
+    let started = Instant::now();
+    let acquire_ms = match tokio::time::timeout(timeout, pool.acquire()).await {
+        Ok(Ok(_conn)) => Some(started.elapsed().as_secs_f64() * 1000.0),
+        Ok(Err(e)) => {
+            tracing::warn!("Pool sample could not acquire a connection: {}", e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("Pool sample timed out acquiring a connection");
+            None
+        }
+    };
+
+    PoolSample {
+        sampled_at: Utc::now(),
+        max,
+        size,
+        idle,
+        in_use: size.saturating_sub(idle),
+        saturated: idle == 0 && size >= max,
+        acquire_ms,
+    }
+}
+
+fn publish(sample: &PoolSample) {
+    // ---
+    metrics::gauge!(POOL_MAX_CONNECTIONS).set(sample.max);
+    metrics::gauge!(POOL_CONNECTIONS).set(sample.size);
+    metrics::gauge!(POOL_IDLE).set(sample.idle);
+    metrics::gauge!(POOL_SATURATED).set(if sample.saturated { 1.0 } else { 0.0 });
+    if let Some(ms) = sample.acquire_ms {
+        metrics::histogram!(POOL_ACQUIRE_SECONDS).record(ms / 1000.0);
+    }
+}
+
+fn summarize<'a>(samples: impl Iterator<Item = &'a PoolSample>) -> RecentSamples {
+    // ---
+    let mut recent = RecentSamples {
+        samples: 0,
+        saturated: 0,
+        failed_acquires: 0,
+        max_in_use: 0,
+        avg_acquire_ms: None,
+        max_acquire_ms: None,
+    };
+    let mut acquire_total = 0.0;
+    let mut acquired = 0;
+    for s in samples {
+        recent.samples += 1;
+        recent.saturated += usize::from(s.saturated);
+        recent.max_in_use = recent.max_in_use.max(s.in_use);
+        match s.acquire_ms {
+            Some(ms) => {
+                acquire_total += ms;
+                acquired += 1;
+                recent.max_acquire_ms = Some(recent.max_acquire_ms.map_or(ms, |m| m.max(ms)));
+            }
+            None => recent.failed_acquires += 1,
+        }
+    }
+    if acquired > 0 {
+        recent.avg_acquire_ms = Some(acquire_total / f64::from(acquired));
+    }
+    recent
+}
+
+/// Lock ignoring poisoning; the guarded data is a plain sample buffer.
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // ---
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn sample(in_use: u32, acquire_ms: Option<f64>) -> PoolSample {
+        // ---
+        PoolSample {
+            sampled_at: Utc::now(),
+            max: 5,
+            size: 5,
+            idle: 5 - in_use,
+            in_use,
+            saturated: in_use == 5,
+            acquire_ms,
+        }
+    }
+
+    #[test]
+    fn summary_counts_saturation_and_acquire_latency() {
+        // ---
+        let samples = [
+            sample(1, Some(1.0)),
+            sample(5, Some(9.0)),
+            sample(5, None),
+            sample(2, Some(2.0)),
+        ];
+        let recent = summarize(samples.iter());
+        assert_eq!(recent.samples, 4);
+        assert_eq!(recent.saturated, 2);
+        assert_eq!(recent.failed_acquires, 1);
+        assert_eq!(recent.max_in_use, 5);
+        assert_eq!(recent.avg_acquire_ms, Some(4.0));
+        assert_eq!(recent.max_acquire_ms, Some(9.0));
+
+        let empty = summarize([].iter());
+        assert_eq!(empty.samples, 0);
+        assert_eq!(empty.avg_acquire_ms, None);
+    }
+}
@@ -0,0 +1,68 @@
+//! Prometheus metrics export.
+//!
+//! Metrics are recorded wherever they occur with the `metrics` crate macros
+//! (`counter!`, `gauge!`, ...) into the process-wide recorder installed by
+//! [`install`] at startup; `GET /metrics` renders them in the Prometheus text
+//! format. Metric names are prefixed `sensorflow_` and declared here.
+use anyhow::{anyhow, Result};
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+/// Queries slower than `SLOW_QUERY_MS`, labelled by `query` and `filters`.
+pub const SLOW_QUERIES_TOTAL: &str = "sensorflow_slow_queries_total";
+
+/// Connection pool samples (see `pool_stats.rs`).
+pub const POOL_CONNECTIONS: &str = "sensorflow_db_pool_connections";
+pub const POOL_IDLE: &str = "sensorflow_db_pool_idle_connections";
+pub const POOL_MAX_CONNECTIONS: &str = "sensorflow_db_pool_max_connections";
+pub const POOL_SATURATED: &str = "sensorflow_db_pool_saturated";
+pub const POOL_ACQUIRE_SECONDS: &str = "sensorflow_db_pool_acquire_seconds";
+
+/// Response cache lookups (see `response_cache.rs`), labelled by `kind` and
+/// `result` (`hit` or `miss`).
+pub const RESPONSE_CACHE_TOTAL: &str = "sensorflow_response_cache_requests_total";
+
+/// 1 while reads go to the replica (`DATABASE_REPLICA_URL`), 0 while on the primary.
+pub const REPLICA_HEALTHY: &str = "sensorflow_db_replica_healthy";
+
+// ---
+
+/// Install the global recorder; the handle renders the scrape payload.
+pub fn install() -> Result<PrometheusHandle> {
+    // ---
+    let handle = PrometheusBuilder::new()
+        .install_recorder()
+        .map_err(|e| anyhow!("Failed to install metrics recorder: {e}"))?;
+
+    describe_counter!(
+        SLOW_QUERIES_TOTAL,
+        Unit::Count,
+        "Database queries that took at least SLOW_QUERY_MS"
+    );
+    describe_gauge!(POOL_CONNECTIONS, Unit::Count, "Open database connections");
+    describe_gauge!(
+        POOL_IDLE,
+        Unit::Count,
+        "Open database connections not running a query"
+    );
+    describe_gauge!(POOL_MAX_CONNECTIONS, Unit::Count, "DB_POOL_MAX");
+    describe_gauge!(
+        POOL_SATURATED,
+        "1 when the last sample found no free connection, so queries had to wait"
+    );
+    describe_histogram!(
+        POOL_ACQUIRE_SECONDS,
+        Unit::Seconds,
+        "Time the pool sampler waited to check out a connection"
+    );
+    describe_counter!(
+        RESPONSE_CACHE_TOTAL,
+        Unit::Count,
+        "Response cache lookups, by query kind and hit or miss"
+    );
+    describe_gauge!(
+        REPLICA_HEALTHY,
+        "1 while reads are routed to the read replica, 0 while they fall back to the primary"
+    );
+    Ok(handle)
+}
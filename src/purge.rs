@@ -0,0 +1,75 @@
+//! Permanent removal of soft-deleted readings.
+//!
+//! `DELETE /v1/readings` only tombstones readings (see `routes/delete.rs`), so
+//! an accidental deletion can be restored. [`purge_deleted`], run by the
+//! `purge` command, removes tombstoned rows for good once that's no longer
+//! wanted, [`BATCH`] per statement, together with the `source_conflicts` rows
+//! naming them (foreign keys do that only while `sensor_data` isn't
+//! partitioned). Duplicates are reconciled again afterwards, so no copy is
+//! left pointing at a purged one.
+use chrono::Duration;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{reconcile_sources, record_event, EventKind};
+
+/// Readings removed per statement.
+const BATCH: i64 = 5_000;
+
+// ---
+
+/// Remove the readings soft-deleted at least `deleted_before` ago (all
+/// soft-deleted readings when `None`); returns how many were removed.
+#[tracing::instrument(name = "db.purge_deleted", skip_all)]
+pub async fn purge_deleted(
+    pool: &PgPool,
+    deleted_before: Option<Duration>,
+    priority: &[String],
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let secs = deleted_before.map_or(0, |d| d.num_seconds()) as f64;
+    let mut purged = 0;
+    loop {
+        let n: i64 = sqlx::query_scalar(
+            r#"
+            WITH gone AS (
+                DELETE FROM sensor_data
+                WHERE id IN (
+                    SELECT id FROM sensor_data
+                    WHERE deleted_at <= now() - make_interval(secs => $1)
+                    LIMIT $2
+                )
+                RETURNING id
+            ), conflicts AS (
+                DELETE FROM source_conflicts
+                WHERE reading_id IN (SELECT id FROM gone) OR preferred_id IN (SELECT id FROM gone)
+            ), alerts AS (
+                DELETE FROM alert_events WHERE reading_id IN (SELECT id FROM gone)
+            )
+            SELECT COUNT(*) FROM gone
+            "#,
+        )
+        .bind(secs)
+        .bind(BATCH)
+        .fetch_one(pool)
+        .await?;
+        purged += n as u64;
+        if n < BATCH {
+            break;
+        }
+    }
+
+    if purged > 0 {
+        reconcile_sources(pool, priority).await?;
+        record_event(
+            pool,
+            EventKind::ReadingsPurged,
+            json!({
+                "readings": purged,
+                "deleted_before_secs": deleted_before.map(|d| d.num_seconds()),
+            }),
+        )
+        .await;
+    }
+    Ok(purged)
+}
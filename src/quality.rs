@@ -0,0 +1,182 @@
+//! Data quality scoring of readings at ingest.
+//!
+//! Every reading stored by an ingest or push is checked for the problems in
+//! [`QualityFlag`]. Each flag found takes its [`QualityFlag::penalty`] off a
+//! perfect score of 1.0 (never below 0), and the score and flags are stored
+//! with the reading in `quality` and `quality_flags`. Readings stored before
+//! scoring existed have no score.
+//!
+//! Checks run on the values as stored, i.e. after calibration.
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+use crate::{RawSensorReading, SensorReading};
+
+/// How far ahead of the ingesting host a device clock may run.
+pub const CLOCK_TOLERANCE: Duration = Duration::minutes(5);
+
+/// Physically plausible temperatures for the deployed sensors, in °C.
+const TEMPERATURE_RANGE: std::ops::RangeInclusive<f32> = -40.0..=85.0;
+
+// ---
+
+/// A problem found in a reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityFlag {
+    // ---
+    /// Temperature outside what the sensors can measure, or humidity outside 0-100 %.
+    OutOfRange,
+
+    /// The same batch held another reading of the device at the same
+    /// timestamp, with different values.
+    DuplicateTimestamp,
+
+    /// The timestamp is more than [`CLOCK_TOLERANCE`] in the future, or
+    /// before 2000 (an unset device clock).
+    StaleClock,
+
+    /// `status` is missing, or it, `mesh_id` or `device_id` is empty.
+    MissingFields,
+}
+
+impl QualityFlag {
+    // ---
+    /// Name as stored in `quality_flags`.
+    pub fn as_str(self) -> &'static str {
+        // ---
+        match self {
+            Self::OutOfRange => "out_of_range",
+            Self::DuplicateTimestamp => "duplicate_timestamp",
+            Self::StaleClock => "stale_clock",
+            Self::MissingFields => "missing_fields",
+        }
+    }
+
+    /// Amount taken off the score when the flag is raised.
+    pub fn penalty(self) -> f32 {
+        // ---
+        match self {
+            Self::OutOfRange => 0.5,
+            Self::DuplicateTimestamp | Self::StaleClock | Self::MissingFields => 0.25,
+        }
+    }
+}
+
+/// Score and flag each of `transformed`, the readings of one batch
+/// transformed from `raw` (in the same order), as ingested at `now`.
+pub fn assess_batch(
+    raw: &[RawSensorReading],
+    transformed: &mut [SensorReading],
+    now: DateTime<Utc>,
+) {
+    // ---
+    // Devices and timestamps with copies whose values differ
+    let mut first = HashMap::new();
+    let mut conflicts = HashSet::new();
+    for r in raw {
+        let key = (r.device_id.as_str(), r.timestamp);
+        let values = (r.temperature_c, r.humidity, r.status.as_str());
+        if *first.entry(key).or_insert(values) != values {
+            conflicts.insert(key);
+        }
+    }
+
+    for (r, t) in raw.iter().zip(transformed) {
+        let conflicting = conflicts.contains(&(r.device_id.as_str(), r.timestamp));
+        let flags = flags_for(t, conflicting, now);
+        t.quality = Some(score(&flags));
+        t.quality_flags = flags.iter().map(|f| f.as_str().to_string()).collect();
+    }
+}
+
+fn flags_for(reading: &SensorReading, conflicting: bool, now: DateTime<Utc>) -> Vec<QualityFlag> {
+    // ---
+    let unset_clock = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+    let mut flags = Vec::new();
+    if !TEMPERATURE_RANGE.contains(&reading.temperature_c)
+        || !(0.0..=100.0).contains(&reading.humidity)
+    {
+        flags.push(QualityFlag::OutOfRange);
+    }
+    if conflicting {
+        flags.push(QualityFlag::DuplicateTimestamp);
+    }
+    if reading.timestamp_utc > now + CLOCK_TOLERANCE || reading.timestamp_utc < unset_clock {
+        flags.push(QualityFlag::StaleClock);
+    }
+    if [&reading.mesh_id, &reading.device_id, &reading.status]
+        .iter()
+        .any(|f| f.trim().is_empty())
+    {
+        flags.push(QualityFlag::MissingFields);
+    }
+    flags
+}
+
+/// 1.0 less the penalties of `flags`, at least 0.
+fn score(flags: &[QualityFlag]) -> f32 {
+    // ---
+    (1.0 - flags.iter().map(|f| f.penalty()).sum::<f32>()).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn raw(device_id: &str, temperature_c: f32, status: &str) -> RawSensorReading {
+        // ---
+        RawSensorReading {
+            mesh_id: "mesh-001".into(),
+            device_id: device_id.into(),
+            timestamp: Utc.with_ymd_and_hms(2025, 3, 26, 18, 45, 0).unwrap(),
+            temperature_c,
+            humidity: 50.0,
+            status: status.into(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
+        }
+    }
+
+    fn assess(batch: &[RawSensorReading], now: DateTime<Utc>) -> Vec<SensorReading> {
+        // ---
+        let mut transformed: Vec<_> = batch.iter().map(|r| r.to_transformed()).collect();
+        assess_batch(batch, &mut transformed, now);
+        transformed
+    }
+
+    #[test]
+    fn flags_lower_the_score() {
+        // ---
+        let now = Utc.with_ymd_and_hms(2025, 3, 27, 0, 0, 0).unwrap();
+        let got = assess(
+            &[
+                raw("device-A", 21.0, "ok"),
+                raw("device-B", 120.0, ""),
+                raw("device-C", 21.0, "ok"),
+                raw("device-C", 22.0, "ok"),
+            ],
+            now,
+        );
+        assert_eq!(got[0].quality, Some(1.0));
+        assert!(got[0].quality_flags.is_empty());
+        assert_eq!(got[1].quality, Some(0.25));
+        assert_eq!(got[1].quality_flags, ["out_of_range", "missing_fields"]);
+        assert_eq!(got[2].quality_flags, ["duplicate_timestamp"]);
+        assert_eq!(got[3].quality, Some(0.75));
+    }
+
+    #[test]
+    fn identical_copies_and_small_skew_are_fine() {
+        // ---
+        let reading = raw("device-A", 21.0, "ok");
+        let now = reading.timestamp - (CLOCK_TOLERANCE - Duration::minutes(1));
+        let got = assess(&[raw("device-A", 21.0, "ok"), reading], now);
+        assert!(got.iter().all(|t| t.quality == Some(1.0)));
+
+        let got = assess(&[raw("device-A", 21.0, "ok")], now - Duration::minutes(2));
+        assert_eq!(got[0].quality_flags, ["stale_clock"]);
+    }
+}
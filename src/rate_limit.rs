@@ -0,0 +1,192 @@
+//! Per-client request rate limiting.
+//!
+//! [`rate_limit`] is a token-bucket middleware: each client earns
+//! `RATE_LIMIT_PER_SEC` tokens per second up to `RATE_LIMIT_BURST`, and every
+//! request spends one. Clients are keyed by their API key when it is a
+//! configured one, otherwise by peer IP, so random keys can't mint fresh
+//! buckets. An empty bucket gets 429 with a `Retry-After` header.
+//!
+//! The limiter lives outside authentication so it also shields the auth
+//! checks, and guards `/sql/readings`, whose first call can trigger an
+//! expensive ingest.
+//!
+//! The limits are read from [`LiveConfig`] on every request, so a reload can
+//! change them or turn limiting on or off; while it is off the middleware
+//! lets everything through.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{ApiError, ApiKeyConfig, LiveConfig, RateLimitConfig};
+
+/// Prune idle buckets once this many clients are tracked.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// ---
+
+/// Token buckets for every client seen recently.
+pub struct RateLimiter {
+    // ---
+    api_keys: HashMap<String, String>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    // ---
+    pub fn new(api_keys: &[ApiKeyConfig]) -> Self {
+        // ---
+        Self {
+            api_keys: api_keys
+                .iter()
+                .map(|k| (k.key.clone(), k.name.clone()))
+                .collect(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Spend one token for `client` under `limits`, or return how long until
+    /// one is available.
+    fn check(&self, client: &str, now: Instant, limits: &RateLimitConfig) -> Result<(), Duration> {
+        // ---
+        let (per_sec, burst) = (limits.per_sec, f64::from(limits.burst));
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            // Buckets that have refilled completely carry no state worth keeping
+            buckets.retain(|_, b| refill(*b, now, per_sec, burst) < burst);
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(*bucket, now, per_sec, burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+fn refill(bucket: Bucket, now: Instant, per_sec: f64, burst: f64) -> f64 {
+    // ---
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * per_sec).min(burst)
+}
+
+/// Middleware: admit the request or reject it with 429 and `Retry-After`;
+/// its state is the [`AppState`](crate::AppState), for the limiter and the limits.
+///
+/// Needs the server to run with `into_make_service_with_connect_info::<SocketAddr>()`.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    State(live): State<LiveConfig>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // ---
+    let Some(limits) = live.load().rate_limit.clone() else {
+        return next.run(req).await;
+    };
+    let client = match req
+        .headers()
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|key| limiter.api_keys.get(key))
+    {
+        Some(name) => format!("key:{name}"),
+        None => format!("ip:{}", peer.ip()),
+    };
+
+    match limiter.check(&client, Instant::now(), &limits) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            tracing::info!("Rate limited {} (retry in {:?})", client, wait);
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            ApiError::TooManyRequests {
+                error: "rate_limited",
+                hint: "too many requests; retry after the Retry-After delay",
+                retry_after_secs: retry_after,
+            }
+            .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn limits(per_sec: f64, burst: u32) -> RateLimitConfig {
+        // ---
+        RateLimitConfig { per_sec, burst }
+    }
+
+    #[test]
+    fn allows_burst_then_rejects_with_wait() {
+        // ---
+        let (l, rl) = (RateLimiter::new(&[]), limits(2.0, 3));
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            assert!(l.check("a", t0, &rl).is_ok());
+        }
+        let wait = l.check("a", t0, &rl).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.5).abs() < 1e-6);
+
+        // Other clients have their own bucket
+        assert!(l.check("b", t0, &rl).is_ok());
+    }
+
+    #[test]
+    fn refills_over_time_up_to_burst() {
+        // ---
+        let (l, rl) = (RateLimiter::new(&[]), limits(1.0, 2));
+        let t0 = Instant::now();
+        assert!(l.check("a", t0, &rl).is_ok());
+        assert!(l.check("a", t0, &rl).is_ok());
+        assert!(l.check("a", t0, &rl).is_err());
+
+        let later = t0 + Duration::from_secs(10);
+        assert!(l.check("a", later, &rl).is_ok());
+        assert!(l.check("a", later, &rl).is_ok());
+        assert!(l.check("a", later, &rl).is_err());
+    }
+
+    #[test]
+    fn changed_limits_apply_to_existing_buckets() {
+        // ---
+        let l = RateLimiter::new(&[]);
+        let t0 = Instant::now();
+        assert!(l.check("a", t0, &limits(1.0, 5)).is_ok());
+
+        // A lower burst caps the tokens the client had saved up
+        let lowered = limits(1.0, 1);
+        assert!(l.check("a", t0, &lowered).is_ok());
+        assert!(l.check("a", t0, &lowered).is_err());
+
+        // A faster rate refills sooner
+        let later = t0 + Duration::from_millis(200);
+        assert!(l.check("a", later, &limits(10.0, 1)).is_ok());
+    }
+}
@@ -0,0 +1,358 @@
+//! Configuration reload without a restart.
+//!
+//! `SIGHUP` or `POST /admin/reload` reads `.env` and the configuration file
+//! again and applies the settings that can change at runtime:
+//! - the upstream sources (`SENSOR_API_*`): on-demand and first-read ingest
+//!   and `/admin/sources` use the new list at once, and the scheduled
+//!   re-ingest loops are restarted for it
+//! - the rate limit (`RATE_LIMIT_PER_SEC`, `RATE_LIMIT_BURST`), which can also
+//!   be turned on or off; clients keep their buckets
+//! - the log level (`AXUM_LOG_LEVEL`), unless `RUST_LOG` is set
+//! - the database URL and credentials, used by connections opened from then
+//!   on; pooled ones are replaced as they reach `DB_MAX_LIFETIME_SECS`
+//!
+//! Settings referring to a secrets backend are read from it again on every
+//! reload, and [`spawn_lease_refresh`] reloads before leased secrets (such as
+//! dynamic database credentials) expire.
+//!
+//! Handlers read them from [`LiveConfig`], swapped whole, so a request sees
+//! either the old settings or the new ones. Variables set in the process
+//! environment or on the command line can't change after startup, and every
+//! other setting keeps its startup value until a restart. An invalid
+//! configuration is rejected and the current settings stay.
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use serde_json::json;
+use sqlx::{postgres::PgConnectOptions, PgPool};
+use tokio::sync::watch;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::{
+    config::load_with_overlay, config_file::Layers, record_event, secrets::Secrets,
+    spawn_scheduled_ingest, Config, Enrichment, EventKind, RateLimitConfig, ScheduledIngest,
+    SourceConfig,
+};
+
+/// Shortest wait before reading leased secrets again.
+const MIN_LEASE_REFRESH: Duration = Duration::from_secs(30);
+
+/// Wait before retrying a failed lease refresh.
+const LEASE_RETRY: Duration = Duration::from_secs(30);
+
+/// Handle replacing the log filter installed by `init_tracing`.
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
+// ---
+
+/// The settings a reload can change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reloadable {
+    // ---
+    pub sources: Vec<SourceConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub log_level: Option<String>,
+    pub db_url: String,
+}
+
+/// The current [`Reloadable`] settings; clones share them.
+#[derive(Clone)]
+pub struct LiveConfig {
+    // ---
+    current: Arc<ArcSwap<Reloadable>>,
+}
+
+/// Applies reloads; see the module docs.
+pub struct Reloader {
+    // ---
+    layers: Layers,
+    live: LiveConfig,
+    log_filter: LogFilter,
+    pool: PgPool,
+    priority: Vec<String>,
+    enrichment: Arc<Enrichment>,
+    secrets: Secrets,
+
+    /// Shortest lease of the secrets read by the last load.
+    lease: watch::Sender<Option<Duration>>,
+
+    /// Held for the whole of a reload, so reloads apply one at a time.
+    scheduled: Mutex<ScheduledIngest>,
+}
+
+impl Reloadable {
+    // ---
+    pub fn from_config(cfg: &Config) -> Self {
+        // ---
+        Reloadable {
+            sources: cfg.sources.clone(),
+            rate_limit: cfg.rate_limit.clone(),
+            log_level: cfg.log_level.clone(),
+            db_url: cfg.db_url.clone(),
+        }
+    }
+
+    /// Names of the settings that differ in `new`.
+    fn changes(&self, new: &Reloadable) -> Vec<&'static str> {
+        // ---
+        let mut changed = Vec::new();
+        if self.sources != new.sources {
+            changed.push("sources");
+        }
+        if self.rate_limit != new.rate_limit {
+            changed.push("rate_limit");
+        }
+        if self.log_level != new.log_level {
+            changed.push("log_level");
+        }
+        if self.db_url != new.db_url {
+            changed.push("database");
+        }
+        changed
+    }
+}
+
+impl LiveConfig {
+    // ---
+    pub fn new(cfg: &Config) -> Self {
+        // ---
+        LiveConfig {
+            current: Arc::new(ArcSwap::from_pointee(Reloadable::from_config(cfg))),
+        }
+    }
+
+    pub fn load(&self) -> Arc<Reloadable> {
+        // ---
+        self.current.load_full()
+    }
+}
+
+impl Reloader {
+    // ---
+    /// `scheduled` are the re-ingest loops started for the startup sources;
+    /// the reloader keeps them running and replaces them when sources change.
+    /// `lease` is the shortest lease of the secrets `cfg` was loaded with.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        layers: Layers,
+        live: LiveConfig,
+        log_filter: LogFilter,
+        pool: PgPool,
+        cfg: &Config,
+        enrichment: Arc<Enrichment>,
+        scheduled: ScheduledIngest,
+        secrets: Secrets,
+        lease: Option<Duration>,
+    ) -> Self {
+        // ---
+        Reloader {
+            layers,
+            live,
+            log_filter,
+            pool,
+            priority: cfg.source_priority.clone(),
+            enrichment,
+            secrets,
+            lease: watch::Sender::new(lease),
+            scheduled: Mutex::new(scheduled),
+        }
+    }
+
+    /// Read the configuration again and apply what changed; returns the
+    /// names of the changed settings, or why the configuration was rejected.
+    /// `trigger` (`sighup`, `api` or `lease`) is recorded with the
+    /// `config_reloaded` event.
+    pub async fn reload(&self, trigger: &str) -> Result<Vec<&'static str>, String> {
+        // ---
+        let loaded = match self.layers.reread() {
+            Ok(overlay) => self.secrets.resolve(overlay).await,
+            Err(e) => Err(e),
+        };
+        let loaded = loaded.and_then(|(overlay, lease)| Ok((load_with_overlay(overlay)?, lease)));
+        let (cfg, lease) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                tracing::error!("Configuration reload failed, keeping the current settings: {e:#}");
+                return Err(format!("{e:#}"));
+            }
+        };
+        let new = Reloadable::from_config(&cfg);
+        let connect = match PgConnectOptions::from_str(&new.db_url) {
+            Ok(connect) => connect,
+            Err(e) => {
+                tracing::error!("Configuration reload failed, keeping the current settings: Invalid DATABASE_URL: {e}");
+                return Err(format!("Invalid DATABASE_URL: {e}"));
+            }
+        };
+
+        let changed = {
+            let mut scheduled = self.scheduled.lock().expect("reload lock poisoned");
+            let changed = self.live.load().changes(&new);
+
+            if changed.contains(&"sources") {
+                // Replacing the handle stops the old loops
+                *scheduled = spawn_scheduled_ingest(
+                    self.pool.clone(),
+                    &new.sources,
+                    &self.priority,
+                    self.enrichment.clone(),
+                );
+            }
+            if changed.contains(&"log_level") {
+                if std::env::var_os("RUST_LOG").is_some() {
+                    tracing::warn!("RUST_LOG is set, so the AXUM_LOG_LEVEL change has no effect");
+                } else if let Err(e) = self.log_filter.reload(log_filter(new.log_level.as_deref()))
+                {
+                    tracing::error!("Replacing the log filter failed: {}", e);
+                }
+            }
+            if changed.contains(&"database") {
+                self.pool.set_connect_options(connect);
+            }
+            self.live.current.store(Arc::new(new));
+            changed
+        };
+        self.lease.send_replace(lease);
+
+        tracing::info!(
+            "Configuration reloaded ({}), changed: {:?}; other settings apply after a restart",
+            trigger,
+            changed
+        );
+        record_event(
+            &self.pool,
+            EventKind::ConfigReloaded,
+            json!({ "trigger": trigger, "changed": changed }),
+        )
+        .await;
+        Ok(changed)
+    }
+}
+
+/// Reload on every `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    // ---
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        // ---
+        while hangups.recv().await.is_some() {
+            // A rejected configuration is logged by reload
+            let _ = reloader.reload("sighup").await;
+        }
+    });
+    Ok(())
+}
+
+/// Without `SIGHUP`, reload through `POST /admin/reload`.
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    // ---
+    tracing::warn!("Configuration reload on SIGHUP is only supported on Unix");
+    Ok(())
+}
+
+/// Reload when two thirds of the shortest secret lease have passed, so
+/// leased credentials are replaced before they expire; every reload starts
+/// the wait again with the lease it read.
+pub fn spawn_lease_refresh(reloader: Arc<Reloader>) {
+    // ---
+    let mut lease = reloader.lease.subscribe();
+    tokio::spawn(async move {
+        // ---
+        let mut retry = false;
+        loop {
+            let current = *lease.borrow_and_update();
+            let wait = match current {
+                _ if retry => LEASE_RETRY,
+                Some(lease) => (lease * 2 / 3).max(MIN_LEASE_REFRESH),
+                None => {
+                    // Nothing leased until a reload reads something that is
+                    if lease.changed().await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                changed = lease.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    retry = false;
+                    continue;
+                }
+            }
+            // A failure is logged by reload
+            retry = reloader.reload("lease").await.is_err();
+        }
+    });
+}
+
+/// The log filter for `level` (`AXUM_LOG_LEVEL`; `debug` when unset or unknown).
+pub fn log_filter(level: Option<&str>) -> EnvFilter {
+    // ---
+    let level = match level {
+        Some("trace") => "trace",
+        Some("debug") => "debug",
+        Some("info") => "info",
+        Some("warn") => "warn",
+        Some("error") => "error",
+        _ => "debug",
+    };
+    EnvFilter::new(format!("{level},sqlx::query=warn"))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    fn source(name: &str, interval_secs: Option<u64>) -> SourceConfig {
+        // ---
+        SourceConfig {
+            name: name.into(),
+            url: format!("http://{name}.example/sensor-data"),
+            max_pages: 100,
+            api_key: None,
+            token: None,
+            auth_header: None,
+            interval_secs,
+        }
+    }
+
+    #[test]
+    fn changes_name_the_settings_that_differ() {
+        // ---
+        let old = Reloadable {
+            sources: vec![source("a", None)],
+            rate_limit: None,
+            log_level: Some("info".into()),
+            db_url: "postgres://app:pw@db/sensors".into(),
+        };
+        assert!(old.changes(&old.clone()).is_empty());
+
+        let new = Reloadable {
+            sources: vec![source("a", Some(60))],
+            rate_limit: Some(RateLimitConfig {
+                per_sec: 5.0,
+                burst: 10,
+            }),
+            ..old.clone()
+        };
+        assert_eq!(old.changes(&new), ["sources", "rate_limit"]);
+
+        let rotated = Reloadable {
+            db_url: "postgres://v-app-x2:pw2@db/sensors".into(),
+            ..old.clone()
+        };
+        assert_eq!(old.changes(&rotated), ["database"]);
+    }
+}
@@ -0,0 +1,1177 @@
+//! Storage of sensor readings.
+//!
+//! Handlers reach `sensor_data` and `mesh_summary`, and what readings are
+//! served with (latest ingests, mesh timezones and device entries), through a
+//! [`ReadingsRepository`] in the application state instead of writing SQL:
+//! they parse a request into a [`ReadingsFilter`] (which readings) and a
+//! [`ReadingsPage`] (which slice of them, in what order and which columns),
+//! and the repository turns those into queries. [`PgReadings`] is the
+//! Postgres implementation; another backend, or an in-memory double in a
+//! test, is another implementation.
+//!
+//! [`PgReadings`] runs reads on the [`ReadPool`] (the replica while healthy)
+//! and writes on the primary. Errors stay `sqlx::Error`, so handlers answer
+//! them with `db_error_response` whatever the backend.
+//!
+//! Filters are applied at the database level with bound parameters.
+//! PostgreSQL picks the index from the filters: single filters use the
+//! single-column indexes on `device_id`, `mesh_id` and `timestamp_utc`,
+//! combined ones prefer the composites `(device_id, timestamp_utc)` and
+//! `(mesh_id, timestamp_utc)`. Slow executions are reported per filter shape
+//! (see `slow_query.rs`).
+use std::{sync::Arc, time::Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use sqlx::{postgres::PgRow, PgPool, Postgres, QueryBuilder, Row};
+
+use crate::{
+    filter_shape, load_aggregates, store_pushed, timed, DeviceInfo, Enrichment, FilterStats,
+    MeshAggregate, RawSensorReading, ReadPool, ReadingsCursor, SensorReading, Smoothed,
+};
+
+// ---
+
+/// A page of readings and the cursor of the page after it, if any.
+pub type Readings = (Vec<SensorReading>, Option<ReadingsCursor>);
+
+/// Where readings are stored; see the module docs.
+pub trait ReadingsRepository: Send + Sync {
+    // ---
+    /// Store readings pushed by a client, tagged with `source`; returns the
+    /// number newly inserted (already stored ones are skipped).
+    fn insert_batch<'a>(
+        &'a self,
+        source: &'a str,
+        readings: &'a [RawSensorReading],
+    ) -> BoxFuture<'a, Result<u64, sqlx::Error>>;
+
+    /// One page of the readings matching `filter`, and the cursor of the next
+    /// page if there is one.
+    fn query<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+        page: &'a ReadingsPage,
+    ) -> BoxFuture<'a, Result<Readings, sqlx::Error>>;
+
+    /// How many readings match `filter`, and the span of their timestamps.
+    fn count<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+    ) -> BoxFuture<'a, Result<ReadingsCount, sqlx::Error>>;
+
+    /// The live reading with `id`, if it is in `meshes` (all when `None`).
+    fn reading<'a>(
+        &'a self,
+        id: i32,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<SensorReading>, sqlx::Error>>;
+
+    /// Newest reading timestamp in `meshes` (all when `None`).
+    fn latest<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>>;
+
+    /// Current mesh summaries for `meshes` (all when `None`), by mesh ID.
+    fn summaries<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Vec<MeshAggregate>, sqlx::Error>>;
+
+    /// Latest ingest into the meshes in `scope` (all when `None`) that
+    /// `meshes` names (all when `None`); `None` when they have none.
+    fn last_ingested<'a>(
+        &'a self,
+        scope: Option<&'a [String]>,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>>;
+
+    /// Registered timezones of `mesh_ids`, as `(mesh_id, timezone)`; meshes
+    /// without one are left out.
+    fn timezones<'a>(
+        &'a self,
+        mesh_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, sqlx::Error>>;
+
+    /// Registry entries for `device_ids`; unregistered devices are left out.
+    fn devices<'a>(
+        &'a self,
+        device_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<DeviceInfo>, sqlx::Error>>;
+}
+
+/// Which readings a query selects; an empty or `None` field doesn't filter.
+/// Soft-deleted readings are never selected.
+#[derive(Debug, Clone, Default)]
+pub struct ReadingsFilter {
+    // ---
+    /// Any of these devices or meshes.
+    pub device_ids: Vec<String>,
+    pub mesh_ids: Vec<String>,
+
+    /// IDs starting with this, taken literally.
+    pub device_id_prefix: Option<String>,
+    pub mesh_id_prefix: Option<String>,
+
+    /// A device-reported status among these; or none of these (a missing
+    /// status is never in the list).
+    pub statuses: Option<Vec<String>>,
+    pub statuses_not: Option<Vec<String>>,
+    pub alert: Option<AlertFilter>,
+
+    /// `[min, max]` of the calibrated values, either end open.
+    pub temperature_c: [Option<f32>; 2],
+    pub humidity: [Option<f32>; 2],
+
+    /// Quality score at least this; unscored readings never match.
+    pub min_quality: Option<f32>,
+
+    /// Carrying this key in `extra`.
+    pub extra_key: Option<String>,
+
+    /// The caller's mesh scope; an empty one matches nothing.
+    pub allowed_meshes: Option<Vec<String>>,
+
+    /// `timestamp_utc` bounds, inclusive.
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+
+    /// Positioned inside the box: by the reading's own coordinates or, for
+    /// fixed devices that report none, those of its `devices` entry; readings
+    /// with neither are left out.
+    pub bbox: Option<BoundingBox>,
+}
+
+/// The alert flags a reading must have set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertFilter {
+    // ---
+    /// Either flag.
+    Any,
+    Temperature,
+    Humidity,
+}
+
+/// Area selected by `bbox`, in WGS84 degrees; `min_lon > max_lon` is a box
+/// across the antimeridian.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// Which part of the matching readings a query returns.
+#[derive(Debug, Clone)]
+pub struct ReadingsPage {
+    // ---
+    pub sort: Sort,
+
+    /// Rows at most, after the row `after` points at (keyset pagination).
+    pub limit: u32,
+    pub after: Option<ReadingsCursor>,
+
+    /// Only load these columns (never from the request, see
+    /// `routes/readings.rs`); the others are left at their defaults (empty,
+    /// zero or `None`) for the caller to drop.
+    pub columns: Option<Vec<&'static str>>,
+
+    /// Sample roughly this fraction (0, 1] of the table.
+    pub sample: Option<f64>,
+
+    /// Add each reading's average over this many of its device's readings.
+    pub rolling_avg: Option<u32>,
+}
+
+/// Row orders `sort` can request. Every order ends in `id`, so keyset pages
+/// are stable across equal sort keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    // ---
+    /// Newest first (the default)
+    TimestampDesc,
+    TimestampAsc,
+    TemperatureDesc,
+    TemperatureAsc,
+    HumidityDesc,
+    HumidityAsc,
+
+    /// By device, each device's readings newest first
+    DeviceId,
+}
+
+/// How many readings match a filter (the body of `GET /v1/readings/count`).
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ReadingsCount {
+    // ---
+    pub count: i64,
+
+    /// Oldest and newest matching `timestamp_utc`; `None` when nothing matches.
+    pub earliest: Option<DateTime<Utc>>,
+    pub latest: Option<DateTime<Utc>>,
+}
+
+/// [`ReadingsRepository`] on Postgres.
+pub struct PgReadings {
+    // ---
+    pool: PgPool,
+    reads: ReadPool,
+
+    /// What pushed readings are reconciled by and enriched with.
+    priority: Vec<String>,
+    enrichment: Arc<Enrichment>,
+
+    /// Filter combinations queried, for the index advisor.
+    filter_stats: Arc<FilterStats>,
+}
+
+impl PgReadings {
+    // ---
+    pub fn new(
+        pool: PgPool,
+        reads: ReadPool,
+        priority: Vec<String>,
+        enrichment: Arc<Enrichment>,
+        filter_stats: Arc<FilterStats>,
+    ) -> Self {
+        // ---
+        Self {
+            pool,
+            reads,
+            priority,
+            enrichment,
+            filter_stats,
+        }
+    }
+}
+
+impl ReadingsRepository for PgReadings {
+    // ---
+    fn insert_batch<'a>(
+        &'a self,
+        source: &'a str,
+        readings: &'a [RawSensorReading],
+    ) -> BoxFuture<'a, Result<u64, sqlx::Error>> {
+        // ---
+        Box::pin(store_pushed(
+            &self.pool,
+            source,
+            readings,
+            &self.priority,
+            &self.enrichment,
+        ))
+    }
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+        page: &'a ReadingsPage,
+    ) -> BoxFuture<'a, Result<Readings, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let started = Instant::now();
+            let loaded = self
+                .reads
+                .read(move |pool| async move { load_readings(&pool, filter, page).await })
+                .await;
+            self.filter_stats
+                .record(&filter.columns(), started.elapsed());
+            loaded
+        })
+    }
+
+    fn count<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+    ) -> BoxFuture<'a, Result<ReadingsCount, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { count_readings(&pool, filter).await }),
+        )
+    }
+
+    fn reading<'a>(
+        &'a self,
+        id: i32,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<SensorReading>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { load_reading(&pool, id, meshes).await }),
+        )
+    }
+
+    fn latest<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { latest_reading(&pool, meshes).await }),
+        )
+    }
+
+    fn summaries<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Vec<MeshAggregate>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { load_aggregates(&pool, meshes).await }),
+        )
+    }
+
+    fn last_ingested<'a>(
+        &'a self,
+        scope: Option<&'a [String]>,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { last_ingested(&pool, scope, meshes).await }),
+        )
+    }
+
+    fn timezones<'a>(
+        &'a self,
+        mesh_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { load_timezones(&pool, mesh_ids).await }),
+        )
+    }
+
+    fn devices<'a>(
+        &'a self,
+        device_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<DeviceInfo>, sqlx::Error>> {
+        // ---
+        Box::pin(
+            self.reads
+                .read(move |pool| async move { load_devices(&pool, device_ids).await }),
+        )
+    }
+}
+
+impl ReadingsFilter {
+    // ---
+    /// `sensor_data` columns this filter narrows, for the index advisor.
+    pub fn columns(&self) -> Vec<&'static str> {
+        // ---
+        let mut cols = Vec::new();
+        if !self.device_ids.is_empty() || self.device_id_prefix.is_some() {
+            cols.push("device_id");
+        }
+        if !self.mesh_ids.is_empty() || self.mesh_id_prefix.is_some() {
+            cols.push("mesh_id");
+        }
+        if self.statuses.is_some() || self.statuses_not.is_some() {
+            cols.push("status");
+        }
+        if self.start.is_some() || self.end.is_some() {
+            cols.push("timestamp_utc");
+        }
+        cols
+    }
+
+    /// [`columns`](Self::columns) plus the other filters set, for slow query reports.
+    fn shape(&self) -> Vec<&'static str> {
+        // ---
+        let mut shape = self.columns();
+        if self.min_quality.is_some() {
+            shape.push("min_quality");
+        }
+        if self.extra_key.is_some() {
+            shape.push("extra_key");
+        }
+        if self.bbox.is_some() {
+            shape.push("bbox");
+        }
+        shape
+    }
+
+    /// Add the `WHERE` conditions of this filter, shared by the readings and
+    /// their count.
+    fn push_conditions<'a>(&'a self, query: &mut QueryBuilder<'a, Postgres>) {
+        // ---
+        // Soft-deleted readings are never served
+        query.push(" AND deleted_at IS NULL");
+
+        // Add device_id and mesh_id filters (use indexes); several values match any
+        for (column, values) in [("device_id", &self.device_ids), ("mesh_id", &self.mesh_ids)] {
+            match values.as_slice() {
+                [] => {}
+                [value] => {
+                    query.push(format!(" AND {column} = "));
+                    query.push_bind(value);
+                }
+                values => {
+                    query.push(format!(" AND {column} = ANY("));
+                    query.push_bind(values);
+                    query.push(")");
+                }
+            }
+        }
+
+        // Ids starting with the prefix (uses the text_pattern_ops indexes)
+        for (column, prefix) in [
+            ("device_id", &self.device_id_prefix),
+            ("mesh_id", &self.mesh_id_prefix),
+        ] {
+            if let Some(prefix) = prefix {
+                query.push(format!(" AND {column} LIKE "));
+                query.push_bind(like_prefix(prefix));
+                query.push(r" ESCAPE '\'");
+            }
+        }
+
+        // Device-reported status; a missing one is never in the list, so `statuses_not` keeps it
+        if let Some(statuses) = &self.statuses {
+            query.push(" AND status = ANY(");
+            query.push_bind(statuses);
+            query.push(")");
+        }
+        if let Some(statuses) = &self.statuses_not {
+            query.push(" AND (status IS NULL OR status <> ALL(");
+            query.push_bind(statuses);
+            query.push("))");
+        }
+
+        // Readings with the requested alert flags set
+        if let Some(alert) = self.alert {
+            query.push(match alert {
+                AlertFilter::Any => " AND (temperature_alert OR humidity_alert)",
+                AlertFilter::Temperature => " AND temperature_alert",
+                AlertFilter::Humidity => " AND humidity_alert",
+            });
+        }
+
+        // Temperature and humidity ranges, either end open
+        for (column, [min, max]) in [
+            ("temperature_c", self.temperature_c),
+            ("humidity", self.humidity),
+        ] {
+            if let Some(min) = min {
+                query.push(format!(" AND {column} >= "));
+                query.push_bind(min);
+            }
+            if let Some(max) = max {
+                query.push(format!(" AND {column} <= "));
+                query.push_bind(max);
+            }
+        }
+
+        // Readings scored at least this high; unscored ones never match
+        if let Some(min) = self.min_quality {
+            query.push(" AND quality >= ");
+            query.push_bind(min);
+        }
+
+        // Readings carrying the extra measurement (uses the GIN index)
+        if let Some(key) = &self.extra_key {
+            query.push(" AND extra ? ");
+            query.push_bind(key);
+        }
+
+        // Restrict to the caller's meshes; an empty scope matches nothing
+        if let Some(allowed) = &self.allowed_meshes {
+            query.push(" AND mesh_id = ANY(");
+            query.push_bind(allowed);
+            query.push(")");
+        }
+
+        // Add timestamp range filter
+        if let Some(start) = self.start {
+            query.push(" AND timestamp_utc >= ");
+            query.push_bind(start);
+        }
+        if let Some(end) = self.end {
+            query.push(" AND timestamp_utc <= ");
+            query.push_bind(end);
+        }
+
+        // Keep readings positioned inside the box
+        if let Some(b) = &self.bbox {
+            let position = |column: &str| {
+                format!(
+                    "COALESCE(sensor_data.{column}, (SELECT d.{column} FROM devices d \
+                     WHERE d.device_id = sensor_data.device_id))"
+                )
+            };
+            let (lat, lon) = (position("latitude"), position("longitude"));
+            query.push(format!(" AND {lat} BETWEEN "));
+            query.push_bind(b.min_lat);
+            query.push(" AND ");
+            query.push_bind(b.max_lat);
+            query.push(format!(" AND ({lon} >= "));
+            query.push_bind(b.min_lon);
+            // Across the antimeridian either side of it matches
+            query.push(if b.min_lon <= b.max_lon {
+                " AND "
+            } else {
+                " OR "
+            });
+            query.push(format!("{lon} <= "));
+            query.push_bind(b.max_lon);
+            query.push(")");
+        }
+    }
+}
+
+impl Sort {
+    // ---
+    /// The order named by `sort`, the default when absent; `None` for unknown names.
+    pub fn parse(raw: Option<&str>) -> Option<Self> {
+        // ---
+        match raw.map(str::trim) {
+            None | Some("timestamp_desc") => Some(Self::TimestampDesc),
+            Some("timestamp_asc") => Some(Self::TimestampAsc),
+            Some("temperature_desc") => Some(Self::TemperatureDesc),
+            Some("temperature_asc") => Some(Self::TemperatureAsc),
+            Some("humidity_desc") => Some(Self::HumidityDesc),
+            Some("humidity_asc") => Some(Self::HumidityAsc),
+            Some("device_id") => Some(Self::DeviceId),
+            Some(_) => None,
+        }
+    }
+
+    /// Name recorded in the cursors of this order; none for the default, so
+    /// cursors issued before `sort` existed stay valid.
+    pub fn cursor_tag(self) -> Option<&'static str> {
+        // ---
+        match self {
+            Self::TimestampDesc => None,
+            Self::TimestampAsc => Some("timestamp_asc"),
+            Self::TemperatureDesc => Some("temperature_desc"),
+            Self::TemperatureAsc => Some("temperature_asc"),
+            Self::HumidityDesc => Some("humidity_desc"),
+            Self::HumidityAsc => Some("humidity_asc"),
+            Self::DeviceId => Some("device_id"),
+        }
+    }
+
+    /// Column holding the sort key besides the keyset's `timestamp_utc` and `id`.
+    pub fn key_column(self) -> Option<&'static str> {
+        // ---
+        match self {
+            Self::TimestampDesc | Self::TimestampAsc => None,
+            Self::TemperatureDesc | Self::TemperatureAsc => Some("temperature_c"),
+            Self::HumidityDesc | Self::HumidityAsc => Some("humidity"),
+            Self::DeviceId => Some("device_id"),
+        }
+    }
+
+    pub(crate) fn order_by(self) -> &'static str {
+        // ---
+        match self {
+            Self::TimestampDesc => "timestamp_utc DESC, id DESC",
+            Self::TimestampAsc => "timestamp_utc ASC, id ASC",
+            Self::TemperatureDesc => "temperature_c DESC, id DESC",
+            Self::TemperatureAsc => "temperature_c ASC, id ASC",
+            Self::HumidityDesc => "humidity DESC, id DESC",
+            Self::HumidityAsc => "humidity ASC, id ASC",
+            Self::DeviceId => "device_id ASC, timestamp_utc DESC, id DESC",
+        }
+    }
+
+    /// Keep only rows strictly after `c` in this order (row-value comparisons
+    /// match the `ORDER BY`).
+    fn push_after<'a>(self, query: &mut QueryBuilder<'a, Postgres>, c: &'a ReadingsCursor) {
+        // ---
+        let (key, op): (&str, &str) = match self {
+            Self::TimestampDesc => ("timestamp_utc", "<"),
+            Self::TimestampAsc => ("timestamp_utc", ">"),
+            Self::TemperatureDesc => ("temperature_c", "<"),
+            Self::TemperatureAsc => ("temperature_c", ">"),
+            Self::HumidityDesc => ("humidity", "<"),
+            Self::HumidityAsc => ("humidity", ">"),
+            Self::DeviceId => {
+                let device_id = c.device_id.as_deref().unwrap_or_default();
+                query.push(" AND (device_id > ");
+                query.push_bind(device_id);
+                query.push(" OR (device_id = ");
+                query.push_bind(device_id);
+                query.push(" AND (timestamp_utc, id) < (");
+                query.push_bind(c.timestamp_utc);
+                query.push(", ");
+                query.push_bind(c.id);
+                query.push(")))");
+                return;
+            }
+        };
+        query.push(format!(" AND ({key}, id) {op} ("));
+        match key {
+            "temperature_c" => query.push_bind(c.temperature_c.unwrap_or_default()),
+            "humidity" => query.push_bind(c.humidity.unwrap_or_default()),
+            _ => query.push_bind(c.timestamp_utc),
+        };
+        query.push(", ");
+        query.push_bind(c.id);
+        query.push(")");
+    }
+}
+
+/// Count the readings matching `filter`.
+#[tracing::instrument(name = "db.count_readings", skip_all)]
+async fn count_readings(
+    pool: &PgPool,
+    filter: &ReadingsFilter,
+) -> Result<ReadingsCount, sqlx::Error> {
+    // ---
+    let mut query = QueryBuilder::new(
+        "SELECT COUNT(*) AS count, MIN(timestamp_utc) AS earliest, \
+         MAX(timestamp_utc) AS latest FROM sensor_data WHERE 1=1",
+    );
+    filter.push_conditions(&mut query);
+
+    let mut shape = filter.shape();
+    shape.push("count");
+    timed(
+        "count_readings",
+        &filter_shape(&shape),
+        query.build_query_as().fetch_one(pool),
+    )
+    .await
+}
+
+/// Build the query for a page of the readings matching `filter`.
+///
+/// Windows see every matching row, so rolling averages don't reset at page
+/// boundaries. One row more than the limit is fetched, to tell whether
+/// another page follows.
+fn readings_query<'a>(
+    filter: &'a ReadingsFilter,
+    page: &'a ReadingsPage,
+) -> QueryBuilder<'a, Postgres> {
+    // ---
+    let mut query = QueryBuilder::new(match &page.columns {
+        Some(columns) => format!("SELECT {}", columns.join(", ")),
+        None => r#"
+        SELECT id, mesh_id, device_id, timestamp_utc,
+               temperature_c, humidity, status,
+               temperature_alert, humidity_alert, attributes,
+               raw_temperature_c, raw_humidity, latitude, longitude, extra,
+               quality, quality_flags, invalid, corrections, ingest_batch_id
+        "#
+        .to_string(),
+    });
+
+    if let Some(window) = page.rolling_avg {
+        let over = format!(
+            "OVER (PARTITION BY device_id ORDER BY timestamp_utc, id \
+             ROWS BETWEEN {} PRECEDING AND CURRENT ROW)",
+            window.saturating_sub(1)
+        );
+        query.push(format!(
+            ", AVG(temperature_c) {over} AS rolling_temperature_c, \
+             AVG(humidity) {over} AS rolling_humidity"
+        ));
+    }
+    query.push(" FROM sensor_data");
+
+    // Block-level sampling skips most of the heap instead of scanning it;
+    // a fixed seed keeps pages of one sampled result set consistent.
+    if let Some(fraction) = page.sample {
+        query.push(" TABLESAMPLE SYSTEM (");
+        query.push_bind((fraction * 100.0) as f32);
+        query.push(") REPEATABLE (0)");
+    }
+    query.push(" WHERE 1=1");
+    filter.push_conditions(&mut query);
+
+    // Resume after the cursor row
+    if let Some(c) = &page.after {
+        page.sort.push_after(&mut query, c);
+    }
+
+    query.push(" ORDER BY ");
+    query.push(page.sort.order_by());
+    query.push(" LIMIT ");
+    query.push_bind(page.limit as i64 + 1);
+    query
+}
+
+/// Load a page of the readings matching `filter`, and the cursor of the next
+/// page: the last row of this one, when more rows follow.
+#[tracing::instrument(name = "db.load_readings", skip_all)]
+async fn load_readings(
+    pool: &PgPool,
+    filter: &ReadingsFilter,
+    page: &ReadingsPage,
+) -> Result<Readings, sqlx::Error> {
+    // ---
+    let mut query = readings_query(filter, page);
+
+    let mut shape = filter.shape();
+    for (set, name) in [
+        (page.sample.is_some(), "sample"),
+        (page.rolling_avg.is_some(), "rolling_avg"),
+        (page.sort != Sort::TimestampDesc, "sort"),
+        (page.columns.is_some(), "fields"),
+        (page.after.is_some(), "cursor"),
+    ] {
+        if set {
+            shape.push(name);
+        }
+    }
+    let mut rows = timed(
+        "load_readings",
+        &filter_shape(&shape),
+        query.build().fetch_all(pool),
+    )
+    .await?;
+
+    let (limit, sort) = (page.limit as usize, page.sort);
+    let next = if rows.len() > limit {
+        rows.truncate(limit);
+        rows.last().map(|row| ReadingsCursor {
+            timestamp_utc: row.get("timestamp_utc"),
+            id: row.get("id"),
+            sort: sort.cursor_tag().map(str::to_string),
+            temperature_c: matches!(sort, Sort::TemperatureDesc | Sort::TemperatureAsc)
+                .then(|| row.get("temperature_c")),
+            humidity: matches!(sort, Sort::HumidityDesc | Sort::HumidityAsc)
+                .then(|| row.get("humidity")),
+            device_id: (sort == Sort::DeviceId).then(|| row.get("device_id")),
+        })
+    } else {
+        None
+    };
+
+    type JsonMap = sqlx::types::Json<serde_json::Map<String, serde_json::Value>>;
+    let readings = rows
+        .into_iter()
+        .map(|row| {
+            Ok(SensorReading {
+                id: Some(row.try_get("id")?),
+                mesh_id: column_or_default(&row, "mesh_id")?,
+                device_id: column_or_default(&row, "device_id")?,
+                timestamp_utc: row.try_get::<DateTime<Utc>, _>("timestamp_utc")?,
+                timestamp_local: None,
+                temperature_c: column_or_default(&row, "temperature_c")?,
+                humidity: column_or_default(&row, "humidity")?,
+                raw_temperature_c: column_or_default(&row, "raw_temperature_c")?,
+                raw_humidity: column_or_default(&row, "raw_humidity")?,
+                status: column_or_default(&row, "status")?,
+                temperature_alert: column_or_default(&row, "temperature_alert")?,
+                humidity_alert: column_or_default(&row, "humidity_alert")?,
+                attributes: column_or_default::<Option<JsonMap>>(&row, "attributes")?
+                    .map(|j| j.0)
+                    .unwrap_or_default(),
+                latitude: column_or_default(&row, "latitude")?,
+                longitude: column_or_default(&row, "longitude")?,
+                extra: column_or_default::<Option<JsonMap>>(&row, "extra")?
+                    .map(|j| j.0)
+                    .unwrap_or_default(),
+                quality: column_or_default(&row, "quality")?,
+                quality_flags: column_or_default(&row, "quality_flags")?,
+                invalid: column_or_default(&row, "invalid")?,
+                corrections: column_or_default::<Option<sqlx::types::Json<_>>>(
+                    &row,
+                    "corrections",
+                )?
+                .map(|j| j.0)
+                .unwrap_or_default(),
+                ingest_batch_id: column_or_default(&row, "ingest_batch_id")?,
+                smoothed: None,
+                rolling_avg: page.rolling_avg.map(|_| Smoothed {
+                    temperature_c: row.get::<f64, _>("rolling_temperature_c") as f32,
+                    humidity: row.get::<f64, _>("rolling_humidity") as f32,
+                }),
+                device: None,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok((readings, next))
+}
+
+/// `column` of `row`, or its default when the query didn't select it.
+fn column_or_default<'r, T>(row: &'r PgRow, column: &str) -> Result<T, sqlx::Error>
+where
+    T: Default + sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres>,
+{
+    // ---
+    match row.try_get(column) {
+        Err(sqlx::Error::ColumnNotFound(_)) => Ok(T::default()),
+        other => other,
+    }
+}
+
+/// The live reading with `id`, if it is in `meshes` (all when `None`).
+///
+/// Public for writers that must read their own change from the primary.
+#[tracing::instrument(name = "db.load_reading", skip(pool, meshes))]
+pub async fn load_reading(
+    pool: &PgPool,
+    id: i32,
+    meshes: Option<&[String]>,
+) -> Result<Option<SensorReading>, sqlx::Error> {
+    // ---
+    sqlx::query_as(
+        r#"
+        SELECT id, mesh_id, device_id, timestamp_utc,
+               temperature_c, humidity, status,
+               temperature_alert, humidity_alert, attributes,
+               raw_temperature_c, raw_humidity, latitude, longitude, extra,
+               quality, quality_flags, invalid, corrections, ingest_batch_id
+        FROM sensor_data
+        WHERE id = $1 AND deleted_at IS NULL
+          AND ($2::TEXT[] IS NULL OR mesh_id = ANY($2))
+        "#,
+    )
+    .bind(id)
+    .bind(meshes)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Newest `timestamp_utc` stored in `meshes` (all when `None`).
+#[tracing::instrument(name = "db.latest_reading", skip_all)]
+async fn latest_reading(
+    pool: &PgPool,
+    meshes: Option<&[String]>,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    // ---
+    sqlx::query_scalar(
+        r#"
+        SELECT MAX(timestamp_utc) FROM sensor_data
+        WHERE deleted_at IS NULL AND ($1::TEXT[] IS NULL OR mesh_id = ANY($1))
+        "#,
+    )
+    .bind(meshes)
+    .fetch_one(pool)
+    .await
+}
+
+/// Latest ingest, from `mesh_ingest`, into the meshes in `scope` (all when
+/// `None`) that `meshes` names (all when `None`); `None` when they have none.
+#[tracing::instrument(name = "db.last_ingested", skip_all)]
+pub async fn last_ingested(
+    pool: &PgPool,
+    scope: Option<&[String]>,
+    meshes: Option<&[String]>,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    // ---
+    sqlx::query_scalar(
+        r#"
+        SELECT MAX(last_ingested_at) FROM mesh_ingest
+        WHERE ($1::TEXT[] IS NULL OR mesh_id = ANY($1))
+          AND ($2::TEXT[] IS NULL OR mesh_id = ANY($2))
+        "#,
+    )
+    .bind(scope)
+    .bind(meshes)
+    .fetch_one(pool)
+    .await
+}
+
+/// Registered timezones of `mesh_ids`, as `(mesh_id, timezone)`; meshes
+/// without one are left out.
+async fn load_timezones(
+    pool: &PgPool,
+    mesh_ids: &[String],
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    // ---
+    sqlx::query_as(
+        "SELECT mesh_id, timezone FROM meshes WHERE mesh_id = ANY($1) AND timezone IS NOT NULL",
+    )
+    .bind(mesh_ids)
+    .fetch_all(pool)
+    .await
+}
+
+/// Registry entries for `device_ids`; unregistered devices are left out.
+///
+/// Public for the device registry routes, which read from the primary.
+pub async fn load_devices(
+    pool: &PgPool,
+    device_ids: &[String],
+) -> Result<Vec<DeviceInfo>, sqlx::Error> {
+    // ---
+    sqlx::query_as(
+        r#"
+        SELECT device_id, mesh_id, display_name, location, latitude, longitude,
+               installed_on, notes, updated_at
+        FROM devices
+        WHERE device_id = ANY($1)
+        "#,
+    )
+    .bind(device_ids)
+    .fetch_all(pool)
+    .await
+}
+
+/// A `LIKE` pattern matching strings that start with `prefix`, taken literally.
+pub(crate) fn like_prefix(prefix: &str) -> String {
+    // ---
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// [`ReadingsRepository`] over readings held in memory, for handler tests.
+///
+/// Filters by device, mesh and the caller's scope only, and serves one page
+/// newest first: no other filters, sorts, cursors or samples.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryReadings {
+    // ---
+    pub(crate) readings: std::sync::Mutex<Vec<SensorReading>>,
+    pub(crate) ingested: Option<DateTime<Utc>>,
+    pub(crate) timezones: Vec<(String, String)>,
+    pub(crate) devices: Vec<DeviceInfo>,
+}
+
+#[cfg(test)]
+impl MemoryReadings {
+    // ---
+    fn matching(&self, filter: &ReadingsFilter) -> Vec<SensorReading> {
+        // ---
+        let listed = |ids: &[String], id: &String| ids.is_empty() || ids.contains(id);
+        let mut matching: Vec<SensorReading> = self
+            .readings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| listed(&filter.device_ids, &r.device_id))
+            .filter(|r| listed(&filter.mesh_ids, &r.mesh_id))
+            .filter(|r| {
+                filter
+                    .allowed_meshes
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.contains(&r.mesh_id))
+            })
+            .cloned()
+            .collect();
+        matching.sort_by_key(|r| std::cmp::Reverse((r.timestamp_utc, r.id)));
+        matching
+    }
+}
+
+#[cfg(test)]
+impl ReadingsRepository for MemoryReadings {
+    // ---
+    fn insert_batch<'a>(
+        &'a self,
+        _source: &'a str,
+        readings: &'a [RawSensorReading],
+    ) -> BoxFuture<'a, Result<u64, sqlx::Error>> {
+        // ---
+        let mut stored = self.readings.lock().unwrap();
+        for raw in readings {
+            let mut reading = raw.to_transformed();
+            reading.id = Some(stored.len() as i32 + 1);
+            stored.push(reading);
+        }
+        let inserted = readings.len() as u64;
+        Box::pin(async move { Ok(inserted) })
+    }
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+        page: &'a ReadingsPage,
+    ) -> BoxFuture<'a, Result<Readings, sqlx::Error>> {
+        // ---
+        let mut readings = self.matching(filter);
+        readings.truncate(page.limit as usize);
+        Box::pin(async move { Ok((readings, None)) })
+    }
+
+    fn count<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+    ) -> BoxFuture<'a, Result<ReadingsCount, sqlx::Error>> {
+        // ---
+        let matching = self.matching(filter);
+        let count = ReadingsCount {
+            count: matching.len() as i64,
+            earliest: matching.last().map(|r| r.timestamp_utc),
+            latest: matching.first().map(|r| r.timestamp_utc),
+        };
+        Box::pin(async move { Ok(count) })
+    }
+
+    fn reading<'a>(
+        &'a self,
+        id: i32,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<SensorReading>, sqlx::Error>> {
+        // ---
+        let filter = ReadingsFilter {
+            allowed_meshes: meshes.map(<[String]>::to_vec),
+            ..ReadingsFilter::default()
+        };
+        let reading = self
+            .matching(&filter)
+            .into_iter()
+            .find(|r| r.id == Some(id));
+        Box::pin(async move { Ok(reading) })
+    }
+
+    fn latest<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        let filter = ReadingsFilter {
+            allowed_meshes: meshes.map(<[String]>::to_vec),
+            ..ReadingsFilter::default()
+        };
+        let latest = self.matching(&filter).first().map(|r| r.timestamp_utc);
+        Box::pin(async move { Ok(latest) })
+    }
+
+    fn summaries<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Vec<MeshAggregate>, sqlx::Error>> {
+        // ---
+        let filter = ReadingsFilter {
+            allowed_meshes: meshes.map(<[String]>::to_vec),
+            ..ReadingsFilter::default()
+        };
+        let mut summaries: Vec<MeshAggregate> = Vec::new();
+        for r in self.matching(&filter) {
+            let at = match summaries.iter().position(|s| s.mesh_id == r.mesh_id) {
+                Some(at) => at,
+                None => {
+                    summaries.push(MeshAggregate {
+                        mesh_id: r.mesh_id.clone(),
+                        avg_temperature_c: 0.0,
+                        avg_humidity: 0.0,
+                        reading_count: 0,
+                        site_name: None,
+                    });
+                    summaries.len() - 1
+                }
+            };
+            let s = &mut summaries[at];
+            let n = s.reading_count as f32;
+            s.avg_temperature_c = (s.avg_temperature_c * n + r.temperature_c) / (n + 1.0);
+            s.avg_humidity = (s.avg_humidity * n + r.humidity) / (n + 1.0);
+            s.reading_count += 1;
+        }
+        summaries.sort_by(|a, b| a.mesh_id.cmp(&b.mesh_id));
+        Box::pin(async move { Ok(summaries) })
+    }
+
+    fn last_ingested<'a>(
+        &'a self,
+        _scope: Option<&'a [String]>,
+        _meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        let ingested = self.ingested;
+        Box::pin(async move { Ok(ingested) })
+    }
+
+    fn timezones<'a>(
+        &'a self,
+        mesh_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, sqlx::Error>> {
+        // ---
+        let zones = self
+            .timezones
+            .iter()
+            .filter(|(mesh_id, _)| mesh_ids.contains(mesh_id))
+            .cloned()
+            .collect();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn devices<'a>(
+        &'a self,
+        device_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<DeviceInfo>, sqlx::Error>> {
+        // ---
+        let devices = self
+            .devices
+            .iter()
+            .filter(|d| device_ids.contains(&d.device_id))
+            .cloned()
+            .collect();
+        Box::pin(async move { Ok(devices) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn sort_names_round_trip_through_cursors() {
+        // ---
+        assert_eq!(Sort::parse(None), Some(Sort::TimestampDesc));
+        assert_eq!(
+            Sort::parse(Some("timestamp_desc")),
+            Some(Sort::TimestampDesc)
+        );
+        for name in [
+            "timestamp_asc",
+            "temperature_desc",
+            "humidity_asc",
+            "device_id",
+        ] {
+            let sort = Sort::parse(Some(name)).expect("known sort");
+            assert_eq!(sort.cursor_tag(), Some(name));
+        }
+        assert_eq!(Sort::parse(Some("temperature")), None);
+        assert_eq!(Sort::parse(Some("id; DROP TABLE sensor_data")), None);
+    }
+
+    #[test]
+    fn like_prefix_escapes_wildcards() {
+        // ---
+        assert_eq!(like_prefix("rack-12-"), "rack-12-%");
+        assert_eq!(like_prefix(r"50%_a\b"), r"50\%\_a\\b%");
+    }
+
+    #[test]
+    fn filters_and_pages_become_bound_conditions() {
+        // ---
+        let filter = ReadingsFilter {
+            device_ids: vec!["dev-1".into()],
+            mesh_ids: vec!["mesh-001".into(), "mesh-002".into()],
+            alert: Some(AlertFilter::Temperature),
+            humidity: [None, Some(80.0)],
+            ..ReadingsFilter::default()
+        };
+        let page = ReadingsPage {
+            sort: Sort::TemperatureDesc,
+            limit: 50,
+            after: None,
+            columns: Some(vec!["id", "temperature_c", "timestamp_utc"]),
+            sample: None,
+            rolling_avg: None,
+        };
+        let query = readings_query(&filter, &page);
+        assert_eq!(
+            query.sql(),
+            "SELECT id, temperature_c, timestamp_utc FROM sensor_data WHERE 1=1 \
+             AND deleted_at IS NULL AND device_id = $1 AND mesh_id = ANY($2) \
+             AND temperature_alert AND humidity <= $3 \
+             ORDER BY temperature_c DESC, id DESC LIMIT $4"
+        );
+        assert_eq!(filter.columns(), ["device_id", "mesh_id"]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Request ID propagation.
+//!
+//! [`request_id`] wraps every route, including the health probes. It takes
+//! the caller's `X-Request-Id` when it is a sane token (up to 128 characters
+//! of `A-Z a-z 0-9 - _ . :`) and otherwise generates one, then:
+//! - runs the request inside a `request` span carrying `request_id`, so
+//!   every log line a handler emits can be matched to the client's report
+//!   (and, with OTLP export on, the root span of the request's trace)
+//! - echoes the ID in the `X-Request-Id` response header
+//! - adds a `request_id` field to JSON object error bodies (4xx/5xx)
+//!
+//! The ID is also available to handlers as `Extension<RequestId>`.
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// Request and response header carrying the ID.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied ID accepted as is.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Largest error body rewritten to include the ID; bigger ones pass through.
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+// ---
+
+/// The current request's ID, available to handlers as `Extension<RequestId>`.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Middleware: accept or generate the request ID and propagate it.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    // ---
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid(v))
+        .map(str::to_owned)
+        .unwrap_or_else(generate);
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        otel.name = format!("{} {}", req.method(), req.uri().path()),
+        otel.kind = "server",
+        http.response.status_code = tracing::field::Empty,
+    );
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut resp = next.run(req).instrument(span.clone()).await;
+    span.record("http.response.status_code", resp.status().as_u16());
+    if resp.status().is_client_error() || resp.status().is_server_error() {
+        resp = tag_error_body(resp, &id).await;
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    resp
+}
+
+fn is_valid(id: &str) -> bool {
+    // ---
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// 128 random bits, hex-encoded.
+fn generate() -> String {
+    // ---
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Add `request_id` to a JSON object body; other bodies are returned unchanged.
+async fn tag_error_body(resp: Response, id: &str) -> Response {
+    // ---
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    if !is_json {
+        return resp;
+    }
+
+    let (mut parts, body) = resp.into_parts();
+    if body
+        .size_hint()
+        .upper()
+        .is_none_or(|n| n > MAX_ERROR_BODY_BYTES as u64)
+    {
+        return Response::from_parts(parts, body);
+    }
+    let bytes = match to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Could not buffer error body to add request_id: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut obj)) => {
+            obj.insert("request_id".into(), id.into());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(obj).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn accepts_only_sane_client_ids() {
+        // ---
+        assert!(is_valid("3f2b9c1e-7d4a-4e2b-9a1c-0b5d6e7f8a9b"));
+        assert!(is_valid("gw-7:batch.42_a"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has space"));
+        assert!(!is_valid("line\nbreak"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn generated_ids_are_valid_and_distinct() {
+        // ---
+        let (a, b) = (generate(), generate());
+        assert_eq!(a.len(), 32);
+        assert!(is_valid(&a));
+        assert_ne!(a, b);
+    }
+}
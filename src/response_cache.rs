@@ -0,0 +1,192 @@
+//! In-process cache of hot query results.
+//!
+//! Dashboards refreshing the same view from many browsers at once send the
+//! same readings and summary queries over and over. With
+//! `RESPONSE_CACHE_SECS` set, [`ResponseCache::get_or_load`] serves a repeat
+//! from memory for that long, and concurrent misses on one key share a
+//! single database query.
+//!
+//! Keys are the normalized query parameters plus everything else the result
+//! depends on (resolved `limit`, mesh scope), chosen by each route. Every
+//! statement changing `sensor_data` announces itself on [`READINGS_CHANNEL`]
+//! (a trigger from `schema.rs`), and a listener in each process drops the
+//! whole cache, so replicas also see readings ingested by others. Dropped
+//! partitions aren't announced and age out with the TTL.
+//!
+//! Each clear starts a new generation that is part of every key, so a query
+//! that was already running when the data changed can't store its stale
+//! result under a reachable key.
+use std::{
+    any::Any,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use moka::future::Cache;
+use sqlx::{postgres::PgListener, PgPool};
+
+use crate::RESPONSE_CACHE_TOTAL;
+
+/// Postgres `NOTIFY` channel for statements that changed `sensor_data`.
+pub const READINGS_CHANNEL: &str = "readings_changed";
+
+/// Results kept at most, least recently used evicted first.
+const MAX_ENTRIES: u64 = 1_000;
+
+// ---
+
+type Entry = Arc<dyn Any + Send + Sync>;
+
+/// Shared result cache; disabled (every call loads) without a TTL.
+pub struct ResponseCache {
+    // ---
+    entries: Option<Cache<String, Entry>>,
+    generation: AtomicU64,
+}
+
+impl ResponseCache {
+    // ---
+    /// Cache keeping results for `ttl`; `None` disables it.
+    pub fn new(ttl: Option<Duration>) -> Self {
+        // ---
+        Self {
+            entries: ttl.map(|ttl| {
+                Cache::builder()
+                    .max_capacity(MAX_ENTRIES)
+                    .time_to_live(ttl)
+                    .build()
+            }),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// [`Self::new`], then, when enabled, listen for changed readings on
+    /// [`READINGS_CHANNEL`] in the background.
+    pub fn spawn(pool: PgPool, ttl: Option<Duration>) -> Arc<Self> {
+        // ---
+        let cache = Arc::new(Self::new(ttl));
+        if cache.entries.is_none() {
+            return cache;
+        }
+
+        let listener = cache.clone();
+        tokio::spawn(async move {
+            // ---
+            loop {
+                if let Err(e) = listener.listen(&pool).await {
+                    tracing::warn!("Response cache listener failed, retrying in 5s: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+        cache
+    }
+
+    /// The cached result of `kind` (e.g. `readings`) for `key`, or else the
+    /// result of `load`, cached unless it failed.
+    pub async fn get_or_load<T, Fut>(
+        &self,
+        kind: &'static str,
+        key: &str,
+        load: Fut,
+    ) -> Result<T, Arc<sqlx::Error>>
+    where
+        T: Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        // ---
+        let Some(entries) = &self.entries else {
+            return load.await.map_err(Arc::new);
+        };
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut missed = false;
+        let entry = entries
+            .try_get_with(format!("{generation}|{kind}|{key}"), async {
+                missed = true;
+                load.await.map(|value| Arc::new(value) as Entry)
+            })
+            .await?;
+
+        let result = if missed { "miss" } else { "hit" };
+        metrics::counter!(RESPONSE_CACHE_TOTAL, "kind" => kind, "result" => result).increment(1);
+        Ok(entry
+            .downcast_ref::<T>()
+            .expect("one result type per kind")
+            .clone())
+    }
+
+    /// Forget every cached result.
+    pub fn clear(&self) {
+        // ---
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(entries) = &self.entries {
+            entries.invalidate_all();
+        }
+    }
+
+    async fn listen(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        // ---
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(READINGS_CHANNEL).await?;
+        tracing::info!("Listening for readings changes on {}", READINGS_CHANNEL);
+
+        // Changes announced while we weren't listening
+        self.clear();
+        loop {
+            listener.recv().await?;
+            self.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[tokio::test]
+    async fn hits_until_cleared() {
+        // ---
+        let cache = ResponseCache::new(Some(Duration::from_secs(60)));
+        let load = |n: u32| async move { Ok::<_, sqlx::Error>(vec![n]) };
+
+        assert_eq!(
+            cache.get_or_load("readings", "a", load(1)).await.unwrap(),
+            [1]
+        );
+        assert_eq!(
+            cache.get_or_load("readings", "a", load(2)).await.unwrap(),
+            [1]
+        );
+        assert_eq!(
+            cache.get_or_load("readings", "b", load(3)).await.unwrap(),
+            [3]
+        );
+
+        cache.clear();
+        assert_eq!(
+            cache.get_or_load("readings", "a", load(4)).await.unwrap(),
+            [4]
+        );
+
+        let disabled = ResponseCache::new(None);
+        assert_eq!(
+            disabled
+                .get_or_load("readings", "a", load(5))
+                .await
+                .unwrap(),
+            [5]
+        );
+        assert_eq!(
+            disabled
+                .get_or_load("readings", "a", load(6))
+                .await
+                .unwrap(),
+            [6]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! Downsampled rollups of `sensor_data`: `sensor_data_hourly` and
+//! `sensor_data_daily`.
+//!
+//! Each rollup row holds the reading count and the avg/min/max temperature
+//! and humidity of one device in one UTC hour or day, attributed to the mesh
+//! the device was assigned to at the time (as `mesh_summary` does). Duplicate
+//! copies of a reading stored by several sources, and soft-deleted or invalid
+//! readings, are not counted.
+//!
+//! A trigger on `sensor_data` records the `(device, hour)` of every inserted
+//! reading, and of every change to a reading's `duplicate_of`, `deleted_at`,
+//! values or `invalid` mark, in `rollup_dirty`; reassigning a device marks
+//! its hours from `effective_from` on ([`mark_device_dirty`]).
+//! [`refresh_rollups`] recomputes the marked hours from `sensor_data`, then
+//! the days holding them from the hourly rollup. It runs every
+//! `ROLLUP_REFRESH_SECS` and after every ingest, push and reassignment
+//! (through [`update_mesh_summaries`](crate::update_mesh_summaries)), so
+//! rollups trail new readings by at most that interval.
+//!
+//! `DELETE /v1/readings` only tombstones rows, which marks their hours like
+//! any other change. Rows physically removed from `sensor_data` don't mark
+//! theirs: `purge` only removes rows already tombstoned, and so already left
+//! out, and retention ([`partitions`](crate::partitions)) expires the rollups
+//! and totals of the months it removes with [`expire_rollups_before`].
+//!
+//! Each refresh also folds the change to the hourly rows it replaced into
+//! `mesh_totals`, a running reading count and temperature/humidity sum per
+//! mesh that the `mesh_summary` view divides out. Summaries thus cost work
+//! proportional to the readings that changed, not to the whole history, and
+//! meshes whose averages or counts changed are announced on
+//! [`SUMMARY_CHANNEL`] for live streams.
+//!
+//! `GET /v1/aggregates` reads from the coarsest rollup whose bucket divides
+//! the requested one ([`Rollup::for_bucket`]), falling back to `sensor_data`
+//! for sub-hour buckets.
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+use crate::{notify_payload, timed, SUMMARY_CHANNEL};
+
+/// Origin of every bucket: UTC midnight, 1970-01-01.
+pub const BUCKET_ORIGIN: &str = "TIMESTAMPTZ '1970-01-01 00:00:00+00'";
+
+/// Dirty hours recomputed per transaction.
+const BATCH: i64 = 10_000;
+
+// ---
+
+/// Where bucketed aggregates are computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rollup {
+    // ---
+    /// Raw readings in `sensor_data`.
+    Raw,
+
+    /// `sensor_data_hourly`.
+    Hourly,
+
+    /// `sensor_data_daily`.
+    Daily,
+}
+
+impl Rollup {
+    // ---
+    /// The coarsest source whose buckets tile `bucket_secs` exactly.
+    pub fn for_bucket(bucket_secs: i64) -> Self {
+        // ---
+        if bucket_secs % 86_400 == 0 {
+            Rollup::Daily
+        } else if bucket_secs % 3_600 == 0 {
+            Rollup::Hourly
+        } else {
+            Rollup::Raw
+        }
+    }
+
+    /// Table holding this source's rows.
+    pub fn table(self) -> &'static str {
+        // ---
+        match self {
+            Rollup::Raw => "sensor_data",
+            Rollup::Hourly => "sensor_data_hourly",
+            Rollup::Daily => "sensor_data_daily",
+        }
+    }
+}
+
+/// Recompute every marked hour every `every`.
+pub fn spawn_refresher(pool: PgPool, every: Duration) {
+    // ---
+    tokio::spawn(async move {
+        // ---
+        let mut ticker = tokio::time::interval(every);
+        loop {
+            ticker.tick().await;
+            match refresh_rollups(&pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::debug!("Refreshed rollups for {} device-hours", n),
+                Err(e) => tracing::error!("Rollup refresh failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Recompute the hourly and daily rollups of every marked hour; returns the
+/// number of `(device, hour)` pairs recomputed.
+#[tracing::instrument(name = "db.refresh_rollups", skip_all)]
+pub async fn refresh_rollups(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    // ---
+    let mut total = 0;
+    loop {
+        let n = timed("refresh_rollups", "none", refresh_batch(pool)).await?;
+        total += n;
+        if n < BATCH as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+async fn refresh_batch(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    // ---
+    let mut tx = pool.begin().await?;
+
+    // One refresh at a time: concurrent ones could both rebuild the same day,
+    // and each must fold its deltas into the totals the previous one left
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('rollup_refresh'))")
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("CREATE TEMP TABLE rollup_batch (device_id TEXT, hour TIMESTAMPTZ) ON COMMIT DROP")
+        .execute(&mut *tx)
+        .await?;
+
+    // Rows locked by a writer still marking them are waited for, so the
+    // recompute below sees that writer's readings
+    let n = sqlx::query(
+        r#"
+        WITH taken AS (
+            DELETE FROM rollup_dirty
+            WHERE (device_id, hour) IN (SELECT device_id, hour FROM rollup_dirty LIMIT $1)
+            RETURNING device_id, hour
+        )
+        INSERT INTO rollup_batch SELECT device_id, hour FROM taken
+        "#,
+    )
+    .bind(BATCH)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if n == 0 {
+        return Ok(0);
+    }
+
+    create_mesh_deltas(&mut tx).await?;
+
+    // The replaced hours leave the mesh totals...
+    sqlx::query(
+        r#"
+        WITH gone AS (
+            DELETE FROM sensor_data_hourly h
+            USING rollup_batch b
+            WHERE h.device_id = b.device_id AND h.bucket = b.hour
+            RETURNING h.mesh_id, h.reading_count, h.avg_temperature_c, h.avg_humidity
+        )
+        INSERT INTO mesh_deltas
+        SELECT mesh_id, -reading_count,
+               -avg_temperature_c * reading_count, -avg_humidity * reading_count
+        FROM gone
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // ...and their recomputed rows join them
+    sqlx::query(
+        r#"
+        WITH added AS (
+            INSERT INTO sensor_data_hourly (
+                device_id, mesh_id, bucket, reading_count,
+                avg_temperature_c, min_temperature_c, max_temperature_c,
+                avg_humidity, min_humidity, max_humidity
+            )
+            SELECT s.device_id, COALESCE(a.mesh_id, s.mesh_id), b.hour, COUNT(*),
+                   AVG(s.temperature_c), MIN(s.temperature_c), MAX(s.temperature_c),
+                   AVG(s.humidity), MIN(s.humidity), MAX(s.humidity)
+            FROM rollup_batch b
+            JOIN sensor_data s
+              ON s.device_id = b.device_id
+             AND s.timestamp_utc >= b.hour AND s.timestamp_utc < b.hour + INTERVAL '1 hour'
+            LEFT JOIN LATERAL (
+                SELECT mesh_id
+                FROM device_mesh_assignments
+                WHERE device_id = s.device_id AND effective_from <= s.timestamp_utc
+                ORDER BY effective_from DESC
+                LIMIT 1
+            ) a ON true
+            WHERE s.duplicate_of IS NULL AND s.deleted_at IS NULL AND NOT s.invalid
+            GROUP BY 1, 2, 3
+            RETURNING mesh_id, reading_count, avg_temperature_c, avg_humidity
+        )
+        INSERT INTO mesh_deltas
+        SELECT mesh_id, reading_count,
+               avg_temperature_c * reading_count, avg_humidity * reading_count
+        FROM added
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Days are rebuilt from their hours, weighting each hour's average by its count
+    sqlx::query(&format!(
+        r#"
+        CREATE TEMP TABLE rollup_days ON COMMIT DROP AS
+        SELECT DISTINCT device_id, date_bin('1 day', hour, {BUCKET_ORIGIN}) AS day
+        FROM rollup_batch
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM sensor_data_daily d
+        USING rollup_days b
+        WHERE d.device_id = b.device_id AND d.bucket = b.day
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sensor_data_daily (
+            device_id, mesh_id, bucket, reading_count,
+            avg_temperature_c, min_temperature_c, max_temperature_c,
+            avg_humidity, min_humidity, max_humidity
+        )
+        SELECT h.device_id, h.mesh_id, b.day, SUM(h.reading_count),
+               SUM(h.avg_temperature_c * h.reading_count) / SUM(h.reading_count),
+               MIN(h.min_temperature_c), MAX(h.max_temperature_c),
+               SUM(h.avg_humidity * h.reading_count) / SUM(h.reading_count),
+               MIN(h.min_humidity), MAX(h.max_humidity)
+        FROM rollup_days b
+        JOIN sensor_data_hourly h
+          ON h.device_id = b.device_id
+         AND h.bucket >= b.day AND h.bucket < b.day + INTERVAL '1 day'
+        GROUP BY 1, 2, 3
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    apply_mesh_deltas(&mut tx).await?;
+    tx.commit().await?;
+    Ok(n)
+}
+
+/// Temp table collecting per-mesh changes to the hourly rollup, for
+/// [`apply_mesh_deltas`].
+async fn create_mesh_deltas(tx: &mut PgConnection) -> Result<(), sqlx::Error> {
+    // ---
+    sqlx::query(
+        r#"
+        CREATE TEMP TABLE mesh_deltas (
+            mesh_id          TEXT,
+            reading_count    BIGINT,
+            temperature_sum  DOUBLE PRECISION,
+            humidity_sum     DOUBLE PRECISION
+        ) ON COMMIT DROP
+        "#,
+    )
+    .execute(tx)
+    .await?;
+    Ok(())
+}
+
+/// Add the collected deltas to `mesh_totals`, drop meshes left without
+/// readings, and announce the meshes whose `mesh_summary` row changed on
+/// [`SUMMARY_CHANNEL`] when the transaction commits.
+async fn apply_mesh_deltas(tx: &mut PgConnection) -> Result<(), sqlx::Error> {
+    // ---
+    sqlx::query(
+        r#"
+        CREATE TEMP TABLE mesh_summary_before ON COMMIT DROP AS
+        SELECT * FROM mesh_summary WHERE mesh_id IN (SELECT mesh_id FROM mesh_deltas)
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO mesh_totals (mesh_id, reading_count, temperature_sum, humidity_sum)
+        SELECT mesh_id, SUM(reading_count), SUM(temperature_sum), SUM(humidity_sum)
+        FROM mesh_deltas
+        GROUP BY 1
+        ON CONFLICT (mesh_id) DO UPDATE SET
+            reading_count   = mesh_totals.reading_count + EXCLUDED.reading_count,
+            temperature_sum = mesh_totals.temperature_sum + EXCLUDED.temperature_sum,
+            humidity_sum    = mesh_totals.humidity_sum + EXCLUDED.humidity_sum
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM mesh_totals WHERE reading_count <= 0")
+        .execute(&mut *tx)
+        .await?;
+
+    let changed: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(n.mesh_id, o.mesh_id)
+        FROM (
+            SELECT * FROM mesh_summary WHERE mesh_id IN (SELECT mesh_id FROM mesh_deltas)
+        ) n
+        FULL JOIN mesh_summary_before o ON o.mesh_id = n.mesh_id
+        WHERE (n.*) IS DISTINCT FROM (o.*)
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if !changed.is_empty() {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(SUMMARY_CHANNEL)
+            .bind(notify_payload(&changed))
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Mark every hour of `device_id` from `from` on for recomputation, after
+/// its mesh assignment changed.
+pub async fn mark_device_dirty(
+    pool: &PgPool,
+    device_id: &str,
+    from: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let result = sqlx::query(&format!(
+        r#"
+        INSERT INTO rollup_dirty (device_id, hour)
+        SELECT DISTINCT device_id, date_bin('1 hour', timestamp_utc, {BUCKET_ORIGIN})
+        FROM sensor_data
+        WHERE device_id = $1 AND timestamp_utc >= date_bin('1 hour', $2, {BUCKET_ORIGIN})
+        ON CONFLICT (device_id, hour) DO UPDATE SET marked_at = now()
+        "#
+    ))
+    .bind(device_id)
+    .bind(from)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete rollups of readings before `before`, after retention removed the
+/// readings themselves (deletions don't mark hours dirty), and take them out
+/// of the mesh totals.
+pub async fn expire_rollups_before(
+    pool: &PgPool,
+    before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('rollup_refresh'))")
+        .execute(&mut *tx)
+        .await?;
+    create_mesh_deltas(&mut tx).await?;
+
+    let expired = sqlx::query(
+        r#"
+        WITH gone AS (
+            DELETE FROM sensor_data_hourly
+            WHERE bucket < $1
+            RETURNING mesh_id, reading_count, avg_temperature_c, avg_humidity
+        )
+        INSERT INTO mesh_deltas
+        SELECT mesh_id, -reading_count,
+               -avg_temperature_c * reading_count, -avg_humidity * reading_count
+        FROM gone
+        "#,
+    )
+    .bind(before)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    for (table, column) in [("sensor_data_daily", "bucket"), ("rollup_dirty", "hour")] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE {column} < $1"))
+            .bind(before)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    apply_mesh_deltas(&mut tx).await?;
+    tx.commit().await?;
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn coarsest_rollup_that_tiles_the_bucket_is_chosen() {
+        // ---
+        assert_eq!(Rollup::for_bucket(60), Rollup::Raw);
+        assert_eq!(Rollup::for_bucket(5_400), Rollup::Raw);
+        assert_eq!(Rollup::for_bucket(3_600), Rollup::Hourly);
+        assert_eq!(Rollup::for_bucket(6 * 3_600), Rollup::Hourly);
+        assert_eq!(Rollup::for_bucket(36 * 3_600), Rollup::Hourly);
+        assert_eq!(Rollup::for_bucket(86_400), Rollup::Daily);
+        assert_eq!(Rollup::for_bucket(7 * 86_400), Rollup::Daily);
+    }
+}
@@ -0,0 +1,666 @@
+//! Administrative endpoints under `/admin/*`.
+//!
+//! Every route in this module requires the `admin` role: [`router`] layers
+//! [`require_role`] over the whole subrouter, so new routes inherit it.
+//!
+//! ## Routes
+//! - `GET /admin/sources` - configured upstream sources (secrets omitted) with
+//!   the number of readings stored per source
+//! - `GET /admin/events` - lifecycle event timeline, newest first; filters
+//!   `kind`, `since` (RFC3339) and `limit` (default 100, max 1000)
+//! - `GET /admin/ingest/batches` - upstream fetches and client pushes, newest
+//!   first, with their page, row and error counts; filters `source` and
+//!   `limit` (default 100, max 1000). `GET /admin/ingest/batches/{id}` returns
+//!   the batch a reading's `ingest_batch_id` names
+//! - `GET /admin/ingest/status` - running batches with their progress so far,
+//!   flagged `stalled` after [`STALLED_AFTER_SECS`] without any, and the latest
+//!   finished batch of each source
+//! - `GET /admin/source-conflicts` - readings stored by several sources, each
+//!   duplicate copy with the copy preferred over it, newest first; filters
+//!   `device_id`, `source`, `differing` (only copies whose values differ) and
+//!   `limit` (default 100, max 1000)
+//! - `GET /admin/pool` - connection pool statistics: the latest sample and a
+//!   summary of recent ones (see `pool_stats.rs`)
+//! - `GET /admin/index-advisor` - composite indexes suggested by observed
+//!   `/sql/readings` filters, ranked by estimated benefit
+//! - `POST /admin/index-advisor/apply` - create a suggested index; body
+//!   `{ "filters": [...], "confirm": true }`
+//! - `GET /admin/calibration` - calibration offsets of every calibrated device
+//! - `PUT /admin/calibration/{device_id}` - body `{ "temperature_offset", "humidity_offset" }`
+//!   (each defaults to 0); replaces the device's offsets
+//! - `DELETE /admin/calibration/{device_id}` - stop calibrating the device; 204, or 404 when it
+//!   had no offsets
+//!
+//! - `GET /admin/maintenance` - whether maintenance mode is on, since when and
+//!   by whom
+//! - `PUT /admin/maintenance` - body `{ "enabled", "message", "retry_after_secs" }`;
+//!   turns maintenance mode on (non-admin callers get 503) or off and returns
+//!   the new state (see `maintenance.rs`)
+//! - `POST /admin/reload` - read `.env` and the configuration file again and
+//!   apply changed sources, rate limit and log level, like `SIGHUP`; returns
+//!   what changed and the settings now in effect, or 422 with the reason the
+//!   configuration was rejected (see `reload.rs`)
+//!
+//! Calibration applies to readings ingested or pushed afterwards; stored
+//! readings keep the values they were stored with.
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, QueryBuilder};
+use tracing::{error, info};
+
+use crate::{
+    advise, create_index, db_error_response, record_event, require_role, AdvisorError, ApiError,
+    AppState, Calibration, EventKind, FilterStats, Json, LiveConfig, Maintenance,
+    MaintenanceWindow, Path, PoolMonitor, Principal, Query, Reloader, Role,
+};
+
+/// How long a running batch may go without progress before it's reported stalled.
+const STALLED_AFTER_SECS: f64 = 300.0;
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new()
+        .route("/admin/sources", get(sources))
+        .route("/admin/events", get(events))
+        .route("/admin/ingest/batches", get(ingest_batches))
+        .route("/admin/ingest/batches/{id}", get(ingest_batch))
+        .route("/admin/ingest/status", get(ingest_status))
+        .route(
+            "/admin/maintenance",
+            get(maintenance_status).put(put_maintenance),
+        )
+        .route("/admin/reload", post(reload))
+        .route("/admin/source-conflicts", get(source_conflicts))
+        .route("/admin/pool", get(pool_stats))
+        .route("/admin/index-advisor", get(index_advisor))
+        .route("/admin/index-advisor/apply", post(apply_index))
+        .route("/admin/calibration", get(calibrations))
+        .route(
+            "/admin/calibration/{device_id}",
+            put(put_calibration).delete(delete_calibration),
+        )
+        .route_layer(middleware::from_fn_with_state(Role::Admin, require_role))
+}
+
+#[derive(Serialize)]
+struct SourceInfo {
+    name: String,
+    url: String,
+    max_pages: u32,
+    interval_secs: Option<u64>,
+    reading_count: i64,
+}
+
+/// Handle `GET /admin/sources`.
+async fn sources(State(pool): State<PgPool>, State(live): State<LiveConfig>) -> Response {
+    // ---
+    let live = live.load();
+    let mut out = Vec::with_capacity(live.sources.len());
+
+    for src in &live.sources {
+        let count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sensor_data WHERE source = $1")
+                .bind(&src.name)
+                .fetch_one(&pool)
+                .await;
+
+        match count {
+            Ok(reading_count) => out.push(SourceInfo {
+                name: src.name.clone(),
+                url: src.url.clone(),
+                max_pages: src.max_pages,
+                interval_secs: src.interval_secs,
+                reading_count,
+            }),
+            Err(e) => {
+                error!("Failed to count readings for source {}: {}", src.name, e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    }
+
+    (StatusCode::OK, Json(out)).into_response()
+}
+
+/// Query parameters for `GET /admin/events`.
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    // ---
+    /// Only events of this kind (e.g. `ingest_failed`).
+    kind: Option<String>,
+
+    /// Only events at or after this time.
+    since: Option<DateTime<Utc>>,
+
+    limit: Option<u32>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct EventRow {
+    id: i64,
+    occurred_at: DateTime<Utc>,
+    kind: String,
+    detail: serde_json::Value,
+}
+
+/// Handle `GET /admin/events`.
+async fn events(Query(params): Query<EventsQuery>, State(pool): State<PgPool>) -> Response {
+    // ---
+    let mut qb = QueryBuilder::new("SELECT id, occurred_at, kind, detail FROM events WHERE 1=1");
+    if let Some(kind) = &params.kind {
+        qb.push(" AND kind = ").push_bind(kind);
+    }
+    if let Some(since) = params.since {
+        qb.push(" AND occurred_at >= ").push_bind(since);
+    }
+    qb.push(" ORDER BY occurred_at DESC, id DESC LIMIT ")
+        .push_bind(i64::from(params.limit.unwrap_or(100).min(1000)));
+
+    match qb.build_query_as::<EventRow>().fetch_all(&pool).await {
+        Ok(rows) => (StatusCode::OK, Json(rows)).into_response(),
+        Err(e) => {
+            error!("Failed to load events: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Query parameters for `GET /admin/ingest/batches`.
+#[derive(Debug, Deserialize)]
+struct BatchesQuery {
+    // ---
+    /// Only batches of this source (e.g. `default` or `push:gateway-7`).
+    source: Option<String>,
+
+    limit: Option<u32>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct BatchRow {
+    id: i64,
+    source: String,
+
+    /// `initial`, `scheduled`, `manual` or `push`.
+    trigger: Option<String>,
+    started_at: DateTime<Utc>,
+
+    /// `None` while the batch is still running.
+    finished_at: Option<DateTime<Utc>>,
+
+    /// When the counts last moved.
+    progressed_at: DateTime<Utc>,
+    pages: i32,
+    fetched: i32,
+    rows: i32,
+    errors: i32,
+    error: Option<String>,
+}
+
+const BATCH_COLUMNS: &str = "id, source, trigger, started_at, finished_at, progressed_at, \
+                             pages, fetched, rows, errors, error";
+
+/// Body of `GET /admin/ingest/status`.
+#[derive(Serialize)]
+struct IngestStatus {
+    // ---
+    running: Vec<RunningBatch>,
+
+    /// The latest finished batch of each source.
+    last_finished: Vec<BatchRow>,
+}
+
+#[derive(Serialize)]
+struct RunningBatch {
+    // ---
+    #[serde(flatten)]
+    batch: BatchRow,
+    elapsed_secs: f64,
+
+    /// Seconds since `progressed_at`.
+    idle_secs: f64,
+
+    /// No progress for [`STALLED_AFTER_SECS`] or more.
+    stalled: bool,
+}
+
+/// Handle `GET /admin/ingest/batches`.
+async fn ingest_batches(
+    Query(params): Query<BatchesQuery>,
+    State(pool): State<PgPool>,
+) -> Response {
+    // ---
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {BATCH_COLUMNS} FROM ingest_batches WHERE 1=1"
+    ));
+    if let Some(source) = &params.source {
+        qb.push(" AND source = ").push_bind(source);
+    }
+    qb.push(" ORDER BY started_at DESC, id DESC LIMIT ")
+        .push_bind(i64::from(params.limit.unwrap_or(100).min(1000)));
+
+    match qb.build_query_as::<BatchRow>().fetch_all(&pool).await {
+        Ok(rows) => (StatusCode::OK, Json(rows)).into_response(),
+        Err(e) => {
+            error!("Failed to load ingest batches: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /admin/ingest/batches/{id}`; 404 for unknown batches.
+async fn ingest_batch(Path(id): Path<i64>, State(pool): State<PgPool>) -> Response {
+    // ---
+    let batch = sqlx::query_as::<_, BatchRow>(&format!(
+        "SELECT {BATCH_COLUMNS} FROM ingest_batches WHERE id = $1"
+    ))
+    .bind(id)
+    .fetch_optional(&pool)
+    .await;
+
+    match batch {
+        Ok(Some(batch)) => (StatusCode::OK, Json(batch)).into_response(),
+        Ok(None) => ApiError::NotFound {
+            error: "batch not found",
+            hint: "list batches with GET /admin/ingest/batches",
+        }
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load ingest batch {}: {}", id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /admin/ingest/status`.
+async fn ingest_status(State(pool): State<PgPool>) -> Response {
+    // ---
+    let loaded = async {
+        let running = sqlx::query_as::<_, BatchRow>(&format!(
+            "SELECT {BATCH_COLUMNS} FROM ingest_batches WHERE finished_at IS NULL \
+             ORDER BY started_at, id"
+        ))
+        .fetch_all(&pool)
+        .await?;
+        let last_finished = sqlx::query_as::<_, BatchRow>(&format!(
+            "SELECT DISTINCT ON (source) {BATCH_COLUMNS} FROM ingest_batches \
+             WHERE finished_at IS NOT NULL ORDER BY source, finished_at DESC, id DESC"
+        ))
+        .fetch_all(&pool)
+        .await?;
+        Ok::<_, sqlx::Error>((running, last_finished))
+    };
+
+    match loaded.await {
+        Ok((running, last_finished)) => {
+            let now = Utc::now();
+            let secs_since = |t: DateTime<Utc>| (now - t).num_milliseconds() as f64 / 1000.0;
+            let running = running
+                .into_iter()
+                .map(|batch| {
+                    let idle_secs = secs_since(batch.progressed_at);
+                    RunningBatch {
+                        elapsed_secs: secs_since(batch.started_at),
+                        idle_secs,
+                        stalled: idle_secs >= STALLED_AFTER_SECS,
+                        batch,
+                    }
+                })
+                .collect();
+            (
+                StatusCode::OK,
+                Json(IngestStatus {
+                    running,
+                    last_finished,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to load ingest status: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Body of `PUT /admin/maintenance`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaintenanceChange {
+    // ---
+    enabled: bool,
+
+    /// Shown to rejected callers.
+    message: Option<String>,
+
+    /// `Retry-After` for rejected callers (default 60).
+    retry_after_secs: Option<u64>,
+}
+
+/// Body of `GET` and `PUT /admin/maintenance`.
+#[derive(Serialize)]
+struct MaintenanceStatus {
+    // ---
+    enabled: bool,
+
+    #[serde(flatten)]
+    window: Option<MaintenanceWindow>,
+}
+
+/// Handle `GET /admin/maintenance`.
+async fn maintenance_status(State(maintenance): State<Maintenance>) -> Response {
+    // ---
+    let window = maintenance.current();
+    let enabled = window.is_some();
+    (StatusCode::OK, Json(MaintenanceStatus { enabled, window })).into_response()
+}
+
+/// Handle `PUT /admin/maintenance`.
+async fn put_maintenance(
+    State(pool): State<PgPool>,
+    State(maintenance): State<Maintenance>,
+    Extension(principal): Extension<Principal>,
+    Json(change): Json<MaintenanceChange>,
+) -> Response {
+    // ---
+    let window = if change.enabled {
+        info!(
+            "Maintenance mode on by {}: {:?}",
+            principal.name, change.message
+        );
+        Some(maintenance.begin(
+            change.message.clone(),
+            &principal.name,
+            change.retry_after_secs,
+        ))
+    } else {
+        if maintenance.end().is_some() {
+            info!("Maintenance mode off by {}", principal.name);
+        }
+        None
+    };
+    record_event(
+        &pool,
+        EventKind::MaintenanceChanged,
+        json!({
+            "enabled": change.enabled,
+            "message": change.message,
+            "by": principal.name,
+        }),
+    )
+    .await;
+    let enabled = window.is_some();
+    (StatusCode::OK, Json(MaintenanceStatus { enabled, window })).into_response()
+}
+
+/// Response of `POST /admin/reload`.
+#[derive(Serialize)]
+struct ReloadResult {
+    // ---
+    /// `sources`, `rate_limit` and/or `log_level`.
+    changed: Vec<&'static str>,
+    sources: Vec<String>,
+
+    /// `{ "per_sec", "burst" }`, or `null` while limiting is off.
+    rate_limit: Option<serde_json::Value>,
+    log_level: Option<String>,
+}
+
+/// Handle `POST /admin/reload`.
+async fn reload(
+    State(reloader): State<Arc<Reloader>>,
+    State(live): State<LiveConfig>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    // ---
+    info!("Configuration reload requested by {}", principal.name);
+    let changed = match reloader.reload("api").await {
+        Ok(changed) => changed,
+        Err(detail) => return ApiError::InvalidConfiguration(detail).into_response(),
+    };
+
+    let current = live.load();
+    let result = ReloadResult {
+        changed,
+        sources: current.sources.iter().map(|s| s.name.clone()).collect(),
+        rate_limit: current
+            .rate_limit
+            .as_ref()
+            .map(|rl| json!({ "per_sec": rl.per_sec, "burst": rl.burst })),
+        log_level: current.log_level.clone(),
+    };
+    (StatusCode::OK, Json(result)).into_response()
+}
+
+/// Query parameters for `GET /admin/source-conflicts`.
+#[derive(Debug, Deserialize)]
+struct ConflictsQuery {
+    // ---
+    device_id: Option<String>,
+
+    /// Only duplicates stored by this source.
+    source: Option<String>,
+
+    /// Only duplicates whose values differ from the preferred copy.
+    #[serde(default)]
+    differing: bool,
+
+    limit: Option<u32>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct ConflictRow {
+    reading_id: i32,
+    preferred_id: i32,
+    device_id: String,
+    timestamp_utc: DateTime<Utc>,
+    source: String,
+    preferred_source: String,
+    values_differ: bool,
+    detected_at: DateTime<Utc>,
+}
+
+/// Handle `GET /admin/source-conflicts`.
+async fn source_conflicts(
+    Query(params): Query<ConflictsQuery>,
+    State(pool): State<PgPool>,
+) -> Response {
+    // ---
+    let mut qb = QueryBuilder::new(
+        "SELECT reading_id, preferred_id, device_id, timestamp_utc, source, preferred_source, \
+         values_differ, detected_at FROM source_conflicts WHERE 1=1",
+    );
+    if let Some(device_id) = &params.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id);
+    }
+    if let Some(source) = &params.source {
+        qb.push(" AND source = ").push_bind(source);
+    }
+    if params.differing {
+        qb.push(" AND values_differ");
+    }
+    qb.push(" ORDER BY timestamp_utc DESC, reading_id DESC LIMIT ")
+        .push_bind(i64::from(params.limit.unwrap_or(100).min(1000)));
+
+    match qb.build_query_as::<ConflictRow>().fetch_all(&pool).await {
+        Ok(rows) => (StatusCode::OK, Json(rows)).into_response(),
+        Err(e) => {
+            error!("Failed to load source conflicts: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /admin/pool`.
+async fn pool_stats(State(monitor): State<PoolMonitor>) -> Response {
+    // ---
+    (StatusCode::OK, Json(monitor.report())).into_response()
+}
+
+/// Handle `GET /admin/index-advisor`.
+async fn index_advisor(
+    State(pool): State<PgPool>,
+    State(stats): State<Arc<FilterStats>>,
+) -> Response {
+    // ---
+    match advise(&pool, &stats).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            error!("Index advisor failed: {}", e);
+            db_error_response(&e, "advisor failed")
+        }
+    }
+}
+
+/// Request body for `POST /admin/index-advisor/apply`.
+#[derive(Debug, Deserialize)]
+struct ApplyRequest {
+    // ---
+    /// Filter combination from the advisor report (its `filters` field).
+    filters: Vec<String>,
+
+    /// Must be `true`; guards against accidental index builds.
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Serialize)]
+struct ApplyResponse {
+    created: Option<String>,
+}
+
+/// Handle `POST /admin/index-advisor/apply`.
+///
+/// Builds the index with `CREATE INDEX CONCURRENTLY` (partition by partition
+/// when `sensor_data` is partitioned), so the table stays writable; `created`
+/// is `null` when an existing index already covers it.
+async fn apply_index(State(pool): State<PgPool>, Json(body): Json<ApplyRequest>) -> Response {
+    // ---
+    if !body.confirm {
+        return ApiError::Validation {
+            error: "confirmation required",
+            hint: r#"index builds are expensive; resend with "confirm": true"#,
+        }
+        .into_response();
+    }
+
+    match create_index(&pool, &body.filters).await {
+        Ok(created) => (StatusCode::OK, Json(ApplyResponse { created })).into_response(),
+        Err(AdvisorError::UnknownColumn) => ApiError::Validation {
+            error: "invalid filters",
+            hint: "use a filters list from GET /admin/index-advisor (device_id, mesh_id, timestamp_utc)",
+        }
+        .into_response(),
+        Err(AdvisorError::Database(e)) => {
+            error!("Index creation failed: {}", e);
+            db_error_response(&e, "index creation failed")
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct CalibrationRow {
+    device_id: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    calibration: Calibration,
+    updated_at: DateTime<Utc>,
+}
+
+/// Handle `GET /admin/calibration`.
+async fn calibrations(State(pool): State<PgPool>) -> Response {
+    // ---
+    let rows = sqlx::query_as::<_, CalibrationRow>(
+        r#"
+        SELECT device_id, temperature_offset, humidity_offset, updated_at
+        FROM device_calibration
+        ORDER BY device_id
+        "#,
+    )
+    .fetch_all(&pool)
+    .await;
+
+    match rows {
+        Ok(rows) => (StatusCode::OK, Json(rows)).into_response(),
+        Err(e) => {
+            error!("Failed to load calibrations: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `PUT /admin/calibration/{device_id}`.
+async fn put_calibration(
+    Path(device_id): Path<String>,
+    State(pool): State<PgPool>,
+    Json(body): Json<Calibration>,
+) -> Response {
+    // ---
+    let stored = sqlx::query_as::<_, CalibrationRow>(
+        r#"
+        INSERT INTO device_calibration (device_id, temperature_offset, humidity_offset)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (device_id) DO UPDATE SET
+            temperature_offset = EXCLUDED.temperature_offset,
+            humidity_offset    = EXCLUDED.humidity_offset,
+            updated_at         = now()
+        RETURNING device_id, temperature_offset, humidity_offset, updated_at
+        "#,
+    )
+    .bind(&device_id)
+    .bind(body.temperature_offset)
+    .bind(body.humidity_offset)
+    .fetch_one(&pool)
+    .await;
+
+    match stored {
+        Ok(row) => {
+            info!(
+                "Device {} calibrated: {:+} °C, {:+} %RH",
+                device_id, body.temperature_offset, body.humidity_offset
+            );
+            (StatusCode::OK, Json(row)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to store calibration for {}: {}", device_id, e);
+            db_error_response(&e, "store failed")
+        }
+    }
+}
+
+/// Handle `DELETE /admin/calibration/{device_id}`.
+async fn delete_calibration(Path(device_id): Path<String>, State(pool): State<PgPool>) -> Response {
+    // ---
+    let deleted = sqlx::query("DELETE FROM device_calibration WHERE device_id = $1")
+        .bind(&device_id)
+        .execute(&pool)
+        .await;
+
+    match deleted {
+        Ok(r) if r.rows_affected() > 0 => {
+            info!("Device {} calibration removed", device_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(_) => ApiError::NotFound {
+            error: "device not calibrated",
+            hint: "list calibrated devices with GET /admin/calibration",
+        }
+        .into_response(),
+        Err(e) => {
+            error!("Failed to delete calibration for {}: {}", device_id, e);
+            db_error_response(&e, "delete failed")
+        }
+    }
+}
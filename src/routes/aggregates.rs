@@ -0,0 +1,587 @@
+//! Time-bucketed aggregates endpoint.
+//!
+//! `GET /v1/aggregates` returns, per device and time bucket, the reading count
+//! and the average, minimum and maximum temperature and humidity. Readings
+//! count toward the mesh their device was assigned to at the time, as in
+//! `mesh_summary`; duplicate copies stored by several sources count once, and
+//! readings marked invalid not at all.
+//!
+//! ## Query Parameters
+//! - `bucket` - bucket size as `<n>s`, `<n>m`, `<n>h` or `<n>d` (default `1h`); buckets
+//!   start at whole multiples of the size since 1970-01-01T00:00:00Z
+//! - `device_id` - Filter by specific device
+//! - `mesh_id` - Filter by (assigned) mesh
+//! - `timestamp_range` - RFC3339 range "start,end" with open ends supported, widened to
+//!   whole buckets
+//! - `limit` - Maximum rows to return, oldest bucket first (default: `DEFAULT_LIMIT`, or the
+//!   caller's per-key default)
+//! - `fill` - `null`, `previous` or `linear`: also return the empty buckets of each device and
+//!   mesh, from the range start (or its first bucket) to the range end (or its last bucket),
+//!   with `reading_count` 0, `"filled": true` and null stats, the previous bucket's stats, or
+//!   stats interpolated between the neighbouring buckets. Buckets with no neighbour to copy or
+//!   interpolate from stay null.
+//! - `smooth` - `ewma` adds each bucket's exponentially weighted moving average of the `avg`
+//!   stats, per device and mesh, as `smoothed` (see `smoothing.rs`); applied after `fill`, and
+//!   buckets without stats are skipped
+//! - `alpha` - EWMA smoothing factor in (0, 1] (default 0.3); needs `smooth=ewma`
+//!
+//! ## Source
+//! Buckets of whole days are summed from `sensor_data_daily`, other buckets of
+//! whole hours from `sensor_data_hourly`, and anything finer is computed from
+//! `sensor_data` (see `rollups.rs`). The response's `source` names the one
+//! used. Rollups trail new readings by up to `ROLLUP_REFRESH_SECS`.
+//!
+//! ## Error Handling
+//! - 403 unless the caller has at least the `reader` role
+//! - 422 for a malformed `bucket` or `timestamp_range`, an unknown `fill` or `smooth`, or an
+//!   `alpha` outside (0, 1] or without `smooth`
+//! - 500 for database failures; 503 when the database is busy or the query timed out
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder, Row};
+use tracing::error;
+
+use super::readings::{parse_timestamp_range, TimestampRange};
+use crate::{
+    db_error_response, ewma_alpha, filter_shape, parse_duration, require_role, timed, ApiError,
+    AppState, Config, Ewma, Principal, Query, ReadPool, Role, Rollup, Smoothed, BUCKET_ORIGIN,
+};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new().route(
+        "/v1/aggregates",
+        get(handler).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct AggregatesQuery {
+    // ---
+    bucket: Option<String>,
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+    limit: Option<u32>,
+    fill: Option<String>,
+    smooth: Option<String>,
+    alpha: Option<f32>,
+}
+
+/// How `fill` fills empty buckets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fill {
+    Null,
+    Previous,
+    Linear,
+}
+
+impl Fill {
+    // ---
+    fn parse(raw: &str) -> Option<Self> {
+        // ---
+        match raw.trim() {
+            "null" => Some(Self::Null),
+            "previous" => Some(Self::Previous),
+            "linear" => Some(Self::Linear),
+            _ => None,
+        }
+    }
+}
+
+/// Body of `GET /v1/aggregates`.
+#[derive(Serialize)]
+struct AggregatesResponse {
+    // ---
+    /// Bucket size as requested, e.g. `6h`.
+    bucket: String,
+    bucket_secs: i64,
+
+    /// Table the buckets were computed from.
+    source: Rollup,
+    data: Vec<Bucket>,
+}
+
+/// One device's readings in one bucket.
+#[derive(Debug, Clone, Serialize)]
+struct Bucket {
+    // ---
+    device_id: String,
+    mesh_id: String,
+    bucket_start: DateTime<Utc>,
+    reading_count: i64,
+
+    /// `None` only in buckets filled with `fill=null` or without neighbours.
+    temperature_c: Option<Stats>,
+    humidity: Option<Stats>,
+
+    /// True for empty buckets added by `fill`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    filled: bool,
+
+    /// EWMA of the series' `avg` stats up to this bucket, with `smooth=ewma`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smoothed: Option<Smoothed>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Stats {
+    avg: f32,
+    min: f32,
+    max: f32,
+}
+
+/// Handle `GET /v1/aggregates`.
+async fn handler(
+    Query(params): Query<AggregatesQuery>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let bucket = params.bucket.clone().unwrap_or_else(|| "1h".into());
+    let Some(bucket_secs) = parse_duration(&bucket).map(|d| d.num_seconds()) else {
+        return ApiError::Validation {
+            error: "invalid bucket",
+            hint: "use <n>s, <n>m, <n>h or <n>d, e.g. bucket=15m or bucket=1d",
+        }
+        .into_response();
+    };
+
+    let range = match params.timestamp_range.as_deref() {
+        None => (None, None),
+        Some(raw) => match parse_timestamp_range(raw) {
+            Some(range) => range,
+            None => {
+                return ApiError::Validation {
+                    error: "invalid timestamp_range",
+                    hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
+                }
+                .into_response();
+            }
+        },
+    };
+
+    let fill = match params.fill.as_deref() {
+        None => None,
+        Some(raw) => match Fill::parse(raw) {
+            Some(fill) => Some(fill),
+            None => {
+                return ApiError::Validation {
+                    error: "invalid fill",
+                    hint: "use fill=null, fill=previous or fill=linear",
+                }
+                .into_response();
+            }
+        },
+    };
+
+    let Some(alpha) = ewma_alpha(params.smooth.as_deref(), params.alpha) else {
+        return ApiError::Validation {
+            error: "invalid smoothing",
+            hint: "use smooth=ewma with an optional alpha in (0, 1], e.g. smooth=ewma&alpha=0.3",
+        }
+        .into_response();
+    };
+
+    let limit = params.limit.unwrap_or_else(|| {
+        let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+        config.default_limit_for(api_key)
+    });
+    let source = Rollup::for_bucket(bucket_secs);
+    let (query, principal) = (&params, &principal);
+    let loaded = reads
+        .read(|pool| async move {
+            load_buckets(&pool, source, bucket_secs, query, range, principal, limit).await
+        })
+        .await;
+
+    match loaded {
+        Ok(data) => {
+            let mut data = match fill {
+                Some(fill) => fill_buckets(data, fill, bucket_secs, range, limit),
+                None => data,
+            };
+            if let Some(alpha) = alpha {
+                smooth_buckets(&mut data, alpha);
+            }
+            (
+                StatusCode::OK,
+                Json(AggregatesResponse {
+                    bucket,
+                    bucket_secs,
+                    source,
+                    data,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to load aggregates: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Add the empty buckets of every device and mesh in `data` (oldest first,
+/// as loaded with `limit`), filled per `fill`, keeping at most `limit`.
+///
+/// Each series runs from the bucket of the range start (or its first bucket)
+/// to that of the range end (or its last). When `data` was cut off at
+/// `limit`, only buckets before its last one are filled: later ones may hold
+/// readings that weren't loaded.
+fn fill_buckets(
+    data: Vec<Bucket>,
+    fill: Fill,
+    bucket_secs: i64,
+    (start, end): TimestampRange,
+    limit: u32,
+) -> Vec<Bucket> {
+    // ---
+    let limit = limit as usize;
+    let step = Duration::seconds(bucket_secs);
+    let bucket_of = |t: DateTime<Utc>| {
+        let secs = t.timestamp().div_euclid(bucket_secs) * bucket_secs;
+        DateTime::from_timestamp(secs, 0).unwrap_or(t)
+    };
+    let cut_off = match data.last() {
+        Some(b) if data.len() >= limit => Some(b.bucket_start),
+        _ => None,
+    };
+
+    let mut series: Vec<Vec<Bucket>> = Vec::new();
+    for b in data {
+        match series
+            .iter_mut()
+            .find(|s| s[0].device_id == b.device_id && s[0].mesh_id == b.mesh_id)
+        {
+            Some(s) => s.push(b),
+            None => series.push(vec![b]),
+        }
+    }
+
+    let mut out = Vec::new();
+    for known in series {
+        let (first_known, last_known) =
+            (known[0].bucket_start, known[known.len() - 1].bucket_start);
+        let mut fill_to = end.map(bucket_of).unwrap_or(last_known);
+        if let Some(cut) = cut_off {
+            fill_to = fill_to.min(cut - step);
+        }
+
+        let mut series_out: Vec<Bucket> = Vec::new();
+        let mut prev: Option<&Bucket> = None;
+        let mut next = known.iter().peekable();
+        let mut t = start.map(bucket_of).unwrap_or(first_known);
+        while (t <= fill_to || t <= last_known) && series_out.len() < limit {
+            if let Some(b) = next.next_if(|b| b.bucket_start == t) {
+                series_out.push(b.clone());
+                prev = Some(b);
+            } else if t <= fill_to {
+                let (temperature_c, humidity) = match (fill, prev, next.peek()) {
+                    (Fill::Previous, Some(p), _) => (p.temperature_c, p.humidity),
+                    (Fill::Linear, Some(p), Some(n)) => {
+                        let span = (n.bucket_start - p.bucket_start).num_seconds() as f32;
+                        let frac = (t - p.bucket_start).num_seconds() as f32 / span;
+                        (
+                            lerp(p.temperature_c, n.temperature_c, frac),
+                            lerp(p.humidity, n.humidity, frac),
+                        )
+                    }
+                    _ => (None, None),
+                };
+                series_out.push(Bucket {
+                    device_id: known[0].device_id.clone(),
+                    mesh_id: known[0].mesh_id.clone(),
+                    bucket_start: t,
+                    reading_count: 0,
+                    temperature_c,
+                    humidity,
+                    filled: true,
+                    smoothed: None,
+                });
+            }
+            t += step;
+        }
+        out.extend(series_out);
+    }
+
+    out.sort_by(|a, b| {
+        (a.bucket_start, &a.device_id, &a.mesh_id).cmp(&(b.bucket_start, &b.device_id, &b.mesh_id))
+    });
+    out.truncate(limit);
+    out
+}
+
+/// Set `smoothed` on each of `data` (oldest first) with stats, from the `avg`
+/// stats of its device and mesh's earlier buckets.
+fn smooth_buckets(data: &mut [Bucket], alpha: f32) {
+    // ---
+    let mut series: Vec<(String, String, Ewma, Ewma)> = Vec::new();
+    for b in data {
+        let (Some(t), Some(h)) = (b.temperature_c, b.humidity) else {
+            continue;
+        };
+        let i = match series
+            .iter()
+            .position(|(d, m, _, _)| *d == b.device_id && *m == b.mesh_id)
+        {
+            Some(i) => i,
+            None => {
+                series.push((
+                    b.device_id.clone(),
+                    b.mesh_id.clone(),
+                    Ewma::new(alpha),
+                    Ewma::new(alpha),
+                ));
+                series.len() - 1
+            }
+        };
+        let (_, _, temperature, humidity) = &mut series[i];
+        b.smoothed = Some(Smoothed {
+            temperature_c: temperature.update(t.avg),
+            humidity: humidity.update(h.avg),
+        });
+    }
+}
+
+/// Stats `frac` of the way from `a` to `b`.
+fn lerp(a: Option<Stats>, b: Option<Stats>, frac: f32) -> Option<Stats> {
+    // ---
+    let (a, b) = (a?, b?);
+    let mix = |x: f32, y: f32| x + (y - x) * frac;
+    Some(Stats {
+        avg: mix(a.avg, b.avg),
+        min: mix(a.min, b.min),
+        max: mix(a.max, b.max),
+    })
+}
+
+/// Aggregate the filtered readings into `bucket_secs` buckets from `source`.
+///
+/// Rollup rows are combined with count-weighted averages, so a bucket has the
+/// same values whichever source it is computed from.
+#[tracing::instrument(name = "db.load_buckets", skip_all)]
+async fn load_buckets(
+    pool: &PgPool,
+    source: Rollup,
+    bucket_secs: i64,
+    params: &AggregatesQuery,
+    (start, end): TimestampRange,
+    principal: &Principal,
+    limit: u32,
+) -> Result<Vec<Bucket>, sqlx::Error> {
+    // ---
+    let interval = format!("make_interval(secs => {bucket_secs})");
+
+    // (device, mesh, time) columns to filter and group on
+    let (device, mesh, time) = match source {
+        Rollup::Raw => (
+            "s.device_id",
+            "COALESCE(a.mesh_id, s.mesh_id)",
+            "s.timestamp_utc",
+        ),
+        Rollup::Hourly | Rollup::Daily => ("device_id", "mesh_id", "bucket"),
+    };
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {device} AS device_id, {mesh} AS mesh_id, \
+         date_bin({interval}, {time}, {BUCKET_ORIGIN}) AS bucket_start, "
+    ));
+    match source {
+        Rollup::Raw => qb.push(
+            r#"
+            COUNT(*)::BIGINT AS reading_count,
+            AVG(s.temperature_c)::REAL AS avg_temperature_c,
+            MIN(s.temperature_c) AS min_temperature_c,
+            MAX(s.temperature_c) AS max_temperature_c,
+            AVG(s.humidity)::REAL AS avg_humidity,
+            MIN(s.humidity) AS min_humidity,
+            MAX(s.humidity) AS max_humidity
+            FROM sensor_data s
+            LEFT JOIN LATERAL (
+                SELECT mesh_id
+                FROM device_mesh_assignments
+                WHERE device_id = s.device_id AND effective_from <= s.timestamp_utc
+                ORDER BY effective_from DESC
+                LIMIT 1
+            ) a ON true
+            WHERE s.duplicate_of IS NULL AND s.deleted_at IS NULL AND NOT s.invalid
+            "#,
+        ),
+        Rollup::Hourly | Rollup::Daily => qb.push(format!(
+            r#"
+            SUM(reading_count)::BIGINT AS reading_count,
+            (SUM(avg_temperature_c * reading_count) / SUM(reading_count))::REAL
+                AS avg_temperature_c,
+            MIN(min_temperature_c) AS min_temperature_c,
+            MAX(max_temperature_c) AS max_temperature_c,
+            (SUM(avg_humidity * reading_count) / SUM(reading_count))::REAL AS avg_humidity,
+            MIN(min_humidity) AS min_humidity,
+            MAX(max_humidity) AS max_humidity
+            FROM {}
+            WHERE 1=1
+            "#,
+            source.table()
+        )),
+    };
+
+    if let Some(device_id) = &params.device_id {
+        qb.push(format!(" AND {device} = ")).push_bind(device_id);
+    }
+    if let Some(mesh_id) = &params.mesh_id {
+        qb.push(format!(" AND {mesh} = ")).push_bind(mesh_id);
+    }
+    if let Some(allowed) = &principal.meshes {
+        qb.push(format!(" AND {mesh} = ANY("))
+            .push_bind(allowed)
+            .push(")");
+    }
+
+    // Whole buckets only, so partial first and last buckets don't mislead
+    if let Some(start) = start {
+        qb.push(format!(" AND {time} >= date_bin({interval}, "))
+            .push_bind(start)
+            .push(format!(", {BUCKET_ORIGIN})"));
+    }
+    if let Some(end) = end {
+        qb.push(format!(" AND {time} < date_bin({interval}, "))
+            .push_bind(end)
+            .push(format!(", {BUCKET_ORIGIN}) + {interval}"));
+    }
+
+    qb.push(" GROUP BY 1, 2, 3 ORDER BY 3, 1, 2 LIMIT ")
+        .push_bind(i64::from(limit));
+
+    let mut shape: Vec<&str> = vec![match source {
+        Rollup::Raw => "raw",
+        Rollup::Hourly => "hourly",
+        Rollup::Daily => "daily",
+    }];
+    if params.device_id.is_some() {
+        shape.push("device_id");
+    }
+    if params.mesh_id.is_some() {
+        shape.push("mesh_id");
+    }
+    if start.is_some() || end.is_some() {
+        shape.push("timestamp_utc");
+    }
+    let rows = timed(
+        "load_buckets",
+        &filter_shape(&shape),
+        qb.build().fetch_all(pool),
+    )
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Bucket {
+            device_id: row.get("device_id"),
+            mesh_id: row.get("mesh_id"),
+            bucket_start: row.get("bucket_start"),
+            reading_count: row.get("reading_count"),
+            temperature_c: Some(Stats {
+                avg: row.get("avg_temperature_c"),
+                min: row.get("min_temperature_c"),
+                max: row.get("max_temperature_c"),
+            }),
+            humidity: Some(Stats {
+                avg: row.get("avg_humidity"),
+                min: row.get("min_humidity"),
+                max: row.get("max_humidity"),
+            }),
+            filled: false,
+            smoothed: None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use chrono::TimeZone;
+
+    fn hour(h: u32) -> DateTime<Utc> {
+        // ---
+        Utc.with_ymd_and_hms(2025, 3, 21, h, 0, 0).unwrap()
+    }
+
+    fn bucket(h: u32, temperature: f32) -> Bucket {
+        // ---
+        let stats = Stats {
+            avg: temperature,
+            min: temperature,
+            max: temperature,
+        };
+        Bucket {
+            device_id: "device-001".into(),
+            mesh_id: "mesh-001".into(),
+            bucket_start: hour(h),
+            reading_count: 4,
+            temperature_c: Some(stats),
+            humidity: Some(stats),
+            filled: false,
+            smoothed: None,
+        }
+    }
+
+    fn temperatures(fill: Fill, range: TimestampRange) -> Vec<Option<f32>> {
+        // ---
+        let data = vec![bucket(1, 20.0), bucket(4, 26.0)];
+        fill_buckets(data, fill, 3600, range, 100)
+            .iter()
+            .map(|b| b.temperature_c.map(|s| s.avg))
+            .collect()
+    }
+
+    #[test]
+    fn fills_empty_buckets_between_and_around_data() {
+        // ---
+        let range = (Some(hour(0)), Some(hour(5)));
+        assert_eq!(
+            temperatures(Fill::Null, (None, None)),
+            [Some(20.0), None, None, Some(26.0)]
+        );
+        assert_eq!(
+            temperatures(Fill::Previous, range),
+            [
+                None,
+                Some(20.0),
+                Some(20.0),
+                Some(20.0),
+                Some(26.0),
+                Some(26.0)
+            ]
+        );
+        assert_eq!(
+            temperatures(Fill::Linear, range),
+            [None, Some(20.0), Some(22.0), Some(24.0), Some(26.0), None]
+        );
+    }
+
+    #[test]
+    fn nothing_is_filled_past_a_cut_off_page() {
+        // ---
+        let mut other = bucket(3, 30.0);
+        other.device_id = "device-002".into();
+        let data = vec![bucket(1, 20.0), other];
+
+        let filled = fill_buckets(data, Fill::Null, 3600, (None, Some(hour(5))), 2);
+        let starts: Vec<_> = filled.iter().map(|b| (b.bucket_start, b.filled)).collect();
+        assert_eq!(starts, [(hour(1), false), (hour(2), true)]);
+    }
+}
@@ -0,0 +1,362 @@
+//! Alert events and their drill-down context.
+//!
+//! Every alert flag on a stored reading (`temperature_alert`,
+//! `humidity_alert`) becomes an `alert_events` row that keeps the triggering
+//! reading's ID.
+//!
+//! ## Routes
+//! - `GET /alerts/events` - newest first; filters `device_id`, `mesh_id`, `kind`, `limit`
+//!   (default 100, max 1000)
+//! - `GET /alerts/events/{id}/context?window=30m` - the alert plus the same
+//!   device's readings from `window` before to `window` after it (default
+//!   `30m`, max `24h`), oldest first, with the triggering reading marked;
+//!   `units=imperial` serves their temperatures in °F (see `units.rs`)
+//! - `GET /sql/alerts/top?window=7d&n=10` - the `n` devices (default 10, max
+//!   100) with the most alerts in the last `window` (default `7d`), most first,
+//!   counted per kind; filter `mesh_id`
+//!
+//! Events carry the `site_name` and `timezone` of registered meshes (see
+//! `meshes.rs`). Both require the `reader` role and honour the caller's mesh
+//! scope; alerts outside it are reported as not found. Bulk downloads (CSV, NDJSON,
+//! Parquet) live in `export.rs`.
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::QueryBuilder;
+use tracing::error;
+
+use crate::{
+    db_error_response, parse_duration, require_role, ApiError, AppState, Path, Principal, Query,
+    ReadPool, Role, SensorReading, UnitSystem,
+};
+
+/// Widest context window a client may request on each side of an alert.
+const MAX_CONTEXT_WINDOW: Duration = Duration::hours(24);
+
+/// Most devices `GET /sql/alerts/top` returns.
+const MAX_TOP_DEVICES: u32 = 100;
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new()
+        .route("/alerts/events", get(list))
+        .route("/alerts/events/{id}/context", get(context))
+        .route("/sql/alerts/top", get(top))
+        .route_layer(middleware::from_fn_with_state(Role::Reader, require_role))
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct AlertEvent {
+    id: i64,
+    reading_id: i32,
+    kind: String,
+    device_id: String,
+    mesh_id: String,
+    occurred_at: DateTime<Utc>,
+
+    /// Site name and timezone of the mesh, when it is registered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    site_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timezone: Option<String>,
+}
+
+/// Query parameters for `GET /alerts/events`.
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    // ---
+    #[serde(alias = "device", alias = "deviceId", alias = "deviceID")]
+    device_id: Option<String>,
+
+    #[serde(alias = "mesh", alias = "meshId", alias = "meshID")]
+    mesh_id: Option<String>,
+
+    /// `temperature` or `humidity`
+    kind: Option<String>,
+
+    limit: Option<u32>,
+}
+
+/// Query parameters for `GET /alerts/events/{id}/context`.
+#[derive(Debug, Deserialize)]
+struct ContextQuery {
+    /// Span on each side of the alert, e.g. `30m` (see `duration.rs`)
+    window: Option<String>,
+
+    /// Unit system of the readings: "metric" (default) or "imperial"
+    units: Option<String>,
+}
+
+/// A reading in an alert's context, and whether it raised the alert.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ContextReading {
+    // ---
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    reading: SensorReading,
+
+    is_trigger: bool,
+}
+
+#[derive(Serialize)]
+struct AlertContext {
+    alert: AlertEvent,
+    window: String,
+    readings: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Query parameters for `GET /sql/alerts/top`.
+#[derive(Debug, Deserialize)]
+struct TopQuery {
+    // ---
+    /// How far back to count, e.g. `7d` (see `duration.rs`)
+    window: Option<String>,
+
+    /// How many devices to return
+    n: Option<u32>,
+
+    #[serde(alias = "mesh", alias = "meshId", alias = "meshID")]
+    mesh_id: Option<String>,
+}
+
+/// A device's alerts in the window, in total and per kind.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct DeviceAlerts {
+    device_id: String,
+    total: i64,
+    temperature: i64,
+    humidity: i64,
+}
+
+#[derive(Serialize)]
+struct TopAlerts {
+    window: String,
+    since: DateTime<Utc>,
+    devices: Vec<DeviceAlerts>,
+}
+
+/// Handle `GET /alerts/events`.
+async fn list(
+    Query(params): Query<ListQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let pool = reads.pool();
+    let mut qb = QueryBuilder::new(
+        r#"
+        SELECT e.id, e.reading_id, e.kind, e.device_id, e.mesh_id, e.occurred_at,
+               m.site_name, m.timezone
+        FROM alert_events e
+        LEFT JOIN meshes m ON m.mesh_id = e.mesh_id
+        WHERE 1=1
+        "#,
+    );
+    if let Some(device_id) = &params.device_id {
+        qb.push(" AND e.device_id = ").push_bind(device_id);
+    }
+    if let Some(mesh_id) = &params.mesh_id {
+        qb.push(" AND e.mesh_id = ").push_bind(mesh_id);
+    }
+    if let Some(kind) = &params.kind {
+        qb.push(" AND e.kind = ").push_bind(kind);
+    }
+    if let Some(allowed) = &principal.meshes {
+        qb.push(" AND e.mesh_id = ANY(")
+            .push_bind(allowed)
+            .push(")");
+    }
+    qb.push(" ORDER BY e.occurred_at DESC, e.id DESC LIMIT ")
+        .push_bind(i64::from(params.limit.unwrap_or(100).min(1000)));
+
+    match qb.build_query_as::<AlertEvent>().fetch_all(pool).await {
+        Ok(rows) => (StatusCode::OK, Json(rows)).into_response(),
+        Err(e) => {
+            error!("Failed to load alert events: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /alerts/events/{id}/context`.
+///
+/// 422 on an invalid or too-wide `window` or unknown `units`; 404 for unknown or out-of-scope alerts.
+async fn context(
+    Path(id): Path<i64>,
+    Query(params): Query<ContextQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let pool = reads.pool();
+    let window_raw = params.window.unwrap_or_else(|| "30m".into());
+    let Some(window) = parse_duration(&window_raw).filter(|w| *w <= MAX_CONTEXT_WINDOW) else {
+        return ApiError::Validation {
+            error: "invalid window",
+            hint: "use <n>s, <n>m, <n>h or <n>d up to 24h, e.g. window=30m",
+        }
+        .into_response();
+    };
+    let Some(units) = params
+        .units
+        .as_deref()
+        .map_or(Some(UnitSystem::Metric), UnitSystem::parse)
+    else {
+        return ApiError::Validation {
+            error: "invalid units",
+            hint: "use units=metric or units=imperial",
+        }
+        .into_response();
+    };
+
+    let alert = sqlx::query_as::<_, AlertEvent>(
+        r#"
+        SELECT e.id, e.reading_id, e.kind, e.device_id, e.mesh_id, e.occurred_at,
+               m.site_name, m.timezone
+        FROM alert_events e
+        LEFT JOIN meshes m ON m.mesh_id = e.mesh_id
+        WHERE e.id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await;
+
+    let alert = match alert {
+        Ok(Some(a)) if principal.can_access_mesh(&a.mesh_id) => a,
+        Ok(_) => {
+            return ApiError::NotFound {
+                error: "alert not found",
+                hint: "list alert IDs with GET /alerts/events",
+            }
+            .into_response();
+        }
+        Err(e) => {
+            error!("Failed to load alert event {}: {}", id, e);
+            return db_error_response(&e, "load failed");
+        }
+    };
+
+    // Same device, both sides of the alert; scoped callers only see their meshes
+    let readings = sqlx::query_as::<_, ContextReading>(
+        r#"
+        SELECT id, mesh_id, device_id, timestamp_utc,
+               temperature_c, humidity, status,
+               temperature_alert, humidity_alert,
+               id = $2 AS is_trigger
+        FROM sensor_data
+        WHERE device_id = $1 AND deleted_at IS NULL
+          AND timestamp_utc BETWEEN $3 AND $4
+          AND ($5::TEXT[] IS NULL OR mesh_id = ANY($5))
+        ORDER BY timestamp_utc, id
+        "#,
+    )
+    .bind(&alert.device_id)
+    .bind(alert.reading_id)
+    .bind(alert.occurred_at - window)
+    .bind(alert.occurred_at + window)
+    .bind(&principal.meshes)
+    .fetch_all(pool)
+    .await;
+
+    match readings {
+        Ok(readings) => {
+            let readings = readings
+                .into_iter()
+                .map(|r| match serde_json::to_value(r) {
+                    Ok(serde_json::Value::Object(mut map)) => {
+                        units.convert(&mut map);
+                        map
+                    }
+                    _ => serde_json::Map::new(),
+                })
+                .collect();
+            (
+                StatusCode::OK,
+                Json(AlertContext {
+                    alert,
+                    window: window_raw,
+                    readings,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to load context for alert {}: {}", id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /sql/alerts/top`.
+///
+/// 422 on an invalid `window` or an `n` outside 1 to [`MAX_TOP_DEVICES`].
+async fn top(
+    Query(params): Query<TopQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let pool = reads.pool();
+    let window_raw = params.window.unwrap_or_else(|| "7d".into());
+    let Some(window) = parse_duration(&window_raw) else {
+        return ApiError::Validation {
+            error: "invalid window",
+            hint: "use <n>s, <n>m, <n>h or <n>d, e.g. window=7d",
+        }
+        .into_response();
+    };
+    let n = params.n.unwrap_or(10);
+    if !(1..=MAX_TOP_DEVICES).contains(&n) {
+        return ApiError::Validation {
+            error: "invalid n",
+            hint: "use 1 to 100 devices, e.g. n=10",
+        }
+        .into_response();
+    }
+
+    let since = Utc::now() - window;
+    let mut qb = QueryBuilder::new(
+        r#"
+        SELECT device_id,
+               COUNT(*) AS total,
+               COUNT(*) FILTER (WHERE kind = 'temperature') AS temperature,
+               COUNT(*) FILTER (WHERE kind = 'humidity') AS humidity
+        FROM alert_events
+        WHERE occurred_at >= "#,
+    );
+    qb.push_bind(since);
+    if let Some(mesh_id) = &params.mesh_id {
+        qb.push(" AND mesh_id = ").push_bind(mesh_id);
+    }
+    if let Some(allowed) = &principal.meshes {
+        qb.push(" AND mesh_id = ANY(").push_bind(allowed).push(")");
+    }
+    qb.push(" GROUP BY device_id ORDER BY total DESC, device_id LIMIT ")
+        .push_bind(i64::from(n));
+
+    match qb.build_query_as::<DeviceAlerts>().fetch_all(pool).await {
+        Ok(devices) => (
+            StatusCode::OK,
+            Json(TopAlerts {
+                window: window_raw,
+                since,
+                devices,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to load top alerting devices: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Statistical anomaly endpoint.
+//!
+//! `GET /sql/anomalies` flags readings that deviate from their device's
+//! recent behaviour, as a complement to the fixed-threshold alerts: a reading
+//! is anomalous when its temperature or humidity is more than `zscore`
+//! standard deviations from the mean of the device's readings in the
+//! preceding `window` (the reading itself excluded). Computed in one query
+//! with a `RANGE` window per device.
+//!
+//! ## Query Parameters
+//! - `window` - rolling window as `<n>s`, `<n>m`, `<n>h` or `<n>d` (default `24h`)
+//! - `zscore` - threshold in standard deviations, > 0 (default 3)
+//! - `device_id` - Filter by specific device
+//! - `mesh_id` - Filter by (reported) mesh
+//! - `timestamp_range` - RFC3339 range "start,end" with open ends supported; readings up to
+//!   `window` before the start still feed the statistics
+//! - `limit` - Maximum anomalies to return, newest first (default: `DEFAULT_LIMIT`, or the
+//!   caller's per-key default)
+//!
+//! Readings with fewer than [`MIN_SAMPLES`] predecessors in the window are
+//! never flagged, duplicates stored by several sources count once, and readings
+//! marked invalid are left out.
+//! Without filters every reading is scanned; narrow by device, mesh or range.
+//!
+//! ## Error Handling
+//! - 403 unless the caller has at least the `reader` role
+//! - 422 for a malformed `window` or `timestamp_range`, or a `zscore` that isn't positive
+//! - 500 for database failures; 503 when the database is busy or the query timed out
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder, Row};
+use tracing::error;
+
+use super::readings::{parse_timestamp_range, TimestampRange};
+use crate::{
+    db_error_response, filter_shape, parse_duration, require_role, timed, ApiError, AppState,
+    Config, Principal, Query, ReadPool, Role,
+};
+
+/// Fewest readings in the window before a reading can be flagged.
+const MIN_SAMPLES: i64 = 10;
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new().route(
+        "/sql/anomalies",
+        get(handler).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct AnomaliesQuery {
+    // ---
+    window: Option<String>,
+    zscore: Option<f64>,
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+    limit: Option<u32>,
+}
+
+/// Body of `GET /sql/anomalies`.
+#[derive(Serialize)]
+struct AnomaliesResponse {
+    // ---
+    window: String,
+    zscore: f64,
+    anomalies: Vec<Anomaly>,
+}
+
+/// A reading outside its device's usual range.
+#[derive(Debug, Serialize)]
+struct Anomaly {
+    // ---
+    reading_id: i32,
+    device_id: String,
+    mesh_id: String,
+    timestamp_utc: DateTime<Utc>,
+    temperature_c: Deviation,
+    humidity: Deviation,
+
+    /// Measurements beyond the threshold: `temperature_c`, `humidity` or both.
+    flagged: Vec<&'static str>,
+}
+
+/// One measurement of a reading against its window.
+#[derive(Debug, Serialize)]
+struct Deviation {
+    value: f32,
+    mean: f64,
+    stddev: f64,
+
+    /// `(value - mean) / stddev`; `None` when the window's values were all equal.
+    zscore: Option<f64>,
+}
+
+/// Handle `GET /sql/anomalies`.
+async fn handler(
+    Query(params): Query<AnomaliesQuery>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let window_raw = params.window.clone().unwrap_or_else(|| "24h".into());
+    let Some(window) = parse_duration(&window_raw) else {
+        return ApiError::Validation {
+            error: "invalid window",
+            hint: "use <n>s, <n>m, <n>h or <n>d, e.g. window=24h",
+        }
+        .into_response();
+    };
+
+    let zscore = params.zscore.unwrap_or(3.0);
+    if !(zscore.is_finite() && zscore > 0.0) {
+        return ApiError::Validation {
+            error: "invalid zscore",
+            hint: "use a positive number of standard deviations, e.g. zscore=3",
+        }
+        .into_response();
+    }
+
+    let range = match params.timestamp_range.as_deref() {
+        None => (None, None),
+        Some(raw) => match parse_timestamp_range(raw) {
+            Some(range) => range,
+            None => {
+                return ApiError::Validation {
+                    error: "invalid timestamp_range",
+                    hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
+                }
+                .into_response();
+            }
+        },
+    };
+
+    let limit = params.limit.unwrap_or_else(|| {
+        let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+        config.default_limit_for(api_key)
+    });
+    let (query, principal) = (&params, &principal);
+    let loaded = reads
+        .read(|pool| async move {
+            load_anomalies(&pool, query, window, zscore, range, principal, limit).await
+        })
+        .await;
+
+    match loaded {
+        Ok(anomalies) => (
+            StatusCode::OK,
+            Json(AnomaliesResponse {
+                window: window_raw,
+                zscore,
+                anomalies,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to load anomalies: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Readings more than `zscore` standard deviations from the mean of their
+/// device's readings in the preceding `window`, newest first.
+#[tracing::instrument(name = "db.load_anomalies", skip_all)]
+async fn load_anomalies(
+    pool: &PgPool,
+    params: &AnomaliesQuery,
+    window: Duration,
+    zscore: f64,
+    (start, end): TimestampRange,
+    principal: &Principal,
+    limit: u32,
+) -> Result<Vec<Anomaly>, sqlx::Error> {
+    // ---
+    let secs = window.num_seconds();
+    let mut qb = QueryBuilder::new(
+        r#"
+        WITH scored AS (
+            SELECT id, device_id, mesh_id, timestamp_utc, temperature_c, humidity,
+                   COUNT(*) OVER w AS samples,
+                   AVG(temperature_c) OVER w AS temperature_mean,
+                   STDDEV_SAMP(temperature_c) OVER w AS temperature_stddev,
+                   AVG(humidity) OVER w AS humidity_mean,
+                   STDDEV_SAMP(humidity) OVER w AS humidity_stddev
+            FROM sensor_data
+            WHERE duplicate_of IS NULL AND deleted_at IS NULL AND NOT invalid
+        "#,
+    );
+
+    if let Some(device_id) = &params.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id);
+    }
+    if let Some(mesh_id) = &params.mesh_id {
+        qb.push(" AND mesh_id = ").push_bind(mesh_id);
+    }
+    if let Some(allowed) = &principal.meshes {
+        qb.push(" AND mesh_id = ANY(").push_bind(allowed).push(")");
+    }
+
+    // The window before the range start still feeds the statistics
+    if let Some(start) = start {
+        qb.push(" AND timestamp_utc >= ").push_bind(start - window);
+    }
+    if let Some(end) = end {
+        qb.push(" AND timestamp_utc <= ").push_bind(end);
+    }
+    qb.push(format!(
+        r#"
+            WINDOW w AS (
+                PARTITION BY device_id ORDER BY timestamp_utc
+                RANGE BETWEEN make_interval(secs => {secs}) PRECEDING AND CURRENT ROW
+                EXCLUDE CURRENT ROW
+            )
+        )
+        SELECT * FROM scored
+        WHERE samples >= {MIN_SAMPLES}
+        "#
+    ));
+    if let Some(start) = start {
+        qb.push(" AND timestamp_utc >= ").push_bind(start);
+    }
+    qb.push(" AND (ABS(temperature_c - temperature_mean) > ")
+        .push_bind(zscore)
+        .push(" * temperature_stddev OR ABS(humidity - humidity_mean) > ")
+        .push_bind(zscore)
+        .push(" * humidity_stddev)")
+        .push(" ORDER BY timestamp_utc DESC, id DESC LIMIT ")
+        .push_bind(i64::from(limit));
+
+    let mut shape = vec!["anomalies"];
+    if params.device_id.is_some() {
+        shape.push("device_id");
+    }
+    if params.mesh_id.is_some() {
+        shape.push("mesh_id");
+    }
+    if start.is_some() || end.is_some() {
+        shape.push("timestamp_utc");
+    }
+    let rows = timed(
+        "load_anomalies",
+        &filter_shape(&shape),
+        qb.build().fetch_all(pool),
+    )
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let deviation = |value: f32, mean: f64, stddev: f64| Deviation {
+                value,
+                mean,
+                stddev,
+                zscore: (stddev > 0.0).then(|| (f64::from(value) - mean) / stddev),
+            };
+            let temperature_c = deviation(
+                row.get("temperature_c"),
+                row.get("temperature_mean"),
+                row.get("temperature_stddev"),
+            );
+            let humidity = deviation(
+                row.get("humidity"),
+                row.get("humidity_mean"),
+                row.get("humidity_stddev"),
+            );
+            let flagged = [("temperature_c", &temperature_c), ("humidity", &humidity)]
+                .into_iter()
+                .filter(|(_, d)| (f64::from(d.value) - d.mean).abs() > zscore * d.stddev)
+                .map(|(name, _)| name)
+                .collect();
+            Anomaly {
+                reading_id: row.get("id"),
+                device_id: row.get("device_id"),
+                mesh_id: row.get("mesh_id"),
+                timestamp_utc: row.get("timestamp_utc"),
+                temperature_c,
+                humidity,
+                flagged,
+            }
+        })
+        .collect())
+}
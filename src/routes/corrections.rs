@@ -0,0 +1,319 @@
+//! Manual corrections of stored readings.
+//!
+//! `PATCH /v1/readings/{id}` (or the deprecated `PATCH /sql/readings/{id}`)
+//! fixes a reading a seized or miswired sensor got obviously wrong: the body
+//! sets any of `temperature_c`, `humidity` and `status`, and `invalid: true`
+//! marks the reading as garbage. Invalid readings are still listed (with
+//! `"invalid": true`) but count toward no summary, rollup, statistic, anomaly
+//! or alert; `invalid: false` takes the mark back.
+//!
+//! Every correction appends to the reading's `corrections` audit column the
+//! values it replaced, who made it, when, and the optional `reason`. Alert
+//! flags are recomputed from the corrected values, alert events follow them,
+//! and summaries are refreshed before the corrected reading is returned, its
+//! encrypted fields revealed as `GET /v1/readings/{id}` reveals them.
+//!
+//! ## Error Handling
+//! - 403 unless the caller has at least the `writer` role
+//! - 404 for unknown, deleted or out-of-scope readings
+//! - 422 for a non-numeric ID, a body changing nothing, a non-finite value or a humidity
+//!   outside 0-100 %
+//! - 500 for database failures; 503 when the database is busy or the query timed out
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::patch,
+    Extension, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    date, db_error_response, deprecated, link_alert_events, load_reading, record_event,
+    require_role, update_mesh_summaries, ApiError, AppState, Deprecated, DeprecationPolicy,
+    Enrichment, EventKind, Json, Path, Principal, ResponseCache, Role, SensorReading,
+};
+
+/// `PATCH /sql/readings/{id}` follows the other `/sql/readings` routes to `/v1/readings`.
+static LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings/{id}",
+    }),
+    params: &[],
+};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    let route =
+        || patch(handler).route_layer(middleware::from_fn_with_state(Role::Writer, require_role));
+    Router::new().route("/v1/readings/{id}", route()).route(
+        "/sql/readings/{id}",
+        route().route_layer(middleware::from_fn_with_state(&LEGACY_POLICY, deprecated)),
+    )
+}
+
+/// Body of `PATCH /v1/readings/{id}`; fields left out keep their value.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Correction {
+    // ---
+    temperature_c: Option<f32>,
+    humidity: Option<f32>,
+    status: Option<String>,
+    invalid: Option<bool>,
+
+    /// Why, kept in the audit entry.
+    reason: Option<String>,
+}
+
+impl Correction {
+    // ---
+    /// Why the correction can't be applied, if it can't.
+    fn rejection(&self) -> Option<ApiError> {
+        // ---
+        if self.temperature_c.is_none()
+            && self.humidity.is_none()
+            && self.status.is_none()
+            && self.invalid.is_none()
+        {
+            return Some(ApiError::Validation {
+                error: "empty correction",
+                hint: "set temperature_c, humidity, status and/or invalid",
+            });
+        }
+        if self.temperature_c.is_some_and(|t| !t.is_finite())
+            || self
+                .humidity
+                .is_some_and(|h| !(h.is_finite() && (0.0..=100.0).contains(&h)))
+        {
+            return Some(ApiError::Validation {
+                error: "invalid value",
+                hint: "temperature_c must be finite and humidity within 0-100",
+            });
+        }
+        None
+    }
+}
+
+/// Handle `PATCH /v1/readings/{id}`.
+async fn handler(
+    Path(id): Path<String>,
+    State(pool): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Json(correction): Json<Correction>,
+) -> Response {
+    // ---
+    let Ok(id) = id.parse::<i32>() else {
+        return ApiError::Validation {
+            error: "invalid id",
+            hint: "reading IDs are integers, as served in each reading's id field",
+        }
+        .into_response();
+    };
+    if let Some(rejection) = correction.rejection() {
+        return rejection.into_response();
+    }
+
+    info!(
+        "PATCH reading {} - {:?} by {}",
+        id, correction, principal.name
+    );
+    match apply_correction(&pool, id, &correction, &principal).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ApiError::NotFound {
+                error: "reading not found",
+                hint: "list reading IDs with GET /v1/readings",
+            }
+            .into_response();
+        }
+        Err(e) => {
+            error!("Failed to correct reading {}: {}", id, e);
+            return db_error_response(&e, "update failed");
+        }
+    }
+
+    cache.clear();
+    let refreshed = async {
+        link_alert_events(&pool).await?;
+        update_mesh_summaries(&pool).await
+    };
+    if let Err(e) = refreshed.await {
+        error!("Summary update after correction failed: {}", e);
+        return db_error_response(&e, "summary update failed");
+    }
+    record_event(
+        &pool,
+        EventKind::ReadingCorrected,
+        json!({
+            "reading_id": id,
+            "temperature_c": correction.temperature_c,
+            "humidity": correction.humidity,
+            "status": correction.status,
+            "invalid": correction.invalid,
+            "reason": correction.reason,
+            "by": principal.name,
+        }),
+    )
+    .await;
+
+    let loaded = load_reading(&pool, id, principal.meshes.as_deref()).await;
+    respond(id, loaded, &enrichment, principal.role)
+}
+
+/// The response carrying corrected reading `id` as loaded, revealed to `role`.
+fn respond(
+    id: i32,
+    loaded: Result<Option<SensorReading>, sqlx::Error>,
+    enrichment: &Enrichment,
+    role: Role,
+) -> Response {
+    // ---
+    match loaded {
+        Ok(Some(mut reading)) => {
+            enrichment.reveal(&mut reading, role);
+            (StatusCode::OK, Json(reading)).into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("Failed to load corrected reading {}: {}", id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Apply `correction` to the live reading `id` within the caller's scope,
+/// recompute its alert flags and audit trail, and drop alert events that no
+/// longer apply; `false` when there is no such reading.
+#[tracing::instrument(name = "db.correct_reading", skip(pool, correction, principal))]
+async fn apply_correction(
+    pool: &PgPool,
+    id: i32,
+    correction: &Correction,
+    principal: &Principal,
+) -> Result<bool, sqlx::Error> {
+    // ---
+    let mut tx = pool.begin().await?;
+
+    // SET expressions see the row as it was, so `original` holds the old values
+    let corrected = sqlx::query(
+        r#"
+        UPDATE sensor_data SET
+            temperature_c     = COALESCE($2, temperature_c),
+            humidity          = COALESCE($3, humidity),
+            status            = COALESCE($4, status),
+            invalid           = COALESCE($5, invalid),
+            temperature_alert = NOT (COALESCE($2, temperature_c) BETWEEN -10 AND 60),
+            humidity_alert    = NOT (COALESCE($3, humidity) BETWEEN 10 AND 90),
+            corrections       = corrections || jsonb_build_array(jsonb_build_object(
+                'at', now(),
+                'by', $6::TEXT,
+                'reason', $7::TEXT,
+                'original', jsonb_build_object(
+                    'temperature_c', temperature_c,
+                    'humidity', humidity,
+                    'status', status,
+                    'invalid', invalid
+                )
+            ))
+        WHERE id = $1 AND deleted_at IS NULL
+          AND ($8::TEXT[] IS NULL OR mesh_id = ANY($8))
+        "#,
+    )
+    .bind(id)
+    .bind(correction.temperature_c)
+    .bind(correction.humidity)
+    .bind(&correction.status)
+    .bind(correction.invalid)
+    .bind(&principal.name)
+    .bind(&correction.reason)
+    .bind(&principal.meshes)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if corrected == 0 {
+        return Ok(false);
+    }
+
+    // Events whose flag the correction cleared; new flags are linked afterwards
+    sqlx::query(
+        r#"
+        DELETE FROM alert_events e
+        USING sensor_data s
+        WHERE s.id = $1 AND e.reading_id = s.id
+          AND (s.invalid
+               OR (e.kind = 'temperature' AND NOT s.temperature_alert)
+               OR (e.kind = 'humidity' AND NOT s.humidity_alert))
+        "#,
+    )
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use crate::{field_crypto::REDACTED, EncryptionConfig, RawSensorReading};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn corrected_readings_are_revealed_by_role() {
+        // ---
+        let enrichment = Enrichment::from_config(
+            &[],
+            Some(&EncryptionConfig {
+                key: [7; 32],
+                key_id: "test".into(),
+                fields: vec!["assets.location".into()],
+                decrypt_role: Role::Admin,
+            }),
+        )
+        .unwrap();
+        let raw: RawSensorReading = serde_json::from_value(json!({
+            "mesh_id": "mesh-001",
+            "device_id": "device-001",
+            "timestamp": "2025-06-10T00:00:00Z",
+            "temperature_c": 21.0,
+            "humidity": 40.0,
+            "status": "ok"
+        }))
+        .unwrap();
+        let mut stored = raw.to_transformed();
+        stored
+            .attributes
+            .insert("assets".into(), json!({ "location": "Plant A" }));
+        enrichment.apply(&mut stored).await;
+        assert_ne!(stored.attributes["assets"]["location"], "Plant A");
+
+        let location = |role| {
+            let resp = respond(1, Ok(Some(stored.clone())), &enrichment, role);
+            async move {
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                body["attributes"]["assets"]["location"].clone()
+            }
+        };
+        assert_eq!(location(Role::Admin).await, "Plant A");
+        assert_eq!(location(Role::Writer).await, REDACTED);
+    }
+}
@@ -0,0 +1,271 @@
+//! Reading deletion and restore endpoints.
+//!
+//! `DELETE /v1/readings` (or the deprecated `DELETE /sql/readings`)
+//! soft-deletes every stored reading matching the filters, e.g. test data
+//! pushed into a live deployment, and returns how many were deleted. Deleted
+//! readings keep their row with a `deleted_at` tombstone that every read path
+//! skips, so `POST /admin/readings/restore` with the same filters brings an
+//! accidental deletion back. Re-ingesting or re-pushing a deleted reading
+//! doesn't revive it.
+//!
+//! Rows change in batches of [`BATCH`], each its own statement, so a large
+//! deletion never holds locks on more rows than that at once. Setting or
+//! clearing the tombstone marks the readings' hours for a rollup refresh and
+//! moves their meshes' `Last-Modified` on (both by trigger); alert events of
+//! deleted readings are dropped with them. Once done, duplicates are
+//! reconciled again (a deleted copy may have been the preferred one), alert
+//! events relinked, the summaries refreshed and this process's response cache
+//! cleared before responding.
+//!
+//! ## Query Parameters
+//! - `device_id` - Readings of this device
+//! - `mesh_id` - Readings reported from this mesh
+//! - `timestamp_range` - RFC3339 range "start,end" with open ends supported
+//!
+//! At least one filter is required (a `timestamp_range` open at both ends
+//! doesn't count); all given filters must match. Mesh scope
+//! applies, so scoped callers only ever delete or restore inside their meshes.
+//!
+//! ## Error Handling
+//! - 403 unless the caller has the `admin` role, or for a `mesh_id` outside its scope
+//! - 422 without any filter, or for a malformed `timestamp_range`
+//! - 500 for database failures; 503 when the database is busy or the query timed out
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, QueryBuilder};
+use tracing::{error, info};
+
+use super::readings::{parse_timestamp_range, TimestampRange};
+use crate::{
+    date, db_error_response, deprecated, link_alert_events, mesh_forbidden, reconcile_sources,
+    record_event, require_role, update_mesh_summaries, ApiError, AppState, Config, Deprecated,
+    DeprecationPolicy, EventKind, Principal, Query, ResponseCache, Role,
+};
+
+/// Readings changed per statement.
+const BATCH: i64 = 5_000;
+
+/// `DELETE /sql/readings` follows the other `/sql/readings` methods to `/v1/readings`.
+static LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings",
+    }),
+    params: &[],
+};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    let admin = || middleware::from_fn_with_state(Role::Admin, require_role);
+    let route = || delete(soft_delete).route_layer(admin());
+    Router::new()
+        .route("/v1/readings", route())
+        .route(
+            "/sql/readings",
+            route().route_layer(middleware::from_fn_with_state(&LEGACY_POLICY, deprecated)),
+        )
+        .route(
+            "/admin/readings/restore",
+            post(restore).route_layer(admin()),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+    // ---
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeleteResponse {
+    deleted: u64,
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    restored: u64,
+}
+
+/// What a request does to the matching readings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Delete,
+    Restore,
+}
+
+/// Handle `DELETE /v1/readings`.
+async fn soft_delete(
+    Query(params): Query<DeleteQuery>,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    Extension(principal): Extension<Principal>,
+    State(cache): State<Arc<ResponseCache>>,
+) -> Response {
+    // ---
+    match apply(&pool, &config, &params, &principal, &cache, Change::Delete).await {
+        Ok(deleted) => (StatusCode::OK, Json(DeleteResponse { deleted })).into_response(),
+        Err(rejection) => rejection,
+    }
+}
+
+/// Handle `POST /admin/readings/restore`.
+async fn restore(
+    Query(params): Query<DeleteQuery>,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    Extension(principal): Extension<Principal>,
+    State(cache): State<Arc<ResponseCache>>,
+) -> Response {
+    // ---
+    match apply(&pool, &config, &params, &principal, &cache, Change::Restore).await {
+        Ok(restored) => (StatusCode::OK, Json(RestoreResponse { restored })).into_response(),
+        Err(rejection) => rejection,
+    }
+}
+
+/// Validate `params`, make `change` to the matching readings, bring
+/// duplicates, alerts and summaries up to date and record the event; returns
+/// the number of readings changed.
+async fn apply(
+    pool: &PgPool,
+    config: &Config,
+    params: &DeleteQuery,
+    principal: &Principal,
+    cache: &ResponseCache,
+    change: Change,
+) -> Result<u64, Response> {
+    // ---
+    let range = match params.timestamp_range.as_deref() {
+        None => (None, None),
+        Some(raw) => parse_timestamp_range(raw).ok_or_else(|| {
+            ApiError::Validation {
+                error: "invalid timestamp_range",
+                hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
+            }
+            .into_response()
+        })?,
+    };
+
+    // Checked on the parsed range, so `timestamp_range=,` filters nothing
+    if params.device_id.is_none() && params.mesh_id.is_none() && range == (None, None) {
+        return Err(ApiError::Validation {
+            error: "missing filter",
+            hint: "give device_id, mesh_id and/or a bounded timestamp_range; changing every reading at once is not supported",
+        }
+        .into_response());
+    }
+
+    if params
+        .mesh_id
+        .as_deref()
+        .is_some_and(|mesh_id| !principal.can_access_mesh(mesh_id))
+    {
+        return Err(mesh_forbidden());
+    }
+
+    info!("{:?} readings - {:?} by {}", change, params, principal.name);
+    let changed = set_tombstones(pool, params, range, principal, change)
+        .await
+        .map_err(|e| {
+            error!("Failed to {:?} readings: {}", change, e);
+            db_error_response(&e, "update failed")
+        })?;
+    if changed == 0 {
+        return Ok(0);
+    }
+
+    cache.clear();
+    let refreshed = async {
+        reconcile_sources(pool, &config.source_priority).await?;
+        link_alert_events(pool).await?;
+        update_mesh_summaries(pool).await
+    };
+    if let Err(e) = refreshed.await {
+        error!("Summary update after {:?} failed: {}", change, e);
+        return Err(db_error_response(&e, "summary update failed"));
+    }
+
+    let kind = match change {
+        Change::Delete => EventKind::ReadingsDeleted,
+        Change::Restore => EventKind::ReadingsRestored,
+    };
+    record_event(
+        pool,
+        kind,
+        json!({
+            "device_id": params.device_id,
+            "mesh_id": params.mesh_id,
+            "timestamp_range": params.timestamp_range,
+            "readings": changed,
+            "by": principal.name,
+        }),
+    )
+    .await;
+    Ok(changed)
+}
+
+/// Set ([`Change::Delete`]) or clear the tombstone of the readings matching
+/// `params` and `range` within the caller's scope, [`BATCH`] at a time;
+/// deleting also drops their alert events. Returns the number changed.
+#[tracing::instrument(name = "db.set_tombstones", skip_all, fields(change = ?change))]
+async fn set_tombstones(
+    pool: &PgPool,
+    params: &DeleteQuery,
+    (start, end): TimestampRange,
+    principal: &Principal,
+    change: Change,
+) -> Result<u64, sqlx::Error> {
+    // ---
+    let (set, current) = match change {
+        Change::Delete => ("now()", "deleted_at IS NULL"),
+        Change::Restore => ("NULL", "deleted_at IS NOT NULL"),
+    };
+    let mut changed = 0;
+    loop {
+        let mut qb = QueryBuilder::new(format!(
+            "WITH hit AS (UPDATE sensor_data SET deleted_at = {set} \
+             WHERE id IN (SELECT id FROM sensor_data WHERE {current}"
+        ));
+        if let Some(device_id) = &params.device_id {
+            qb.push(" AND device_id = ").push_bind(device_id);
+        }
+        if let Some(mesh_id) = &params.mesh_id {
+            qb.push(" AND mesh_id = ").push_bind(mesh_id);
+        }
+        if let Some(allowed) = &principal.meshes {
+            qb.push(" AND mesh_id = ANY(").push_bind(allowed).push(")");
+        }
+        if let Some(start) = start {
+            qb.push(" AND timestamp_utc >= ").push_bind(start);
+        }
+        if let Some(end) = end {
+            qb.push(" AND timestamp_utc <= ").push_bind(end);
+        }
+        qb.push(" LIMIT ").push_bind(BATCH).push(") RETURNING id)");
+        if change == Change::Delete {
+            qb.push(", unalerted AS (DELETE FROM alert_events WHERE reading_id IN (SELECT id FROM hit))");
+        }
+        qb.push(" SELECT COUNT(*) FROM hit");
+
+        let n: i64 = qb.build_query_scalar().fetch_one(pool).await?;
+        changed += n as u64;
+        if n < BATCH {
+            return Ok(changed);
+        }
+    }
+}
@@ -0,0 +1,582 @@
+//! Device registry and device-to-mesh reassignment endpoints.
+//!
+//! Raw device IDs mean little to operators, so each device can be given a
+//! registry entry in `devices`: the mesh it is installed in, a display name,
+//! location and coordinates, install date and notes. `GET /v1/readings?include=device`
+//! attaches the entry to every reading, and `bbox` filters readings of fixed
+//! devices by the coordinates stored here.
+//!
+//! Devices occasionally move between meshes. Each reassignment is recorded in
+//! `device_mesh_assignments` with an `effective_from` timestamp, so history is
+//! kept rather than overwritten, and `mesh_summary` attributes every reading
+//! to the mesh that was active at the reading's time.
+//!
+//! ## Routes
+//! - `PUT /sql/devices/{device_id}` - body `{ "mesh_id", "display_name", "location",
+//!   "latitude", "longitude", "installed_on": "YYYY-MM-DD", "notes" }`, every field
+//!   optional; replaces the entry
+//! - `GET /sql/devices/{device_id}` - the registry entry; 404 when none
+//! - `PUT /sql/devices/{device_id}/mesh` - body `{ "mesh_id": "...", "effective_from": "RFC3339" }`;
+//!   `effective_from` defaults to now. Re-putting the same `effective_from` replaces that entry.
+//! - `GET /sql/devices/{device_id}/mesh` - assignment history, newest first
+//! - `GET /sql/devices/{device_id}/gaps` - intervals of at least `min_gap` (default `10m`)
+//!   without readings, oldest first; `timestamp_range` bounds the report and also counts the
+//!   time between its bounds and the first or last reading, `limit` caps the gaps (default 1000)
+//!
+//! `PUT` requires the `writer` role, `GET` the `reader` role. Mesh-scoped
+//! callers may only assign devices into their meshes, may not move devices
+//! that have reported from other meshes, and only see assignments for their
+//! meshes and gaps between readings reported from them. They may only
+//! register devices in their meshes and only see entries whose `mesh_id` is
+//! one of them.
+//!
+//! `location` and `notes` are stored encrypted when `ENCRYPTED_FIELDS` names
+//! them (`devices.location`, `devices.notes`; see `field_crypto.rs`), and
+//! served decrypted to callers with `DECRYPT_ROLE`, `"[encrypted]"` to others.
+//!
+//! Stored readings keep the `mesh_id` the device reported; only the summaries
+//! and rollups follow the assignment history. A reassignment marks the
+//! device's rollups from `effective_from` on for the next refresh.
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Extension, Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use super::readings::{parse_timestamp_range, TimestampRange};
+use crate::{
+    db_error_response, filter_shape, load_devices, mark_device_dirty, mesh_forbidden,
+    parse_duration, require_role, timed, update_mesh_summaries, valid_position, ApiError, AppState,
+    DeviceInfo, Enrichment, Json, Path, Principal, Query, ReadPool, Role,
+};
+
+/// Most gaps returned by default.
+const DEFAULT_GAP_LIMIT: u32 = 1000;
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new()
+        .route(
+            "/sql/devices/{device_id}",
+            get(get_device).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+        )
+        .route(
+            "/sql/devices/{device_id}",
+            put(put_device).route_layer(middleware::from_fn_with_state(Role::Writer, require_role)),
+        )
+        .route(
+            "/sql/devices/{device_id}/mesh",
+            get(get_assignments)
+                .route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+        )
+        .route(
+            "/sql/devices/{device_id}/mesh",
+            put(put_assignment)
+                .route_layer(middleware::from_fn_with_state(Role::Writer, require_role)),
+        )
+        .route(
+            "/sql/devices/{device_id}/gaps",
+            get(get_gaps).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+        )
+}
+
+/// Request body for `PUT /sql/devices/{device_id}`.
+#[derive(Debug, Deserialize)]
+struct DeviceRequest {
+    // ---
+    mesh_id: Option<String>,
+    display_name: Option<String>,
+    location: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    installed_on: Option<NaiveDate>,
+    notes: Option<String>,
+}
+
+impl DeviceRequest {
+    // ---
+    /// The entry as stored for `device_id`, its sensitive columns encrypted.
+    fn sealed(mut self, device_id: &str, enrichment: &Enrichment) -> Self {
+        // ---
+        enrichment.encrypt_column("devices", "location", device_id, &mut self.location);
+        enrichment.encrypt_column("devices", "notes", device_id, &mut self.notes);
+        self
+    }
+}
+
+/// Request body for `PUT /sql/devices/{device_id}/mesh`.
+#[derive(Debug, Deserialize)]
+struct AssignRequest {
+    // ---
+    mesh_id: String,
+
+    /// When the device joined `mesh_id`; defaults to the time of the request.
+    effective_from: Option<DateTime<Utc>>,
+}
+
+/// One entry in a device's assignment history.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct Assignment {
+    mesh_id: String,
+    effective_from: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct AssignmentHistory {
+    device_id: String,
+    assignments: Vec<Assignment>,
+}
+
+/// Handle `PUT /sql/devices/{device_id}`.
+///
+/// Stores the body as the device's registry entry, replacing any earlier one,
+/// and returns it. 422 on an empty `mesh_id` or a partial or out-of-range
+/// position; 403 when a scoped caller names
+/// no mesh or one outside its scope, or the existing entry is outside it.
+async fn put_device(
+    Path(device_id): Path<String>,
+    State(pool): State<PgPool>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<DeviceRequest>,
+) -> Response {
+    // ---
+    let body = body.sealed(&device_id, &enrichment);
+    let mesh_id = body.mesh_id.as_deref().map(str::trim);
+    if mesh_id == Some("") {
+        return ApiError::Validation {
+            error: "invalid mesh_id",
+            hint: "mesh_id must be a non-empty string or omitted",
+        }
+        .into_response();
+    }
+    if !valid_position(body.latitude, body.longitude) {
+        return ApiError::Validation {
+            error: "invalid position",
+            hint: "send latitude (-90..90) and longitude (-180..180) together, or neither",
+        }
+        .into_response();
+    }
+    if principal.meshes.is_some() && !mesh_id.is_some_and(|m| principal.can_access_mesh(m)) {
+        return mesh_forbidden();
+    }
+
+    // A scoped caller must not take over another customer's entry
+    if principal.meshes.is_some() {
+        match load_devices(&pool, std::slice::from_ref(&device_id)).await {
+            Ok(existing) if existing.iter().all(|d| visible(d, &principal)) => {}
+            Ok(_) => return mesh_forbidden(),
+            Err(e) => {
+                error!("Failed to load device {}: {}", device_id, e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    }
+
+    let stored = sqlx::query_as::<_, DeviceInfo>(
+        r#"
+        INSERT INTO devices (
+            device_id, mesh_id, display_name, location, latitude, longitude, installed_on, notes
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (device_id) DO UPDATE SET
+            mesh_id      = EXCLUDED.mesh_id,
+            display_name = EXCLUDED.display_name,
+            location     = EXCLUDED.location,
+            latitude     = EXCLUDED.latitude,
+            longitude    = EXCLUDED.longitude,
+            installed_on = EXCLUDED.installed_on,
+            notes        = EXCLUDED.notes,
+            updated_at   = now()
+        RETURNING device_id, mesh_id, display_name, location, latitude, longitude,
+                  installed_on, notes, updated_at
+        "#,
+    )
+    .bind(&device_id)
+    .bind(mesh_id)
+    .bind(&body.display_name)
+    .bind(&body.location)
+    .bind(body.latitude)
+    .bind(body.longitude)
+    .bind(body.installed_on)
+    .bind(&body.notes)
+    .fetch_one(&pool)
+    .await;
+
+    match stored {
+        Ok(mut device) => {
+            info!("Device {} registered", device_id);
+            enrichment.reveal_device(&mut device, principal.role);
+            (StatusCode::OK, Json(device)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to store device {}: {}", device_id, e);
+            db_error_response(&e, "store failed")
+        }
+    }
+}
+
+/// Handle `GET /sql/devices/{device_id}`.
+///
+/// 404 for unregistered devices and entries outside the caller's meshes.
+async fn get_device(
+    Path(device_id): Path<String>,
+    State(pool): State<PgPool>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    // ---
+    match load_devices(&pool, std::slice::from_ref(&device_id)).await {
+        Ok(mut found) => match found.pop() {
+            Some(mut device) if visible(&device, &principal) => {
+                enrichment.reveal_device(&mut device, principal.role);
+                (StatusCode::OK, Json(device)).into_response()
+            }
+            _ => ApiError::NotFound {
+                error: "device not registered",
+                hint: "register it with PUT /sql/devices/{device_id}",
+            }
+            .into_response(),
+        },
+        Err(e) => {
+            error!("Failed to load device {}: {}", device_id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Whether a scoped caller may see `device`'s entry: only with a mesh of its own.
+fn visible(device: &DeviceInfo, principal: &Principal) -> bool {
+    // ---
+    principal.meshes.is_none()
+        || device
+            .mesh_id
+            .as_deref()
+            .is_some_and(|m| principal.can_access_mesh(m))
+}
+
+/// Handle `PUT /sql/devices/{device_id}/mesh`.
+///
+/// Records the assignment, recomputes `mesh_summary`, and returns the
+/// device's full history. 422 on an empty `mesh_id`; 403 when the target
+/// mesh or the device's reported meshes are outside the caller's scope.
+async fn put_assignment(
+    Path(device_id): Path<String>,
+    State(pool): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<AssignRequest>,
+) -> Response {
+    // ---
+    let mesh_id = body.mesh_id.trim();
+    if mesh_id.is_empty() {
+        return ApiError::Validation {
+            error: "invalid mesh_id",
+            hint: "mesh_id must be a non-empty string",
+        }
+        .into_response();
+    }
+    if !principal.can_access_mesh(mesh_id) {
+        return mesh_forbidden();
+    }
+
+    // A scoped caller must not pull another customer's device into its meshes
+    if let Some(allowed) = &principal.meshes {
+        let foreign = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM sensor_data
+                WHERE device_id = $1 AND NOT (mesh_id = ANY($2))
+            )
+            "#,
+        )
+        .bind(&device_id)
+        .bind(allowed)
+        .fetch_one(&pool)
+        .await;
+
+        match foreign {
+            Ok(false) => {}
+            Ok(true) => return mesh_forbidden(),
+            Err(e) => {
+                error!("Failed to check device meshes: {}", e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    }
+    let effective_from = body.effective_from.unwrap_or_else(Utc::now);
+
+    let stored = sqlx::query(
+        r#"
+        INSERT INTO device_mesh_assignments (device_id, mesh_id, effective_from)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (device_id, effective_from) DO UPDATE SET
+            mesh_id     = EXCLUDED.mesh_id,
+            assigned_at = now()
+        "#,
+    )
+    .bind(&device_id)
+    .bind(mesh_id)
+    .bind(effective_from)
+    .execute(&pool)
+    .await;
+
+    if let Err(e) = stored {
+        error!("Failed to store mesh assignment: {}", e);
+        return db_error_response(&e, "store failed");
+    }
+    info!(
+        "Device {} assigned to mesh {} from {}",
+        device_id, mesh_id, effective_from
+    );
+
+    if let Err(e) = mark_device_dirty(&pool, &device_id, effective_from).await {
+        error!("Marking rollups after reassignment failed: {}", e);
+        return db_error_response(&e, "rollup update failed");
+    }
+    if let Err(e) = update_mesh_summaries(&pool).await {
+        error!("Summary update after reassignment failed: {}", e);
+        return db_error_response(&e, "summary update failed");
+    }
+
+    history(&pool, device_id, &principal).await
+}
+
+/// Handle `GET /sql/devices/{device_id}/mesh`.
+async fn get_assignments(
+    Path(device_id): Path<String>,
+    State(pool): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    // ---
+    history(&pool, device_id, &principal).await
+}
+
+/// Assignment history for `device_id`, limited to the caller's meshes.
+async fn history(pool: &PgPool, device_id: String, principal: &Principal) -> Response {
+    // ---
+    let rows = sqlx::query_as::<_, Assignment>(
+        r#"
+        SELECT mesh_id, effective_from
+        FROM device_mesh_assignments
+        WHERE device_id = $1 AND ($2::TEXT[] IS NULL OR mesh_id = ANY($2))
+        ORDER BY effective_from DESC
+        "#,
+    )
+    .bind(&device_id)
+    .bind(&principal.meshes)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(assignments) => (
+            StatusCode::OK,
+            Json(AssignmentHistory {
+                device_id,
+                assignments,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to load mesh assignments: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Query parameters for `GET /sql/devices/{device_id}/gaps`.
+#[derive(Debug, Deserialize)]
+struct GapsQuery {
+    // ---
+    /// Shortest interval reported, e.g. `10m`.
+    min_gap: Option<String>,
+    timestamp_range: Option<String>,
+    limit: Option<u32>,
+}
+
+/// An interval without readings: from the reading (or range start) before it
+/// to the one (or range end) after it.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct Gap {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    duration_secs: f64,
+}
+
+#[derive(Serialize)]
+struct GapReport {
+    device_id: String,
+    min_gap: String,
+    gap_count: usize,
+
+    /// Sum of `duration_secs` over the gaps returned.
+    total_gap_secs: f64,
+    gaps: Vec<Gap>,
+}
+
+/// Handle `GET /sql/devices/{device_id}/gaps`.
+///
+/// 422 for a malformed `min_gap` or `timestamp_range`.
+async fn get_gaps(
+    Path(device_id): Path<String>,
+    Query(params): Query<GapsQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let min_gap = params.min_gap.clone().unwrap_or_else(|| "10m".into());
+    let Some(min) = parse_duration(&min_gap) else {
+        return ApiError::Validation {
+            error: "invalid min_gap",
+            hint: "use <n>s, <n>m, <n>h or <n>d, e.g. min_gap=10m",
+        }
+        .into_response();
+    };
+    let range = match params.timestamp_range.as_deref() {
+        None => (None, None),
+        Some(raw) => match parse_timestamp_range(raw) {
+            Some(range) => range,
+            None => {
+                return ApiError::Validation {
+                    error: "invalid timestamp_range",
+                    hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
+                }
+                .into_response();
+            }
+        },
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_GAP_LIMIT);
+
+    let (id, principal) = (&device_id, &principal);
+    let loaded = reads
+        .read(|pool| async move { load_gaps(&pool, id, min, range, principal, limit).await })
+        .await;
+
+    match loaded {
+        Ok(gaps) => (
+            StatusCode::OK,
+            Json(GapReport {
+                device_id,
+                min_gap,
+                gap_count: gaps.len(),
+                total_gap_secs: gaps.iter().map(|g| g.duration_secs).sum(),
+                gaps,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to load gaps for {}: {}", device_id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Intervals of at least `min` between consecutive readings of `device_id`
+/// within `(start, end)`, whose bounds count as readings.
+#[tracing::instrument(name = "db.load_gaps", skip_all)]
+async fn load_gaps(
+    pool: &PgPool,
+    device_id: &str,
+    min: Duration,
+    (start, end): TimestampRange,
+    principal: &Principal,
+    limit: u32,
+) -> Result<Vec<Gap>, sqlx::Error> {
+    // ---
+    let query = sqlx::query_as::<_, Gap>(
+        r#"
+        WITH points AS (
+            SELECT timestamp_utc AS t
+            FROM sensor_data
+            WHERE device_id = $1 AND deleted_at IS NULL
+              AND ($3::TIMESTAMPTZ IS NULL OR timestamp_utc >= $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR timestamp_utc <= $4)
+              AND ($5::TEXT[] IS NULL OR mesh_id = ANY($5))
+            UNION ALL SELECT $3 WHERE $3 IS NOT NULL
+            UNION ALL SELECT $4 WHERE $4 IS NOT NULL
+        ),
+        steps AS (
+            SELECT LAG(t) OVER (ORDER BY t) AS start, t AS "end"
+            FROM points
+        )
+        SELECT start, "end", EXTRACT(EPOCH FROM "end" - start)::FLOAT8 AS duration_secs
+        FROM steps
+        WHERE "end" - start >= make_interval(secs => $2)
+        ORDER BY start
+        LIMIT $6
+        "#,
+    )
+    .bind(device_id)
+    .bind(min.num_seconds() as f64)
+    .bind(start)
+    .bind(end)
+    .bind(&principal.meshes)
+    .bind(i64::from(limit));
+
+    let mut shape = vec!["device_id"];
+    if start.is_some() || end.is_some() {
+        shape.push("timestamp_utc");
+    }
+    timed("load_gaps", &filter_shape(&shape), query.fetch_all(pool)).await
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use crate::{field_crypto::REDACTED, EncryptionConfig};
+
+    #[test]
+    fn sensitive_columns_are_stored_encrypted() {
+        // ---
+        let enrichment = Enrichment::from_config(
+            &[],
+            Some(&EncryptionConfig {
+                key: [7; 32],
+                key_id: "test".into(),
+                fields: vec!["devices.notes".into()],
+                decrypt_role: Role::Admin,
+            }),
+        )
+        .unwrap();
+        let body: DeviceRequest = serde_json::from_value(serde_json::json!({
+            "location": "Plant A",
+            "notes": "gate code 4711",
+        }))
+        .unwrap();
+
+        // What put_device binds into the `notes` column
+        let stored = body.sealed("device-001", &enrichment);
+        let notes = stored.notes.clone().unwrap();
+        assert!(notes.starts_with("enc:v1:test:"), "{notes}");
+        assert!(!notes.contains("4711"));
+        assert_eq!(stored.location.as_deref(), Some("Plant A"));
+
+        let mut device = DeviceInfo {
+            device_id: "device-001".into(),
+            mesh_id: None,
+            display_name: None,
+            location: stored.location,
+            latitude: None,
+            longitude: None,
+            installed_on: None,
+            notes: stored.notes,
+            updated_at: Utc::now(),
+        };
+        let mut reader_view = device.clone();
+        enrichment.reveal_device(&mut reader_view, Role::Reader);
+        assert_eq!(reader_view.notes.as_deref(), Some(REDACTED));
+        enrichment.reveal_device(&mut device, Role::Admin);
+        assert_eq!(device.notes.as_deref(), Some("gate code 4711"));
+    }
+}
@@ -0,0 +1,336 @@
+//! Bulk export of alert events for archiving.
+//!
+//! `GET /alerts/events/export` returns every alert event matching the filters
+//! as a download, oldest first:
+//! - `device_id`, `mesh_id`, `kind` (`temperature` or `humidity`) - as for
+//!   `GET /alerts/events`
+//! - `since`, `until` (RFC3339) - `occurred_at` range, inclusive
+//! - `format` - `csv` (default), `ndjson` or `parquet` (see `export.rs`)
+//!
+//! CSV and NDJSON are streamed in pages of [`PAGE_SIZE`] rows, so exports of
+//! any size run in constant memory. Parquet is assembled in memory and capped
+//! at [`MAX_PARQUET_ROWS`]; narrow the range for larger archives.
+//!
+//! Requires the `reader` role and honours the caller's mesh scope.
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder};
+use tracing::{error, info};
+
+use crate::{
+    csv_record, db_error_response, require_role, write_parquet, ApiError, AppState, Column,
+    ExportFormat, Principal, Query, ReadPool, Role,
+};
+
+/// Rows fetched per query (and per Parquet row group).
+const PAGE_SIZE: i64 = 5000;
+
+/// Most rows a Parquet export may contain.
+const MAX_PARQUET_ROWS: usize = 1_000_000;
+
+/// Column order for every format.
+const COLUMNS: [&str; 6] = [
+    "id",
+    "reading_id",
+    "kind",
+    "device_id",
+    "mesh_id",
+    "occurred_at",
+];
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new().route(
+        "/alerts/events/export",
+        get(alert_events).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+    )
+}
+
+/// Query parameters for `GET /alerts/events/export`.
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    // ---
+    #[serde(alias = "device", alias = "deviceId", alias = "deviceID")]
+    device_id: Option<String>,
+
+    #[serde(alias = "mesh", alias = "meshId", alias = "meshID")]
+    mesh_id: Option<String>,
+
+    /// `temperature` or `humidity`
+    kind: Option<String>,
+
+    since: Option<DateTime<Utc>>,
+
+    until: Option<DateTime<Utc>>,
+
+    /// `csv` (default), `ndjson` or `parquet`
+    format: Option<String>,
+}
+
+/// Filters applied to every page, including the caller's mesh scope.
+#[derive(Clone)]
+struct Filter {
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    kind: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    meshes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct AlertRow {
+    id: i64,
+    reading_id: i32,
+    kind: String,
+    device_id: String,
+    mesh_id: String,
+    occurred_at: DateTime<Utc>,
+}
+
+/// Handle `GET /alerts/events/export`.
+///
+/// 422 for an unknown `format`; 413 when a Parquet export exceeds [`MAX_PARQUET_ROWS`].
+async fn alert_events(
+    Query(params): Query<ExportQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let format = match params
+        .format
+        .as_deref()
+        .unwrap_or("csv")
+        .parse::<ExportFormat>()
+    {
+        Ok(f) => f,
+        Err(_) => {
+            return ApiError::Validation {
+                error: "invalid format",
+                hint: "use format=csv, format=ndjson or format=parquet",
+            }
+            .into_response();
+        }
+    };
+
+    let filter = Filter {
+        device_id: params.device_id,
+        mesh_id: params.mesh_id,
+        kind: params.kind,
+        since: params.since,
+        until: params.until,
+        meshes: principal.meshes,
+    };
+    info!(
+        "GET /alerts/events/export - {} export for {}",
+        format.extension(),
+        principal.name
+    );
+
+    // Every page from the same pool, even if the replica's health changes mid-export
+    let pool = reads.pool().clone();
+    let body = match format {
+        ExportFormat::Parquet => match parquet_body(&pool, filter).await {
+            Ok(body) => body,
+            Err(resp) => return resp,
+        },
+        _ => streamed_body(pool, filter, format),
+    };
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"alert_events.{}\"",
+                    format.extension()
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// CSV or NDJSON, fetched and encoded one page at a time.
+fn streamed_body(pool: PgPool, filter: Filter, format: ExportFormat) -> Body {
+    // ---
+    let header = match format {
+        ExportFormat::Csv => Some(Ok(Bytes::from(csv_record(COLUMNS)))),
+        _ => None,
+    };
+
+    // State: the last row's keyset position, or `None` once the final page is sent
+    let pages = stream::unfold(Some(None), move |after| {
+        let (pool, filter) = (pool.clone(), filter.clone());
+        async move {
+            // ---
+            let after = after?;
+            match fetch_page(&pool, &filter, after).await {
+                Ok(rows) => {
+                    let next = (rows.len() as i64 == PAGE_SIZE)
+                        .then(|| rows.last().map(|r| (r.occurred_at, r.id)));
+                    Some((Ok(Bytes::from(encode_page(&rows, format))), next))
+                }
+                Err(e) => {
+                    error!("Alert event export failed mid-stream: {}", e);
+                    Some((Err(e), None))
+                }
+            }
+        }
+    });
+
+    // Fused: response compression polls the body once more after it ends
+    Body::from_stream(stream::iter(header).chain(pages.fuse()))
+}
+
+/// Whole Parquet file, one row group per page.
+async fn parquet_body(pool: &PgPool, filter: Filter) -> Result<Body, Response> {
+    // ---
+    let mut groups = Vec::new();
+    let mut total = 0;
+    let mut after = None;
+    loop {
+        let rows = fetch_page(pool, &filter, after).await.map_err(|e| {
+            error!("Failed to load alert events for export: {}", e);
+            db_error_response(&e, "export failed")
+        })?;
+        total += rows.len();
+        if total > MAX_PARQUET_ROWS {
+            return Err(ApiError::TooLarge {
+                error: "export too large",
+                hint: "narrow since/until (Parquet exports are capped at 1000000 rows) or use format=csv",
+            }
+            .into_response());
+        }
+
+        let full = rows.len() as i64 == PAGE_SIZE;
+        after = rows.last().map(|r| (r.occurred_at, r.id));
+        // An empty export still needs one (empty) row group to carry the schema
+        if !rows.is_empty() || groups.is_empty() {
+            groups.push(parquet_group(rows));
+        }
+        if !full {
+            break;
+        }
+    }
+
+    match tokio::task::spawn_blocking(move || write_parquet(groups)).await {
+        Ok(Ok(file)) => Ok(Body::from(file)),
+        Ok(Err(e)) => {
+            error!("Failed to encode Parquet export: {}", e);
+            Err(ApiError::Internal("export failed").into_response())
+        }
+        Err(e) => {
+            error!("Parquet export task failed: {}", e);
+            Err(ApiError::Internal("export failed").into_response())
+        }
+    }
+}
+
+/// Next page after keyset position `after` (`occurred_at`, `id`), oldest first.
+#[tracing::instrument(name = "db.export_page", skip_all)]
+async fn fetch_page(
+    pool: &PgPool,
+    filter: &Filter,
+    after: Option<(DateTime<Utc>, i64)>,
+) -> Result<Vec<AlertRow>, sqlx::Error> {
+    // ---
+    let mut qb = QueryBuilder::new(
+        "SELECT id, reading_id, kind, device_id, mesh_id, occurred_at FROM alert_events WHERE 1=1",
+    );
+    if let Some(device_id) = &filter.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id);
+    }
+    if let Some(mesh_id) = &filter.mesh_id {
+        qb.push(" AND mesh_id = ").push_bind(mesh_id);
+    }
+    if let Some(kind) = &filter.kind {
+        qb.push(" AND kind = ").push_bind(kind);
+    }
+    if let Some(since) = filter.since {
+        qb.push(" AND occurred_at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        qb.push(" AND occurred_at <= ").push_bind(until);
+    }
+    if let Some(allowed) = &filter.meshes {
+        qb.push(" AND mesh_id = ANY(").push_bind(allowed).push(")");
+    }
+    if let Some((at, id)) = after {
+        qb.push(" AND (occurred_at, id) > (")
+            .push_bind(at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    qb.push(" ORDER BY occurred_at, id LIMIT ")
+        .push_bind(PAGE_SIZE);
+
+    qb.build_query_as::<AlertRow>().fetch_all(pool).await
+}
+
+fn encode_page(rows: &[AlertRow], format: ExportFormat) -> String {
+    // ---
+    let mut out = String::new();
+    for row in rows {
+        match format {
+            ExportFormat::Csv => out.push_str(&csv_record([
+                row.id.to_string().as_str(),
+                row.reading_id.to_string().as_str(),
+                &row.kind,
+                &row.device_id,
+                &row.mesh_id,
+                &row.occurred_at.to_rfc3339(),
+            ])),
+            _ => {
+                out.push_str(&serde_json::to_string(row).unwrap_or_default());
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+fn parquet_group(rows: Vec<AlertRow>) -> Vec<(&'static str, Column)> {
+    // ---
+    let n = rows.len();
+    let (mut id, mut reading_id, mut kind, mut device_id, mut mesh_id, mut occurred_at) = (
+        Vec::with_capacity(n),
+        Vec::with_capacity(n),
+        Vec::with_capacity(n),
+        Vec::with_capacity(n),
+        Vec::with_capacity(n),
+        Vec::with_capacity(n),
+    );
+    for r in rows {
+        id.push(r.id);
+        reading_id.push(i64::from(r.reading_id));
+        kind.push(r.kind);
+        device_id.push(r.device_id);
+        mesh_id.push(r.mesh_id);
+        occurred_at.push(r.occurred_at);
+    }
+    vec![
+        (COLUMNS[0], Column::Int64(id)),
+        (COLUMNS[1], Column::Int64(reading_id)),
+        (COLUMNS[2], Column::Utf8(kind)),
+        (COLUMNS[3], Column::Utf8(device_id)),
+        (COLUMNS[4], Column::Utf8(mesh_id)),
+        (COLUMNS[5], Column::Timestamp(occurred_at)),
+    ]
+}
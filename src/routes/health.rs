@@ -32,7 +32,7 @@ async fn health() -> Json<HealthResponse> {
 /// Create a subrouter containing the `/health` route.
 ///
 /// This router is generic over the application state so it can merge cleanly
-/// with the gateway router, regardless of the state type (e.g., `(PgPool, Config)`).
+/// with the gateway router, regardless of the state type (e.g., [`AppState`](crate::AppState)).
 ///
 /// # Returns
 /// A [`Router<S>`] with a single GET `/health` route.
@@ -0,0 +1,80 @@
+//! On-demand ingestion endpoint.
+//!
+//! `POST /sql/ingest` re-fetches every configured upstream source, storing
+//! readings not already present, and refreshes `mesh_summary`. Requires the
+//! `writer` role and unrestricted mesh access, since upstream sources span
+//! every mesh.
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    ingest_all, mesh_forbidden, require_role, ApiError, AppState, Config, Enrichment, LiveConfig,
+    Principal, ResponseCache, Role,
+};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new().route(
+        "/sql/ingest",
+        post(handler).route_layer(middleware::from_fn_with_state(Role::Writer, require_role)),
+    )
+}
+
+#[derive(Serialize)]
+struct IngestResponse {
+    sources: Vec<SourceResult>,
+}
+
+#[derive(Serialize)]
+struct SourceResult {
+    name: String,
+    inserted: u64,
+}
+
+/// Handle `POST /sql/ingest`; 403 for mesh-scoped callers, 500 if any source fails.
+async fn handler(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    Extension(principal): Extension<Principal>,
+    State(enrichment): State<Arc<Enrichment>>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(live): State<LiveConfig>,
+) -> Response {
+    // ---
+    if principal.meshes.is_some() {
+        return mesh_forbidden();
+    }
+
+    let live = live.load();
+    let sources = &live.sources;
+    info!("POST /sql/ingest - Re-ingesting {} sources", sources.len());
+
+    let ingested = ingest_all(&pool, sources, &config.source_priority, &enrichment).await;
+    cache.clear();
+    match ingested {
+        Ok(counts) => {
+            let sources = counts
+                .into_iter()
+                .map(|(name, inserted)| SourceResult { name, inserted })
+                .collect();
+            (StatusCode::OK, Json(IngestResponse { sources })).into_response()
+        }
+        Err(e) => {
+            error!("On-demand ingest failed: {}", e);
+            ApiError::from(e).into_response()
+        }
+    }
+}
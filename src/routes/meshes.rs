@@ -0,0 +1,295 @@
+//! Mesh metadata registry.
+//!
+//! Mesh IDs come from upstream and say nothing about where a mesh is. Each
+//! mesh can get an entry in `meshes` with a site name, an IANA timezone and
+//! a contact. Mesh summaries (`GET /sql/stream/mesh-summary`) carry the
+//! `site_name`, and alert events the `site_name` and `timezone`, so clients
+//! can show local times without a lookup of their own. Timestamps stay UTC;
+//! readings add a `timestamp_local` in their mesh's timezone.
+//!
+//! ## Routes
+//! - `GET /sql/meshes` - every entry, by mesh ID
+//! - `GET /sql/meshes/{mesh_id}` - one entry; 404 when none
+//! - `PUT /sql/meshes/{mesh_id}` - body `{ "site_name", "timezone", "contact" }`, every field
+//!   optional; replaces the entry
+//! - `DELETE /sql/meshes/{mesh_id}` - removes the entry (204); 404 when none
+//!
+//! `PUT` and `DELETE` require the `writer` role, `GET` the `reader` role.
+//! Mesh-scoped callers only see and change entries for their meshes. The
+//! `contact` is stored encrypted when `ENCRYPTED_FIELDS` names
+//! `meshes.contact`, and served as the device routes serve theirs.
+//!
+//! ## Error Handling
+//! - 422 for a `timezone` Postgres doesn't know (see `pg_timezone_names`)
+//! - 403 when a scoped caller writes a mesh outside its scope
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    db_error_response, mesh_forbidden, notify_payload, require_role, ApiError, AppState,
+    Enrichment, Json, Path, Principal, ReadPool, Role, SUMMARY_CHANNEL,
+};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new()
+        .route(
+            "/sql/meshes",
+            get(list).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+        )
+        .route(
+            "/sql/meshes/{mesh_id}",
+            get(get_mesh).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+        )
+        .route(
+            "/sql/meshes/{mesh_id}",
+            put(put_mesh)
+                .delete(delete_mesh)
+                .route_layer(middleware::from_fn_with_state(Role::Writer, require_role)),
+        )
+}
+
+/// One registry entry.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct MeshInfo {
+    // ---
+    mesh_id: String,
+
+    /// Human-readable site, e.g. "Plant A".
+    site_name: Option<String>,
+
+    /// IANA timezone of the site, e.g. `Europe/Berlin`.
+    timezone: Option<String>,
+
+    /// Who to call about the site.
+    contact: Option<String>,
+
+    /// When the entry was last written.
+    updated_at: DateTime<Utc>,
+}
+
+impl MeshInfo {
+    // ---
+    /// The entry as a caller with `role` may see it.
+    fn revealed(mut self, enrichment: &Enrichment, role: Role) -> Self {
+        // ---
+        enrichment.reveal_column("meshes", "contact", &self.mesh_id, &mut self.contact, role);
+        self
+    }
+}
+
+/// Request body for `PUT /sql/meshes/{mesh_id}`.
+#[derive(Debug, Deserialize)]
+struct MeshRequest {
+    site_name: Option<String>,
+    timezone: Option<String>,
+    contact: Option<String>,
+}
+
+/// Handle `GET /sql/meshes`.
+async fn list(
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+    State(enrichment): State<Arc<Enrichment>>,
+) -> Response {
+    // ---
+    let rows = sqlx::query_as::<_, MeshInfo>(
+        r#"
+        SELECT mesh_id, site_name, timezone, contact, updated_at
+        FROM meshes
+        WHERE $1::TEXT[] IS NULL OR mesh_id = ANY($1)
+        ORDER BY mesh_id
+        "#,
+    )
+    .bind(&principal.meshes)
+    .fetch_all(reads.pool())
+    .await;
+
+    match rows {
+        Ok(meshes) => {
+            let meshes: Vec<_> = meshes
+                .into_iter()
+                .map(|m| m.revealed(&enrichment, principal.role))
+                .collect();
+            (StatusCode::OK, Json(meshes)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to load meshes: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /sql/meshes/{mesh_id}`.
+///
+/// 404 for unregistered meshes and meshes outside the caller's scope.
+async fn get_mesh(
+    Path(mesh_id): Path<String>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+    State(enrichment): State<Arc<Enrichment>>,
+) -> Response {
+    // ---
+    if !principal.can_access_mesh(&mesh_id) {
+        return not_registered();
+    }
+    let row = sqlx::query_as::<_, MeshInfo>(
+        "SELECT mesh_id, site_name, timezone, contact, updated_at FROM meshes WHERE mesh_id = $1",
+    )
+    .bind(&mesh_id)
+    .fetch_optional(reads.pool())
+    .await;
+
+    match row {
+        Ok(Some(mesh)) => {
+            let mesh = mesh.revealed(&enrichment, principal.role);
+            (StatusCode::OK, Json(mesh)).into_response()
+        }
+        Ok(None) => not_registered(),
+        Err(e) => {
+            error!("Failed to load mesh {}: {}", mesh_id, e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `PUT /sql/meshes/{mesh_id}`.
+///
+/// Stores the body as the mesh's entry, replacing any earlier one, and
+/// returns it. Streams of the mesh's summary get the new `site_name`.
+async fn put_mesh(
+    Path(mesh_id): Path<String>,
+    State(pool): State<PgPool>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Extension(principal): Extension<Principal>,
+    Json(mut body): Json<MeshRequest>,
+) -> Response {
+    // ---
+    if !principal.can_access_mesh(&mesh_id) {
+        return mesh_forbidden();
+    }
+    if let Some(tz) = &body.timezone {
+        let known = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)",
+        )
+        .bind(tz)
+        .fetch_one(&pool)
+        .await;
+        match known {
+            Ok(true) => {}
+            Ok(false) => {
+                return ApiError::Validation {
+                    error: "invalid timezone",
+                    hint: "use an IANA timezone name, e.g. Europe/Berlin or America/Chicago",
+                }
+                .into_response();
+            }
+            Err(e) => {
+                error!("Failed to check timezone: {}", e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    }
+
+    enrichment.encrypt_column("meshes", "contact", &mesh_id, &mut body.contact);
+    let stored = sqlx::query_as::<_, MeshInfo>(
+        r#"
+        INSERT INTO meshes (mesh_id, site_name, timezone, contact)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (mesh_id) DO UPDATE SET
+            site_name  = EXCLUDED.site_name,
+            timezone   = EXCLUDED.timezone,
+            contact    = EXCLUDED.contact,
+            updated_at = now()
+        RETURNING mesh_id, site_name, timezone, contact, updated_at
+        "#,
+    )
+    .bind(&mesh_id)
+    .bind(&body.site_name)
+    .bind(&body.timezone)
+    .bind(&body.contact)
+    .fetch_one(&pool)
+    .await;
+
+    match stored {
+        Ok(mesh) => {
+            info!("Mesh {} registered", mesh_id);
+            announce(&pool, &mesh_id).await;
+            let mesh = mesh.revealed(&enrichment, principal.role);
+            (StatusCode::OK, Json(mesh)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to store mesh {}: {}", mesh_id, e);
+            db_error_response(&e, "store failed")
+        }
+    }
+}
+
+/// Handle `DELETE /sql/meshes/{mesh_id}`.
+async fn delete_mesh(
+    Path(mesh_id): Path<String>,
+    State(pool): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    // ---
+    if !principal.can_access_mesh(&mesh_id) {
+        return mesh_forbidden();
+    }
+    let deleted = sqlx::query("DELETE FROM meshes WHERE mesh_id = $1")
+        .bind(&mesh_id)
+        .execute(&pool)
+        .await;
+
+    match deleted {
+        Ok(r) if r.rows_affected() == 0 => not_registered(),
+        Ok(_) => {
+            info!("Mesh {} unregistered", mesh_id);
+            announce(&pool, &mesh_id).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            error!("Failed to delete mesh {}: {}", mesh_id, e);
+            db_error_response(&e, "delete failed")
+        }
+    }
+}
+
+/// Tell summary streams that `mesh_id`'s row changed, if it has one;
+/// failures only delay the new name until the next summary change.
+async fn announce(pool: &PgPool, mesh_id: &str) {
+    // ---
+    let sent = sqlx::query(
+        "SELECT pg_notify($1, $2) WHERE EXISTS (SELECT 1 FROM mesh_summary WHERE mesh_id = $3)",
+    )
+    .bind(SUMMARY_CHANNEL)
+    .bind(notify_payload(&[mesh_id.to_string()]))
+    .bind(mesh_id)
+    .execute(pool)
+    .await;
+    if let Err(e) = sent {
+        tracing::warn!("Failed to announce mesh {} change: {}", mesh_id, e);
+    }
+}
+
+fn not_registered() -> Response {
+    // ---
+    ApiError::NotFound {
+        error: "mesh not registered",
+        hint: "register it with PUT /sql/meshes/{mesh_id}",
+    }
+    .into_response()
+}
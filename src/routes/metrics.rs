@@ -0,0 +1,42 @@
+//! Prometheus scrape endpoint.
+//!
+//! `GET /metrics` renders every metric recorded since startup (see
+//! `prometheus.rs`) in the Prometheus text format. Requires the `admin` role;
+//! scrapers authenticate like any other client, e.g. with an `x-api-key`.
+use axum::{
+    extract::{FromRef, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::{require_role, Role};
+
+// ---
+
+pub fn router<S>() -> Router<S>
+where
+    PrometheusHandle: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    // ---
+    Router::new().route(
+        "/metrics",
+        get(handler).route_layer(middleware::from_fn_with_state(Role::Admin, require_role)),
+    )
+}
+
+/// Handle `GET /metrics`.
+async fn handler(State(metrics): State<PrometheusHandle>) -> Response {
+    // ---
+    metrics.run_upkeep();
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response()
+}
@@ -1,17 +1,413 @@
-use axum::Router;
-use sqlx::PgPool;
+use axum::{
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware, Router,
+};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+};
 
-use crate::Config;
+use crate::{
+    authenticate, demo_guard, demo_watermark, idempotency, maintenance_guard, rate_limit,
+    report_errors, request_id, AppState, CorsConfig, EdgeState, DEMO_HEADER, REPLAYED_HEADER,
+    REQUEST_ID_HEADER,
+};
 
+mod admin;
+mod aggregates;
+mod alerts;
+mod anomalies;
+mod corrections;
+mod delete;
+mod devices;
+mod export;
 mod health;
+mod ingest;
+mod meshes;
+mod metrics;
+mod push;
 mod readings;
+mod ready;
+mod stats;
+mod stream;
+mod version;
 
 // ---
 
-pub fn router(pool: PgPool, config: Config) -> Router {
+/// Build the API router over the shared [`AppState`].
+///
+/// Data routes sit behind the [`authenticate`] middleware and each enforces
+/// its minimum role (`reader`, `writer`, `admin`); `/health`, `/ready` and
+/// `/version` stay open for orchestrator probes and deployment tooling. The
+/// [`RateLimiter`](crate::RateLimiter) wraps the data routes outside
+/// authentication, admitting everything while [`LiveConfig`](crate::LiveConfig)
+/// has no limit. With CORS configured, the CORS layer sits outside both so
+/// preflight requests are answered before auth or rate limiting. During
+/// maintenance, [`maintenance_guard`] (just inside authentication) turns away
+/// everyone but admins. The request ID layer sits outside them, so every
+/// response carries one; error reporting sits just inside it, so reports carry
+/// the ID.
+/// Compression (gzip or brotli, as `Accept-Encoding` allows) is outermost, so
+/// error bodies are tagged with the ID before they are encoded; SSE streams
+/// and tiny bodies are left alone. In demo mode,
+/// [`demo_guard`] rejects mutating and admin routes inside the rate limiter
+/// and every response is watermarked.
+pub fn router(state: AppState) -> Router {
     // ---
-    Router::new()
+    let config = &state.config;
+    let readings = readings::router().route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        readings::await_warmup,
+    ));
+    let mut api = Router::new()
+        .merge(readings)
+        .merge(aggregates::router())
+        .merge(push::router())
+        .merge(delete::router())
+        .merge(corrections::router())
+        .merge(alerts::router())
+        .merge(anomalies::router())
+        .merge(stats::router())
+        .merge(export::router())
+        .merge(devices::router())
+        .merge(meshes::router())
+        .merge(ingest::router())
+        .merge(admin::router())
+        .merge(stream::router())
+        .merge(metrics::router())
+        .route_layer(middleware::from_fn_with_state(
+            state.pool.clone(),
+            idempotency,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.maintenance.clone(),
+            maintenance_guard,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.auth.clone(),
+            authenticate,
+        ));
+
+    // Inside the limiter, so blocked requests still spend tokens
+    if config.demo_mode {
+        api = api.route_layer(middleware::from_fn(demo_guard));
+    }
+    let api = api.route_layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+
+    let cors = config.cors.as_ref().map(cors_layer);
+    let demo_mode = config.demo_mode;
+    let app = api
+        .merge(health::router())
+        .merge(version::router())
+        .merge(ready::router())
+        .with_state(state);
+
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    let app = if demo_mode {
+        app.layer(middleware::from_fn(demo_watermark))
+    } else {
+        app
+    };
+    app.layer(middleware::from_fn(report_errors))
+        .layer(middleware::from_fn(request_id))
+        .layer(CompressionLayer::new().gzip(true).br(true))
+}
+
+/// Build the router of deployments storing readings in SQLite or MySQL, over
+/// [`EdgeState`].
+///
+/// Serves the readings and mesh summary stream with the same handlers as
+/// [`router`], over the repository, along with pushes, `/metrics`, `/health`
+/// and `/version`. Authentication, rate limiting, CORS, request IDs, error
+/// reporting and compression are layered as in [`router`]. Idempotency keys,
+/// maintenance mode and demo mode need Postgres and aren't available, and
+/// readings are served without waiting for a first ingest.
+pub fn edge_router(state: EdgeState) -> Router {
+    // ---
+    let cors = state.config.cors.as_ref().map(cors_layer);
+    let app = Router::new()
         .merge(readings::router())
+        .merge(stream::router())
+        .merge(push::router())
+        .merge(metrics::router())
+        .route_layer(middleware::from_fn_with_state(
+            state.auth.clone(),
+            authenticate,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
         .merge(health::router())
-        .with_state((pool, config))
+        .merge(version::router())
+        .with_state(state);
+
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    app.layer(middleware::from_fn(report_errors))
+        .layer(middleware::from_fn(request_id))
+        .layer(CompressionLayer::new().gzip(true).br(true))
+}
+
+/// Build the CORS layer from (already validated) configuration.
+fn cors_layer(cfg: &CorsConfig) -> CorsLayer {
+    // ---
+    let origins = if cfg.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            cfg.allowed_origins
+                .iter()
+                .filter_map(|o| HeaderValue::from_str(o).ok()),
+        )
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(
+            cfg.allowed_methods
+                .iter()
+                .filter_map(|m| Method::from_bytes(m.as_bytes()).ok())
+                .collect::<Vec<_>>(),
+        )
+        .allow_headers(
+            cfg.allowed_headers
+                .iter()
+                .filter_map(|h| HeaderName::from_bytes(h.as_bytes()).ok())
+                .collect::<Vec<_>>(),
+        )
+        // Let browser clients read the pagination, throttling and deprecation headers
+        .expose_headers([
+            HeaderName::from_static("x-next-cursor"),
+            HeaderName::from_static("x-total-count"),
+            header::ETAG,
+            HeaderName::from_static("retry-after"),
+            HeaderName::from_static("deprecation"),
+            HeaderName::from_static("sunset"),
+            header::LINK,
+            REQUEST_ID_HEADER,
+            REPLAYED_HEADER,
+            DEMO_HEADER,
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+    use axum::{
+        body::{to_bytes, Body},
+        extract::ConnectInfo,
+        http::{Request, StatusCode},
+    };
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::load_with_overlay, sqlite, Authenticator, Enrichment, LiveConfig, RateLimiter,
+        ResponseCache, SqliteReadings, SummaryFeed,
+    };
+
+    /// The edge router over an in-memory SQLite database, configured with
+    /// `vars` on top of a database URL and one upstream.
+    async fn edge_app(vars: &[(&str, &str)]) -> Router {
+        // ---
+        let overlay: HashMap<_, _> = [
+            ("DATABASE_URL", Some("sqlite::memory:")),
+            ("SENSOR_API_URL", Some("http://upstream/sensor-data")),
+            ("SENSOR_API_1_URL", None),
+            ("JWT_HS256_SECRET", None),
+            ("JWT_RS256_PUBLIC_KEY_FILE", None),
+            ("JWT_JWKS_URL", None),
+            ("API_KEY_1", None),
+            ("RATE_LIMIT_PER_SEC", None),
+            ("CORS_ALLOWED_ORIGINS", None),
+        ]
+        .into_iter()
+        .chain(vars.iter().map(|(k, v)| (*k, Some(*v))))
+        .map(|(k, v)| (k.to_string(), v.map(String::from)))
+        .collect();
+        let cfg = load_with_overlay(overlay).expect("test configuration");
+        let enrichment = Arc::new(Enrichment::default());
+        let pool = sqlite::open(&cfg.db_url, 1)
+            .await
+            .expect("in-memory database");
+        let summaries = SummaryFeed::default();
+        edge_router(EdgeState {
+            auth: Arc::new(Authenticator::from_config(&cfg).unwrap()),
+            limiter: Arc::new(RateLimiter::new(&cfg.api_keys)),
+            live: LiveConfig::new(&cfg),
+            config: Arc::new(cfg),
+            enrichment: enrichment.clone(),
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            cache: Arc::new(ResponseCache::new(None)),
+            readings: Arc::new(SqliteReadings::new(pool, enrichment, summaries.clone())),
+            summaries,
+        })
+    }
+
+    /// Send `method path` with `x-api-key: key` (none for `None`) and a JSON
+    /// `body`; returns the status and the JSON response body.
+    async fn call(
+        app: &Router,
+        method: &str,
+        path: &str,
+        key: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        // ---
+        let mut req = Request::builder()
+            .method(method)
+            .uri(path)
+            .header("content-type", "application/json");
+        if let Some(key) = key {
+            req = req.header("x-api-key", key);
+        }
+        let mut req = req
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+
+        let resp = app.clone().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    fn reading(mesh_id: &str, device_id: &str) -> Value {
+        // ---
+        json!({
+            "mesh_id": mesh_id,
+            "device_id": device_id,
+            "timestamp": "2025-03-21T12:00:00Z",
+            "temperature_c": 21.5,
+            "humidity": 40.0,
+            "status": "ok"
+        })
+    }
+
+    #[tokio::test]
+    async fn api_keys_without_jwt_authenticate_and_scope_callers() {
+        // ---
+        let app = edge_app(&[
+            ("API_KEY_1", "key-a"),
+            ("API_KEY_1_ROLE", "writer"),
+            ("API_KEY_1_MESHES", "mesh-a"),
+            ("API_KEY_2", "key-b"),
+            ("API_KEY_2_ROLE", "writer"),
+            ("API_KEY_2_MESHES", "mesh-b"),
+        ])
+        .await;
+
+        // Each key pushes into its own mesh only
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/readings",
+            Some("key-a"),
+            Some(json!([reading("mesh-a", "dev-a")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/readings",
+            Some("key-b"),
+            Some(json!([reading("mesh-b", "dev-b")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/readings",
+            Some("key-a"),
+            Some(json!([reading("mesh-b", "dev-x")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // ... and reads only its own mesh's readings
+        for (key, mesh) in [("key-a", "mesh-a"), ("key-b", "mesh-b")] {
+            let (status, body) = call(&app, "GET", "/v1/readings", Some(key), None).await;
+            assert_eq!(status, StatusCode::OK);
+            let meshes: Vec<&str> = body
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r["mesh_id"].as_str().unwrap())
+                .collect();
+            assert_eq!(meshes, [mesh], "readings served to {key}");
+        }
+
+        // Without a known key there is no anonymous access
+        for key in [None, Some("key-c")] {
+            let (status, body) = call(&app, "GET", "/v1/readings", key, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["error"], "unauthorized");
+        }
+    }
+
+    #[tokio::test]
+    async fn without_credentials_configured_callers_are_admins() {
+        // ---
+        let app = edge_app(&[]).await;
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/readings",
+            None,
+            Some(json!([reading("mesh-a", "dev-a")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn edge_deployments_serve_the_shared_readings_and_stream_routes() {
+        // ---
+        let app = edge_app(&[]).await;
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/readings",
+            None,
+            Some(json!([reading("mesh-a", "dev-a")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Without a device registry `include=device` attaches nothing
+        let (status, body) = call(&app, "GET", "/v1/readings?include=device", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["device_id"], "dev-a");
+        assert!(body[0].get("device").is_none());
+
+        let (status, body) = call(&app, "GET", "/sql/readings/count", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["count"], 1);
+
+        // The push recorded an ingest; the stream answers without a listener
+        for (path, header) in [
+            ("/v1/readings", header::LAST_MODIFIED),
+            ("/sql/stream/mesh-summary", header::CONTENT_TYPE),
+        ] {
+            let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+            let resp = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{path}");
+            assert!(resp.headers().contains_key(&header), "{path}");
+        }
+    }
 }
@@ -0,0 +1,137 @@
+//! Reading push endpoint for device gateways.
+//!
+//! `POST /v1/readings` (or the deprecated `POST /sql/readings`) accepts a JSON
+//! array of readings in the upstream wire format and stores them tagged with
+//! source `push:<caller>`, skipping any the caller already pushed (same device
+//! and timestamp). Alert events and `mesh_summary` are updated, and this process's
+//! response cache cleared, before responding.
+//!
+//! Gateways usually authenticate with a client certificate (mutual TLS), but
+//! any `writer` credentials work. Every reading must fall within the caller's
+//! mesh scope and, for certificate identities, its device scope; otherwise the
+//! whole batch is rejected with 403.
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Router,
+};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::{
+    date, db_error_response, deprecated, mesh_forbidden, require_role, valid_position, ApiError,
+    Deprecated, DeprecationPolicy, Json, Principal, RawSensorReading, ReadingsRepository,
+    ResponseCache, Role,
+};
+
+/// Most readings accepted in one push.
+const MAX_PUSH_BATCH: usize = 1000;
+
+// ---
+
+/// Retired along with `GET /sql/readings`; see `readings.rs`.
+static LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings",
+    }),
+    params: &[],
+};
+
+/// The push routes, over any state with a repository and a cache.
+pub fn router<S>() -> Router<S>
+where
+    Arc<dyn ReadingsRepository>: FromRef<S>,
+    Arc<ResponseCache>: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    // ---
+    let route =
+        || post(handler).route_layer(middleware::from_fn_with_state(Role::Writer, require_role));
+    Router::new().route("/v1/readings", route()).route(
+        "/sql/readings",
+        route().route_layer(middleware::from_fn_with_state(&LEGACY_POLICY, deprecated)),
+    )
+}
+
+#[derive(Serialize)]
+struct PushResponse {
+    received: usize,
+    inserted: u64,
+}
+
+/// Handle `POST /v1/readings`.
+///
+/// 413 for batches over [`MAX_PUSH_BATCH`]; 422 if any reading has a partial
+/// or out-of-range position; 403 if any reading is outside the caller's mesh
+/// or device scope.
+async fn handler(
+    Extension(principal): Extension<Principal>,
+    State(repo): State<Arc<dyn ReadingsRepository>>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(batch): Json<Vec<RawSensorReading>>,
+) -> Response {
+    // ---
+    if batch.len() > MAX_PUSH_BATCH {
+        return ApiError::TooLarge {
+            error: "batch too large",
+            hint: "push at most 1000 readings per request",
+        }
+        .into_response();
+    }
+
+    if batch
+        .iter()
+        .any(|r| !valid_position(r.latitude, r.longitude))
+    {
+        return ApiError::Validation {
+            error: "invalid position",
+            hint: "send latitude (-90..90) and longitude (-180..180) together, or neither",
+        }
+        .into_response();
+    }
+
+    if batch.iter().any(|r| !principal.can_access_mesh(&r.mesh_id)) {
+        return mesh_forbidden();
+    }
+    if batch
+        .iter()
+        .any(|r| !principal.can_push_device(&r.device_id))
+    {
+        return ApiError::Forbidden {
+            error: "forbidden",
+            hint:
+                "these credentials may not push readings for this device (CLIENT_CERT_<N>_DEVICES)",
+        }
+        .into_response();
+    }
+
+    let source = format!("push:{}", principal.name);
+    info!("POST readings - {} readings from {}", batch.len(), source);
+
+    let stored = repo.insert_batch(&source, &batch).await;
+    // Without waiting for the NOTIFY, so the pusher reads its own writes
+    if matches!(stored, Ok(n) if n > 0) {
+        cache.clear();
+    }
+    match stored {
+        Ok(inserted) => (
+            StatusCode::OK,
+            Json(PushResponse {
+                received: batch.len(),
+                inserted,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to store pushed readings: {}", e);
+            db_error_response(&e, "store failed")
+        }
+    }
+}
@@ -1,281 +1,1090 @@
 //! Sensor readings API endpoint with database integration and filtering.
 //!
-//! This module provides the `GET /sql/readings` endpoint that:
+//! This module provides the `GET /v1/readings` endpoint (also served at the
+//! deprecated `GET /sql/readings`, which signals its sunset) that:
 //!
 //! ## Core Functionality
-//! - **Auto-ingestion**: Automatically fetches and stores sensor data from upstream API if database is empty
+//! - **Auto-ingestion**: Starts or joins the initial ingest (see `warmup.rs`) so every upstream source with no stored data
+//!   is fetched first; a request waits at most `INGEST_WAIT_SECS` for it and gets 503 with `Retry-After` after that
 //! - **Efficient filtering**: Database-level filtering by device_id, mesh_id, and timestamp ranges
-//! - **Pagination**: Cursor-based pagination for upstream API consumption with configurable limits
-//! - **Data transformation**: Converts raw sensor readings to normalized format with alert flags
-//! - **Aggregate maintenance**: Updates mesh-level summary statistics after data ingestion
+//! - **Mesh scoping**: Callers limited to certain meshes only ever see rows from those meshes
+//! - **Counting**: `GET /v1/readings/count` (deprecated: `GET /sql/readings/count`) takes the same
+//!   filters and returns `{ "count", "earliest", "latest" }` instead of rows
+//! - **By ID**: `GET /v1/readings/{id}` (deprecated: `GET /sql/readings/{id}`) returns the one
+//!   reading with that `id`, the stable reference every served reading carries
 //!
 //! ## Query Parameters
-//! - `device_id` (aliases: device, deviceId, deviceID) - Filter by specific device
-//! - `mesh_id` (aliases: mesh, meshId, meshID) - Filter by mesh network
-//! - `timestamp_range` (aliases: ts_range, timestampRange) - RFC3339 range "start,end" with open ends supported
-//! - `limit` - Maximum records to return (default: 1000)
+//! - `device_id` (aliases: device, deviceId, deviceID) - Filter by device; comma-separated or
+//!   repeated for any of up to [`MAX_FILTER_VALUES`] devices
+//! - `mesh_id` (aliases: mesh, meshId, meshID) - Filter by mesh network; several as for `device_id`
+//! - `device_id_prefix`, `mesh_id_prefix` - Only ids starting with this, e.g. `rack-12-` for a
+//!   whole rack; matched literally (`%` and `_` are not wildcards) with `LIKE`
+//! - `timestamp_range` (aliases: ts_range, timestampRange) - RFC3339 range "start,end" with open ends supported,
+//!   or `last_<n><unit>` (e.g. `last_24h`) for the span up to now
+//! - `since` - Shorthand for `timestamp_range=last_<since>`, e.g. `since=15m`
+//! - `status` - Only readings whose device-reported status is one of these (comma-separated or
+//!   repeated), e.g. `degraded,faulty`; `status_not` - only readings whose status is none of
+//!   these (readings without a status included), e.g. `status_not=ok`
+//! - `alerts_only` - `true` keeps only readings with an alert flag set; `alert_type`
+//!   (`temperature` or `humidity`) keeps only those with that flag, with or without `alerts_only`
+//! - `temp_min`, `temp_max` - Only readings whose (calibrated) `temperature_c` is at least /
+//!   at most this; either end may be left open
+//! - `humidity_min`, `humidity_max` - The same for `humidity`, in % RH
+//! - `min_quality` - Only readings whose quality score (see `quality.rs`) is at least this, in [0, 1];
+//!   readings stored before scoring existed have none and are left out
+//! - `extra_key` - Only readings carrying this extra measurement (e.g. `pressure`)
+//! - `bbox` - `minLon,minLat,maxLon,maxLat` in WGS84 degrees; keeps readings positioned inside the box,
+//!   using the reading's own coordinates or else its device's registry entry. `minLon > maxLon` crosses
+//!   the antimeridian
+//! - `limit` - Maximum records to return (default: `DEFAULT_LIMIT`, or the caller's per-key default)
+//! - `sort` - Row order, one of [`Sort`]'s names (default `timestamp_desc`); cursors keep to
+//!   the order they were issued for
+//! - `cursor` - Signed keyset cursor from a previous page's `X-Next-Cursor` header (or the
+//!   `Link: <...>; rel="next"` URL, sent with `rel="first"` once past the first page)
+//! - `sample` - Approximate preview over a `TABLESAMPLE SYSTEM` fraction in (0, 1]; always enveloped
+//! - `envelope` - `true` wraps rows as `{ "data": [...], "total", "limit", "sampled", "next_cursor", "units",
+//!   "warnings" }`, `total` counting every matching reading (also sent as `X-Total-Count`)
+//! - `meta` - `true` adds `"meta": { "filters", "sort", "took_ms", "latest_reading" }` to the
+//!   envelope (implies `envelope`): the filters as applied, server time and the newest stored
+//!   reading in the caller's scope
+//! - `tz` - IANA timezone (e.g. `America/New_York`) for each reading's `timestamp_local`; without
+//!   it, readings of meshes with a registered timezone (see `meshes.rs`) get theirs
+//! - `units` - `metric` (default) or `imperial`: temperatures in °F, as `temperature_f` (see
+//!   `units.rs`)
+//! - `include` - `device` attaches each device's registry entry (see `devices.rs`) as `device`
+//! - `fields` - Comma-separated reading fields to return (see [`COLUMN_FIELDS`]); unlisted
+//!   columns aren't loaded or serialized
+//! - `smooth` - `ewma` adds each reading's exponentially weighted moving average as `smoothed`
+//!   (see `smoothing.rs`), per device over the returned page
+//! - `alpha` - EWMA smoothing factor in (0, 1] (default 0.3); needs `smooth=ewma`
+//! - `rolling_avg` - Window of 1 to [`MAX_ROLLING_AVG`] readings: adds the average of each
+//!   reading and its device's preceding ones as `rolling_avg`, via SQL window functions over
+//!   every matching reading (not just the page)
 //!
-//! ## Database Schema
-//! Expects tables:
-//! - `sensor_data`: Main readings with indexes on device_id, mesh_id, timestamp_utc
-//! - `mesh_summary`: Aggregated statistics per mesh (avg temp/humidity, counts)
+//! The camelCase and `ts_range` aliases are deprecated (see [`DEPRECATED_ALIASES`]).
 //!
-//! ## Performance Notes
-//! - Uses composite indexes `(device_id, timestamp_utc)` and `(mesh_id, timestamp_utc)` for optimal filtering
-//! - SQL injection protection via parameterized queries and sqlx binding
-//! - Memory-efficient processing with database-level LIMIT application
-//! - Upstream API protection with configurable page limits
+//! ## Storage
+//! The handlers validate the parameters into a `ReadingsFilter` and `ReadingsPage` and load
+//! through the `ReadingsRepository` in the state (see `repository.rs`), which filters with
+//! bound parameters and applies `LIMIT` at the database level. Latest ingests, mesh timezones
+//! and device entries come from the repository too, so the handlers serve any backend; only
+//! the initial ingest ([`await_warmup`], layered by the Postgres router) is Postgres's own.
+//!
+//! ## Conditional requests
+//! Unsampled responses carry a weak `ETag` derived from the request, the caller's role and
+//! scope, and the count and newest timestamp of the matching readings. A request whose
+//! `If-None-Match` names it is answered 304 after that one count, without loading rows.
+//! They also carry `Last-Modified`, the latest ingest into the meshes in scope (those named
+//! by `mesh_id`, else all the caller may read); without `If-None-Match`, an
+//! `If-Modified-Since` at or after it is answered 304 the same way.
 //!
 //! ## Error Handling
-//! - 422 for malformed timestamp ranges
+//! - 403 unless the caller has at least the `reader` role
+//! - 400 for forged, edited, or otherwise invalid cursors, or ones issued for another `sort`
+//! - 422 for `since` together with `timestamp_range`, more than [`MAX_FILTER_VALUES`] devices or meshes, an empty prefix, malformed timestamp ranges or `bbox`, an unknown `alert_type` or one with `alerts_only=false`,
+//!   a non-finite temperature or humidity bound or a minimum above its maximum, a sample fraction or `min_quality` outside its range,
+//!   or an unknown `include` or `smooth`, or `alpha` outside (0, 1] or without `smooth`, or a
+//!   `rolling_avg` outside 1 to [`MAX_ROLLING_AVG`], or an unknown `sort`, field in `fields`, `tz` or `units`
 //! - 500 for database/ingestion failures
-//! - Graceful handling of upstream API parsing errors (logs and continues)
-//!
-//! ## Future Improvements
-//! - TODO: Add cursor-based pagination for client responses
-//! - Consider connection pooling for frequent upstream API calls
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use axum::{
-    extract::Query, extract::State, http::StatusCode, response::IntoResponse, routing::get, Json,
-    Router,
+    extract::{FromRef, FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, MethodRouter},
+    Extension, Json, Router,
 };
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use sqlx::{PgPool, Row};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
 use tracing::{error, info};
 
-use crate::{Config, RawSensorReading, SensorReading};
+use crate::{
+    date, db_error_response, deprecated, ewma_alpha, parse_duration, query_error, require_role,
+    smooth_readings, warming_up, AlertFilter, ApiError, BoundingBox, Config, Deprecated,
+    DeprecationPolicy, DeprecationWarnings, Enrichment, LiveConfig, Path, Principal, ReadingsCount,
+    ReadingsCursor, ReadingsFilter, ReadingsPage, ReadingsRepository, ResponseCache, Role,
+    SensorReading, Sort, UnitSystem, Warmup, WarmupStatus, DEFAULT_LIMIT,
+};
+
+/// Reading fields `fields` can select that are loaded from `sensor_data` columns.
+const COLUMN_FIELDS: &[&str] = &[
+    "id",
+    "mesh_id",
+    "device_id",
+    "timestamp_utc",
+    "temperature_c",
+    "humidity",
+    "raw_temperature_c",
+    "raw_humidity",
+    "status",
+    "temperature_alert",
+    "humidity_alert",
+    "latitude",
+    "longitude",
+    "extra",
+    "quality",
+    "quality_flags",
+    "attributes",
+    "invalid",
+    "corrections",
+    "ingest_batch_id",
+];
+
+/// Reading fields `fields` can select that are computed, present only with
+/// their options (`smooth`, `rolling_avg`, `include=device`).
+const COMPUTED_FIELDS: &[&str] = &["timestamp_local", "smoothed", "rolling_avg", "device"];
+
+/// Columns loaded for every reading whatever `fields` says: the keyset position.
+const KEY_COLUMNS: &[&str] = &["id", "timestamp_utc"];
+
+/// Most values one `device_id` or `mesh_id` filter may list.
+const MAX_FILTER_VALUES: usize = 100;
+
+/// Filters that may be given several times; repeats are joined with commas.
+/// The aliases count as their canonical name.
+const MULTI_VALUED: &[(&str, &[&str])] = &[
+    (
+        "device_id",
+        &["device_id", "device", "deviceId", "deviceID"],
+    ),
+    ("mesh_id", &["mesh_id", "mesh", "meshId", "meshID"]),
+    ("status", &["status"]),
+    ("status_not", &["status_not"]),
+];
+
+/// Largest `rolling_avg` window, in readings.
+const MAX_ROLLING_AVG: u32 = 1000;
 
 // ---
 
-pub fn router() -> Router<(PgPool, Config)> {
+/// Alias spellings kept for older clients; new code should use the canonical names.
+const DEPRECATED_ALIASES: &[(&str, Deprecated)] = &[
+    ("deviceId", alias_of("device_id")),
+    ("deviceID", alias_of("device_id")),
+    ("meshId", alias_of("mesh_id")),
+    ("meshID", alias_of("mesh_id")),
+    ("ts_range", alias_of("timestamp_range")),
+    ("timestampRange", alias_of("timestamp_range")),
+];
+
+/// `GET /sql/readings` predates versioned paths and is retired in favour of `/v1/readings`.
+static LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings",
+    }),
+    params: DEPRECATED_ALIASES,
+};
+
+/// `GET /sql/readings/count` follows its listing to `/v1/readings/count`.
+static COUNT_LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings/count",
+    }),
+    params: DEPRECATED_ALIASES,
+};
+
+/// `GET /sql/readings/{id}` moves with the listing, to `/v1/readings/{id}`.
+static BY_ID_LEGACY_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: Some(Deprecated {
+        since: date(2026, 10, 14),
+        sunset: Some(date(2027, 4, 14)),
+        replacement: "/v1/readings/{id}",
+    }),
+    params: &[],
+};
+
+static V1_POLICY: DeprecationPolicy = DeprecationPolicy {
+    route: None,
+    params: DEPRECATED_ALIASES,
+};
+
+const fn alias_of(replacement: &'static str) -> Deprecated {
     // ---
-    Router::new().route("/sql/readings", get(handler))
+    Deprecated {
+        since: date(2026, 10, 14),
+        sunset: None,
+        replacement,
+    }
+}
+
+/// The readings routes, over any state with a [`ReadingsRepository`]; the
+/// Postgres router layers [`await_warmup`] on them.
+pub fn router<S>() -> Router<S>
+where
+    Arc<Config>: FromRef<S>,
+    Arc<Enrichment>: FromRef<S>,
+    Arc<dyn ReadingsRepository>: FromRef<S>,
+    Arc<ResponseCache>: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    // ---
+    let route = |method: MethodRouter<S>, policy: &'static DeprecationPolicy| {
+        method
+            .route_layer(middleware::from_fn_with_state(Role::Reader, require_role))
+            .route_layer(middleware::from_fn_with_state(policy, deprecated))
+    };
+    Router::new()
+        .route("/v1/readings", route(get(handler), &V1_POLICY))
+        .route("/sql/readings", route(get(handler), &LEGACY_POLICY))
+        .route("/v1/readings/count", route(get(count), &V1_POLICY))
+        .route(
+            "/sql/readings/count",
+            route(get(count), &COUNT_LEGACY_POLICY),
+        )
+        .route("/v1/readings/{id}", route(get(by_id), &V1_POLICY))
+        .route(
+            "/sql/readings/{id}",
+            route(get(by_id), &BY_ID_LEGACY_POLICY),
+        )
 }
 
-/// Handle `GET /sql/readings`.
-/// Validates params (422 on bad `timestamp_range`), ingests once if the DB is empty,
-/// then loads from Postgres, applies filters (`device_id`, `mesh_id`, `timestamp_range`, `limit`),
-/// and returns the readings as JSON.
+/// Handle `GET /v1/readings` (and the deprecated `GET /sql/readings`).
+/// Validates params (422 on bad `timestamp_range`/`sample`/`min_quality`/`bbox`/`smooth`/
+/// `rolling_avg`, 400 on an invalid `cursor`), then loads through the repository, applies filters
+/// (`device_id`, `mesh_id`, `timestamp_range`, `min_quality`, `extra_key`, `bbox`, `limit`), and
+/// returns the readings as JSON. When more rows remain, the signed cursor for the next page is
+/// returned in the `X-Next-Cursor` header. Unsampled responses carry a weak `ETag` and
+/// `Last-Modified`; a matching `If-None-Match` or `If-Modified-Since` gets 304 without loading
+/// rows. With `RESPONSE_CACHE_SECS` set, unsampled counts and rows come from the response cache
+/// while fresh.
 async fn handler(
-    Query(params): Query<ReadingsQuery>,
-    State((pool, config)): State<(PgPool, Config)>,
-) -> impl IntoResponse {
-    // ---
-    info!("GET /sql/readings - Starting pipeline");
-
-    // 0) Validate timestamp_range (422 on bad input)
-    if let Some(raw) = params.timestamp_range.as_deref() {
-        if parse_timestamp_range(raw).is_none() {
-            return (
-                StatusCode::UNPROCESSABLE_ENTITY,
-                Json(ApiError {
-                    error: "invalid timestamp_range",
-                    hint:  r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
-                }),
-            ).into_response();
-        }
-    }
+    params: ReadingsQuery,
+    State(config): State<Arc<Config>>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Extension(principal): Extension<Principal>,
+    State(repo): State<Arc<dyn ReadingsRepository>>,
+    State(cache): State<Arc<ResponseCache>>,
+    warnings: Option<Extension<DeprecationWarnings>>,
+) -> Response {
+    // ---
+    info!("GET readings - Starting pipeline");
+    let received = Instant::now();
 
-    let api_url = &config.api_url;
-    let api_max_pages = config.api_max_pages;
+    // 0) Validate the parameters (422 on bad input, 400 on a bad cursor)
+    let plan = match plan(&params, &config) {
+        Ok(plan) => plan,
+        Err(rejection) => return rejection,
+    };
 
-    // 1) Ingest once if empty
-    if let Err(e) = ensure_data_loaded(&pool, api_url, api_max_pages).await {
-        error!("Ingest failed: {}", e);
-        // TODO: Production would distinguish upstream (502) vs internal (500) errors
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json("ingest failed")).into_response();
+    // 1) Conditional GET: a weak ETag from the filter's count and newest
+    // reading, and the meshes' latest ingest (samples differ every time, so
+    // they get neither); 304 on a match, If-None-Match taking precedence
+    let (matching, last_modified) = if plan.page.sample.is_none() {
+        let (repo, filter) = (&repo, &plan.filter);
+        let scope = filter.allowed_meshes.as_deref();
+        let meshes = (!filter.mesh_ids.is_empty()).then_some(filter.mesh_ids.as_slice());
+        let load = async move {
+            let count = repo.count(filter).await?;
+            let ingested = repo.last_ingested(scope, meshes).await?;
+            Ok((count, ingested))
+        };
+        let loaded = cache
+            .get_or_load("readings_count", &params.cache_key(), load)
+            .await;
+        match loaded {
+            Ok((count, ingested)) => (Some(count), ingested),
+            Err(e) => {
+                error!("Failed to count readings: {}", e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    } else {
+        (None, None)
+    };
+    let etag = matching
+        .as_ref()
+        .map(|count| params.etag(count, last_modified, principal.role));
+    if let Some(not_modified) = not_modified(&params, &etag, last_modified) {
+        return not_modified;
     }
 
     // 2) Load from DB with filters applied at database level
-    let readings = match load_filtered_readings(&pool, &params).await {
+    let load = repo.query(&plan.filter, &plan.page);
+    // Samples differ on every request, so they are never cached
+    let loaded = match plan.page.sample {
+        None => {
+            cache
+                .get_or_load("readings", &params.cache_key(), load)
+                .await
+        }
+        Some(_) => load.await.map_err(Arc::new),
+    };
+    let (mut readings, next) = match loaded {
         Ok(v) => v,
         Err(e) => {
             error!("Failed to load readings: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json("load failed")).into_response();
+            return db_error_response(&e, "load failed");
         }
     };
+    for r in &mut readings {
+        enrichment.reveal(r, principal.role);
+    }
+    if let Some(alpha) = plan.alpha {
+        smooth_readings(&mut readings, alpha);
+    }
+    if plan.localize {
+        let zones = match plan.tz {
+            Some(_) => vec![],
+            None => {
+                let mut ids: Vec<String> = readings.iter().map(|r| r.mesh_id.clone()).collect();
+                ids.sort_unstable();
+                ids.dedup();
+                match repo.timezones(&ids).await {
+                    Ok(zones) => zones,
+                    Err(e) => {
+                        error!("Failed to load mesh timezones: {}", e);
+                        return db_error_response(&e, "load failed");
+                    }
+                }
+            }
+        };
+        localize_timestamps(&mut readings, plan.tz, &zones);
+    }
+    if plan.include_device {
+        let mut ids: Vec<String> = readings.iter().map(|r| r.device_id.clone()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let devices = match repo.devices(&ids).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("Failed to load device metadata: {}", e);
+                return db_error_response(&e, "load failed");
+            }
+        };
+        for r in &mut readings {
+            r.device = devices.iter().find(|d| d.device_id == r.device_id).cloned();
+            if let Some(device) = &mut r.device {
+                enrichment.reveal_device(device, principal.role);
+            }
+        }
+    }
+
+    // 3) What the request was answered with, for `meta=true`
+    let meta = if params.wants_meta() {
+        match repo.latest(plan.filter.allowed_meshes.as_deref()).await {
+            Ok(latest_reading) => Some(meta(&params, &plan, received, latest_reading)),
+            Err(e) => {
+                error!("Failed to load data freshness: {}", e);
+                return db_error_response(&e, "load failed");
+            }
+        }
+    } else {
+        None
+    };
 
     info!("Pipeline complete, returning {} readings", readings.len());
-    (StatusCode::OK, Json(readings)).into_response()
+    render(
+        &params,
+        &plan,
+        Loaded {
+            readings,
+            next,
+            matching,
+            meta,
+            etag,
+            last_modified,
+            warnings: warnings.map(|Extension(w)| w.0).unwrap_or_default(),
+        },
+        &config,
+    )
 }
 
-// ---
+/// A readings request as validated by [`plan`]: the readings to load, and how
+/// to shape them.
+struct Plan {
+    // ---
+    filter: ReadingsFilter,
+    page: ReadingsPage,
 
-/// Fetch all pages from the upstream sensor API.
-///
-/// Starts at `base_url`, follows `next_cursor` until exhausted or `max_pages` reached,
-/// and returns the concatenated `RawSensorReading` list. Logs each page at `debug` level.
-///
-/// Notes:
-/// - Uses a new `reqwest::Client` per call (cheap). Consider reusing if hot-path.
-/// - Silently skips JSON items that fail to deserialize (logs at `debug`).
-/// - Stops early when `max_pages` is hit to protect the backend.
-async fn fetch_sensor_data(
-    base_url: &str,
-    max_pages: u32,
-) -> Result<Vec<RawSensorReading>, Box<dyn std::error::Error>> {
-    // ---
-
-    // New client per call; fine here, could reuse if calling this often.
-    let client = reqwest::Client::new();
-
-    let mut all_data = Vec::new();
-    let mut cursor: Option<String> = None;
-    let mut page_count = 0;
-
-    // https://www.postgresql.org/docs/current/queries-limit.html
-    // Above is interesting by we actually use CURSOR-BASED pagination pattern instead,
-    // keep fetching pages until max_pages or no more data
-    loop {
-        // Guardrail: don’t hammer upstream forever.
-        if page_count >= max_pages {
-            tracing::debug!(
-                "Hit page limit of {}, stopping pagination. Fetched {} records so far.",
-                max_pages,
-                all_data.len()
-            );
-            break;
-        }
-        page_count += 1;
-
-        // Build URL, use cursor if we have it
-        let url = if let Some(ref cursor) = cursor {
-            format!("{base_url}?cursor={cursor}")
-        } else {
-            base_url.to_string()
-        };
+    /// The `fields` to serve, when given.
+    fields: Option<Vec<&'static str>>,
+    include_device: bool,
+
+    /// EWMA smoothing factor, with `smooth=ewma`.
+    alpha: Option<f32>,
+
+    /// The `tz` asked for, and whether `timestamp_local` is served at all.
+    tz: Option<Tz>,
+    localize: bool,
+    units: UnitSystem,
+}
 
-        tracing::debug!("Fetching page {} from: {}", page_count, url);
+/// Validate `params` into a [`Plan`], or the 422 (400 for a bad `cursor`) to
+/// answer instead.
+#[allow(clippy::result_large_err)] // built once per request, on the error path only
+fn plan(params: &ReadingsQuery, config: &Config) -> Result<Plan, Response> {
+    // ---
+    // The filters shared with the count
+    let filter = readings_filter(params)?;
 
-        // Fetch + parse the page payload as generic JSON.
-        let response: serde_json::Value = client.get(&url).send().await?.json().await?;
+    // Sample fraction (422 outside (0, 1])
+    if let Some(fraction) = params.sample {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(ApiError::Validation {
+                error: "invalid sample",
+                hint: "use a fraction in (0, 1], e.g. sample=0.01 for ~1% of rows",
+            }
+            .into_response());
+        }
+    }
 
-        tracing::debug!("Page {} raw response: {}", page_count, response);
+    // Include (422 on unknown values)
+    let include_device = match params.include.as_deref().map(include_device) {
+        None => false,
+        Some(Some(device)) => device,
+        Some(None) => {
+            return Err(ApiError::Validation {
+                error: "invalid include",
+                hint: "the only supported value is include=device",
+            }
+            .into_response());
+        }
+    };
 
-        // Extract "results" array; skip page if missing/malformed.
-        if let Some(data) = response.get("results").and_then(|d| d.as_array()) {
-            tracing::debug!(
-                "Page {} found data array with {} items",
-                page_count,
-                data.len()
-            );
+    // Smoothing (422 on unknown method or alpha outside (0, 1])
+    let Some(alpha) = ewma_alpha(params.smooth.as_deref(), params.alpha) else {
+        return Err(ApiError::Validation {
+            error: "invalid smoothing",
+            hint: "use smooth=ewma with an optional alpha in (0, 1], e.g. smooth=ewma&alpha=0.3",
+        }
+        .into_response());
+    };
 
-            // Deserialize each item; keep going on per-item errors.
-            for (i, item) in data.iter().enumerate() {
-                match serde_json::from_value::<RawSensorReading>(item.clone()) {
-                    Ok(reading) => {
-                        all_data.push(reading);
-                    }
-                    Err(e) => {
-                        tracing::debug!(
-                            "Failed to parse item {} on page {}: {} - Raw item: {}",
-                            i,
-                            page_count,
-                            e,
-                            item
-                        );
-                    }
+    // The rolling window (422 outside 1..=MAX_ROLLING_AVG)
+    if let Some(window) = params.rolling_avg {
+        if !(1..=MAX_ROLLING_AVG).contains(&window) {
+            return Err(ApiError::Validation {
+                error: "invalid rolling_avg",
+                hint: "use a window of 1 to 1000 readings, e.g. rolling_avg=5",
+            }
+            .into_response());
+        }
+    }
+
+    // Sort (422 on unknown orders)
+    let Some(sort) = Sort::parse(params.sort.as_deref()) else {
+        return Err(ApiError::Validation {
+            error: "invalid sort",
+            hint: "use timestamp_desc, timestamp_asc, temperature_desc, temperature_asc, \
+                       humidity_desc, humidity_asc or device_id",
+        }
+        .into_response());
+    };
+
+    // Field selection (422 on unknown fields)
+    let fields = match params.fields.as_deref() {
+        None => None,
+        Some(raw) => match parse_fields(raw) {
+            Some(fields) => Some(fields),
+            None => {
+                return Err(ApiError::Validation {
+                    error: "invalid fields",
+                    hint: "list reading fields, e.g. fields=device_id,timestamp_utc,temperature_c",
                 }
+                .into_response());
             }
-        } else {
-            tracing::debug!(
-                "Page {} response missing 'results' field or not an array",
-                page_count
-            );
+        },
+    };
+
+    // The display timezone (422 on names chrono-tz doesn't know)
+    let tz = match params.tz.as_deref().map(str::parse::<Tz>) {
+        None => None,
+        Some(Ok(tz)) => Some(tz),
+        Some(Err(_)) => {
+            return Err(ApiError::Validation {
+                error: "invalid tz",
+                hint: "use an IANA timezone name, e.g. tz=America/New_York",
+            }
+            .into_response());
         }
+    };
+    let localize = fields
+        .as_ref()
+        .is_none_or(|fields| fields.contains(&"timestamp_local"));
 
-        // Advance pagination; stop when there is no next cursor.
-        cursor = response
-            .get("next_cursor")
-            .and_then(|c| c.as_str())
-            .map(String::from);
+    // The unit system (422 on anything but metric or imperial)
+    let Some(units) = params
+        .units
+        .as_deref()
+        .map_or(Some(UnitSystem::Metric), UnitSystem::parse)
+    else {
+        return Err(ApiError::Validation {
+            error: "invalid units",
+            hint: "use units=metric or units=imperial",
+        }
+        .into_response());
+    };
 
-        tracing::debug!("Page {} next_cursor: {:?}", page_count, cursor);
+    // The pagination cursor (400 on forged or mangled input, or another sort's)
+    let after = match params.cursor.as_deref() {
+        None => None,
+        Some(raw) => match ReadingsCursor::decode(config.cursor_secret.as_bytes(), raw) {
+            Ok(c) if c.sort.as_deref() == sort.cursor_tag() => Some(c),
+            Ok(_) => {
+                return Err(ApiError::BadRequest {
+                    error: "invalid cursor",
+                    hint: "cursor was issued for a different sort; keep the sort or restart pagination",
+                }
+                .into_response());
+            }
+            Err(e) => {
+                info!("Rejected cursor: {}", e);
+                return Err(ApiError::BadRequest {
+                    error: "invalid cursor",
+                    hint: e.hint(),
+                }
+                .into_response());
+            }
+        },
+    };
 
-        if cursor.is_none() {
-            tracing::info!(
-                "No more pages, stopping. Total records fetched: {}",
-                all_data.len()
-            );
-            break;
+    // Columns the rest of the pipeline reads, beyond the ones asked for
+    let columns = fields.as_ref().map(|fields| {
+        let mut columns: Vec<&str> = KEY_COLUMNS.to_vec();
+        columns.extend(fields.iter().filter(|f| COLUMN_FIELDS.contains(f)));
+        columns.extend(sort.key_column());
+        if alpha.is_some() {
+            columns.extend(["device_id", "temperature_c", "humidity"]);
+        }
+        if include_device {
+            columns.push("device_id");
+        }
+        if localize && tz.is_none() {
+            columns.push("mesh_id");
+        }
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    });
+
+    Ok(Plan {
+        filter,
+        page: ReadingsPage {
+            sort,
+            limit: params.limit.unwrap_or(DEFAULT_LIMIT),
+            after,
+            columns,
+            sample: params.sample,
+            rolling_avg: params.rolling_avg,
+        },
+        fields,
+        include_device,
+        alpha,
+        tz,
+        localize,
+        units,
+    })
+}
+
+/// The 304 to answer when the request's `If-None-Match` names `etag` or, without
+/// one, its `If-Modified-Since` is at or after `last_modified`.
+fn not_modified(
+    params: &ReadingsQuery,
+    etag: &Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+) -> Option<Response> {
+    // ---
+    let fresh = match (etag, &params.if_none_match) {
+        (Some(etag), Some(tags)) => etag_matches(tags, etag),
+        (_, None) => last_modified
+            .is_some_and(|at| not_modified_since(params.if_modified_since.as_deref(), at)),
+        (None, Some(_)) => false,
+    };
+    fresh.then(|| {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        set_validators(response.headers_mut(), etag.clone(), last_modified);
+        response
+    })
+}
+
+/// `meta` for a request received at `received`, with the newest reading in
+/// its scope.
+fn meta<'a>(
+    params: &'a ReadingsQuery,
+    plan: &'a Plan,
+    received: Instant,
+    latest_reading: Option<DateTime<Utc>>,
+) -> ReadingsMeta<'a> {
+    // ---
+    ReadingsMeta {
+        filters: applied_filters(params, &plan.filter),
+        sort: plan.page.sort.cursor_tag().unwrap_or("timestamp_desc"),
+        took_ms: received.elapsed().as_millis() as u64,
+        latest_reading,
+    }
+}
+
+/// A page of readings loaded for a [`Plan`], with what [`render`] needs
+/// besides.
+struct Loaded<'a> {
+    // ---
+    readings: Vec<SensorReading>,
+    next: Option<ReadingsCursor>,
+
+    /// Every reading matching the filter, unless sampled.
+    matching: Option<ReadingsCount>,
+    meta: Option<ReadingsMeta<'a>>,
+    etag: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+    warnings: Vec<String>,
+}
+
+/// The response serving `loaded` as `plan` says: bare or enveloped, in its
+/// fields and units, with the count, validator, link and cursor headers.
+fn render(params: &ReadingsQuery, plan: &Plan, loaded: Loaded<'_>, config: &Config) -> Response {
+    // ---
+    // Total matching readings for page controls, enveloped (unsampled) responses only
+    let enveloped =
+        params.envelope.unwrap_or(false) || params.sample.is_some() || params.wants_meta();
+    let total = loaded
+        .matching
+        .filter(|_| enveloped)
+        .map(|count| count.count);
+
+    let next_cursor = loaded
+        .next
+        .map(|c| c.encode(config.cursor_secret.as_bytes()));
+    let readings = match (plan.fields.as_deref(), plan.units) {
+        (None, UnitSystem::Metric) => ReadingsData::Full(loaded.readings),
+        (fields, units) => ReadingsData::Sparse(sparse(loaded.readings, fields, units)),
+    };
+
+    // Sampled results are always enveloped so they can't be mistaken for full data
+    let mut response = if enveloped {
+        let envelope = ReadingsEnvelope {
+            data: readings,
+            total,
+            limit: plan.page.limit,
+            sampled: params.sample.is_some(),
+            sample_fraction: params.sample,
+            next_cursor: next_cursor.clone(),
+            units: Units::of(plan.units),
+            meta: loaded.meta,
+            warnings: loaded.warnings,
+        };
+        (StatusCode::OK, Json(envelope)).into_response()
+    } else {
+        (StatusCode::OK, Json(readings)).into_response()
+    };
+
+    if let Some(total) = total {
+        response.headers_mut().insert("x-total-count", total.into());
+    }
+    set_validators(response.headers_mut(), loaded.etag, loaded.last_modified);
+    // RFC 8288 links for generic clients: `first` once past it, `next` while rows remain
+    let links = [
+        params.cursor.is_some().then_some(("first", None)),
+        next_cursor.as_deref().map(|token| ("next", Some(token))),
+    ];
+    for (rel, cursor) in links.into_iter().flatten() {
+        let link = format!("<{}>; rel=\"{rel}\"", params.page_url(cursor));
+        if let Ok(v) = HeaderValue::from_str(&link) {
+            response.headers_mut().append(header::LINK, v);
+        }
+    }
+    if let Some(token) = next_cursor {
+        // base64url and '.' only, always a valid header value
+        response.headers_mut().insert(
+            "x-next-cursor",
+            token.parse().expect("cursor is header-safe"),
+        );
+    }
+    response
+}
+
+/// Add the `ETag` and `Last-Modified` validators that are known to `headers`.
+fn set_validators(
+    headers: &mut HeaderMap,
+    etag: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+) {
+    // ---
+    if let Some(v) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        headers.insert(header::ETAG, v);
+    }
+    if let Some(v) = last_modified.and_then(|at| HeaderValue::from_str(&http_date(at)).ok()) {
+        headers.insert(header::LAST_MODIFIED, v);
+    }
+}
+
+/// `at` as an HTTP-date (RFC 9110), e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(super) fn http_date(at: DateTime<Utc>) -> String {
+    // ---
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether an `If-Modified-Since` value is at or after `last_modified`, to the
+/// second HTTP-dates resolve. Unparseable dates are ignored, as RFC 9110 asks.
+pub(super) fn not_modified_since(
+    if_modified_since: Option<&str>,
+    last_modified: DateTime<Utc>,
+) -> bool {
+    // ---
+    if_modified_since
+        .and_then(|raw| DateTime::parse_from_rfc2822(raw.trim()).ok())
+        .is_some_and(|since| last_modified.timestamp() <= since.timestamp())
+}
+
+/// Whether an `If-None-Match` value names `etag`, by weak comparison.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    // ---
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// `scheme://host` the request was addressed to: scheme from
+/// `X-Forwarded-Proto`, else whether we serve TLS. Empty without a `Host`,
+/// leaving page links relative.
+fn request_origin(headers: &HeaderMap, tls: bool) -> String {
+    // ---
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let Some(host) = header("host") else {
+        return String::new();
+    };
+    let scheme = header("x-forwarded-proto").unwrap_or(if tls { "https" } else { "http" });
+    format!("{scheme}://{host}")
+}
+
+/// Middleware: make sure every source (as currently configured) has data
+/// before serving readings, ingesting once per source when it has none; 503
+/// when the initial ingest failed or is still running after `INGEST_WAIT_SECS`.
+pub(super) async fn await_warmup(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(live): State<LiveConfig>,
+    State(enrichment): State<Arc<Enrichment>>,
+    State(warmup): State<Warmup>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // ---
+    let wait = Duration::from_secs(config.ingest_wait_secs);
+    let status = warmup
+        .ensure(
+            &pool,
+            &live.load().sources,
+            &config.source_priority,
+            &enrichment,
+            wait,
+        )
+        .await;
+    match status {
+        Ok(WarmupStatus::Ready) => next.run(req).await,
+        Ok(WarmupStatus::WarmingUp) => {
+            info!("Initial ingest still running; answering 503");
+            warming_up()
+        }
+        Err(e) => {
+            error!("Ingest failed: {}", e);
+            ApiError::from(e).into_response()
+        }
+    }
+}
+
+/// Handle `GET /v1/readings/count` (and the deprecated `GET /sql/readings/count`).
+/// Takes the same filters as the readings and returns how many match, and the
+/// span of their timestamps, without transferring rows. Other readings params
+/// (`limit`, `cursor`, `sample`, ...) are ignored.
+async fn count(params: ReadingsQuery, State(repo): State<Arc<dyn ReadingsRepository>>) -> Response {
+    // ---
+    let filter = match readings_filter(&params) {
+        Ok(filter) => filter,
+        Err(rejection) => return rejection,
+    };
+
+    match repo.count(&filter).await {
+        Ok(count) => (StatusCode::OK, Json(count)).into_response(),
+        Err(e) => {
+            error!("Failed to count readings: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /v1/readings/{id}` (and the deprecated `GET /sql/readings/{id}`).
+/// Returns the reading with that `id`, as in a listing with its mesh's
+/// `timestamp_local`; 404 for unknown, deleted or out-of-scope IDs, 422 for
+/// one that isn't a number.
+async fn by_id(
+    Path(id): Path<String>,
+    State(enrichment): State<Arc<Enrichment>>,
+    Extension(principal): Extension<Principal>,
+    State(repo): State<Arc<dyn ReadingsRepository>>,
+) -> Response {
+    // ---
+    let mut reading = match load_by_id(&id, repo.as_ref(), &enrichment, &principal).await {
+        Ok(reading) => reading,
+        Err(rejection) => return rejection,
+    };
+
+    match repo.timezones(std::slice::from_ref(&reading.mesh_id)).await {
+        Ok(zones) => localize_timestamps(std::slice::from_mut(&mut reading), None, &zones),
+        Err(e) => {
+            error!("Failed to load mesh timezones: {}", e);
+            return db_error_response(&e, "load failed");
+        }
+    }
+    (StatusCode::OK, Json(reading)).into_response()
+}
+
+/// The reading with ID `raw`, as `principal` may see it; or the 422 (not a
+/// number), 404 (unknown, deleted or out of scope) or database error to answer.
+#[allow(clippy::result_large_err)] // built once per request, on the error path only
+async fn load_by_id(
+    raw: &str,
+    repo: &dyn ReadingsRepository,
+    enrichment: &Enrichment,
+    principal: &Principal,
+) -> Result<SensorReading, Response> {
+    // ---
+    let Ok(id) = raw.parse::<i32>() else {
+        return Err(ApiError::Validation {
+            error: "invalid id",
+            hint: "reading IDs are integers, as served in each reading's id field",
+        }
+        .into_response());
+    };
+
+    match repo.reading(id, principal.meshes.as_deref()).await {
+        Ok(Some(mut reading)) => {
+            enrichment.reveal(&mut reading, principal.role);
+            Ok(reading)
+        }
+        Ok(None) => Err(ApiError::NotFound {
+            error: "reading not found",
+            hint: "list reading IDs with GET /v1/readings",
+        }
+        .into_response()),
+        Err(e) => {
+            error!("Failed to load reading {}: {}", id, e);
+            Err(db_error_response(&e, "load failed"))
         }
     }
+}
 
-    tracing::info!(
-        "Finished fetching {} total records from {} pages",
-        all_data.len(),
-        page_count
-    );
-    Ok(all_data)
+/// Readings as served: whole, or reshaped for `fields` or `units`.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ReadingsData {
+    Full(Vec<SensorReading>),
+    Sparse(Vec<serde_json::Map<String, serde_json::Value>>),
 }
 
-/// Insert one normalized reading into `sensor_data`.
+/// Set each reading's `timestamp_local` in `tz`, or else in its mesh's timezone
+/// from `zones` (`(mesh_id, timezone)`); names chrono-tz doesn't know are skipped.
+fn localize_timestamps(readings: &mut [SensorReading], tz: Option<Tz>, zones: &[(String, String)]) {
+    // ---
+    let zones: Vec<(&str, Tz)> = zones
+        .iter()
+        .filter_map(|(mesh_id, name)| Some((mesh_id.as_str(), name.parse().ok()?)))
+        .collect();
+    for r in readings {
+        let zone = tz.or_else(|| {
+            zones
+                .iter()
+                .find(|(mesh_id, _)| *mesh_id == r.mesh_id)
+                .map(|(_, tz)| *tz)
+        });
+        r.timestamp_local = zone.map(|tz| r.timestamp_utc.with_timezone(&tz).fixed_offset());
+    }
+}
+
+/// Serialize `readings` keeping only `fields` (all when `None`), in `units`.
+fn sparse(
+    readings: Vec<SensorReading>,
+    fields: Option<&[&str]>,
+    units: UnitSystem,
+) -> Vec<serde_json::Map<String, serde_json::Value>> {
+    // ---
+    readings
+        .into_iter()
+        .map(|r| match serde_json::to_value(r) {
+            Ok(serde_json::Value::Object(mut map)) => {
+                if let Some(fields) = fields {
+                    map.retain(|k, _| fields.contains(&k.as_str()));
+                }
+                units.convert(&mut map);
+                map
+            }
+            _ => serde_json::Map::new(),
+        })
+        .collect()
+}
+
+/// Opt-in response wrapper for readings (`envelope=true`).
 ///
-/// - Uses a parameterized `INSERT`
-/// - No string interpolation → safe from SQL injection; `sqlx` handles quoting & types.
-/// - Executes via the provided `PgPool`; returns `sqlx::Error` on constraint/type failures.
-/// - For bulk ingest, wrap calls in a single transaction or accept a generic `Executor`.
-async fn store_sensor_reading(pool: &PgPool, reading: &SensorReading) -> Result<(), sqlx::Error> {
-    // ---
-    sqlx::query(
-        r#"
-        INSERT INTO sensor_data (
-            mesh_id, device_id, timestamp_utc,
-            temperature_c, humidity, status,
-            temperature_alert, humidity_alert
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-        "#,
-    )
-    .bind(&reading.mesh_id)
-    .bind(&reading.device_id)
-    .bind(reading.timestamp_utc)
-    .bind(reading.temperature_c)
-    .bind(reading.humidity)
-    .bind(&reading.status)
-    .bind(reading.temperature_alert)
-    .bind(reading.humidity_alert)
-    .execute(pool)
-    .await?;
-
-    Ok(())
-}
-
-/// Recompute per-mesh aggregates from `sensor_data` and upsert into `mesh_summary`.
-/// Aggregates all history (AVG temps/humidity, COUNT) and uses ON CONFLICT(mesh_id) to update.
-async fn update_mesh_summaries(pool: &PgPool) -> Result<(), sqlx::Error> {
-    // ---
-
-    // Run one SQL that groups sensor_data by mesh_id and calculates:
-    //     - avg_temperature_c,
-    //     - avg_humidity
-    //     - reading_count
-    //
-    // Write into table mesh_summary using ON CONFLICT (mesh_id) DO UPDATE
-    // (so each mesh has one row that gets updated).
-    //
-    // Scope: aggregates all rows in sensor_data (no time window).
-    sqlx::query(
-        r#"
-        INSERT INTO mesh_summary (mesh_id, avg_temperature_c, avg_humidity, reading_count)
-        SELECT 
-            mesh_id,
-            AVG(temperature_c) as avg_temperature_c,
-            AVG(humidity) as avg_humidity,
-            COUNT(*) as reading_count
-        FROM sensor_data 
-        GROUP BY mesh_id
-        ON CONFLICT (mesh_id) DO UPDATE SET
-            avg_temperature_c = EXCLUDED.avg_temperature_c,
-            avg_humidity      = EXCLUDED.avg_humidity,
-            reading_count     = EXCLUDED.reading_count
-        "#,
-    )
-    .execute(pool)
-    .await?;
+/// Always used when `sample` is set, so approximate results are explicitly
+/// marked as such.
+#[derive(Serialize)]
+struct ReadingsEnvelope<'a> {
+    // ---
+    data: ReadingsData,
 
-    Ok(())
+    /// Readings matching the filters across all pages, as in `X-Total-Count`;
+    /// left out of sampled responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<i64>,
+
+    /// Page size the rows were loaded with.
+    limit: u32,
+
+    /// True when rows come from a `TABLESAMPLE` rather than the full table.
+    sampled: bool,
+
+    /// Requested fraction of table blocks sampled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sample_fraction: Option<f64>,
+
+    /// Same value as the `X-Next-Cursor` header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+
+    /// Units of the measurement fields in `data`.
+    units: Units,
+
+    /// Applied filters, timing and data freshness, with `meta=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<ReadingsMeta<'a>>,
+
+    /// Deprecation notices for the route or parameters used (see `deprecation.rs`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
 }
 
+/// How a readings request was answered (`meta=true`), for debugging empty
+/// or surprising results.
+#[derive(Serialize)]
+struct ReadingsMeta<'a> {
+    // ---
+    filters: AppliedFilters<'a>,
+    sort: &'static str,
+
+    /// Server time spent on the request up to serializing, in milliseconds.
+    took_ms: u64,
+
+    /// Newest stored reading within the caller's scope, whatever the filters;
+    /// `None` when nothing is stored.
+    latest_reading: Option<DateTime<Utc>>,
+}
+
+/// Filters as applied: aliases resolved, lists split, relative ranges
+/// resolved to timestamps. Unset filters are left out.
+#[derive(Serialize)]
+struct AppliedFilters<'a> {
+    // ---
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    device_id: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    mesh_id: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_id_prefix: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mesh_id_prefix: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    status: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    status_not: &'a [String],
+
+    /// `any`, `temperature` or `humidity`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temp_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temp_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    humidity_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    humidity_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_quality: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bbox: Option<&'a BoundingBox>,
+
+    /// Meshes the caller is limited to, when scoped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mesh_scope: Option<&'a [String]>,
+    limit: u32,
+
+    /// Whether the page continues from a cursor.
+    after_cursor: bool,
+}
+
+/// The `filter` parsed from `params` as applied.
+fn applied_filters<'a>(
+    params: &'a ReadingsQuery,
+    filter: &'a ReadingsFilter,
+) -> AppliedFilters<'a> {
+    // ---
+    AppliedFilters {
+        device_id: &filter.device_ids,
+        mesh_id: &filter.mesh_ids,
+        device_id_prefix: filter.device_id_prefix.as_deref(),
+        mesh_id_prefix: filter.mesh_id_prefix.as_deref(),
+        status: filter.statuses.as_deref().unwrap_or_default(),
+        status_not: filter.statuses_not.as_deref().unwrap_or_default(),
+        alert: params
+            .alert_type
+            .as_deref()
+            .or(params.alerts_only.filter(|only| *only).map(|_| "any")),
+        temp_min: filter.temperature_c[0],
+        temp_max: filter.temperature_c[1],
+        humidity_min: filter.humidity[0],
+        humidity_max: filter.humidity[1],
+        start: filter.start,
+        end: filter.end,
+        min_quality: filter.min_quality,
+        extra_key: filter.extra_key.as_deref(),
+        bbox: filter.bbox.as_ref(),
+        mesh_scope: filter.allowed_meshes.as_deref(),
+        limit: params.limit.unwrap_or(DEFAULT_LIMIT),
+        after_cursor: params.cursor.is_some(),
+    }
+}
+
+/// Unit-of-measure metadata for envelope consumers.
+///
+/// Values are stored in Celsius and relative-humidity percent (see README
+/// "Temperature units") and served in the requested [`UnitSystem`].
+#[derive(Serialize)]
+struct Units {
+    temperature: &'static str,
+    humidity: &'static str,
+}
+
+impl Units {
+    // ---
+    fn of(system: UnitSystem) -> Self {
+        // ---
+        Self {
+            temperature: system.temperature_unit(),
+            humidity: "percent",
+        }
+    }
+}
+
+// ---
+
 /// Query parameters for filtering sensor readings
 #[derive(Debug, Deserialize)]
 pub struct ReadingsQuery {
@@ -286,18 +1095,301 @@ pub struct ReadingsQuery {
     #[serde(alias = "mesh", alias = "meshId", alias = "meshID")]
     mesh_id: Option<String>,
 
+    /// Only devices whose id starts with this (e.g., "rack-12-")
+    device_id_prefix: Option<String>,
+
+    /// Only meshes whose id starts with this
+    mesh_id_prefix: Option<String>,
+
     /// Timestamp range filter (e.g., "2025-03-21T00:00:00Z,2025-03-22T00:00:00Z")
     #[serde(alias = "ts_range", alias = "timestampRange")]
     timestamp_range: Option<String>,
+
+    /// Shorthand for `timestamp_range=last_<since>` (e.g., "15m"); folded into it by the extractor
+    since: Option<String>,
     limit: Option<u32>,
+
+    /// Only readings with one of these statuses (e.g., "degraded,faulty")
+    status: Option<String>,
+
+    /// Only readings with none of these statuses (e.g., "ok")
+    status_not: Option<String>,
+
+    /// Only readings with an alert flag set
+    alerts_only: Option<bool>,
+
+    /// Only readings with this alert flag set: "temperature" or "humidity"
+    alert_type: Option<String>,
+
+    /// Only readings at least this warm, in °C
+    temp_min: Option<f32>,
+
+    /// Only readings at most this warm, in °C
+    temp_max: Option<f32>,
+
+    /// Only readings at least this humid, in % RH
+    humidity_min: Option<f32>,
+
+    /// Only readings at most this humid, in % RH
+    humidity_max: Option<f32>,
+
+    /// Only readings scoring at least this quality (0 to 1)
+    min_quality: Option<f32>,
+
+    /// Only readings with this key in `extra` (e.g., "pressure")
+    extra_key: Option<String>,
+
+    /// Bounding box "minLon,minLat,maxLon,maxLat" (e.g., "13.0,52.3,13.8,52.7")
+    bbox: Option<String>,
+
+    /// Fields to return, e.g. "device_id,timestamp_utc,temperature_c" (see `COLUMN_FIELDS`)
+    fields: Option<String>,
+
+    /// Row order, e.g. "temperature_desc" (see `Sort`)
+    sort: Option<String>,
+
+    /// Opaque pagination cursor from a previous response's `X-Next-Cursor` header
+    cursor: Option<String>,
+
+    /// Approximate preview: sample roughly this fraction (0, 1] of the table
+    sample: Option<f64>,
+
+    /// Wrap the response in a `ReadingsEnvelope` instead of a bare array
+    envelope: Option<bool>,
+
+    /// Add `ReadingsMeta` (applied filters, timing, freshness) to the envelope; implies `envelope`
+    meta: Option<bool>,
+
+    /// Timezone for `timestamp_local` (e.g., "America/New_York"); defaults to the mesh's
+    tz: Option<String>,
+
+    /// Unit system of the response: "metric" (default) or "imperial"
+    units: Option<String>,
+
+    /// Comma-separated extras to attach to each reading; only `device` so far
+    include: Option<String>,
+
+    /// Smoothing method to add to each reading; only `ewma` so far
+    smooth: Option<String>,
+
+    /// EWMA smoothing factor (0, 1]
+    alpha: Option<f32>,
+
+    /// Add each reading's average over this many of its device's latest readings
+    rolling_avg: Option<u32>,
+
+    /// Mesh scope of the authenticated caller (`None` = all); set by the extractor
+    #[serde(skip)]
+    allowed_meshes: Option<Vec<String>>,
+
+    /// Origin and path of the request, and its raw query, for page links; set by the extractor
+    #[serde(skip)]
+    request_url: (String, String),
+
+    /// `If-None-Match` request header; set by the extractor
+    #[serde(skip)]
+    if_none_match: Option<String>,
+
+    /// `If-Modified-Since` request header; set by the extractor
+    #[serde(skip)]
+    if_modified_since: Option<String>,
+}
+
+/// Query-parsing layer: every handler taking `ReadingsQuery` gets `limit`
+/// resolved here, from the request or else the caller's default (per
+/// `x-api-key`, falling back to `DEFAULT_LIMIT` config).
+impl<S> FromRequestParts<S> for ReadingsQuery
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    // ---
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // ---
+        let config = Arc::<Config>::from_ref(state);
+        let query = join_repeated(parts.uri.query().unwrap_or_default());
+        let mut params: ReadingsQuery =
+            serde_urlencoded::from_str(&query).map_err(|e| query_error(&e).into_response())?;
+
+        if let Some(since) = params.since.take() {
+            if params.timestamp_range.is_some() {
+                return Err(ApiError::Validation {
+                    error: "conflicting time filters",
+                    hint: "give either since or timestamp_range, not both",
+                }
+                .into_response());
+            }
+            params.timestamp_range = Some(format!("last_{since}"));
+        }
+
+        if params.limit.is_none() {
+            let api_key = parts.headers.get("x-api-key").and_then(|v| v.to_str().ok());
+            params.limit = Some(config.default_limit_for(api_key));
+        }
+        params.allowed_meshes = parts
+            .extensions
+            .get::<Principal>()
+            .and_then(|p| p.meshes.clone());
+        params.request_url = (
+            request_origin(&parts.headers, config.tls.is_some()) + parts.uri.path(),
+            parts.uri.query().unwrap_or_default().to_string(),
+        );
+        params.if_none_match = parts
+            .headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        params.if_modified_since = parts
+            .headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        Ok(params)
+    }
+}
+
+/// `query` with every repeated [`MULTI_VALUED`] filter joined into one
+/// comma-separated value under its canonical name, in request order.
+fn join_repeated(query: &str) -> String {
+    // ---
+    let Ok(pairs) = serde_urlencoded::from_str::<Vec<(String, String)>>(query) else {
+        // Left for the typed parse to reject
+        return query.to_string();
+    };
+    let mut joined: Vec<(String, String)> = Vec::with_capacity(pairs.len());
+    for (key, value) in pairs {
+        let canonical = MULTI_VALUED
+            .iter()
+            .find(|(_, names)| names.contains(&key.as_str()))
+            .map(|(name, _)| *name);
+        match canonical {
+            Some(name) => match joined.iter_mut().find(|(k, _)| k == name) {
+                Some((_, values)) => {
+                    values.push(',');
+                    values.push_str(&value);
+                }
+                None => joined.push((name.to_string(), value)),
+            },
+            None => joined.push((key, value)),
+        }
+    }
+    serde_urlencoded::to_string(&joined).unwrap_or_else(|_| query.to_string())
+}
+
+/// The values of a multi-valued filter, e.g. `"a, b"` is `["a", "b"]`.
+fn filter_values(raw: &str) -> Vec<String> {
+    // ---
+    raw.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Each measurement range filter as `([min, max], error, hint)`.
+fn value_ranges(params: &ReadingsQuery) -> [([Option<f32>; 2], &'static str, &'static str); 2] {
+    // ---
+    [
+        (
+            [params.temp_min, params.temp_max],
+            "invalid temperature range",
+            "use temp_min <= temp_max in °C, e.g. temp_min=-10&temp_max=60",
+        ),
+        (
+            [params.humidity_min, params.humidity_max],
+            "invalid humidity range",
+            "use humidity_min <= humidity_max in % RH, e.g. humidity_min=85",
+        ),
+    ]
+}
+
+/// The alert flags `alerts_only` and `alert_type` ask for: `Some(None)` for
+/// none, `None` when `alert_type` is unknown or contradicts `alerts_only=false`.
+fn alert_filter(params: &ReadingsQuery) -> Option<Option<AlertFilter>> {
+    // ---
+    match (params.alerts_only, params.alert_type.as_deref()) {
+        (None | Some(false), None) => Some(None),
+        (Some(true), None) => Some(Some(AlertFilter::Any)),
+        (None | Some(true), Some("temperature")) => Some(Some(AlertFilter::Temperature)),
+        (None | Some(true), Some("humidity")) => Some(Some(AlertFilter::Humidity)),
+        _ => None,
+    }
+}
+
+impl ReadingsQuery {
+    // ---
+    /// Weak ETag of this request's response for `role`, given the `count` of
+    /// its matching readings and their meshes' `last_modified`: it changes
+    /// whenever readings are added to or removed from the filter, the newest
+    /// one changes, or a reading in scope is corrected.
+    fn etag(
+        &self,
+        count: &ReadingsCount,
+        last_modified: Option<DateTime<Utc>>,
+        role: Role,
+    ) -> String {
+        // ---
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}?{}", self.request_url.0, self.request_url.1));
+        hasher.update(format!("|{role:?}|{:?}", self.allowed_meshes));
+        hasher.update(format!(
+            "|{}|{:?}|{:?}",
+            count.count, count.latest, last_modified
+        ));
+        let digest: String = hasher.finalize()[..12]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        format!("W/\"{digest}\"")
+    }
+
+    /// Response cache key of this request: its parameters in canonical order,
+    /// repeated filters joined, with the resolved `limit` and the caller's scope.
+    fn cache_key(&self) -> String {
+        // ---
+        let mut pairs: Vec<(String, String)> =
+            serde_urlencoded::from_str(&join_repeated(&self.request_url.1)).unwrap_or_default();
+        pairs.sort();
+        format!(
+            "{}|{:?}|{:?}",
+            serde_urlencoded::to_string(&pairs).unwrap_or_default(),
+            self.limit,
+            self.allowed_meshes
+        )
+    }
+
+    /// Whether `meta=true` was asked for (which implies `envelope`).
+    fn wants_meta(&self) -> bool {
+        // ---
+        self.meta.unwrap_or(false)
+    }
+
+    /// URL of this request with its `cursor` replaced by `cursor` (none for
+    /// the first page).
+    fn page_url(&self, cursor: Option<&str>) -> String {
+        // ---
+        let (base, query) = &self.request_url;
+        let mut pairs: Vec<(String, String)> =
+            serde_urlencoded::from_str(query).unwrap_or_default();
+        pairs.retain(|(k, _)| k != "cursor");
+        pairs.extend(cursor.map(|c| ("cursor".to_string(), c.to_string())));
+        match serde_urlencoded::to_string(&pairs).unwrap_or_default() {
+            query if query.is_empty() => base.clone(),
+            query => format!("{base}?{query}"),
+        }
+    }
 }
 
 /// Type alias for timestamp range parsing result: (start, end) where each can be None for open ranges
-type TimestampRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+pub(super) type TimestampRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
 
 /// Parse `"start,end"` (RFC3339) into UTC datetimes.
-/// Supports open ends (`"start,"`, `",end"`). Returns `None` on parse error or if `start > end`.
-fn parse_timestamp_range(s: &str) -> Option<TimestampRange> {
+/// Supports open ends (`"start,"`, `",end"`), and `"last_<duration>"` (e.g. `last_24h`, see
+/// `parse_duration`) for that long before now. Returns `None` if a non-empty bound isn't RFC3339
+/// or if `start > end`; `","` is a range open at both ends.
+pub(super) fn parse_timestamp_range(s: &str) -> Option<TimestampRange> {
     // ---
     // Expected timestamp syntax (RFC3339):
     //   2025-03-21T00:00:00Z
@@ -306,24 +1398,28 @@ fn parse_timestamp_range(s: &str) -> Option<TimestampRange> {
     //   2025-03-21T00:00:00-07:00
     // Range forms (whitespace OK):
     //   "start,end" | "start," | ",end"
+    // Relative form, resolved against the current time:
+    //   "last_15m" | "last_24h" | "last_7d"
 
     let s = s.trim();
+    if let Some(duration) = s.strip_prefix("last_") {
+        return Some((Some(Utc::now() - parse_duration(duration)?), None));
+    }
     let (a, b) = s.split_once(',')?;
+    // `Some(None)` for an empty (open) bound, `None` for one that doesn't parse
     let parse = |t: &str| {
         let t = t.trim();
         if t.is_empty() {
             tracing::trace!("Got empty range:{s}");
-            None
+            Some(None)
         } else {
-            // Parse RFC3339 timestamp and convert to UTC; .ok() discards parse errors
-            // since validation failure is handled by the parent function returning None
             chrono::DateTime::parse_from_rfc3339(t)
                 .ok()
-                .map(|d| d.with_timezone(&Utc))
+                .map(|d| Some(d.with_timezone(&Utc)))
         }
     };
-    let start = parse(a);
-    let end = parse(b);
+    let start = parse(a)?;
+    let end = parse(b)?;
     if let (Some(st), Some(en)) = (start, end) {
         if st > en {
             tracing::trace!("Start > End:{s}");
@@ -333,135 +1429,163 @@ fn parse_timestamp_range(s: &str) -> Option<TimestampRange> {
     Some((start, end))
 }
 
-#[derive(Serialize)]
-struct ApiError {
-    error: &'static str,
-    hint: &'static str,
+/// Parse `"minLon,minLat,maxLon,maxLat"`. Returns `None` unless all four are
+/// in range and `minLat <= maxLat`; `minLon > maxLon` is a box across the
+/// antimeridian.
+fn parse_bbox(raw: &str) -> Option<BoundingBox> {
+    // ---
+    let parts: Vec<f64> = raw
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let [min_lon, min_lat, max_lon, max_lat] = parts[..] else {
+        return None;
+    };
+    let lat = -90.0..=90.0;
+    let lon = -180.0..=180.0;
+    let in_range = lon.contains(&min_lon)
+        && lon.contains(&max_lon)
+        && lat.contains(&min_lat)
+        && lat.contains(&max_lat);
+    (in_range && min_lat <= max_lat).then_some(BoundingBox {
+        min_lon,
+        min_lat,
+        max_lon,
+        max_lat,
+    })
 }
 
-/// Ensure data exists: if `sensor_data` is empty, fetch from the API,
-/// transform, persist, and update summaries; otherwise no-op. Used to avoid
-/// re-ingesting on every GET.
-async fn ensure_data_loaded(
-    pool: &PgPool,
-    api_url: &str,
-    api_max_pages: u32,
-) -> Result<(), String> {
+/// The filters `GET /v1/readings` shares with its count, validated: too many
+/// devices or meshes, a malformed `timestamp_range` or `bbox`, or a
+/// `min_quality` outside [0, 1], is a 422.
+#[allow(clippy::result_large_err)] // built once per request, on the error path only
+fn readings_filter(params: &ReadingsQuery) -> Result<ReadingsFilter, Response> {
     // ---
+    let too_many = |raw: &Option<String>| {
+        raw.as_deref()
+            .is_some_and(|raw| filter_values(raw).len() > MAX_FILTER_VALUES)
+    };
+    if too_many(&params.device_id) || too_many(&params.mesh_id) {
+        return Err(ApiError::Validation {
+            error: "too many filter values",
+            hint: "list at most 100 devices or meshes per request",
+        }
+        .into_response());
+    }
 
-    // Quick query of posgres then skip ingest if we already have data
-    let has_data: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sensor_data)")
-        .fetch_one(pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if has_data {
-        tracing::debug!("Data present; skipping ingest");
-        return Ok(());
+    let empty = |prefix: &Option<String>| prefix.as_deref().is_some_and(str::is_empty);
+    if empty(&params.device_id_prefix) || empty(&params.mesh_id_prefix) {
+        return Err(ApiError::Validation {
+            error: "invalid prefix",
+            hint: "give the start of the id, e.g. device_id_prefix=rack-12-",
+        }
+        .into_response());
     }
 
-    tracing::info!("No data present; performing initial ingest");
+    let (start, end) = match params.timestamp_range.as_deref() {
+        None => (None, None),
+        Some(raw) => match parse_timestamp_range(raw) {
+            Some(range) => range,
+            None => {
+                return Err(ApiError::Validation {
+                    error: "invalid timestamp_range",
+                    hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z), or last_<n><unit> (e.g. last_24h)"#,
+                }
+                .into_response());
+            }
+        },
+    };
 
-    // Expensive call to ingest data and store in DB
-    let raw = fetch_sensor_data(api_url, api_max_pages)
-        .await
-        .map_err(|e| e.to_string())?;
+    let Some(alert) = alert_filter(params) else {
+        return Err(ApiError::Validation {
+            error: "invalid alert_type",
+            hint: "use alert_type=temperature or alert_type=humidity, without alerts_only=false",
+        }
+        .into_response());
+    };
 
-    for r in raw {
-        let t = r.to_transformed();
-        if let Err(e) = store_sensor_reading(pool, &t).await {
-            tracing::error!("store failed: {e}");
+    for (bounds, error, hint) in value_ranges(params) {
+        let finite = bounds.iter().flatten().all(|v| v.is_finite());
+        if !finite || matches!(bounds, [Some(min), Some(max)] if min > max) {
+            return Err(ApiError::Validation { error, hint }.into_response());
         }
     }
-    update_mesh_summaries(pool)
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
-}
 
-/// Load filtered readings from `sensor_data` using database-level filtering.
-///
-/// Builds dynamic SQL queries with proper parameter binding. PostgreSQL automatically
-/// selects the optimal index based on query filters:
-///   - Single filters use corresponding single-column indexes
-///   - Combined filters prefer composite indexes when available
-///   - Results ordered by `timestamp_utc DESC` for deterministic output
-///   - `LIMIT` applied at database level for memory efficiency
-///
-/// Available indexes: `device_id`, `mesh_id`, `timestamp_utc`, and composites
-/// `(device_id, timestamp_utc)`, `(mesh_id, timestamp_utc)` for optimal performance.
-///
-/// TODO: For production, this API should return a `next_cursor` field in the response
-/// to enable cursor-based pagination for downstream clients, following the same
-/// pattern we use when consuming the upstream sensor API.
-async fn load_filtered_readings(
-    pool: &PgPool,
-    params: &ReadingsQuery,
-) -> Result<Vec<SensorReading>, sqlx::Error> {
-    use sqlx::QueryBuilder;
-
-    let mut query = QueryBuilder::new(
-        r#"
-        SELECT mesh_id, device_id, timestamp_utc,
-               temperature_c, humidity, status,
-               temperature_alert, humidity_alert
-        FROM sensor_data
-        WHERE 1=1
-        "#,
-    );
-
-    // Add device_id filter (uses index)
-    if let Some(device_id) = &params.device_id {
-        query.push(" AND device_id = ");
-        query.push_bind(device_id);
-    }
-
-    // Add mesh_id filter (uses index)
-    if let Some(mesh_id) = &params.mesh_id {
-        query.push(" AND mesh_id = ");
-        query.push_bind(mesh_id);
-    }
-
-    // Add timestamp range filter
-    if let Some(ts_range) = &params.timestamp_range {
-        if let Some((start, end)) = parse_timestamp_range(ts_range) {
-            if let Some(start_time) = start {
-                query.push(" AND timestamp_utc >= ");
-                query.push_bind(start_time);
-            }
-            if let Some(end_time) = end {
-                query.push(" AND timestamp_utc <= ");
-                query.push_bind(end_time);
+    if let Some(min) = params.min_quality {
+        if !(0.0..=1.0).contains(&min) {
+            return Err(ApiError::Validation {
+                error: "invalid min_quality",
+                hint: "use a score in [0, 1], e.g. min_quality=0.75",
             }
+            .into_response());
         }
     }
 
-    // Add ORDER BY for deterministic results
-    query.push(" ORDER BY timestamp_utc DESC");
-
-    // Add LIMIT
-    let limit = params.limit.unwrap_or(1000);
-    query.push(" LIMIT ");
-    query.push_bind(limit as i64);
+    let bbox = match params.bbox.as_deref() {
+        None => None,
+        Some(raw) => match parse_bbox(raw) {
+            Some(bbox) => Some(bbox),
+            None => {
+                return Err(ApiError::Validation {
+                    error: "invalid bbox",
+                    hint:
+                        "use minLon,minLat,maxLon,maxLat in degrees, e.g. bbox=13.0,52.3,13.8,52.7",
+                }
+                .into_response());
+            }
+        },
+    };
 
-    // Execute query and map results
-    let rows = query.build().fetch_all(pool).await?;
+    let values = |raw: &Option<String>| raw.as_deref().map(filter_values);
+    Ok(ReadingsFilter {
+        device_ids: values(&params.device_id).unwrap_or_default(),
+        mesh_ids: values(&params.mesh_id).unwrap_or_default(),
+        device_id_prefix: params.device_id_prefix.clone(),
+        mesh_id_prefix: params.mesh_id_prefix.clone(),
+        statuses: values(&params.status),
+        statuses_not: values(&params.status_not),
+        alert,
+        temperature_c: [params.temp_min, params.temp_max],
+        humidity: [params.humidity_min, params.humidity_max],
+        min_quality: params.min_quality,
+        extra_key: params.extra_key.clone(),
+        allowed_meshes: params.allowed_meshes.clone(),
+        start,
+        end,
+        bbox,
+    })
+}
 
-    let readings = rows
-        .into_iter()
-        .map(|row| SensorReading {
-            mesh_id: row.get("mesh_id"),
-            device_id: row.get("device_id"),
-            timestamp_utc: row.get::<DateTime<Utc>, _>("timestamp_utc"),
-            temperature_c: row.get("temperature_c"),
-            humidity: row.get("humidity"),
-            status: row.get("status"),
-            temperature_alert: row.get("temperature_alert"),
-            humidity_alert: row.get("humidity_alert"),
-        })
-        .collect();
+/// Parse `fields`: distinct names from [`COLUMN_FIELDS`] and
+/// [`COMPUTED_FIELDS`], in request order; `None` when any is unknown or there
+/// are none.
+fn parse_fields(raw: &str) -> Option<Vec<&'static str>> {
+    // ---
+    let mut fields = Vec::new();
+    for part in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let field = COLUMN_FIELDS
+            .iter()
+            .chain(COMPUTED_FIELDS)
+            .find(|f| **f == part)?;
+        if !fields.contains(field) {
+            fields.push(*field);
+        }
+    }
+    (!fields.is_empty()).then_some(fields)
+}
 
-    Ok(readings)
+/// Whether an `include` list asks for device metadata; `None` when it names
+/// anything unknown.
+fn include_device(raw: &str) -> Option<bool> {
+    // ---
+    let mut device = false;
+    for part in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part {
+            "device" => device = true,
+            _ => return None,
+        }
+    }
+    Some(device)
 }
 
 #[cfg(test)]
@@ -495,8 +1619,254 @@ mod tests {
         assert!(parse_timestamp_range("2025-03-22T00:00:00Z,2025-03-21T00:00:00Z").is_none());
     }
 
+    #[test]
+    fn parses_relative_range() {
+        // ---
+        let before = Utc::now();
+        let (start, end) = parse_timestamp_range(" last_24h ").expect("should parse");
+        let start = start.expect("has a start");
+        assert!(start >= before - chrono::Duration::hours(24) && start <= Utc::now());
+        assert!(end.is_none());
+        assert!(parse_timestamp_range("last_").is_none());
+        assert!(parse_timestamp_range("last_0h").is_none());
+    }
+
     #[test]
     fn rejects_missing_comma() {
         assert!(parse_timestamp_range("2025-03-21T00:00:00Z").is_none());
     }
+
+    #[test]
+    fn rejects_malformed_bounds_but_not_open_ones() {
+        // ---
+        assert!(parse_timestamp_range("typo,typo").is_none());
+        assert!(parse_timestamp_range("2025-03-21T00:00:00Z,typo").is_none());
+        assert!(parse_timestamp_range("typo,").is_none());
+        assert_eq!(parse_timestamp_range(" , "), Some((None, None)));
+    }
+
+    #[test]
+    fn joins_repeated_filters_under_canonical_names() {
+        // ---
+        let got = join_repeated("device_id=a&limit=5&device=b%2Cc&meshId=m1&mesh_id=m2");
+        assert_eq!(got, "device_id=a%2Cb%2Cc&limit=5&mesh_id=m1%2Cm2");
+        assert_eq!(filter_values(" a, ,b "), ["a", "b"]);
+    }
+
+    #[test]
+    fn localizes_in_tz_or_the_mesh_timezone() {
+        // ---
+        let reading = |mesh_id: &str| {
+            serde_json::from_value::<crate::RawSensorReading>(serde_json::json!({
+                "mesh_id": mesh_id,
+                "device_id": "device-A",
+                "timestamp": "2025-01-15T12:00:00Z",
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+            }))
+            .expect("valid reading")
+            .to_transformed()
+        };
+        let zones = [("mesh-berlin".to_string(), "Europe/Berlin".to_string())];
+        let mut readings = [reading("mesh-berlin"), reading("mesh-unregistered")];
+        localize_timestamps(&mut readings, None, &zones);
+        let local = readings[0].timestamp_local.expect("localized");
+        assert_eq!(local.to_rfc3339(), "2025-01-15T13:00:00+01:00");
+        assert!(readings[1].timestamp_local.is_none());
+
+        localize_timestamps(&mut readings, Some(chrono_tz::Asia::Tokyo), &zones);
+        let local = readings[1].timestamp_local.expect("localized");
+        assert_eq!(local.to_rfc3339(), "2025-01-15T21:00:00+09:00");
+    }
+
+    #[test]
+    fn page_urls_swap_the_cursor() {
+        // ---
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("api.example.com"));
+        assert_eq!(request_origin(&headers, true), "https://api.example.com");
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+        assert_eq!(request_origin(&headers, true), "http://api.example.com");
+        assert_eq!(request_origin(&HeaderMap::new(), false), "");
+
+        let mut params: ReadingsQuery = serde_urlencoded::from_str("").unwrap();
+        params.request_url = (
+            "https://api.example.com/v1/readings".into(),
+            "limit=2&cursor=old&device_id=a%2Cb".into(),
+        );
+        assert_eq!(
+            params.page_url(Some("new")),
+            "https://api.example.com/v1/readings?limit=2&device_id=a%2Cb&cursor=new"
+        );
+        params.request_url.1 = "cursor=old".into();
+        assert_eq!(params.page_url(None), "https://api.example.com/v1/readings");
+    }
+
+    #[test]
+    fn etags_match_weakly_and_by_wildcard() {
+        // ---
+        let etag = r#"W/"0123abcd""#;
+        assert!(etag_matches(r#"W/"0123abcd""#, etag));
+        assert!(etag_matches(r#""ffff", "0123abcd""#, etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches(r#"W/"ffff""#, etag));
+    }
+
+    #[test]
+    fn if_modified_since_compares_whole_seconds() {
+        // ---
+        let at = DateTime::parse_from_rfc3339("2025-03-21T10:00:00.750Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(http_date(at), "Fri, 21 Mar 2025 10:00:00 GMT");
+        assert!(not_modified_since(Some(&http_date(at)), at));
+        assert!(not_modified_since(
+            Some("Fri, 21 Mar 2025 11:00:00 GMT"),
+            at
+        ));
+        assert!(!not_modified_since(
+            Some("Fri, 21 Mar 2025 09:59:59 GMT"),
+            at
+        ));
+        assert!(!not_modified_since(Some("yesterday"), at));
+        assert!(!not_modified_since(None, at));
+    }
+
+    #[test]
+    fn parses_bbox_and_rejects_bad_boxes() {
+        // ---
+        let got = parse_bbox("13.0, 52.3,13.8,52.7").expect("should parse");
+        assert_eq!((got.min_lon, got.max_lat), (13.0, 52.7));
+        assert!(parse_bbox("170,-20,-170,20").is_some()); // across the antimeridian
+        assert!(parse_bbox("13.0,52.7,13.8,52.3").is_none()); // minLat > maxLat
+        assert!(parse_bbox("13.0,52.3,13.8").is_none());
+        assert!(parse_bbox("13.0,52.3,13.8,52.7,1").is_none());
+        assert!(parse_bbox("13.0,52.3,193.8,52.7").is_none());
+        assert!(parse_bbox("west,52.3,13.8,52.7").is_none());
+    }
+
+    #[test]
+    fn include_accepts_device_only() {
+        // ---
+        assert_eq!(include_device("device"), Some(true));
+        assert_eq!(include_device(" device, "), Some(true));
+        assert_eq!(include_device(""), Some(false));
+        assert_eq!(include_device("device,owner"), None);
+    }
+
+    #[test]
+    fn fields_are_whitelisted() {
+        // ---
+        assert_eq!(
+            parse_fields("device_id, temperature_c,device_id,smoothed"),
+            Some(vec!["device_id", "temperature_c", "smoothed"])
+        );
+        assert_eq!(parse_fields("device_id,password"), None);
+        assert_eq!(parse_fields("temperature_c FROM pg_user --"), None);
+        assert_eq!(parse_fields(","), None);
+    }
+
+    /// `GET /v1/readings?{query}` as `principal`, through [`handler`] over `repo`.
+    async fn get_readings(
+        repo: &Arc<dyn ReadingsRepository>,
+        query: &str,
+        principal: &Principal,
+        if_none_match: Option<&str>,
+    ) -> Response {
+        // ---
+        let overlay = [
+            ("DATABASE_URL", "postgres://db/sensors"),
+            ("SENSOR_API_URL", "http://upstream/sensor-data"),
+            ("CURSOR_SECRET", "test"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+        .collect();
+        let config = Arc::new(crate::config::load_with_overlay(overlay).unwrap());
+
+        let mut req = axum::http::Request::builder().uri(format!("/v1/readings?{query}"));
+        if let Some(etag) = if_none_match {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        let (mut parts, ()) = req.body(()).unwrap().into_parts();
+        parts.extensions.insert(principal.clone());
+        let params = ReadingsQuery::from_request_parts(&mut parts, &config)
+            .await
+            .unwrap();
+
+        handler(
+            params,
+            State(config),
+            State(Arc::new(Enrichment::default())),
+            Extension(principal.clone()),
+            State(repo.clone()),
+            State(Arc::new(ResponseCache::new(None))),
+            None,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn handler_serves_readings_from_any_repository() {
+        // ---
+        let ingested = Utc.with_ymd_and_hms(2025, 1, 15, 12, 30, 0).unwrap();
+        let repo = crate::repository::MemoryReadings {
+            ingested: Some(ingested),
+            timezones: vec![("mesh-berlin".into(), "Europe/Berlin".into())],
+            devices: vec![crate::DeviceInfo {
+                device_id: "device-A".into(),
+                mesh_id: Some("mesh-berlin".into()),
+                display_name: Some("Freezer 3".into()),
+                location: None,
+                latitude: None,
+                longitude: None,
+                installed_on: None,
+                notes: None,
+                updated_at: ingested,
+            }],
+            ..Default::default()
+        };
+        let raw = |mesh_id: &str, device_id: &str| {
+            serde_json::from_value::<crate::RawSensorReading>(serde_json::json!({
+                "mesh_id": mesh_id,
+                "device_id": device_id,
+                "timestamp": "2025-01-15T12:00:00Z",
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+            }))
+            .expect("valid reading")
+        };
+        repo.insert_batch(
+            "test",
+            &[raw("mesh-berlin", "device-A"), raw("mesh-oslo", "device-B")],
+        )
+        .await
+        .unwrap();
+        let repo: Arc<dyn ReadingsRepository> = Arc::new(repo);
+        let scoped = Principal {
+            name: "berlin".into(),
+            role: Role::Reader,
+            meshes: Some(vec!["mesh-berlin".into()]),
+            devices: None,
+        };
+
+        let resp = get_readings(&repo, "include=device&meta=true", &scoped, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::LAST_MODIFIED], http_date(ingested));
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["total"], 1);
+        let reading = &body["data"][0];
+        assert_eq!(reading["mesh_id"], "mesh-berlin");
+        assert_eq!(reading["device"]["display_name"], "Freezer 3");
+        assert_eq!(reading["timestamp_local"], "2025-01-15T13:00:00+01:00");
+        assert_eq!(body["meta"]["latest_reading"], "2025-01-15T12:00:00Z");
+
+        // The validators come from the repository too
+        let resp = get_readings(&repo, "include=device&meta=true", &scoped, Some(&etag)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    }
 }
@@ -0,0 +1,64 @@
+//! Readiness probe for load balancers.
+//!
+//! `GET /ready` answers 200 once this replica can serve traffic and 503
+//! otherwise. By default that means the database is reachable. With
+//! `READY_REQUIRES_DATA=true` it additionally stays 503 until `sensor_data`
+//! holds at least one reading, so traffic isn't routed to a fresh replica
+//! whose first `/sql/readings` call would block on a cold ingest; `main.rs`
+//! starts that initial ingest in the background in this mode. In either mode
+//! it answers 503 `warming_up` while an initial ingest runs (see `warmup.rs`).
+//!
+//! Unlike `/health` (liveness), this route touches the database. Like
+//! `/health`, it is not behind authentication.
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::{AppState, Config, Warmup};
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new().route("/ready", get(ready))
+}
+
+/// JSON response body for the `/ready` endpoint.
+#[derive(Serialize)]
+struct ReadyResponse {
+    status: &'static str,
+}
+
+/// Handle `GET /ready`.
+async fn ready(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(warmup): State<Warmup>,
+) -> (StatusCode, Json<ReadyResponse>) {
+    // ---
+    if warmup.is_running() {
+        let status = "warming_up";
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadyResponse { status }),
+        );
+    }
+    let query = if config.ready_requires_data {
+        "SELECT EXISTS (SELECT 1 FROM sensor_data)"
+    } else {
+        "SELECT true"
+    };
+
+    let (code, status) = match sqlx::query_scalar::<_, bool>(query).fetch_one(&pool).await {
+        Ok(true) => (StatusCode::OK, "ready"),
+        Ok(false) => (StatusCode::SERVICE_UNAVAILABLE, "waiting_for_data"),
+        Err(e) => {
+            warn!("Readiness check failed: {}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, "database_unavailable")
+        }
+    };
+    (code, Json(ReadyResponse { status }))
+}
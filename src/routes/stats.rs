@@ -0,0 +1,673 @@
+//! Distribution statistics endpoints.
+//!
+//! All describe the filtered readings, computed in Postgres; duplicate copies
+//! stored by several sources count once, and readings marked invalid not at all.
+//!
+//! - `GET /sql/stats` summarises both measurements per mesh or per device:
+//!   count, average, minimum, maximum and sample standard deviation, which
+//!   `mesh_summary`'s averages and counts leave out.
+//! - `GET /sql/stats/histogram` counts the readings in equal-width buckets
+//!   spanning the set's minimum to maximum (`width_bucket`), for quick
+//!   distribution charts. Every bucket is returned, empty ones with a count
+//!   of 0. The maximum falls in the last bucket, and a set with a single
+//!   distinct value yields one bucket holding everything.
+//! - `GET /sql/stats/percentiles` returns continuous percentiles
+//!   (`percentile_cont`, interpolating between readings), showing the tails
+//!   that the averages in `mesh_summary` hide.
+//!
+//! ## Query Parameters
+//! - `group_by` - summary only: `mesh` (default) or `device`
+//! - `metric` - histogram and percentiles: `temperature_c` or `humidity` (required)
+//! - `buckets` - histogram only: number of buckets, 1 to [`MAX_BUCKETS`] (default 20)
+//! - `p` - percentiles only: comma-separated percentiles in [0, 100], at most
+//!   [`MAX_PERCENTILES`] (default `50,90,95,99`)
+//! - `device_id` - Filter by specific device
+//! - `mesh_id` - Filter by (reported) mesh
+//! - `timestamp_range` - RFC3339 range "start,end" with open ends supported
+//!
+//! The summary carries `Last-Modified`, the latest ingest into the meshes in
+//! scope; an `If-Modified-Since` at or after it is answered 304 without
+//! computing the summary. With `RESPONSE_CACHE_SECS` set, both come from the
+//! response cache while fresh.
+//!
+//! ## Error Handling
+//! - 403 unless the caller has at least the `reader` role
+//! - 422 for an unknown `group_by`, a missing or unknown `metric`, `buckets` or `p` outside its
+//!   range, or a malformed `timestamp_range`
+//! - 500 for database failures; 503 when the database is busy or the query timed out
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use tracing::error;
+
+use super::readings::{http_date, not_modified_since, parse_timestamp_range, TimestampRange};
+use crate::{
+    db_error_response, filter_shape, last_ingested, require_role, timed, ApiError, AppState,
+    Principal, Query, ReadPool, ResponseCache, Role,
+};
+
+/// Most buckets one histogram may have.
+const MAX_BUCKETS: u32 = 200;
+
+/// Most percentiles one request may ask for.
+const MAX_PERCENTILES: usize = 20;
+
+/// Percentiles returned when `p` is not given.
+const DEFAULT_PERCENTILES: &str = "50,90,95,99";
+
+// ---
+
+pub fn router() -> Router<AppState> {
+    // ---
+    Router::new()
+        .route("/sql/stats", get(summary))
+        .route("/sql/stats/histogram", get(histogram))
+        .route("/sql/stats/percentiles", get(percentiles))
+        .route_layer(middleware::from_fn_with_state(Role::Reader, require_role))
+}
+
+#[derive(Debug, Deserialize)]
+struct HistogramQuery {
+    // ---
+    metric: Option<String>,
+    buckets: Option<u32>,
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummaryQuery {
+    // ---
+    group_by: Option<String>,
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PercentilesQuery {
+    // ---
+    metric: Option<String>,
+    p: Option<String>,
+    device_id: Option<String>,
+    mesh_id: Option<String>,
+    timestamp_range: Option<String>,
+}
+
+/// Body of `GET /sql/stats`.
+#[derive(Debug, Serialize)]
+struct Summary {
+    // ---
+    /// `mesh` or `device`: which of `mesh_id` and `device_id` the groups carry.
+    group_by: &'static str,
+    groups: Vec<GroupSummary>,
+}
+
+/// The readings of one mesh or device.
+#[derive(Debug, Clone, Serialize)]
+struct GroupSummary {
+    // ---
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mesh_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_id: Option<String>,
+
+    count: i64,
+    temperature_c: MetricSummary,
+    humidity: MetricSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MetricSummary {
+    avg: f64,
+    min: f64,
+    max: f64,
+
+    /// Sample standard deviation; `None` for a single reading.
+    stddev: Option<f64>,
+}
+
+/// Body of `GET /sql/stats/histogram`.
+#[derive(Debug, Serialize)]
+struct Histogram {
+    // ---
+    metric: &'static str,
+
+    /// Readings counted; 0 leaves `min`, `max` and `histogram` empty.
+    count: i64,
+    min: Option<f64>,
+    max: Option<f64>,
+    histogram: Vec<HistogramBucket>,
+}
+
+/// Readings with `lower <= value < upper` (`<= upper` in the last bucket).
+#[derive(Debug, PartialEq, Serialize)]
+struct HistogramBucket {
+    lower: f64,
+    upper: f64,
+    count: i64,
+}
+
+/// Body of `GET /sql/stats/percentiles`.
+#[derive(Debug, Serialize)]
+struct Percentiles {
+    // ---
+    metric: &'static str,
+
+    /// Readings considered; 0 leaves every `value` null.
+    count: i64,
+    percentiles: Vec<Percentile>,
+}
+
+#[derive(Debug, Serialize)]
+struct Percentile {
+    p: f64,
+    value: Option<f64>,
+}
+
+/// Handle `GET /sql/stats`.
+async fn summary(
+    Query(params): Query<SummaryQuery>,
+    headers: HeaderMap,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+    State(cache): State<Arc<ResponseCache>>,
+) -> Response {
+    // ---
+    let group_by = match params.group_by.as_deref().map(str::trim) {
+        None | Some("mesh") => "mesh",
+        Some("device") => "device",
+        Some(_) => {
+            return ApiError::Validation {
+                error: "invalid group_by",
+                hint: "use group_by=mesh or group_by=device",
+            }
+            .into_response();
+        }
+    };
+
+    let range = match parse_range(&params.timestamp_range) {
+        Ok(range) => range,
+        Err(rejection) => return rejection,
+    };
+
+    let (scope, meshes) = (
+        principal.meshes.as_deref(),
+        params.mesh_id.as_ref().map(std::slice::from_ref),
+    );
+    let key = format!(
+        "{group_by}|{:?}|{:?}|{:?}|{:?}",
+        params.device_id, params.mesh_id, params.timestamp_range, principal.meshes
+    );
+    let load = reads.read(|pool| async move { last_ingested(&pool, scope, meshes).await });
+    let last_modified = match cache.get_or_load("summary_freshness", &key, load).await {
+        Ok(last_modified) => last_modified,
+        Err(e) => {
+            error!("Failed to load last ingest: {}", e);
+            return db_error_response(&e, "load failed");
+        }
+    };
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok());
+    let mut validators = HeaderMap::new();
+    if let Some(v) = last_modified.and_then(|at| HeaderValue::from_str(&http_date(at)).ok()) {
+        validators.insert(header::LAST_MODIFIED, v);
+    }
+    if last_modified.is_some_and(|at| not_modified_since(if_modified_since, at)) {
+        return (StatusCode::NOT_MODIFIED, validators).into_response();
+    }
+
+    let (query, principal) = (&params, &principal);
+    let load = reads
+        .read(|pool| async move { load_summary(&pool, group_by, query, range, principal).await });
+    match cache.get_or_load("summary", &key, load).await {
+        Ok(groups) => (
+            StatusCode::OK,
+            validators,
+            Json(Summary { group_by, groups }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to load stats: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /sql/stats/histogram`.
+async fn histogram(
+    Query(params): Query<HistogramQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let metric = match parse_metric(&params.metric) {
+        Ok(metric) => metric,
+        Err(rejection) => return rejection,
+    };
+    let range = match parse_range(&params.timestamp_range) {
+        Ok(range) => range,
+        Err(rejection) => return rejection,
+    };
+
+    let buckets = params.buckets.unwrap_or(20);
+    if !(1..=MAX_BUCKETS).contains(&buckets) {
+        return ApiError::Validation {
+            error: "invalid buckets",
+            hint: "use 1 to 200 buckets, e.g. buckets=20",
+        }
+        .into_response();
+    }
+
+    let (query, principal) = (&params, &principal);
+    let loaded = reads
+        .read(|pool| async move {
+            load_histogram(&pool, metric, buckets, query, range, principal).await
+        })
+        .await;
+
+    match loaded {
+        Ok(histogram) => (StatusCode::OK, Json(histogram)).into_response(),
+        Err(e) => {
+            error!("Failed to load histogram: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// Handle `GET /sql/stats/percentiles`.
+async fn percentiles(
+    Query(params): Query<PercentilesQuery>,
+    Extension(principal): Extension<Principal>,
+    State(reads): State<ReadPool>,
+) -> Response {
+    // ---
+    let metric = match parse_metric(&params.metric) {
+        Ok(metric) => metric,
+        Err(rejection) => return rejection,
+    };
+    let range = match parse_range(&params.timestamp_range) {
+        Ok(range) => range,
+        Err(rejection) => return rejection,
+    };
+
+    let Some(ps) = parse_percentiles(params.p.as_deref().unwrap_or(DEFAULT_PERCENTILES)) else {
+        return ApiError::Validation {
+            error: "invalid p",
+            hint: "use up to 20 comma-separated percentiles in [0, 100], e.g. p=50,95,99",
+        }
+        .into_response();
+    };
+
+    let (query, principal, ps) = (&params, &principal, &ps);
+    let loaded =
+        reads
+            .read(|pool| async move {
+                load_percentiles(&pool, metric, ps, query, range, principal).await
+            })
+            .await;
+
+    match loaded {
+        Ok(percentiles) => (StatusCode::OK, Json(percentiles)).into_response(),
+        Err(e) => {
+            error!("Failed to load percentiles: {}", e);
+            db_error_response(&e, "load failed")
+        }
+    }
+}
+
+/// The column named by `metric`, or the 422 when it's missing or unknown.
+#[allow(clippy::result_large_err)] // built once per request, on the error path only
+fn parse_metric(metric: &Option<String>) -> Result<&'static str, Response> {
+    // ---
+    match metric.as_deref().map(str::trim) {
+        Some("temperature_c") => Ok("temperature_c"),
+        Some("humidity") => Ok("humidity"),
+        _ => Err(ApiError::Validation {
+            error: "invalid metric",
+            hint: "use metric=temperature_c or metric=humidity",
+        }
+        .into_response()),
+    }
+}
+
+/// The parsed `timestamp_range` (open when absent), or the 422 when it's malformed.
+#[allow(clippy::result_large_err)]
+fn parse_range(timestamp_range: &Option<String>) -> Result<TimestampRange, Response> {
+    // ---
+    match timestamp_range.as_deref() {
+        None => Ok((None, None)),
+        Some(raw) => parse_timestamp_range(raw).ok_or_else(|| {
+            ApiError::Validation {
+                error: "invalid timestamp_range",
+                hint: r#"use RFC3339 "start,end" (e.g. 2025-03-21T00:00:00Z,2025-03-22T00:00:00Z)"#,
+            }
+            .into_response()
+        }),
+    }
+}
+
+/// Parse `p`: comma-separated percentiles in [0, 100], at most
+/// [`MAX_PERCENTILES`]; `None` when any is invalid or there are none.
+fn parse_percentiles(raw: &str) -> Option<Vec<f64>> {
+    // ---
+    let ps = raw
+        .split(',')
+        .map(|p| {
+            p.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!ps.is_empty() && ps.len() <= MAX_PERCENTILES).then_some(ps)
+}
+
+/// Start a query selecting `columns` of the filtered readings in a `filtered`
+/// CTE (left open for the caller to close), and add the filters' labels to
+/// `shape`.
+fn filtered_cte<'a>(
+    columns: &str,
+    device_id: &'a Option<String>,
+    mesh_id: &'a Option<String>,
+    (start, end): TimestampRange,
+    principal: &'a Principal,
+    shape: &mut Vec<&'static str>,
+) -> QueryBuilder<'a, Postgres> {
+    // ---
+    let mut qb = QueryBuilder::new(format!(
+        "WITH filtered AS (SELECT {columns} FROM sensor_data WHERE duplicate_of IS NULL AND deleted_at IS NULL AND NOT invalid"
+    ));
+    if let Some(device_id) = device_id {
+        qb.push(" AND device_id = ").push_bind(device_id);
+        shape.push("device_id");
+    }
+    if let Some(mesh_id) = mesh_id {
+        qb.push(" AND mesh_id = ").push_bind(mesh_id);
+        shape.push("mesh_id");
+    }
+    if let Some(allowed) = &principal.meshes {
+        qb.push(" AND mesh_id = ANY(").push_bind(allowed).push(")");
+    }
+    if let Some(start) = start {
+        qb.push(" AND timestamp_utc >= ").push_bind(start);
+    }
+    if let Some(end) = end {
+        qb.push(" AND timestamp_utc <= ").push_bind(end);
+    }
+    if start.is_some() || end.is_some() {
+        shape.push("timestamp_utc");
+    }
+    qb
+}
+
+/// Count, average, extremes and standard deviation of both measurements per
+/// mesh or device (`group_by`), ordered by mesh or device ID.
+#[tracing::instrument(name = "db.load_stats", skip_all)]
+async fn load_summary(
+    pool: &PgPool,
+    group_by: &'static str,
+    params: &SummaryQuery,
+    range: TimestampRange,
+    principal: &Principal,
+) -> Result<Vec<GroupSummary>, sqlx::Error> {
+    // ---
+    let (key, label) = if group_by == "device" {
+        ("device_id", "by_device")
+    } else {
+        ("mesh_id", "by_mesh")
+    };
+    let mut shape = vec!["stats", label];
+    let mut qb = filtered_cte(
+        &format!("{key} AS key, temperature_c::FLOAT8 AS t, humidity::FLOAT8 AS h"),
+        &params.device_id,
+        &params.mesh_id,
+        range,
+        principal,
+        &mut shape,
+    );
+    qb.push(
+        ") SELECT key, COUNT(*) AS count, \
+         AVG(t) AS t_avg, MIN(t) AS t_min, MAX(t) AS t_max, STDDEV_SAMP(t) AS t_stddev, \
+         AVG(h) AS h_avg, MIN(h) AS h_min, MAX(h) AS h_max, STDDEV_SAMP(h) AS h_stddev \
+         FROM filtered GROUP BY key ORDER BY key",
+    );
+
+    let rows = timed(
+        "load_stats",
+        &filter_shape(&shape),
+        qb.build().fetch_all(pool),
+    )
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let metric = |m: &str| MetricSummary {
+                avg: row.get(format!("{m}_avg").as_str()),
+                min: row.get(format!("{m}_min").as_str()),
+                max: row.get(format!("{m}_max").as_str()),
+                stddev: row.get(format!("{m}_stddev").as_str()),
+            };
+            let id: String = row.get("key");
+            let (mesh_id, device_id) = if group_by == "device" {
+                (None, Some(id))
+            } else {
+                (Some(id), None)
+            };
+            GroupSummary {
+                mesh_id,
+                device_id,
+                count: row.get("count"),
+                temperature_c: metric("t"),
+                humidity: metric("h"),
+            }
+        })
+        .collect())
+}
+
+/// Count the filtered readings' `metric` in `buckets` equal-width buckets
+/// from its minimum to its maximum.
+#[tracing::instrument(name = "db.load_histogram", skip_all)]
+async fn load_histogram(
+    pool: &PgPool,
+    metric: &'static str,
+    buckets: u32,
+    params: &HistogramQuery,
+    range: TimestampRange,
+    principal: &Principal,
+) -> Result<Histogram, sqlx::Error> {
+    // ---
+    let mut shape = vec!["histogram"];
+    let mut qb = filtered_cte(
+        &format!("{metric}::FLOAT8 AS v"),
+        &params.device_id,
+        &params.mesh_id,
+        range,
+        principal,
+        &mut shape,
+    );
+
+    // width_bucket puts the maximum in bucket n + 1 and rejects equal bounds
+    let n = buckets as i32;
+    qb.push(
+        "), bounds AS (SELECT MIN(v) AS lo, MAX(v) AS hi FROM filtered) \
+         SELECT lo, hi, CASE WHEN hi = lo THEN 1 ELSE LEAST(width_bucket(v, lo, hi, ",
+    )
+    .push_bind(n)
+    .push("), ")
+    .push_bind(n)
+    .push(
+        ") END AS bucket, COUNT(*) AS count \
+         FROM filtered CROSS JOIN bounds GROUP BY lo, hi, bucket ORDER BY bucket",
+    );
+
+    let rows = timed(
+        "load_histogram",
+        &filter_shape(&shape),
+        qb.build().fetch_all(pool),
+    )
+    .await?;
+
+    let Some(first) = rows.first() else {
+        return Ok(Histogram {
+            metric,
+            count: 0,
+            min: None,
+            max: None,
+            histogram: Vec::new(),
+        });
+    };
+    let (lo, hi): (f64, f64) = (first.get("lo"), first.get("hi"));
+    let counts: Vec<(i32, i64)> = rows
+        .iter()
+        .map(|row| (row.get("bucket"), row.get("count")))
+        .collect();
+    let histogram = histogram_buckets(lo, hi, buckets, &counts);
+    Ok(Histogram {
+        metric,
+        count: histogram.iter().map(|b| b.count).sum(),
+        min: Some(lo),
+        max: Some(hi),
+        histogram,
+    })
+}
+
+/// The filtered readings' `metric` at each of the percentiles `ps` (0-100).
+#[tracing::instrument(name = "db.load_percentiles", skip_all)]
+async fn load_percentiles(
+    pool: &PgPool,
+    metric: &'static str,
+    ps: &[f64],
+    params: &PercentilesQuery,
+    range: TimestampRange,
+    principal: &Principal,
+) -> Result<Percentiles, sqlx::Error> {
+    // ---
+    let mut shape = vec!["percentiles"];
+    let mut qb = filtered_cte(
+        &format!("{metric}::FLOAT8 AS v"),
+        &params.device_id,
+        &params.mesh_id,
+        range,
+        principal,
+        &mut shape,
+    );
+    let fractions: Vec<f64> = ps.iter().map(|p| p / 100.0).collect();
+    qb.push(
+        ") SELECT COUNT(*) AS count, \
+         percentile_cont(",
+    )
+    .push_bind(fractions)
+    .push("::FLOAT8[]) WITHIN GROUP (ORDER BY v) AS values FROM filtered");
+
+    let row = timed(
+        "load_percentiles",
+        &filter_shape(&shape),
+        qb.build().fetch_one(pool),
+    )
+    .await?;
+
+    // NULL (no readings) or one value per percentile
+    let values: Option<Vec<f64>> = row.get("values");
+    Ok(Percentiles {
+        metric,
+        count: row.get("count"),
+        percentiles: ps
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| Percentile {
+                p,
+                value: values.as_ref().and_then(|v| v.get(i).copied()),
+            })
+            .collect(),
+    })
+}
+
+/// Every bucket from `lo` to `hi`, with the counts of the 1-based bucket
+/// numbers in `counts`; a single bucket when `lo == hi`.
+fn histogram_buckets(
+    lo: f64,
+    hi: f64,
+    buckets: u32,
+    counts: &[(i32, i64)],
+) -> Vec<HistogramBucket> {
+    // ---
+    let buckets = if hi > lo { buckets } else { 1 };
+    let width = (hi - lo) / f64::from(buckets);
+    (1..=buckets as i32)
+        .map(|i| HistogramBucket {
+            lower: lo + width * f64::from(i - 1),
+            upper: if i == buckets as i32 {
+                hi
+            } else {
+                lo + width * f64::from(i)
+            },
+            count: counts
+                .iter()
+                .find(|(b, _)| *b == i)
+                .map_or(0, |(_, count)| *count),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn percentiles_are_validated() {
+        // ---
+        assert_eq!(
+            parse_percentiles(" 50, 99.9 ,0"),
+            Some(vec![50.0, 99.9, 0.0])
+        );
+        assert_eq!(
+            parse_percentiles(DEFAULT_PERCENTILES).map(|p| p.len()),
+            Some(4)
+        );
+        assert_eq!(parse_percentiles("50,101"), None);
+        assert_eq!(parse_percentiles("50,,95"), None);
+        assert_eq!(parse_percentiles(""), None);
+        assert_eq!(parse_percentiles(&["50"; 21].join(",")), None);
+    }
+
+    #[test]
+    fn every_bucket_is_returned() {
+        // ---
+        let got = histogram_buckets(10.0, 20.0, 4, &[(1, 3), (4, 2)]);
+        let bounds: Vec<_> = got.iter().map(|b| (b.lower, b.upper, b.count)).collect();
+        assert_eq!(
+            bounds,
+            [
+                (10.0, 12.5, 3),
+                (12.5, 15.0, 0),
+                (15.0, 17.5, 0),
+                (17.5, 20.0, 2)
+            ]
+        );
+
+        let single = histogram_buckets(21.0, 21.0, 20, &[(1, 5)]);
+        assert_eq!(
+            single,
+            [HistogramBucket {
+                lower: 21.0,
+                upper: 21.0,
+                count: 5
+            }]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Live mesh aggregates over Server-Sent Events.
+//!
+//! `GET /sql/stream/mesh-summary` lets dashboards update summary tiles without
+//! polling. On connect it sends one `aggregate` event per mesh (the current
+//! snapshot), then an `aggregate` event whenever a mesh's averages or count
+//! change and a `removed` event when a mesh loses its last reading. Event
+//! data is JSON: `MeshAggregate` for `aggregate`, `{"mesh_id": ...}` for
+//! `removed`.
+//!
+//! A client that falls too far behind gets a fresh snapshot instead of the
+//! missed updates. Requires the `reader` role; mesh-scoped callers only
+//! receive their meshes.
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{FromRef, State},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Extension, Router,
+};
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{error, warn};
+
+use crate::{
+    db_error_response, require_role, MeshAggregate, Principal, ReadingsRepository, Role,
+    SummaryFeed, SummaryUpdate,
+};
+
+// ---
+
+pub fn router<S>() -> Router<S>
+where
+    SummaryFeed: FromRef<S>,
+    Arc<dyn ReadingsRepository>: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    // ---
+    Router::new().route(
+        "/sql/stream/mesh-summary",
+        get(mesh_summary).route_layer(middleware::from_fn_with_state(Role::Reader, require_role)),
+    )
+}
+
+/// Per-connection state for the update stream.
+struct Subscription {
+    repo: Arc<dyn ReadingsRepository>,
+    principal: Principal,
+    updates: Receiver<SummaryUpdate>,
+}
+
+/// Handle `GET /sql/stream/mesh-summary`.
+async fn mesh_summary(
+    Extension(principal): Extension<Principal>,
+    State(feed): State<SummaryFeed>,
+    State(repo): State<Arc<dyn ReadingsRepository>>,
+) -> Response {
+    // ---
+    // Subscribe before the snapshot so no change falls between the two
+    let updates = feed.subscribe();
+    let loaded = repo.summaries(principal.meshes.as_deref()).await;
+    let snapshot = match loaded {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!("Failed to load mesh summary snapshot: {}", e);
+            return db_error_response(&e, "load failed");
+        }
+    };
+
+    let sub = Subscription {
+        repo,
+        principal,
+        updates,
+    };
+    let events = stream::iter(snapshot.into_iter().map(aggregate_event))
+        .chain(stream::unfold(sub, next_events).flat_map(stream::iter));
+
+    Sse::new(events.map(Ok::<_, Infallible>))
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+/// Wait for the next update visible to the caller; ends when the feed closes.
+async fn next_events(mut sub: Subscription) -> Option<(Vec<Event>, Subscription)> {
+    // ---
+    loop {
+        match sub.updates.recv().await {
+            Ok(update) if sub.principal.can_access_mesh(update.mesh_id()) => {
+                let event = match update {
+                    SummaryUpdate::Updated(agg) => aggregate_event(agg),
+                    SummaryUpdate::Removed(mesh_id) => Event::default()
+                        .event("removed")
+                        .json_data(serde_json::json!({ "mesh_id": mesh_id }))
+                        .unwrap_or_default(),
+                };
+                return Some((vec![event], sub));
+            }
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                warn!(
+                    "Mesh summary stream lagged by {} updates; resending snapshot",
+                    missed
+                );
+                let loaded = sub.repo.summaries(sub.principal.meshes.as_deref()).await;
+                match loaded {
+                    Ok(rows) => {
+                        let events = rows.into_iter().map(aggregate_event).collect();
+                        return Some((events, sub));
+                    }
+                    Err(e) => {
+                        error!("Failed to reload mesh summary snapshot: {}", e);
+                        return None;
+                    }
+                }
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+fn aggregate_event(agg: MeshAggregate) -> Event {
+    // ---
+    Event::default()
+        .event("aggregate")
+        .json_data(agg)
+        .unwrap_or_default()
+}
@@ -0,0 +1,66 @@
+//! Build information endpoint.
+//!
+//! `GET /version` reports what this process was built from, so deployment
+//! tooling can verify a rollout reached every replica: the crate version, the
+//! git commit, when it was built and the Cargo features enabled (embedded by
+//! `build.rs`), and the `APP_ENV` profile it runs under. Like `/health`, it
+//! touches no database and is not behind authentication.
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::Config;
+
+// ---
+
+/// Create a subrouter containing the `/version` route.
+pub fn router<S>() -> Router<S>
+where
+    Arc<Config>: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    // ---
+    Router::new().route("/version", get(version))
+}
+
+/// JSON response body for the `/version` endpoint.
+#[derive(Serialize)]
+struct VersionResponse {
+    // ---
+    name: &'static str,
+    version: &'static str,
+
+    /// Commit built, or `unknown` when built outside the repository without `GIT_SHA`.
+    git_sha: &'static str,
+    build_timestamp: Option<DateTime<Utc>>,
+    features: Vec<&'static str>,
+
+    /// `APP_ENV` profile: `dev`, `staging` or `prod`.
+    environment: &'static str,
+}
+
+/// Handle `GET /version`.
+async fn version(State(config): State<Arc<Config>>) -> Json<VersionResponse> {
+    // ---
+    let build_timestamp = env!("BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    Json(VersionResponse {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        build_timestamp,
+        features: env!("BUILD_FEATURES")
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .collect(),
+        environment: config.app_env.name(),
+    })
+}
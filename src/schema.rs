@@ -4,24 +4,114 @@
 //! Applied once on startup from `main.rs` (EMBP: single gateway call).
 
 use anyhow::Result;
-use sqlx::PgPool;
+use serde_json::json;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+
+use crate::{
+    create_partitioned_table, is_partitioned, list_partitions, record_event, Config, EventKind,
+    BUCKET_ORIGIN, READINGS_CHANNEL,
+};
+
+/// `sensor_data` indexes as `(name, definition after ON sensor_data)`.
+pub const SENSOR_DATA_INDEXES: [(&str, &str); 10] = [
+    // Single-column indexes for equality filters
+    ("idx_sensor_data_mesh_id", "(mesh_id)"),
+    ("idx_sensor_data_device_id", "(device_id)"),
+    // Source index for per-source "already ingested?" checks
+    ("idx_sensor_data_source", "(source)"),
+    // Timestamp index for range queries and ORDER BY
+    ("idx_sensor_data_timestamp_utc", "(timestamp_utc)"),
+    // Composite indexes for a device or mesh over a time range
+    (
+        "idx_sensor_data_device_timestamp",
+        "(device_id, timestamp_utc)",
+    ),
+    ("idx_sensor_data_mesh_timestamp", "(mesh_id, timestamp_utc)"),
+    // Block-range index: tiny, and serves range scans over time-ordered history
+    (
+        "idx_sensor_data_timestamp_brin",
+        "USING brin (timestamp_utc)",
+    ),
+    // Key-existence index for `extra_key` filters
+    ("idx_sensor_data_extra", "USING gin (extra)"),
+    // Pattern indexes for `LIKE 'prefix%'` filters, whatever the collation
+    (
+        "idx_sensor_data_device_prefix",
+        "(device_id text_pattern_ops)",
+    ),
+    ("idx_sensor_data_mesh_prefix", "(mesh_id text_pattern_ops)"),
+];
 
 // ---
 
 /// Create or update the database schema (idempotent).
 ///
-/// Creates the `sensor_data` table for transformed readings and `mesh_summary`
-/// table for aggregations. Also creates indexes for query optimization:
-/// - Single-column indexes: `mesh_id`, `device_id`, `timestamp_utc`
+/// Creates the `sensor_data` table for transformed readings, the
+/// `mesh_summary` view over the running per-mesh `mesh_totals` for
+/// aggregations (replacing the table of older versions),
+/// `device_mesh_assignments` for reassignment history, `devices` and
+/// `meshes` for operator-maintained metadata, `device_calibration` for
+/// per-device offsets, `alert_events` for alerts linked to
+/// their readings, `source_conflicts` for readings stored by several
+/// sources, `events` for the lifecycle log, and the `sensor_data_hourly`/`sensor_data_daily`
+/// rollups with the trigger marking their out-of-date hours (see
+/// `rollups.rs`), adding the `source`, `attributes`, `duplicate_of`,
+/// `latitude`/`longitude`, `extra`, `raw_temperature_c`/`raw_humidity` and
+/// `quality`/`quality_flags` columns to existing tables. Records a
+/// `migration_applied` event when the schema was created from scratch.
+/// Also creates the [`SENSOR_DATA_INDEXES`] for query optimization:
+/// - Single-column indexes: `mesh_id`, `device_id`, `timestamp_utc`, `source`
 /// - Composite indexes: `(device_id, timestamp_utc)`, `(mesh_id, timestamp_utc)`
+/// - A BRIN index on `timestamp_utc` for range scans over long history
+/// - A GIN index on `extra` for `extra_key` filters
+/// - `text_pattern_ops` indexes on `device_id` and `mesh_id` for prefix filters
+///
+/// Those are built in the migration transaction, which blocks writes to
+/// `sensor_data` while a new index is built on a large table. With
+/// `SCHEMA_INDEXES_CONCURRENTLY=true` they are built after it instead, with
+/// [`create_index_concurrently`].
+///
+/// With `partition_by_month`, a new `sensor_data` is created partitioned by
+/// month (see `partitions.rs`); `alert_events` and `source_conflicts` then
+/// don't reference it by foreign key, since Postgres can't enforce one.
 ///
 /// Safe to call on every startup; no-op if objects already exist.
 ///
 /// Errors are propagated if any SQL execution fails.
-pub async fn create_schema(pool: &PgPool) -> Result<()> {
+pub async fn create_schema(pool: &PgPool, cfg: &Config) -> Result<()> {
     // ---
+    let partition_by_month = cfg.partitioning.is_some();
+    let indexes_concurrently = cfg.schema_indexes_concurrently;
+    let fresh: bool = sqlx::query_scalar("SELECT to_regclass('sensor_data') IS NULL")
+        .fetch_one(pool)
+        .await?;
+
     let mut tx = pool.begin().await?;
 
+    // Migrations on a large table may legitimately outlast DB_STATEMENT_TIMEOUT_MS
+    sqlx::query("SET LOCAL statement_timeout = 0")
+        .execute(&mut *tx)
+        .await?;
+
+    let partitioned = if partition_by_month {
+        create_partitioned_table(&mut tx).await?;
+        true
+    } else {
+        let partitioned = is_partitioned(&mut tx).await?;
+        if partitioned {
+            tracing::warn!(
+                "sensor_data is partitioned but PARTITION_BY_MONTH is off; \
+                 new months go to the default partition"
+            );
+        }
+        partitioned
+    };
+    let reading_ref = if partitioned {
+        ""
+    } else {
+        "REFERENCES sensor_data (id) ON DELETE CASCADE"
+    };
+
     // Core table for transformed readings served by `/sql/readings`
     sqlx::query(
         r#"
@@ -41,68 +131,665 @@ pub async fn create_schema(pool: &PgPool) -> Result<()> {
     .execute(&mut *tx)
     .await?;
 
-    // Summary table for mesh aggregations
+    // Upstream source tag; rows ingested before multi-source support are 'default'
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'default';
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Fields attached by enrichers at ingest, keyed by enricher name
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS attributes JSONB NOT NULL DEFAULT '{}';
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Position reported by mobile sensors; NULL for readings of fixed devices
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS latitude  DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Numeric upstream fields beyond temperature and humidity, keyed by name
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS extra JSONB NOT NULL DEFAULT '{}';
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Reported values of readings stored with a device calibration applied
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS raw_temperature_c REAL,
+            ADD COLUMN IF NOT EXISTS raw_humidity      REAL;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Data quality score and flags computed at ingest (see `quality.rs`)
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS quality       REAL,
+            ADD COLUMN IF NOT EXISTS quality_flags TEXT[] NOT NULL DEFAULT '{}';
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Preferred copy of a reading also stored by other sources (see
+    // `ingest::reconcile_sources`); NULL for the copy that counts
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS duplicate_of INTEGER
+                REFERENCES sensor_data (id) ON DELETE SET NULL;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Tombstone of a soft-deleted reading (see `routes/delete.rs`); every read
+    // path skips readings that have one
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Manual corrections (see `routes/corrections.rs`): invalid readings stay
+    // listed but count toward no summary or alert, and `corrections` keeps
+    // each edit's original values and editor
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS invalid     BOOLEAN NOT NULL DEFAULT false,
+            ADD COLUMN IF NOT EXISTS corrections JSONB   NOT NULL DEFAULT '[]';
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // One row per upstream fetch or client push (see ingest.rs); every reading
+    // stored since keeps the batch that produced it
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ingest_batches (
+            id           BIGSERIAL   PRIMARY KEY,
+            source       TEXT        NOT NULL,
+            started_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+            finished_at  TIMESTAMPTZ,
+            pages        INTEGER     NOT NULL DEFAULT 0,
+            fetched      INTEGER     NOT NULL DEFAULT 0,
+            rows         INTEGER     NOT NULL DEFAULT 0,
+            errors       INTEGER     NOT NULL DEFAULT 0,
+            error        TEXT
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_ingest_batches_source_started
+            ON ingest_batches (source, started_at);
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        ALTER TABLE sensor_data
+            ADD COLUMN IF NOT EXISTS ingest_batch_id BIGINT REFERENCES ingest_batches (id);
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // What started a batch, and when it last moved; running batches whose
+    // progressed_at falls behind are wedged
+    sqlx::query(
+        r#"
+        ALTER TABLE ingest_batches
+            ADD COLUMN IF NOT EXISTS trigger       TEXT,
+            ADD COLUMN IF NOT EXISTS progressed_at TIMESTAMPTZ NOT NULL DEFAULT now();
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Device-to-mesh reassignment history; a reading belongs to the mesh whose
+    // assignment has the latest effective_from <= the reading's timestamp
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS device_mesh_assignments (
+            device_id       TEXT        NOT NULL,
+            mesh_id         TEXT        NOT NULL,
+            effective_from  TIMESTAMPTZ NOT NULL,
+            assigned_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (device_id, effective_from)
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Operator-maintained device metadata (see routes/devices.rs)
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS mesh_summary (
-            mesh_id               TEXT PRIMARY KEY,
-            avg_temperature_c     REAL NOT NULL,
-            avg_humidity          REAL NOT NULL,
-            reading_count         INTEGER NOT NULL
+        CREATE TABLE IF NOT EXISTS devices (
+            device_id     TEXT        PRIMARY KEY,
+            mesh_id       TEXT,
+            display_name  TEXT,
+            location      TEXT,
+            installed_on  DATE,
+            notes         TEXT,
+            updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
         );
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
-    // Basic indexes for common queries
     sqlx::query(
         r#"
-        CREATE INDEX IF NOT EXISTS idx_sensor_data_mesh_id
-            ON sensor_data (mesh_id);
+        ALTER TABLE devices
+            ADD COLUMN IF NOT EXISTS latitude  DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
+    // Per-device offsets applied at ingest (see routes/admin.rs)
     sqlx::query(
         r#"
-        CREATE INDEX IF NOT EXISTS idx_sensor_data_device_id
-            ON sensor_data (device_id);
+        CREATE TABLE IF NOT EXISTS device_calibration (
+            device_id           TEXT        PRIMARY KEY,
+            temperature_offset  REAL        NOT NULL DEFAULT 0,
+            humidity_offset     REAL        NOT NULL DEFAULT 0,
+            updated_at          TIMESTAMPTZ NOT NULL DEFAULT now()
+        );
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
-    // Timestamp index for range queries
+    // Operator-maintained mesh metadata (see routes/meshes.rs)
     sqlx::query(
         r#"
-        CREATE INDEX IF NOT EXISTS idx_sensor_data_timestamp_utc
-            ON sensor_data (timestamp_utc);
+        CREATE TABLE IF NOT EXISTS meshes (
+            mesh_id     TEXT        PRIMARY KEY,
+            site_name   TEXT,
+            timezone    TEXT,
+            contact     TEXT,
+            updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+        );
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
-    // Composite indexes for combined filtering
+    // One row per alert condition on a stored reading, linked to the reading
+    sqlx::query(&format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS alert_events (
+            id           BIGSERIAL   PRIMARY KEY,
+            reading_id   INTEGER     NOT NULL {reading_ref},
+            kind         TEXT        NOT NULL,
+            device_id    TEXT        NOT NULL,
+            mesh_id      TEXT        NOT NULL,
+            occurred_at  TIMESTAMPTZ NOT NULL,
+            UNIQUE (reading_id, kind)
+        );
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?;
+
     sqlx::query(
         r#"
-        CREATE INDEX IF NOT EXISTS idx_sensor_data_device_timestamp
-            ON sensor_data (device_id, timestamp_utc);
+        CREATE INDEX IF NOT EXISTS idx_alert_events_occurred_at
+            ON alert_events (occurred_at);
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
+    // One row per duplicate copy of a reading, naming the copy preferred over it
+    sqlx::query(&format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS source_conflicts (
+            reading_id       INTEGER     PRIMARY KEY {reading_ref},
+            preferred_id     INTEGER     NOT NULL {reading_ref},
+            device_id        TEXT        NOT NULL,
+            timestamp_utc    TIMESTAMPTZ NOT NULL,
+            source           TEXT        NOT NULL,
+            preferred_source TEXT        NOT NULL,
+            values_differ    BOOLEAN     NOT NULL,
+            detected_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+        );
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    // Pipeline lifecycle event log (see events.rs)
     sqlx::query(
         r#"
-        CREATE INDEX IF NOT EXISTS idx_sensor_data_mesh_timestamp
-            ON sensor_data (mesh_id, timestamp_utc);
+        CREATE TABLE IF NOT EXISTS events (
+            id           BIGSERIAL   PRIMARY KEY,
+            occurred_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+            kind         TEXT        NOT NULL,
+            detail       JSONB       NOT NULL DEFAULT '{}'
+        );
         "#,
     )
     .execute(&mut *tx)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_events_occurred_at
+            ON events (occurred_at);
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Responses to keyed write requests, answered again on retry (see
+    // idempotency.rs); `status` stays NULL while the first attempt runs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            caller          TEXT        NOT NULL,
+            key             TEXT        NOT NULL,
+            request_digest  TEXT        NOT NULL,
+            status          SMALLINT,
+            content_type    TEXT,
+            body            BYTEA,
+            created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (caller, key)
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at
+            ON idempotency_keys (created_at);
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Hourly and daily rollups of sensor_data (see rollups.rs)
+    let rollups_fresh: bool = sqlx::query_scalar("SELECT to_regclass('rollup_dirty') IS NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+    for table in ["sensor_data_hourly", "sensor_data_daily"] {
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {table} (
+                device_id          TEXT             NOT NULL,
+                mesh_id            TEXT             NOT NULL,
+                bucket             TIMESTAMPTZ      NOT NULL,
+                reading_count      INTEGER          NOT NULL,
+                avg_temperature_c  DOUBLE PRECISION NOT NULL,
+                min_temperature_c  REAL             NOT NULL,
+                max_temperature_c  REAL             NOT NULL,
+                avg_humidity       DOUBLE PRECISION NOT NULL,
+                min_humidity       REAL             NOT NULL,
+                max_humidity       REAL             NOT NULL,
+                PRIMARY KEY (device_id, bucket, mesh_id)
+            );
+            "#
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS idx_{table}_bucket ON {table} (bucket)"
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS idx_{table}_mesh_bucket ON {table} (mesh_id, bucket)"
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Hours whose rollups are out of date, filled by the trigger below
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS rollup_dirty (
+            device_id  TEXT        NOT NULL,
+            hour       TIMESTAMPTZ NOT NULL,
+            marked_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (device_id, hour)
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Updating the mark (rather than skipping it) locks the row, so a refresh
+    // taking it waits for this writer's readings to commit
+    sqlx::query(&format!(
+        r#"
+        CREATE OR REPLACE FUNCTION mark_rollup_dirty() RETURNS trigger AS $$
+        BEGIN
+            INSERT INTO rollup_dirty (device_id, hour)
+            VALUES (NEW.device_id, date_bin('1 hour', NEW.timestamp_utc, {BUCKET_ORIGIN}))
+            ON CONFLICT (device_id, hour) DO UPDATE SET marked_at = now();
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql;
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE TRIGGER sensor_data_rollup_dirty
+            AFTER INSERT OR UPDATE OF duplicate_of, deleted_at, temperature_c, humidity, invalid
+            ON sensor_data
+            FOR EACH ROW EXECUTE FUNCTION mark_rollup_dirty();
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Readings stored before rollups existed are rolled up on the first refresh
+    if rollups_fresh {
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO rollup_dirty (device_id, hour)
+            SELECT DISTINCT device_id, date_bin('1 hour', timestamp_utc, {BUCKET_ORIGIN})
+            FROM sensor_data
+            "#
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Latest ingest per mesh, for `Last-Modified` (see routes/readings.rs);
+    // meshes stored before it existed count as ingested now
+    let ingest_fresh: bool = sqlx::query_scalar("SELECT to_regclass('mesh_ingest') IS NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mesh_ingest (
+            mesh_id           TEXT        PRIMARY KEY,
+            last_ingested_at  TIMESTAMPTZ NOT NULL
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION mark_mesh_ingest() RETURNS trigger AS $$
+        BEGIN
+            INSERT INTO mesh_ingest (mesh_id, last_ingested_at)
+            VALUES (NEW.mesh_id, clock_timestamp())
+            ON CONFLICT (mesh_id) DO UPDATE SET last_ingested_at = EXCLUDED.last_ingested_at;
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE TRIGGER sensor_data_mesh_ingest
+            AFTER INSERT OR UPDATE OF deleted_at, corrections ON sensor_data
+            FOR EACH ROW EXECUTE FUNCTION mark_mesh_ingest();
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // One announcement per changing statement, for response caches (see
+    // response_cache.rs); Postgres folds repeats within a transaction
+    sqlx::query(&format!(
+        r#"
+        CREATE OR REPLACE FUNCTION notify_readings_changed() RETURNS trigger AS $$
+        BEGIN
+            PERFORM pg_notify('{READINGS_CHANNEL}', '');
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql;
+        "#
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE TRIGGER sensor_data_readings_changed
+            AFTER INSERT OR UPDATE OR DELETE ON sensor_data
+            FOR EACH STATEMENT EXECUTE FUNCTION notify_readings_changed();
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if ingest_fresh {
+        sqlx::query(
+            r#"
+            INSERT INTO mesh_ingest (mesh_id, last_ingested_at)
+            SELECT DISTINCT mesh_id, now() FROM sensor_data
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Running per-mesh totals behind mesh_summary, adjusted by each rollup
+    // refresh. Older databases kept mesh_summary as a table or materialized view.
+    let totals_fresh: bool = sqlx::query_scalar("SELECT to_regclass('mesh_totals') IS NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mesh_totals (
+            mesh_id          TEXT             PRIMARY KEY,
+            reading_count    BIGINT           NOT NULL,
+            temperature_sum  DOUBLE PRECISION NOT NULL,
+            humidity_sum     DOUBLE PRECISION NOT NULL
+        );
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Hours already rolled up count toward the totals now; marked ones are
+    // added by the next refresh
+    if totals_fresh && !rollups_fresh {
+        sqlx::query(
+            r#"
+            INSERT INTO mesh_totals (mesh_id, reading_count, temperature_sum, humidity_sum)
+            SELECT mesh_id, SUM(reading_count),
+                   SUM(avg_temperature_c * reading_count), SUM(avg_humidity * reading_count)
+            FROM sensor_data_hourly
+            GROUP BY 1
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        DO $$
+        BEGIN
+            CASE (SELECT relkind FROM pg_class WHERE oid = to_regclass('mesh_summary'))
+                WHEN 'r' THEN DROP TABLE mesh_summary;
+                WHEN 'm' THEN DROP MATERIALIZED VIEW mesh_summary;
+                ELSE NULL;
+            END CASE;
+        END
+        $$;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE VIEW mesh_summary AS
+        SELECT
+            mesh_id,
+            (temperature_sum / reading_count)::REAL AS avg_temperature_c,
+            (humidity_sum / reading_count)::REAL AS avg_humidity,
+            reading_count::INTEGER AS reading_count
+        FROM mesh_totals;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if !indexes_concurrently {
+        for (name, def) in SENSOR_DATA_INDEXES {
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS {name} ON sensor_data {def}"
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
     tx.commit().await?;
+
+    if indexes_concurrently {
+        create_indexes_concurrently(pool).await?;
+    }
+
+    if fresh {
+        record_event(
+            pool,
+            EventKind::MigrationApplied,
+            json!({ "migration": "initial_schema" }),
+        )
+        .await;
+    }
     Ok(())
 }
+
+/// Build the [`SENSOR_DATA_INDEXES`] without blocking writes, on a
+/// connection of its own with `statement_timeout` lifted.
+async fn create_indexes_concurrently(pool: &PgPool) -> Result<()> {
+    // ---
+    let mut conn = pool.acquire().await?.detach();
+    conn.execute("SET statement_timeout = 0").await?;
+    let mut built = Ok(());
+    for (name, def) in SENSOR_DATA_INDEXES {
+        built = create_index_concurrently(&mut conn, name, def).await;
+        if built.is_err() {
+            break;
+        }
+    }
+    let _ = conn.close().await;
+    Ok(built?)
+}
+
+/// Create index `name` on `sensor_data` with `CONCURRENTLY`, unless a valid
+/// one exists. `def` follows `ON sensor_data`, e.g. `(device_id, timestamp_utc)`.
+///
+/// CONCURRENTLY can't run in a transaction, so `conn` must not be in one. An
+/// invalid index left by an interrupted build is dropped and rebuilt. A
+/// partitioned `sensor_data` can't be indexed concurrently as a whole: the
+/// index is declared on the parent alone, then built concurrently on each
+/// partition and attached. Partitions created later get it automatically.
+pub async fn create_index_concurrently(
+    conn: &mut PgConnection,
+    name: &str,
+    def: &str,
+) -> Result<(), sqlx::Error> {
+    // ---
+    let valid: Option<bool> =
+        sqlx::query_scalar("SELECT indisvalid FROM pg_index WHERE indexrelid = to_regclass($1)")
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await?;
+    if valid == Some(true) {
+        return Ok(());
+    }
+
+    if is_partitioned(conn).await? {
+        conn.execute(
+            format!("CREATE INDEX IF NOT EXISTS {name} ON ONLY sensor_data {def}").as_str(),
+        )
+        .await?;
+        let suffix = name.strip_prefix("idx_sensor_data_").unwrap_or(name);
+        for partition in list_partitions(&mut *conn).await? {
+            let child = format!("{partition}_{suffix}");
+            conn.execute(
+                format!("CREATE INDEX CONCURRENTLY IF NOT EXISTS {child} ON {partition} {def}")
+                    .as_str(),
+            )
+            .await?;
+            conn.execute(format!("ALTER INDEX {name} ATTACH PARTITION {child}").as_str())
+                .await?;
+        }
+        return Ok(());
+    }
+
+    if valid == Some(false) {
+        tracing::warn!("Rebuilding invalid index {}", name);
+        conn.execute(format!("DROP INDEX CONCURRENTLY {name}").as_str())
+            .await?;
+    }
+    conn.execute(format!("CREATE INDEX CONCURRENTLY {name} ON sensor_data {def}").as_str())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn partition_index_names_fit_postgres_identifiers() {
+        // ---
+        for (name, _) in SENSOR_DATA_INDEXES {
+            let suffix = name.strip_prefix("idx_sensor_data_");
+            assert!(suffix.is_some(), "{name} lacks the idx_sensor_data_ prefix");
+            let child = format!("sensor_data_y2026m10_{}", suffix.unwrap());
+            assert!(child.len() <= 63, "{child} would be truncated");
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! External secrets providers.
+//!
+//! A setting whose value is `<backend>:<path>#<field>` is looked up in a
+//! secrets backend before the configuration is loaded, e.g.
+//! `SENSOR_API_1_TOKEN=vault:kv/data/sensorflow#plant_a_token` or, with
+//! dynamic database credentials, `DATABASE_USER` and `DATABASE_PASSWORD`
+//! both from `vault:database/creds/sensorflow`. Each path is read once per
+//! load, so fields of one dynamic credential always belong together.
+//!
+//! [`Vault`] (HashiCorp Vault's HTTP API: KV v1 and v2 and dynamic secrets
+//! engines) is the one backend built in, enabled by `VAULT_ADDR`; another,
+//! such as AWS Secrets Manager, is a [`SecretsBackend`] implementation.
+//!
+//! Secrets with a lease are read again, through a reload (see `reload.rs`),
+//! when two thirds of the shortest lease have passed.
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+
+use crate::config::setting;
+
+/// Longest a backend request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// ---
+
+/// The fields of a secret and how long they may be used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Secret {
+    // ---
+    pub fields: HashMap<String, String>,
+
+    /// `None` when the secret isn't leased (e.g. a KV entry).
+    pub lease: Option<Duration>,
+}
+
+/// A place secrets are read from.
+pub trait SecretsBackend: Send + Sync {
+    // ---
+    /// Prefix of the references it resolves, e.g. `vault`.
+    fn scheme(&self) -> &str;
+
+    /// Read the secret at `path`.
+    fn fetch<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Secret, String>>;
+}
+
+/// HashiCorp Vault, authenticated by `VAULT_TOKEN` (or `VAULT_TOKEN_FILE`, e.g.
+/// kept fresh by Vault Agent), read again for every request.
+pub struct Vault {
+    // ---
+    addr: String,
+    namespace: Option<String>,
+    client: reqwest::Client,
+}
+
+/// The configured backends; clones share them.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    // ---
+    backends: Vec<Arc<dyn SecretsBackend>>,
+}
+
+impl Vault {
+    // ---
+    /// From `VAULT_ADDR` and `VAULT_NAMESPACE`; `None` when `VAULT_ADDR` is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        // ---
+        let Some(addr) = setting("VAULT_ADDR")? else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| anyhow!("Vault client: {e}"))?;
+        Ok(Some(Vault {
+            addr: addr.trim_end_matches('/').to_string(),
+            namespace: setting("VAULT_NAMESPACE")?,
+            client,
+        }))
+    }
+}
+
+impl SecretsBackend for Vault {
+    // ---
+    fn scheme(&self) -> &str {
+        // ---
+        "vault"
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Secret, String>> {
+        // ---
+        Box::pin(async move {
+            // ---
+            let token = setting("VAULT_TOKEN")
+                .map_err(|e| e.to_string())?
+                .ok_or("VAULT_TOKEN is not set")?;
+            let mut request = self
+                .client
+                .get(format!("{}/v1/{}", self.addr, path.trim_start_matches('/')))
+                .header("x-vault-token", token);
+            if let Some(namespace) = &self.namespace {
+                request = request.header("x-vault-namespace", namespace);
+            }
+            let body: Value = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.without_url().to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            vault_secret(&body)
+        })
+    }
+}
+
+/// The secret in a Vault read response; KV v2 nests the fields one level deeper.
+fn vault_secret(body: &Value) -> Result<Secret, String> {
+    // ---
+    let data = &body["data"];
+    let fields = match (data.get("data"), data.get("metadata")) {
+        (Some(Value::Object(fields)), Some(_)) => fields,
+        _ => data.as_object().ok_or("response has no data")?,
+    };
+    let fields = fields
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => return None,
+            };
+            Some((name.clone(), value))
+        })
+        .collect();
+    let lease = body["lease_duration"]
+        .as_u64()
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    Ok(Secret { fields, lease })
+}
+
+impl Secrets {
+    // ---
+    /// The backends the environment configures (none without `VAULT_ADDR`).
+    pub fn from_env() -> Result<Self> {
+        // ---
+        let mut backends: Vec<Arc<dyn SecretsBackend>> = Vec::new();
+        if let Some(vault) = Vault::from_env()? {
+            backends.push(Arc::new(vault));
+        }
+        Ok(Secrets { backends })
+    }
+
+    /// Add to `overlay` (see [`load_with_overlay`]) the value of every
+    /// variable referring to a secret; returns it with the shortest lease of
+    /// the secrets read, if any is leased.
+    ///
+    /// [`load_with_overlay`]: crate::config::load_with_overlay
+    pub async fn resolve(
+        &self,
+        mut overlay: HashMap<String, Option<String>>,
+    ) -> Result<(HashMap<String, Option<String>>, Option<Duration>)> {
+        // ---
+        let mut values: HashMap<String, String> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        for (name, value) in &overlay {
+            match value {
+                Some(value) => values.insert(name.clone(), value.clone()),
+                None => values.remove(name),
+            };
+        }
+
+        let mut references: Vec<(String, &dyn SecretsBackend, String, String)> = Vec::new();
+        for (name, value) in values {
+            let Some((scheme, reference)) = value.split_once(':') else {
+                continue;
+            };
+            let Some(backend) = self.backends.iter().find(|b| b.scheme() == scheme) else {
+                if scheme == "vault" {
+                    return Err(anyhow!("{name} refers to Vault, but VAULT_ADDR is not set"));
+                }
+                continue;
+            };
+            let (path, field) = parse_reference(reference)
+                .ok_or_else(|| anyhow!("Invalid {name}: expected {scheme}:<path>#<field>"))?;
+            references.push((name, backend.as_ref(), path.into(), field.into()));
+        }
+
+        let mut read: HashMap<(String, String), Secret> = HashMap::new();
+        let mut lease: Option<Duration> = None;
+        for (name, backend, path, field) in references {
+            let key = (backend.scheme().to_string(), path);
+            if !read.contains_key(&key) {
+                let secret = backend
+                    .fetch(&key.1)
+                    .await
+                    .map_err(|e| anyhow!("Reading {name} from {}:{}: {e}", key.0, key.1))?;
+                lease = match (lease, secret.lease) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                read.insert(key.clone(), secret);
+            }
+            let value = read[&key].fields.get(&field).cloned().ok_or_else(|| {
+                anyhow!("Invalid {name}: {}:{} has no field {field:?}", key.0, key.1)
+            })?;
+            overlay.insert(name, Some(value));
+        }
+
+        if !read.is_empty() {
+            tracing::info!("Read {} secret(s) from the secrets backend", read.len());
+        }
+        Ok((overlay, lease))
+    }
+}
+
+/// `<path>#<field>`, both non-empty.
+fn parse_reference(reference: &str) -> Option<(&str, &str)> {
+    // ---
+    reference
+        .rsplit_once('#')
+        .filter(|(path, field)| !path.is_empty() && !field.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn vault_responses_yield_their_fields_and_lease() {
+        // ---
+        let kv2 = serde_json::json!({
+            "lease_duration": 0,
+            "data": { "data": { "token": "t0k3n", "port": 5432 }, "metadata": { "version": 3 } }
+        });
+        let secret = vault_secret(&kv2).unwrap();
+        assert_eq!(secret.fields["token"], "t0k3n");
+        assert_eq!(secret.fields["port"], "5432");
+        assert_eq!(secret.lease, None);
+
+        let dynamic = serde_json::json!({
+            "lease_id": "database/creds/sensorflow/abc",
+            "lease_duration": 3600,
+            "data": { "username": "v-sensorflow-x1", "password": "p4ss" }
+        });
+        let secret = vault_secret(&dynamic).unwrap();
+        assert_eq!(secret.fields["username"], "v-sensorflow-x1");
+        assert_eq!(secret.lease, Some(Duration::from_secs(3600)));
+
+        assert!(vault_secret(&serde_json::json!({ "errors": [] })).is_err());
+    }
+
+    #[test]
+    fn references_need_a_path_and_a_field() {
+        // ---
+        assert_eq!(
+            parse_reference("kv/data/sensorflow#token"),
+            Some(("kv/data/sensorflow", "token"))
+        );
+        assert_eq!(parse_reference("kv/data/sensorflow"), None);
+        assert_eq!(parse_reference("kv/data/sensorflow#"), None);
+        assert_eq!(parse_reference("#token"), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Slow query logging.
+//!
+//! Query sites wrap their database call in [`timed`], naming the query and
+//! its filter shape: which filters were used, never their values (e.g.
+//! `device_id,timestamp_utc`). With `SLOW_QUERY_MS` set, a call taking at
+//! least that long is logged at `warn` and counted in
+//! `sensorflow_slow_queries_total{query, filters}`, so missing indexes show
+//! up in production as a growing series for one filter shape.
+use std::{
+    future::Future,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::SLOW_QUERIES_TOTAL;
+
+static THRESHOLD: OnceLock<Duration> = OnceLock::new();
+
+// ---
+
+/// Enable slow query logging for calls taking at least `threshold`; set once at startup.
+pub fn set_threshold(threshold: Duration) {
+    // ---
+    let _ = THRESHOLD.set(threshold);
+}
+
+/// Await `query_fut`, reporting it if slow (see module docs).
+pub async fn timed<F: Future>(query: &'static str, filters: &str, query_fut: F) -> F::Output {
+    // ---
+    let Some(threshold) = THRESHOLD.get().copied() else {
+        return query_fut.await;
+    };
+
+    let started = Instant::now();
+    let out = query_fut.await;
+    let elapsed = started.elapsed();
+    if elapsed >= threshold {
+        tracing::warn!(
+            "Slow query {} [{}] took {} ms (SLOW_QUERY_MS={})",
+            query,
+            filters,
+            elapsed.as_millis(),
+            threshold.as_millis()
+        );
+        metrics::counter!(SLOW_QUERIES_TOTAL, "query" => query, "filters" => filters.to_string())
+            .increment(1);
+    }
+    out
+}
+
+/// Filter shape label for `filters`: comma-joined, or `none` when empty.
+pub fn filter_shape(filters: &[&str]) -> String {
+    // ---
+    if filters.is_empty() {
+        "none".into()
+    } else {
+        filters.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn shapes_name_filters_not_values() {
+        // ---
+        assert_eq!(filter_shape(&[]), "none");
+        assert_eq!(
+            filter_shape(&["device_id", "timestamp_utc"]),
+            "device_id,timestamp_utc"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Exponentially weighted moving averages for `smooth=ewma` responses.
+//!
+//! Smoothing is computed server-side so every dashboard gets the same
+//! series: the first value of a series is its own average, and each later
+//! one moves the average `alpha` of the way toward it. Series are smoothed
+//! oldest first, per device, over the rows of one response only, so a page
+//! of readings starts afresh from its oldest reading.
+use std::collections::HashMap;
+
+use crate::{SensorReading, Smoothed};
+
+/// Smoothing factor used when `alpha` is not given.
+pub const DEFAULT_ALPHA: f32 = 0.3;
+
+// ---
+
+/// Running EWMA of one series.
+#[derive(Debug, Clone, Copy)]
+pub struct Ewma {
+    // ---
+    alpha: f32,
+    average: Option<f32>,
+}
+
+impl Ewma {
+    // ---
+    /// `alpha` in (0, 1]: the weight of each new value.
+    pub fn new(alpha: f32) -> Self {
+        // ---
+        Self {
+            alpha,
+            average: None,
+        }
+    }
+
+    /// Add `value` and return the average including it.
+    pub fn update(&mut self, value: f32) -> f32 {
+        // ---
+        let average = match self.average {
+            Some(prev) => prev + self.alpha * (value - prev),
+            None => value,
+        };
+        self.average = Some(average);
+        average
+    }
+}
+
+/// Set `smoothed` on each of `readings` (in any order, newest first by
+/// default) from its device's earlier readings in the slice.
+pub fn smooth_readings(readings: &mut [SensorReading], alpha: f32) {
+    // ---
+    // Oldest first; reversing first keeps the default order's ties in ID order
+    let mut chronological: Vec<usize> = (0..readings.len()).rev().collect();
+    chronological.sort_by_key(|&i| readings[i].timestamp_utc);
+
+    let mut series: HashMap<String, (Ewma, Ewma)> = HashMap::new();
+    for i in chronological {
+        let r = &mut readings[i];
+        let (temperature, humidity) = series
+            .entry(r.device_id.clone())
+            .or_insert((Ewma::new(alpha), Ewma::new(alpha)));
+        r.smoothed = Some(Smoothed {
+            temperature_c: temperature.update(r.temperature_c),
+            humidity: humidity.update(r.humidity),
+        });
+    }
+}
+
+/// The smoothing factor requested by `smooth` and `alpha`: `Some(None)` when
+/// not smoothing, `None` when the parameters are invalid (an unknown
+/// `smooth`, `alpha` outside (0, 1], or `alpha` without `smooth`).
+pub fn ewma_alpha(smooth: Option<&str>, alpha: Option<f32>) -> Option<Option<f32>> {
+    // ---
+    match (smooth.map(str::trim), alpha) {
+        (None, None) => Some(None),
+        (Some("ewma"), None) => Some(Some(DEFAULT_ALPHA)),
+        (Some("ewma"), Some(a)) if a > 0.0 && a <= 1.0 => Some(Some(a)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn averages_move_alpha_toward_each_value() {
+        // ---
+        let mut ewma = Ewma::new(0.5);
+        let got: Vec<f32> = [10.0, 20.0, 20.0, 0.0]
+            .into_iter()
+            .map(|v| ewma.update(v))
+            .collect();
+        assert_eq!(got, [10.0, 15.0, 17.5, 8.75]);
+
+        let mut unsmoothed = Ewma::new(1.0);
+        assert_eq!(unsmoothed.update(3.0), 3.0);
+        assert_eq!(unsmoothed.update(7.0), 7.0);
+    }
+
+    #[test]
+    fn smoothing_parameters_are_validated() {
+        // ---
+        assert_eq!(ewma_alpha(None, None), Some(None));
+        assert_eq!(ewma_alpha(Some("ewma"), None), Some(Some(DEFAULT_ALPHA)));
+        assert_eq!(ewma_alpha(Some("ewma"), Some(1.0)), Some(Some(1.0)));
+        assert_eq!(ewma_alpha(Some("ewma"), Some(0.0)), None);
+        assert_eq!(ewma_alpha(Some("median"), None), None);
+        assert_eq!(ewma_alpha(None, Some(0.3)), None);
+    }
+}
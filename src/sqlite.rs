@@ -0,0 +1,915 @@
+//! SQLite storage for single-node and edge deployments.
+//!
+//! A `DATABASE_URL` starting with `sqlite:` (e.g. `sqlite:///var/lib/sensorflow/readings.db`,
+//! or `sqlite::memory:` for a store that lives as long as the process) keeps
+//! readings in a SQLite database instead of Postgres: [`SqliteReadings`] is
+//! the [`ReadingsRepository`] on it, and `serve` mounts the edge router (see
+//! `routes/mod.rs`) over it. The database file is created when missing and
+//! runs in WAL mode, so reads don't wait for an ingest.
+//!
+//! Such a deployment serves what the repository covers, with the handlers of
+//! a Postgres deployment: readings, their count and single readings under
+//! `/v1/readings` (and the deprecated `/sql/readings`) with every filter,
+//! sort, `fields` and cursor option, the mesh summary stream, pushes to
+//! `POST /v1/readings`, and upstream ingest of every source at startup and
+//! then every `interval_secs`. Authentication, rate limits, CORS, TLS and the
+//! response cache work as on Postgres, along with `/health`, `/version` and
+//! `/metrics`. Everything else needs Postgres: aggregates, alerts,
+//! corrections, deletes, the device and mesh registries (so no calibrations
+//! or mesh timezones, and `include=device` attaches nothing), ingest batches,
+//! idempotency keys and the admin routes.
+//!
+//! Each write records when its meshes were last ingested in `mesh_ingest`,
+//! for `Last-Modified`, and publishes their new aggregates to the
+//! [`SummaryFeed`] the stream reads: there is one process, so no listener.
+//!
+//! The schema is [`MIGRATIONS`], applied in order with the count applied kept
+//! in `PRAGMA user_version`: SQLite has no `ADD COLUMN IF NOT EXISTS`, so
+//! unlike `schema.rs` the statements can't simply be re-run. Queries are
+//! built as in `repository.rs`, adapted to SQLite:
+//! - Lists of devices, meshes or statuses are `IN (...)` lists rather than arrays.
+//! - Timestamps are stored as RFC 3339 text in UTC to the microsecond, with a
+//!   fixed width, so comparing them as text compares them in time.
+//! - `extra_key` looks the key up with `json_each`.
+//! - `sample` keeps the rows whose ID hashes below the fraction, rather than
+//!   sampling table blocks; each request still gets the same rows.
+//! - `bbox` matches by the reading's own position only, as there is no
+//!   device registry.
+//!
+//! `SOURCE_PRIORITY` isn't applied: copies of a reading from several sources
+//! are all stored and listed, and mesh summaries count the first one stored.
+use std::{str::FromStr, sync::Arc};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::future::BoxFuture;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow},
+    QueryBuilder, Row, Sqlite, SqlitePool,
+};
+
+use crate::repository::{like_prefix, Readings};
+use crate::{
+    assess_batch, AlertFilter, DeviceInfo, Enrichment, MeshAggregate, RawSensorReading,
+    ReadingsCount, ReadingsCursor, ReadingsFilter, ReadingsPage, ReadingsRepository, SensorReading,
+    Smoothed, Sort, SummaryFeed, SummaryUpdate,
+};
+
+/// Schema changes, oldest first; append new ones, never edit applied ones.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE sensor_data (
+        id                INTEGER PRIMARY KEY AUTOINCREMENT,
+        source            TEXT    NOT NULL DEFAULT 'default',
+        mesh_id           TEXT    NOT NULL,
+        device_id         TEXT    NOT NULL,
+        timestamp_utc     TEXT    NOT NULL,
+        temperature_c     REAL    NOT NULL,
+        humidity          REAL    NOT NULL,
+        status            TEXT    NOT NULL DEFAULT '',
+        temperature_alert BOOLEAN NOT NULL DEFAULT 0,
+        humidity_alert    BOOLEAN NOT NULL DEFAULT 0,
+        attributes        TEXT    NOT NULL DEFAULT '{}',
+        raw_temperature_c REAL,
+        raw_humidity      REAL,
+        latitude          REAL,
+        longitude         REAL,
+        extra             TEXT    NOT NULL DEFAULT '{}',
+        quality           REAL,
+        quality_flags     TEXT    NOT NULL DEFAULT '[]',
+        invalid           BOOLEAN NOT NULL DEFAULT 0,
+        corrections       TEXT    NOT NULL DEFAULT '[]',
+        ingest_batch_id   INTEGER,
+        deleted_at        TEXT,
+        UNIQUE (source, device_id, timestamp_utc)
+    );
+    CREATE INDEX idx_sensor_data_timestamp ON sensor_data (timestamp_utc);
+    CREATE INDEX idx_sensor_data_device_ts ON sensor_data (device_id, timestamp_utc);
+    CREATE INDEX idx_sensor_data_mesh_ts ON sensor_data (mesh_id, timestamp_utc);
+    "#,
+    // Meshes stored before ingests were recorded count as ingested now
+    r#"
+    CREATE TABLE mesh_ingest (
+        mesh_id          TEXT PRIMARY KEY,
+        last_ingested_at TEXT NOT NULL
+    );
+    INSERT INTO mesh_ingest (mesh_id, last_ingested_at)
+    SELECT DISTINCT mesh_id, strftime('%Y-%m-%dT%H:%M:%S.000000Z', 'now') FROM sensor_data;
+    "#,
+];
+
+/// Every column of a reading, as loaded without `fields`.
+const READING_COLUMNS: &str = "id, mesh_id, device_id, timestamp_utc, \
+     temperature_c, humidity, status, temperature_alert, humidity_alert, attributes, \
+     raw_temperature_c, raw_humidity, latitude, longitude, extra, \
+     quality, quality_flags, invalid, corrections, ingest_batch_id";
+
+/// `sample` keeps rows whose `id * SAMPLE_HASH` modulo [`SAMPLE_BUCKETS`] is
+/// below the fraction of the buckets; the multiplier is coprime with the
+/// bucket count, so every run of that many IDs is spread over all of them.
+const SAMPLE_HASH: i64 = 2_654_435_761;
+const SAMPLE_BUCKETS: i64 = 1_000_000;
+
+// ---
+
+/// Whether `url` (a `DATABASE_URL`) names a SQLite database.
+pub fn is_sqlite_url(url: &str) -> bool {
+    // ---
+    url.starts_with("sqlite:")
+}
+
+/// Open the SQLite database at `url`, creating it when missing, and bring its
+/// schema up to date.
+///
+/// `sqlite::memory:` gets a single connection that is never closed: the
+/// database lives in it.
+pub async fn open(url: &str, max_connections: u32) -> Result<SqlitePool, sqlx::Error> {
+    // ---
+    let options = SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+    let pool = if in_memory {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?
+    } else {
+        SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?
+    };
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Apply the [`MIGRATIONS`] the database hasn't had yet; returns how many ran.
+async fn migrate(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    // ---
+    let mut tx = pool.begin().await?;
+    let applied: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(&mut *tx)
+        .await?;
+    let pending = MIGRATIONS.get(applied as usize..).unwrap_or_default();
+    for migration in pending {
+        sqlx::raw_sql(migration).execute(&mut *tx).await?;
+    }
+    if !pending.is_empty() {
+        // PRAGMA takes no bound parameters
+        sqlx::raw_sql(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))
+            .execute(&mut *tx)
+            .await?;
+        tracing::info!(
+            "Applied {} SQLite schema migration(s), now at version {}",
+            pending.len(),
+            MIGRATIONS.len()
+        );
+    }
+    tx.commit().await?;
+    Ok(pending.len())
+}
+
+/// [`ReadingsRepository`] on SQLite; see the module docs.
+pub struct SqliteReadings {
+    // ---
+    pool: SqlitePool,
+
+    /// Applied to every reading stored.
+    enrichment: Arc<Enrichment>,
+
+    /// Where the aggregates of meshes written to are published.
+    feed: SummaryFeed,
+}
+
+impl SqliteReadings {
+    // ---
+    pub fn new(pool: SqlitePool, enrichment: Arc<Enrichment>, feed: SummaryFeed) -> Self {
+        // ---
+        Self {
+            pool,
+            enrichment,
+            feed,
+        }
+    }
+
+    /// Score, enrich and insert `readings` in one transaction, skipping those
+    /// `source` already stored (same device and timestamp), then record and
+    /// publish the meshes that gained readings.
+    async fn store(&self, source: &str, readings: &[RawSensorReading]) -> Result<u64, sqlx::Error> {
+        // ---
+        let mut transformed: Vec<_> = readings.iter().map(|r| r.to_transformed()).collect();
+        assess_batch(readings, &mut transformed, Utc::now());
+        // Before the transaction: enrichers may call out over HTTP
+        for t in &mut transformed {
+            self.enrichment.apply(t).await;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        let mut meshes: Vec<String> = Vec::new();
+        for t in &transformed {
+            let stored_now = sqlx::query(
+                r#"
+                INSERT INTO sensor_data (
+                    source, mesh_id, device_id, timestamp_utc,
+                    temperature_c, humidity, status,
+                    temperature_alert, humidity_alert, attributes,
+                    latitude, longitude, extra, raw_temperature_c, raw_humidity,
+                    quality, quality_flags
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (source, device_id, timestamp_utc) DO NOTHING
+                "#,
+            )
+            .bind(source)
+            .bind(&t.mesh_id)
+            .bind(&t.device_id)
+            .bind(stored(t.timestamp_utc))
+            .bind(t.temperature_c)
+            .bind(t.humidity)
+            .bind(&t.status)
+            .bind(t.temperature_alert)
+            .bind(t.humidity_alert)
+            .bind(sqlx::types::Json(&t.attributes))
+            .bind(t.latitude)
+            .bind(t.longitude)
+            .bind(sqlx::types::Json(&t.extra))
+            .bind(t.raw_temperature_c)
+            .bind(t.raw_humidity)
+            .bind(t.quality)
+            .bind(sqlx::types::Json(&t.quality_flags))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if stored_now > 0 && !meshes.contains(&t.mesh_id) {
+                meshes.push(t.mesh_id.clone());
+            }
+            inserted += stored_now;
+        }
+        let now = stored(Utc::now());
+        for mesh_id in &meshes {
+            sqlx::query(
+                "INSERT INTO mesh_ingest (mesh_id, last_ingested_at) VALUES (?, ?) \
+                 ON CONFLICT (mesh_id) DO UPDATE SET last_ingested_at = excluded.last_ingested_at",
+            )
+            .bind(mesh_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.publish(&meshes).await;
+        Ok(inserted)
+    }
+
+    /// Publish the aggregates of `meshes` when anyone is streaming them; a
+    /// failed load only costs the streams this update.
+    async fn publish(&self, meshes: &[String]) {
+        // ---
+        if meshes.is_empty() || !self.feed.has_subscribers() {
+            return;
+        }
+        match self.summaries(Some(meshes)).await {
+            Ok(rows) => {
+                for row in rows {
+                    self.feed.publish(SummaryUpdate::Updated(row));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load mesh summaries to publish: {}", e),
+        }
+    }
+}
+
+impl ReadingsRepository for SqliteReadings {
+    // ---
+    fn insert_batch<'a>(
+        &'a self,
+        source: &'a str,
+        readings: &'a [RawSensorReading],
+    ) -> BoxFuture<'a, Result<u64, sqlx::Error>> {
+        // ---
+        Box::pin(self.store(source, readings))
+    }
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+        page: &'a ReadingsPage,
+    ) -> BoxFuture<'a, Result<Readings, sqlx::Error>> {
+        // ---
+        Box::pin(load_readings(&self.pool, filter, page))
+    }
+
+    fn count<'a>(
+        &'a self,
+        filter: &'a ReadingsFilter,
+    ) -> BoxFuture<'a, Result<ReadingsCount, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(
+                "SELECT COUNT(*) AS count, MIN(timestamp_utc) AS earliest, \
+                 MAX(timestamp_utc) AS latest FROM sensor_data WHERE 1=1",
+            );
+            push_conditions(&mut query, filter);
+            query.build_query_as().fetch_one(&self.pool).await
+        })
+    }
+
+    fn reading<'a>(
+        &'a self,
+        id: i32,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<SensorReading>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(format!(
+                "SELECT {READING_COLUMNS} FROM sensor_data WHERE deleted_at IS NULL AND id = "
+            ));
+            query.push_bind(id);
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            let row = query.build().fetch_optional(&self.pool).await?;
+            row.map(|row| reading_from_row(&row, false)).transpose()
+        })
+    }
+
+    fn latest<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query = QueryBuilder::new(
+                "SELECT MAX(timestamp_utc) FROM sensor_data WHERE deleted_at IS NULL",
+            );
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            query.build_query_scalar().fetch_one(&self.pool).await
+        })
+    }
+
+    fn summaries<'a>(
+        &'a self,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Vec<MeshAggregate>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            // Computed on read: an edge store is small, and this needs no
+            // totals kept in step with every write
+            let mut query = QueryBuilder::new(
+                r#"
+                SELECT mesh_id, AVG(temperature_c) AS avg_temperature_c,
+                       AVG(humidity) AS avg_humidity, COUNT(*) AS reading_count,
+                       NULL AS site_name
+                FROM sensor_data
+                WHERE id IN (
+                    SELECT MIN(id) FROM sensor_data
+                    WHERE deleted_at IS NULL AND NOT invalid
+                    GROUP BY device_id, timestamp_utc
+                )
+                "#,
+            );
+            if let Some(meshes) = meshes {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            query.push(" GROUP BY mesh_id ORDER BY mesh_id");
+            query.build_query_as().fetch_all(&self.pool).await
+        })
+    }
+
+    fn last_ingested<'a>(
+        &'a self,
+        scope: Option<&'a [String]>,
+        meshes: Option<&'a [String]>,
+    ) -> BoxFuture<'a, Result<Option<DateTime<Utc>>, sqlx::Error>> {
+        // ---
+        Box::pin(async move {
+            let mut query =
+                QueryBuilder::new("SELECT MAX(last_ingested_at) FROM mesh_ingest WHERE 1=1");
+            for meshes in [scope, meshes].into_iter().flatten() {
+                push_in(&mut query, "mesh_id", meshes);
+            }
+            query.build_query_scalar().fetch_one(&self.pool).await
+        })
+    }
+
+    fn timezones<'a>(
+        &'a self,
+        _mesh_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, sqlx::Error>> {
+        // ---
+        // There is no mesh registry
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn devices<'a>(
+        &'a self,
+        _device_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<DeviceInfo>, sqlx::Error>> {
+        // ---
+        // There is no device registry
+        Box::pin(async { Ok(Vec::new()) })
+    }
+}
+
+/// `at` as stored; the fixed width makes text order time order.
+fn stored(at: DateTime<Utc>) -> String {
+    // ---
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Add ` AND column IN (values...)`; an empty list matches nothing.
+fn push_in<'a>(query: &mut QueryBuilder<'a, Sqlite>, column: &str, values: &'a [String]) {
+    // ---
+    query.push(format!(" AND {column} IN ("));
+    let mut list = query.separated(", ");
+    for value in values {
+        list.push_bind(value);
+    }
+    list.push_unseparated(")");
+}
+
+/// Add the `WHERE` conditions of `filter`, shared by the readings and their
+/// count; the SQLite counterpart of `ReadingsFilter::push_conditions`.
+fn push_conditions<'a>(query: &mut QueryBuilder<'a, Sqlite>, filter: &'a ReadingsFilter) {
+    // ---
+    query.push(" AND deleted_at IS NULL");
+
+    for (column, values) in [
+        ("device_id", &filter.device_ids),
+        ("mesh_id", &filter.mesh_ids),
+    ] {
+        if !values.is_empty() {
+            push_in(query, column, values);
+        }
+    }
+
+    for (column, prefix) in [
+        ("device_id", &filter.device_id_prefix),
+        ("mesh_id", &filter.mesh_id_prefix),
+    ] {
+        if let Some(prefix) = prefix {
+            query.push(format!(" AND {column} LIKE "));
+            query.push_bind(like_prefix(prefix));
+            query.push(r" ESCAPE '\'");
+        }
+    }
+
+    if let Some(statuses) = &filter.statuses {
+        push_in(query, "status", statuses);
+    }
+    if let Some(statuses) = &filter.statuses_not {
+        query.push(" AND status NOT IN (");
+        let mut list = query.separated(", ");
+        for status in statuses {
+            list.push_bind(status);
+        }
+        list.push_unseparated(")");
+    }
+
+    if let Some(alert) = filter.alert {
+        query.push(match alert {
+            AlertFilter::Any => " AND (temperature_alert OR humidity_alert)",
+            AlertFilter::Temperature => " AND temperature_alert",
+            AlertFilter::Humidity => " AND humidity_alert",
+        });
+    }
+
+    for (column, [min, max]) in [
+        ("temperature_c", filter.temperature_c),
+        ("humidity", filter.humidity),
+    ] {
+        if let Some(min) = min {
+            query.push(format!(" AND {column} >= "));
+            query.push_bind(min);
+        }
+        if let Some(max) = max {
+            query.push(format!(" AND {column} <= "));
+            query.push_bind(max);
+        }
+    }
+
+    if let Some(min) = filter.min_quality {
+        query.push(" AND quality >= ");
+        query.push_bind(min);
+    }
+
+    if let Some(key) = &filter.extra_key {
+        query.push(" AND EXISTS (SELECT 1 FROM json_each(sensor_data.extra) WHERE key = ");
+        query.push_bind(key);
+        query.push(")");
+    }
+
+    if let Some(allowed) = &filter.allowed_meshes {
+        push_in(query, "mesh_id", allowed);
+    }
+
+    if let Some(start) = filter.start {
+        query.push(" AND timestamp_utc >= ");
+        query.push_bind(stored(start));
+    }
+    if let Some(end) = filter.end {
+        query.push(" AND timestamp_utc <= ");
+        query.push_bind(stored(end));
+    }
+
+    if let Some(b) = &filter.bbox {
+        query.push(" AND latitude BETWEEN ");
+        query.push_bind(b.min_lat);
+        query.push(" AND ");
+        query.push_bind(b.max_lat);
+        query.push(" AND (longitude >= ");
+        query.push_bind(b.min_lon);
+        // Across the antimeridian either side of it matches
+        query.push(if b.min_lon <= b.max_lon {
+            " AND "
+        } else {
+            " OR "
+        });
+        query.push("longitude <= ");
+        query.push_bind(b.max_lon);
+        query.push(")");
+    }
+}
+
+/// Keep only rows strictly after `c` in `sort`, as `Sort::push_after` does
+/// for Postgres (SQLite compares row values the same way).
+fn push_after<'a>(query: &mut QueryBuilder<'a, Sqlite>, sort: Sort, c: &'a ReadingsCursor) {
+    // ---
+    let (key, op) = match sort {
+        Sort::TimestampDesc => ("timestamp_utc", "<"),
+        Sort::TimestampAsc => ("timestamp_utc", ">"),
+        Sort::TemperatureDesc => ("temperature_c", "<"),
+        Sort::TemperatureAsc => ("temperature_c", ">"),
+        Sort::HumidityDesc => ("humidity", "<"),
+        Sort::HumidityAsc => ("humidity", ">"),
+        Sort::DeviceId => {
+            let device_id = c.device_id.as_deref().unwrap_or_default();
+            query.push(" AND (device_id > ");
+            query.push_bind(device_id);
+            query.push(" OR (device_id = ");
+            query.push_bind(device_id);
+            query.push(" AND (timestamp_utc, id) < (");
+            query.push_bind(stored(c.timestamp_utc));
+            query.push(", ");
+            query.push_bind(c.id);
+            query.push(")))");
+            return;
+        }
+    };
+    query.push(format!(" AND ({key}, id) {op} ("));
+    match key {
+        "temperature_c" => query.push_bind(c.temperature_c.unwrap_or_default()),
+        "humidity" => query.push_bind(c.humidity.unwrap_or_default()),
+        _ => query.push_bind(stored(c.timestamp_utc)),
+    };
+    query.push(", ");
+    query.push_bind(c.id);
+    query.push(")");
+}
+
+/// Build the query for a page of the readings matching `filter`; like the
+/// Postgres one, it fetches one row more than the limit and computes rolling
+/// averages over every matching row.
+fn readings_query<'a>(
+    filter: &'a ReadingsFilter,
+    page: &'a ReadingsPage,
+) -> QueryBuilder<'a, Sqlite> {
+    // ---
+    let columns = match &page.columns {
+        Some(columns) => columns.join(", "),
+        None => READING_COLUMNS.to_string(),
+    };
+    let mut query = QueryBuilder::new(format!("SELECT {columns}"));
+
+    if let Some(window) = page.rolling_avg {
+        let over = format!(
+            "OVER (PARTITION BY device_id ORDER BY timestamp_utc, id \
+             ROWS BETWEEN {} PRECEDING AND CURRENT ROW)",
+            window.saturating_sub(1)
+        );
+        query.push(format!(
+            ", AVG(temperature_c) {over} AS rolling_temperature_c, \
+             AVG(humidity) {over} AS rolling_humidity"
+        ));
+    }
+    query.push(" FROM sensor_data WHERE 1=1");
+
+    if let Some(fraction) = page.sample {
+        query.push(format!(" AND (id * {SAMPLE_HASH}) % {SAMPLE_BUCKETS} < "));
+        query.push_bind((fraction * SAMPLE_BUCKETS as f64) as i64);
+    }
+    push_conditions(&mut query, filter);
+
+    if let Some(c) = &page.after {
+        push_after(&mut query, page.sort, c);
+    }
+
+    query.push(" ORDER BY ");
+    query.push(page.sort.order_by());
+    query.push(" LIMIT ");
+    query.push_bind(page.limit as i64 + 1);
+    query
+}
+
+/// Load a page of the readings matching `filter`, and the cursor of the next
+/// page: the last row of this one, when more rows follow.
+async fn load_readings(
+    pool: &SqlitePool,
+    filter: &ReadingsFilter,
+    page: &ReadingsPage,
+) -> Result<Readings, sqlx::Error> {
+    // ---
+    let mut rows = readings_query(filter, page).build().fetch_all(pool).await?;
+
+    let (limit, sort) = (page.limit as usize, page.sort);
+    let next = if rows.len() > limit {
+        rows.truncate(limit);
+        match rows.last() {
+            Some(row) => Some(ReadingsCursor {
+                timestamp_utc: row.try_get("timestamp_utc")?,
+                id: row.try_get("id")?,
+                sort: sort.cursor_tag().map(str::to_string),
+                temperature_c: matches!(sort, Sort::TemperatureDesc | Sort::TemperatureAsc)
+                    .then(|| row.try_get("temperature_c"))
+                    .transpose()?,
+                humidity: matches!(sort, Sort::HumidityDesc | Sort::HumidityAsc)
+                    .then(|| row.try_get("humidity"))
+                    .transpose()?,
+                device_id: (sort == Sort::DeviceId)
+                    .then(|| row.try_get("device_id"))
+                    .transpose()?,
+            }),
+            None => None,
+        }
+    } else {
+        None
+    };
+
+    let readings = rows
+        .iter()
+        .map(|row| reading_from_row(row, page.rolling_avg.is_some()))
+        .collect::<Result<_, _>>()?;
+    Ok((readings, next))
+}
+
+/// The reading in `row`, with the columns it lacks at their defaults;
+/// `rolling` when it carries the rolling averages.
+fn reading_from_row(row: &SqliteRow, rolling: bool) -> Result<SensorReading, sqlx::Error> {
+    // ---
+    type JsonMap = sqlx::types::Json<serde_json::Map<String, serde_json::Value>>;
+    type JsonList<T> = sqlx::types::Json<Vec<T>>;
+    Ok(SensorReading {
+        id: Some(row.try_get("id")?),
+        mesh_id: column_or_default(row, "mesh_id")?,
+        device_id: column_or_default(row, "device_id")?,
+        timestamp_utc: row.try_get("timestamp_utc")?,
+        timestamp_local: None,
+        temperature_c: column_or_default(row, "temperature_c")?,
+        humidity: column_or_default(row, "humidity")?,
+        raw_temperature_c: column_or_default(row, "raw_temperature_c")?,
+        raw_humidity: column_or_default(row, "raw_humidity")?,
+        status: column_or_default(row, "status")?,
+        temperature_alert: column_or_default(row, "temperature_alert")?,
+        humidity_alert: column_or_default(row, "humidity_alert")?,
+        attributes: column_or_default::<Option<JsonMap>>(row, "attributes")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        latitude: column_or_default(row, "latitude")?,
+        longitude: column_or_default(row, "longitude")?,
+        extra: column_or_default::<Option<JsonMap>>(row, "extra")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        quality: column_or_default(row, "quality")?,
+        quality_flags: column_or_default::<Option<JsonList<String>>>(row, "quality_flags")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        invalid: column_or_default(row, "invalid")?,
+        corrections: column_or_default::<Option<JsonList<serde_json::Value>>>(row, "corrections")?
+            .map(|j| j.0)
+            .unwrap_or_default(),
+        ingest_batch_id: column_or_default(row, "ingest_batch_id")?,
+        smoothed: None,
+        rolling_avg: match rolling {
+            true => Some(Smoothed {
+                temperature_c: row.try_get::<f64, _>("rolling_temperature_c")? as f32,
+                humidity: row.try_get::<f64, _>("rolling_humidity")? as f32,
+            }),
+            false => None,
+        },
+        device: None,
+    })
+}
+
+/// `column` of `row`, or its default when the query didn't select it.
+fn column_or_default<'r, T>(row: &'r SqliteRow, column: &str) -> Result<T, sqlx::Error>
+where
+    T: Default + sqlx::Decode<'r, Sqlite> + sqlx::Type<Sqlite>,
+{
+    // ---
+    match row.try_get(column) {
+        Err(sqlx::Error::ColumnNotFound(_)) => Ok(T::default()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use chrono::{SubsecRound, TimeZone};
+
+    fn raw(device_id: &str, minute: u32, temperature_c: f32) -> RawSensorReading {
+        // ---
+        RawSensorReading {
+            mesh_id: "mesh-001".into(),
+            device_id: device_id.into(),
+            timestamp: Utc.with_ymd_and_hms(2025, 3, 21, 12, minute, 0).unwrap(),
+            temperature_c,
+            humidity: 50.0,
+            status: "ok".into(),
+            latitude: None,
+            longitude: None,
+            unrecognized: serde_json::Map::new(),
+        }
+    }
+
+    async fn repo() -> SqliteReadings {
+        // ---
+        let pool = open("sqlite::memory:", 1)
+            .await
+            .expect("in-memory database");
+        SqliteReadings::new(
+            pool,
+            Arc::new(Enrichment::default()),
+            SummaryFeed::default(),
+        )
+    }
+
+    fn page(sort: Sort, limit: u32, after: Option<ReadingsCursor>) -> ReadingsPage {
+        // ---
+        ReadingsPage {
+            sort,
+            limit,
+            after,
+            columns: None,
+            sample: None,
+            rolling_avg: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn migrations_run_once() {
+        // ---
+        let repo = repo().await;
+        assert_eq!(migrate(&repo.pool).await.unwrap(), 0);
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&repo.pool)
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn pushes_skip_readings_the_source_already_stored() {
+        // ---
+        let repo = repo().await;
+        let batch = [raw("dev-1", 0, 20.0), raw("dev-2", 0, 80.0)];
+        assert_eq!(repo.insert_batch("push:gw", &batch).await.unwrap(), 2);
+        assert_eq!(repo.insert_batch("push:gw", &batch).await.unwrap(), 0);
+        // Another source's copy is stored, but summarized once
+        assert_eq!(
+            repo.insert_batch("push:other", &batch[..1]).await.unwrap(),
+            1
+        );
+
+        let all = ReadingsFilter::default();
+        assert_eq!(repo.count(&all).await.unwrap().count, 3);
+        let summaries = repo.summaries(None).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].reading_count, 2);
+        assert_eq!(summaries[0].avg_temperature_c, 50.0);
+
+        let alerts = ReadingsFilter {
+            alert: Some(AlertFilter::Temperature),
+            ..ReadingsFilter::default()
+        };
+        let (readings, _) = repo
+            .query(&alerts, &page(Sort::TimestampDesc, 10, None))
+            .await
+            .unwrap();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].device_id, "dev-2");
+        assert!(readings[0].quality.is_some());
+
+        let id = readings[0].id.unwrap();
+        let scope = ["mesh-002".to_string()];
+        assert!(repo.reading(id, None).await.unwrap().is_some());
+        assert!(repo.reading(id, Some(&scope)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn writes_record_ingests_and_publish_summaries() {
+        // ---
+        let repo = repo().await;
+        let scope = ["mesh-002".to_string()];
+        assert_eq!(repo.last_ingested(None, None).await.unwrap(), None);
+
+        let mut updates = repo.feed.subscribe();
+        let before = Utc::now();
+        repo.insert_batch("push:gw", &[raw("dev-1", 0, 20.0)])
+            .await
+            .unwrap();
+        let ingested = repo.last_ingested(None, None).await.unwrap().unwrap();
+        assert!(ingested >= before.trunc_subsecs(6));
+        assert_eq!(repo.last_ingested(Some(&scope), None).await.unwrap(), None);
+
+        match updates.try_recv().unwrap() {
+            SummaryUpdate::Updated(agg) => {
+                assert_eq!(agg.mesh_id, "mesh-001");
+                assert_eq!(agg.reading_count, 1);
+            }
+            update => panic!("unexpected {update:?}"),
+        }
+        // Nothing new stored, nothing recorded or published
+        repo.insert_batch("push:gw", &[raw("dev-1", 0, 20.0)])
+            .await
+            .unwrap();
+        assert!(updates.try_recv().is_err());
+        assert_eq!(
+            repo.last_ingested(None, None).await.unwrap(),
+            Some(ingested)
+        );
+    }
+
+    #[tokio::test]
+    async fn cursors_page_through_every_reading_once() {
+        // ---
+        let repo = repo().await;
+        let batch: Vec<_> = (0..5)
+            .map(|i| {
+                raw(
+                    if i % 2 == 0 { "dev-a" } else { "dev-b" },
+                    i,
+                    20.0 + i as f32,
+                )
+            })
+            .collect();
+        repo.insert_batch("push:gw", &batch).await.unwrap();
+
+        let filter = ReadingsFilter {
+            start: Some(Utc.with_ymd_and_hms(2025, 3, 21, 12, 1, 0).unwrap()),
+            ..ReadingsFilter::default()
+        };
+        for sort in [Sort::TimestampAsc, Sort::TemperatureDesc, Sort::DeviceId] {
+            let (mut seen, mut after) = (vec![], None);
+            loop {
+                let (readings, next) = repo.query(&filter, &page(sort, 2, after)).await.unwrap();
+                seen.extend(readings.into_iter().map(|r| r.temperature_c as u32));
+                match next {
+                    Some(next) => after = Some(next),
+                    None => break,
+                }
+            }
+            let expected: &[u32] = match sort {
+                Sort::TimestampAsc => &[21, 22, 23, 24],
+                Sort::TemperatureDesc => &[24, 23, 22, 21],
+                _ => &[24, 22, 23, 21],
+            };
+            assert_eq!(seen, expected, "{sort:?}");
+        }
+    }
+
+    #[test]
+    fn filters_become_bound_sqlite_conditions() {
+        // ---
+        let filter = ReadingsFilter {
+            mesh_ids: vec!["mesh-001".into(), "mesh-002".into()],
+            statuses_not: Some(vec!["ok".into()]),
+            extra_key: Some("pressure".into()),
+            ..ReadingsFilter::default()
+        };
+        let page = ReadingsPage {
+            columns: Some(vec!["id", "timestamp_utc"]),
+            sample: Some(0.5),
+            ..page(Sort::TimestampDesc, 50, None)
+        };
+        assert_eq!(
+            readings_query(&filter, &page).sql(),
+            "SELECT id, timestamp_utc FROM sensor_data WHERE 1=1 \
+             AND (id * 2654435761) % 1000000 < ? \
+             AND deleted_at IS NULL AND mesh_id IN (?, ?) AND status NOT IN (?) \
+             AND EXISTS (SELECT 1 FROM json_each(sensor_data.extra) WHERE key = ?) \
+             ORDER BY timestamp_utc DESC, id DESC LIMIT ?"
+        );
+        assert_eq!(
+            stored(Utc.with_ymd_and_hms(2025, 3, 21, 0, 0, 0).unwrap()),
+            "2025-03-21T00:00:00.000000Z"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Shared application state.
+//!
+//! [`AppState`] holds every handle the routes share, built once in `serve`.
+//! Handlers extract only the parts they use, e.g. `State(pool): State<PgPool>`
+//! or `State(cache): State<Arc<ResponseCache>>`, through the [`FromRef`]
+//! implementations below; a new shared resource is a field and one line in
+//! [`from_ref!`], without touching the handlers that don't need it.
+//!
+//! Everything in it is a cheap handle (a pool, an `Arc` or a channel), so
+//! cloning the state per request costs a few reference counts.
+//!
+//! [`EdgeState`] is the state of deployments storing readings in SQLite or
+//! MySQL (see `sqlite.rs` and `mysql.rs`): the handles of [`AppState`] that
+//! don't need Postgres. Routes mounted on both only extract parts both have.
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use metrics_exporter_prometheus::PrometheusHandle;
+use sqlx::PgPool;
+
+use crate::{
+    Authenticator, Config, Enrichment, FilterStats, LiveConfig, Maintenance, PoolMonitor,
+    RateLimiter, ReadPool, ReadingsRepository, Reloader, ResponseCache, SummaryFeed, Warmup,
+};
+
+// ---
+
+/// The state of the API router; see the module docs.
+#[derive(Clone)]
+pub struct AppState {
+    // ---
+    /// The primary database, for writes and reads that must see them.
+    pub pool: PgPool,
+
+    /// Startup configuration; the reloadable settings are in `live`.
+    pub config: Arc<Config>,
+
+    /// Read-only queries, preferring the replica.
+    pub reads: ReadPool,
+
+    pub auth: Arc<Authenticator>,
+    pub limiter: Arc<RateLimiter>,
+
+    /// Mesh summary changes, streamed by `/sql/stream/mesh-summary`.
+    pub summaries: SummaryFeed,
+
+    /// Applied by every ingest path: on-demand, first read, and pushes.
+    pub enrichment: Arc<Enrichment>,
+    pub metrics: PrometheusHandle,
+    pub pool_monitor: PoolMonitor,
+
+    /// Hot readings and summary results; cleared by ingest paths.
+    pub cache: Arc<ResponseCache>,
+
+    /// The initial ingest, awaited by readings and reported by `/ready`.
+    pub warmup: Warmup,
+
+    /// Toggled under `/admin/maintenance`.
+    pub maintenance: Maintenance,
+
+    /// Sources, rate limit and log level, replaced by reloads.
+    pub live: LiveConfig,
+    pub reloader: Arc<Reloader>,
+
+    /// Recorded by `PgReadings`, read by the index advisor routes.
+    pub filter_stats: Arc<FilterStats>,
+
+    /// Readings and mesh summaries, read and pushed (see `repository.rs`).
+    pub readings: Arc<dyn ReadingsRepository>,
+}
+
+/// The state of the edge router, for SQLite or MySQL storage; see the module docs.
+#[derive(Clone)]
+pub struct EdgeState {
+    // ---
+    pub config: Arc<Config>,
+    pub auth: Arc<Authenticator>,
+    pub limiter: Arc<RateLimiter>,
+
+    /// Read once at startup: edge deployments don't reload.
+    pub live: LiveConfig,
+
+    /// Applied by upstream ingest and pushes.
+    pub enrichment: Arc<Enrichment>,
+    pub metrics: PrometheusHandle,
+
+    /// Cleared by ingest and pushes; with one process, that is every writer.
+    pub cache: Arc<ResponseCache>,
+    pub readings: Arc<dyn ReadingsRepository>,
+
+    /// Published to by `readings` after each write.
+    pub summaries: SummaryFeed,
+}
+
+/// `FromRef<$state>` for each field's type.
+macro_rules! from_ref {
+    ($state:ty { $($field:ident: $ty:ty),* $(,)? }) => {
+        $(
+            impl FromRef<$state> for $ty {
+                fn from_ref(state: &$state) -> Self {
+                    // ---
+                    state.$field.clone()
+                }
+            }
+        )*
+    };
+}
+
+from_ref! { AppState {
+    pool: PgPool,
+    config: Arc<Config>,
+    reads: ReadPool,
+    auth: Arc<Authenticator>,
+    limiter: Arc<RateLimiter>,
+    summaries: SummaryFeed,
+    enrichment: Arc<Enrichment>,
+    metrics: PrometheusHandle,
+    pool_monitor: PoolMonitor,
+    cache: Arc<ResponseCache>,
+    warmup: Warmup,
+    maintenance: Maintenance,
+    live: LiveConfig,
+    reloader: Arc<Reloader>,
+    filter_stats: Arc<FilterStats>,
+    readings: Arc<dyn ReadingsRepository>,
+}}
+
+from_ref! { EdgeState {
+    config: Arc<Config>,
+    auth: Arc<Authenticator>,
+    limiter: Arc<RateLimiter>,
+    live: LiveConfig,
+    enrichment: Arc<Enrichment>,
+    metrics: PrometheusHandle,
+    cache: Arc<ResponseCache>,
+    readings: Arc<dyn ReadingsRepository>,
+    summaries: SummaryFeed,
+}}
@@ -0,0 +1,220 @@
+//! Live per-mesh aggregate updates for streaming clients.
+//!
+//! Whenever a rollup refresh changes a mesh's `mesh_summary` row (see
+//! `rollups.rs`), the mesh ID is announced on the `mesh_summary_changed`
+//! Postgres channel in the same transaction. One
+//! background task per process listens on that channel, re-reads the changed
+//! rows, and fans them out to subscribers as [`SummaryUpdate`]s. Going through
+//! Postgres means every replica's streams see summaries refreshed by any other.
+//!
+//! The listener holds one pooled connection for the life of the process.
+//! Notifications sent while it is reconnecting are lost; streams re-send a
+//! full snapshot when they fall behind, so dashboards converge regardless.
+//!
+//! Deployments storing readings in SQLite or MySQL have no listener: a single
+//! process writes their readings, so the repository publishes the changed
+//! meshes' aggregates to the feed itself after each write (see `sqlite.rs`).
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast;
+
+use crate::timed;
+
+/// Postgres `NOTIFY` channel for changed `mesh_summary` rows.
+pub const SUMMARY_CHANNEL: &str = "mesh_summary_changed";
+
+/// Updates buffered per subscriber before it is considered lagging.
+const FEED_CAPACITY: usize = 256;
+
+/// `NOTIFY` payloads must stay under 8000 bytes; larger change sets are sent as "all".
+const MAX_NOTIFY_PAYLOAD: usize = 7900;
+
+// ---
+
+/// Current aggregates for one mesh, as stored in `mesh_summary`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct MeshAggregate {
+    // ---
+    pub mesh_id: String,
+    pub avg_temperature_c: f32,
+    pub avg_humidity: f32,
+    pub reading_count: i32,
+
+    /// Site name from the mesh registry, when the mesh is registered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+}
+
+/// One change to a mesh's aggregates.
+#[derive(Debug, Clone)]
+pub enum SummaryUpdate {
+    // ---
+    /// The mesh's aggregates were created or changed.
+    Updated(MeshAggregate),
+
+    /// The mesh no longer has any readings attributed to it.
+    Removed(String),
+}
+
+impl SummaryUpdate {
+    // ---
+    pub fn mesh_id(&self) -> &str {
+        // ---
+        match self {
+            SummaryUpdate::Updated(agg) => &agg.mesh_id,
+            SummaryUpdate::Removed(mesh_id) => mesh_id,
+        }
+    }
+}
+
+/// `NOTIFY` payload: the changed mesh IDs, or `null` for "re-read everything".
+#[derive(Debug, Serialize, Deserialize)]
+struct ChangedMeshes {
+    meshes: Option<Vec<String>>,
+}
+
+/// Payload announcing `meshes` on [`SUMMARY_CHANNEL`].
+pub fn notify_payload(meshes: &[String]) -> String {
+    // ---
+    let payload = serde_json::json!({ "meshes": meshes }).to_string();
+    if payload.len() <= MAX_NOTIFY_PAYLOAD {
+        payload
+    } else {
+        serde_json::json!({ "meshes": null }).to_string()
+    }
+}
+
+/// Fan-out handle; clone freely.
+///
+/// The default feed has no listener; updates reach it through [`publish`](Self::publish).
+#[derive(Clone)]
+pub struct SummaryFeed {
+    // ---
+    tx: broadcast::Sender<SummaryUpdate>,
+}
+
+impl Default for SummaryFeed {
+    // ---
+    fn default() -> Self {
+        // ---
+        let (tx, _) = broadcast::channel(FEED_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl SummaryFeed {
+    // ---
+    /// Start the background listener and return the feed it publishes to.
+    pub fn spawn(pool: PgPool) -> Self {
+        // ---
+        let feed = Self::default();
+
+        let publisher = feed.clone();
+        tokio::spawn(async move {
+            // ---
+            loop {
+                if let Err(e) = publisher.listen(&pool).await {
+                    tracing::warn!("Mesh summary listener failed, retrying in 5s: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+        feed
+    }
+
+    /// Receive updates published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SummaryUpdate> {
+        // ---
+        self.tx.subscribe()
+    }
+
+    /// Whether anyone is streaming; publishers skip loading updates otherwise.
+    pub fn has_subscribers(&self) -> bool {
+        // ---
+        self.tx.receiver_count() > 0
+    }
+
+    /// Send `update` to every current subscriber.
+    pub fn publish(&self, update: SummaryUpdate) {
+        // ---
+        // With nobody subscribed there is no one to miss it
+        let _ = self.tx.send(update);
+    }
+
+    async fn listen(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        // ---
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(SUMMARY_CHANNEL).await?;
+        tracing::info!("Listening for mesh summary changes on {}", SUMMARY_CHANNEL);
+
+        loop {
+            let notification = listener.recv().await?;
+            let changed = match serde_json::from_str::<ChangedMeshes>(notification.payload()) {
+                Ok(c) => c.meshes,
+                Err(e) => {
+                    tracing::warn!("Ignoring malformed {} payload: {}", SUMMARY_CHANNEL, e);
+                    continue;
+                }
+            };
+            // Nobody streaming: skip the re-read
+            if !self.has_subscribers() {
+                continue;
+            }
+
+            let rows = load_aggregates(pool, changed.as_deref()).await?;
+            if let Some(meshes) = changed {
+                for mesh_id in meshes {
+                    if !rows.iter().any(|r| r.mesh_id == mesh_id) {
+                        self.publish(SummaryUpdate::Removed(mesh_id));
+                    }
+                }
+            }
+            for row in rows {
+                self.publish(SummaryUpdate::Updated(row));
+            }
+        }
+    }
+}
+
+/// Current `mesh_summary` rows for `meshes` (all meshes when `None`), by mesh
+/// ID, with their registered site names.
+#[tracing::instrument(name = "db.load_aggregates", skip_all)]
+pub async fn load_aggregates(
+    pool: &PgPool,
+    meshes: Option<&[String]>,
+) -> Result<Vec<MeshAggregate>, sqlx::Error> {
+    // ---
+    let shape = if meshes.is_some() { "mesh_id" } else { "none" };
+    let query = sqlx::query_as(
+        r#"
+        SELECT s.mesh_id, s.avg_temperature_c, s.avg_humidity, s.reading_count, m.site_name
+        FROM mesh_summary s
+        LEFT JOIN meshes m ON m.mesh_id = s.mesh_id
+        WHERE $1::TEXT[] IS NULL OR s.mesh_id = ANY($1)
+        ORDER BY s.mesh_id
+        "#,
+    )
+    .bind(meshes)
+    .fetch_all(pool);
+    timed("load_aggregates", shape, query).await
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn oversized_change_sets_become_all() {
+        // ---
+        let few = vec!["mesh-001".to_string(), "mesh-002".to_string()];
+        let parsed: ChangedMeshes = serde_json::from_str(&notify_payload(&few)).unwrap();
+        assert_eq!(parsed.meshes, Some(few));
+
+        let many: Vec<String> = (0..2000).map(|n| format!("mesh-{n:04}")).collect();
+        let parsed: ChangedMeshes = serde_json::from_str(&notify_payload(&many)).unwrap();
+        assert_eq!(parsed.meshes, None);
+    }
+}
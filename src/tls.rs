@@ -0,0 +1,195 @@
+//! Optional HTTPS termination with rustls.
+//!
+//! For edge deployments without a reverse proxy: when `TLS_CERT_PATH` and
+//! `TLS_KEY_PATH` are set, `main.rs` serves HTTPS via `axum-server` instead of
+//! plain HTTP. Sending the process `SIGHUP` re-reads the PEM files, so
+//! renewed certificates are picked up without a restart; a failed reload keeps
+//! serving the previous certificate.
+//!
+//! With `TLS_CLIENT_CA_PATH` set the listener also verifies client
+//! certificates (mutual TLS) against that CA bundle. [`ClientCertAcceptor`]
+//! attaches each connection's verified subject CN to its requests as a
+//! [`PeerCertificate`]; `auth.rs` maps it to a configured identity.
+use std::{fs::File, future::Future, io, io::BufReader, pin::Pin, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use axum::{middleware::AddExtension, Extension};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+
+use crate::TlsConfig;
+
+// ---
+
+/// Client certificate presented on the request's TLS connection.
+///
+/// Present on every request served over HTTPS; `common_name` is `None` when
+/// the client sent no certificate (optional mutual TLS) or its subject has no CN.
+#[derive(Debug, Clone)]
+pub struct PeerCertificate {
+    // ---
+    /// Subject CN of the verified leaf certificate.
+    pub common_name: Option<String>,
+}
+
+/// Load the certificate chain, private key and client CA into a reloadable rustls config.
+pub async fn load(cfg: &TlsConfig) -> Result<RustlsConfig> {
+    // ---
+    // sqlx and reqwest also use ring; make it the process-wide provider
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    Ok(RustlsConfig::from_config(Arc::new(server_config(cfg)?)))
+}
+
+/// Reload the certificate, key and client CA from disk on every `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(rustls: RustlsConfig, cfg: TlsConfig) -> Result<()> {
+    // ---
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        // ---
+        while hangups.recv().await.is_some() {
+            match server_config(&cfg) {
+                Ok(server) => {
+                    rustls.reload_from_config(Arc::new(server));
+                    tracing::info!("Reloaded TLS certificate from {}", cfg.cert_path);
+                }
+                Err(e) => {
+                    tracing::error!("TLS reload failed, keeping the current certificate: {}", e)
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Certificate reload needs `SIGHUP`; elsewhere a restart is required.
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_rustls: RustlsConfig, _cfg: TlsConfig) -> Result<()> {
+    // ---
+    tracing::warn!("TLS certificate reload on SIGHUP is only supported on Unix");
+    Ok(())
+}
+
+/// Build the rustls server config, with client verification when a CA is set.
+fn server_config(cfg: &TlsConfig) -> Result<ServerConfig> {
+    // ---
+    let certs = read_certs(&cfg.cert_path)?;
+    let key: PrivateKeyDer<'static> =
+        rustls_pemfile::private_key(&mut BufReader::new(open(&cfg.key_path)?))
+            .map_err(|e| anyhow!("Failed to read TLS key {}: {}", cfg.key_path, e))?
+            .ok_or_else(|| anyhow!("No private key found in {}", cfg.key_path))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &cfg.client_ca_path {
+        None => builder.with_no_client_auth(),
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for ca in read_certs(ca_path)? {
+                roots
+                    .add(ca)
+                    .map_err(|e| anyhow!("Invalid client CA in {}: {}", ca_path, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if cfg.client_cert_required {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            builder.with_client_cert_verifier(
+                verifier
+                    .build()
+                    .map_err(|e| anyhow!("Invalid client CA {}: {}", ca_path, e))?,
+            )
+        }
+    };
+
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| anyhow!("Invalid TLS certificate {}: {}", cfg.cert_path, e))?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server)
+}
+
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    // ---
+    let certs = rustls_pemfile::certs(&mut BufReader::new(open(path)?))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("Failed to read certificates from {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn open(path: &str) -> Result<File> {
+    // ---
+    File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path, e))
+}
+
+/// Subject CN of a DER-encoded certificate.
+fn common_name(der: &[u8]) -> Option<String> {
+    // ---
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(str::to_owned)
+}
+
+/// TLS acceptor that tags each connection's requests with its [`PeerCertificate`].
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    // ---
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    // ---
+    pub fn new(rustls: RustlsConfig) -> Self {
+        // ---
+        Self {
+            inner: RustlsAcceptor::new(rustls),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    // ---
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, PeerCertificate>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        // ---
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            // ---
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            // The verifier already checked the chain; only the leaf identifies the client
+            let peer = PeerCertificate {
+                common_name: stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|chain| chain.first())
+                    .and_then(|leaf| common_name(leaf)),
+            };
+            Ok((stream, Extension(peer).layer(service)))
+        })
+    }
+}
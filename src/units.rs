@@ -0,0 +1,105 @@
+//! Presentation units for `units=metric|imperial` responses.
+//!
+//! Readings are stored and computed in °C; imperial responses convert when
+//! serializing, renaming each `temperature_c` field to `temperature_f` so a
+//! value is never shown under the wrong unit. Humidity is a percentage in
+//! both systems.
+use serde_json::{Map, Value};
+
+/// Fields holding an object of measurements, converted like the reading itself.
+const NESTED: &[&str] = &["smoothed", "rolling_avg"];
+
+// ---
+
+/// Unit system of a response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnitSystem {
+    // ---
+    /// °C, as stored.
+    #[default]
+    Metric,
+
+    /// °F.
+    Imperial,
+}
+
+impl UnitSystem {
+    // ---
+    /// `metric` or `imperial`; `None` for anything else.
+    pub fn parse(s: &str) -> Option<Self> {
+        // ---
+        match s {
+            "metric" => Some(Self::Metric),
+            "imperial" => Some(Self::Imperial),
+            _ => None,
+        }
+    }
+
+    /// Name of the temperature unit, as served in envelope metadata.
+    pub fn temperature_unit(self) -> &'static str {
+        // ---
+        match self {
+            Self::Metric => "celsius",
+            Self::Imperial => "fahrenheit",
+        }
+    }
+
+    /// Rewrite the temperatures of `reading`, a serialized reading, into this
+    /// system: `temperature_c` and `raw_temperature_c` become `temperature_f` and
+    /// `raw_temperature_f`, here and in [`NESTED`] objects.
+    pub fn convert(self, reading: &mut Map<String, Value>) {
+        // ---
+        if self == Self::Metric {
+            return;
+        }
+        for key in ["temperature_c", "raw_temperature_c"] {
+            if let Some(value) = reading.remove(key) {
+                let fahrenheit = value
+                    .as_f64()
+                    .map_or(Value::Null, |c| Value::from(celsius_to_fahrenheit(c)));
+                let renamed = format!("{}_f", key.trim_end_matches("_c"));
+                reading.insert(renamed, fahrenheit);
+            }
+        }
+        for key in NESTED {
+            if let Some(Value::Object(nested)) = reading.get_mut(*key) {
+                self.convert(nested);
+            }
+        }
+    }
+}
+
+/// `c` °C in °F, rounded to 0.01 so the `f32` origin doesn't show.
+pub fn celsius_to_fahrenheit(c: f64) -> f64 {
+    // ---
+    ((c * 9.0 / 5.0 + 32.0) * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+
+    #[test]
+    fn imperial_renames_and_converts_temperatures() {
+        // ---
+        let mut reading = serde_json::json!({
+            "temperature_c": 21.5,
+            "humidity": 40.0,
+            "smoothed": { "temperature_c": -40.0, "humidity": 41.0 },
+        });
+        let map = reading.as_object_mut().unwrap();
+        UnitSystem::Metric.convert(map);
+        assert_eq!(map["temperature_c"], 21.5);
+
+        UnitSystem::Imperial.convert(map);
+        assert_eq!(
+            reading,
+            serde_json::json!({
+                "temperature_f": 70.7,
+                "humidity": 40.0,
+                "smoothed": { "temperature_f": -40.0, "humidity": 41.0 },
+            })
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! The initial ingest, run in the background.
+//!
+//! A source without stored data is ingested before its readings are served,
+//! and against a large upstream that first ingest can take many minutes.
+//! [`Warmup`] runs it as one background task shared by every caller instead of
+//! inside the request that noticed it: readings handlers wait for it at most
+//! `INGEST_WAIT_SECS` and answer [`warming_up`] (503 with `Retry-After`) past
+//! that, and `/ready` reports `warming_up` while it runs. A failed ingest is
+//! reported to the callers waiting for it; the next request starts another.
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::response::{IntoResponse, Response};
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::{
+    ensure_data_loaded, sources_without_data, ApiError, Enrichment, IngestError, SourceConfig,
+};
+
+/// Seconds a client is told to wait before retrying a read during warm-up.
+const RETRY_AFTER_SECS: u64 = 10;
+
+// ---
+
+/// Outcome of a run: `None` while it runs, then the ingest's result.
+type Outcome = Option<Result<(), IngestError>>;
+
+/// Handle on the background initial ingest; clones share it.
+#[derive(Clone, Default)]
+pub struct Warmup {
+    // ---
+    /// The latest run, finished or not.
+    run: Arc<Mutex<Option<watch::Receiver<Outcome>>>>,
+}
+
+/// Whether the data a read needs is there.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WarmupStatus {
+    // ---
+    Ready,
+
+    /// The initial ingest is still running.
+    WarmingUp,
+}
+
+impl Warmup {
+    // ---
+    /// Whether an initial ingest is running.
+    pub fn is_running(&self) -> bool {
+        // ---
+        let run = self.run.lock().expect("warmup lock poisoned");
+        run.as_ref().is_some_and(|rx| rx.borrow().is_none())
+    }
+
+    /// Start the initial ingest of every source without data in the
+    /// background, unless one is already running; returns a receiver for its
+    /// outcome.
+    pub fn start(
+        &self,
+        pool: &PgPool,
+        sources: &[SourceConfig],
+        priority: &[String],
+        enrichment: &Arc<Enrichment>,
+    ) -> watch::Receiver<Outcome> {
+        // ---
+        let mut run = self.run.lock().expect("warmup lock poisoned");
+        if let Some(rx) = run.as_ref().filter(|rx| rx.borrow().is_none()) {
+            return rx.clone();
+        }
+
+        let (tx, rx) = watch::channel(None);
+        let (pool, sources, priority, enrichment) = (
+            pool.clone(),
+            sources.to_vec(),
+            priority.to_vec(),
+            enrichment.clone(),
+        );
+        tokio::spawn(async move {
+            // ---
+            let result = ensure_data_loaded(&pool, &sources, &priority, &enrichment).await;
+            if let Err(e) = &result {
+                tracing::error!("Initial ingest failed: {}", e);
+            }
+            let _ = tx.send(Some(result));
+        });
+        *run = Some(rx.clone());
+        rx
+    }
+
+    /// Make sure every source has data before a read: starts (or joins) the
+    /// initial ingest when some source has none, and waits for it at most
+    /// `wait`. Errors are the ingest's.
+    pub async fn ensure(
+        &self,
+        pool: &PgPool,
+        sources: &[SourceConfig],
+        priority: &[String],
+        enrichment: &Arc<Enrichment>,
+        wait: Duration,
+    ) -> Result<WarmupStatus, IngestError> {
+        // ---
+        if !self.is_running() && !sources_without_data(pool, sources).await? {
+            return Ok(WarmupStatus::Ready);
+        }
+
+        let mut rx = self.start(pool, sources, priority, enrichment);
+        let status = match tokio::time::timeout(wait, rx.wait_for(Option::is_some)).await {
+            Ok(Ok(outcome)) => match outcome.as_ref() {
+                Some(Err(e)) => Err(e.clone()),
+                _ => Ok(WarmupStatus::Ready),
+            },
+            Ok(Err(_)) => Err(IngestError::Internal(
+                "initial ingest task ended without a result".into(),
+            )),
+            Err(_) => Ok(WarmupStatus::WarmingUp),
+        };
+        status
+    }
+}
+
+/// 503 with `Retry-After` for reads arriving during warm-up.
+pub fn warming_up() -> Response {
+    // ---
+    ApiError::Unavailable {
+        error: "warming up",
+        hint: "the initial ingest is still running; retry after the Retry-After delay",
+        retry_after_secs: RETRY_AFTER_SECS,
+    }
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+    use super::*;
+    use axum::http::{header, StatusCode};
+
+    #[test]
+    fn warming_up_is_503_with_retry_after() {
+        // ---
+        let resp = warming_up();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            resp.headers()[header::RETRY_AFTER],
+            RETRY_AFTER_SECS.to_string()
+        );
+        assert!(!Warmup::default().is_running());
+    }
+}
@@ -176,3 +176,2983 @@ async fn timestamp_range_bad_returns_422() -> Result<(), Box<dyn std::error::Err
 
     Ok(())
 }
+
+#[tokio::test]
+async fn cursor_pagination_walks_pages() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let first = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("limit", "5")])
+        .send()
+        .await?;
+    assert!(first.status().is_success());
+    let cursor = first
+        .headers()
+        .get("x-next-cursor")
+        .expect("first page should carry X-Next-Cursor")
+        .to_str()?
+        .to_string();
+    let page1: Vec<SensorReading> = first.json().await?;
+    assert_eq!(page1.len(), 5);
+
+    let page2: Vec<SensorReading> = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("limit", "5"), ("cursor", cursor.as_str())])
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert!(!page2.is_empty());
+
+    // Pages continue in descending timestamp order
+    let last = page1.last().unwrap().timestamp_utc;
+    assert!(page2.iter().all(|r| r.timestamp_utc <= last));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_cursor_returns_400() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    for c in ["garbage", "v1.e30.AAAA", "v9.e30.AAAA"] {
+        let resp = client
+            .get(format!("{base}/sql/readings"))
+            .query(&[("cursor", c)])
+            .send()
+            .await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "cursor={c}");
+        let body: Value = resp.json().await?;
+        assert_eq!(
+            body.get("error"),
+            Some(&Value::String("invalid cursor".into()))
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sampled_readings_are_enveloped() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let body: Value = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("sample", "0.5"), ("limit", "10")])
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(body.get("sampled"), Some(&Value::Bool(true)));
+    assert!(body.get("data").is_some_and(Value::is_array));
+    assert_eq!(body["units"]["temperature"], "celsius");
+    assert_eq!(body["units"]["humidity"], "percent");
+
+    for bad in ["0", "1.5", "-0.1"] {
+        let resp = client
+            .get(format!("{base}/sql/readings"))
+            .query(&[("sample", bad)])
+            .send()
+            .await?;
+        assert_eq!(
+            resp.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "sample={bad}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn device_mesh_reassignment_keeps_history() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    // Device with no readings, so summaries seen by other tests are unaffected
+    let url = format!("{base}/sql/devices/itest-reassign-device/mesh");
+
+    for (mesh, from) in [
+        ("mesh-a", "2025-03-20T00:00:00Z"),
+        ("mesh-b", "2025-03-21T00:00:00Z"),
+    ] {
+        let resp = client
+            .put(&url)
+            .json(&serde_json::json!({ "mesh_id": mesh, "effective_from": from }))
+            .send()
+            .await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let body: Value = client.get(&url).send().await?.json().await?;
+    let meshes: Vec<&str> = body["assignments"]
+        .as_array()
+        .expect("assignments array")
+        .iter()
+        .filter_map(|a| a["mesh_id"].as_str())
+        .collect();
+    assert_eq!(meshes, ["mesh-b", "mesh-a"]);
+
+    let resp = client
+        .put(&url)
+        .json(&serde_json::json!({ "mesh_id": " " }))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_demand_ingest_reports_per_source_counts() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // Trigger the initial ingest first so the re-ingest has nothing new to store
+    client
+        .get(format!("{base}/sql/readings?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let resp = client.post(format!("{base}/sql/ingest")).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = resp.json().await?;
+    let sources = body["sources"].as_array().expect("sources array");
+    assert!(!sources.is_empty());
+    assert!(sources.iter().all(|s| s["inserted"] == 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_events_include_startup() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let events: Vec<Value> = client
+        .get(format!("{base}/admin/events"))
+        .query(&[("kind", "startup")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|e| e["kind"] == "startup"));
+    assert!(events[0]["detail"]["version"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn index_advisor_reports_observed_filters() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("device_id", "device-001"), ("mesh_id", "mesh-001")])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let report: Value = client
+        .get(format!("{base}/admin/index-advisor"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let advice = report["advice"].as_array().expect("advice array");
+    let entry = advice
+        .iter()
+        .find(|a| a["filters"] == serde_json::json!(["device_id", "mesh_id"]))
+        .expect("device+mesh combination recorded");
+    assert_eq!(
+        entry["suggested_index"],
+        serde_json::json!(["device_id", "mesh_id", "timestamp_utc"])
+    );
+
+    // Index creation is never implicit
+    let resp = client
+        .post(format!("{base}/admin/index-advisor/apply"))
+        .json(&serde_json::json!({ "filters": ["device_id", "mesh_id"] }))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pushed_readings_are_stored_once() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch = serde_json::json!([{
+        "mesh_id": "mesh-push-test",
+        "device_id": "device-push-test",
+        "timestamp": "2025-06-01T12:00:00Z",
+        "temperature_c": 21.5,
+        "humidity": 40.0,
+        "status": "ok"
+    }]);
+
+    let mut inserted = Vec::new();
+    for _ in 0..2 {
+        let body: Value = client
+            .post(format!("{base}/sql/readings"))
+            .json(&batch)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(body["received"], 1);
+        inserted.push(body["inserted"].as_u64());
+    }
+    assert_eq!(inserted, [Some(1), Some(0)]);
+
+    let rows: Vec<Value> = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("device_id", "device-push-test")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["mesh_id"], "mesh-push-test");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn request_id_is_echoed_in_headers_and_errors() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let resp = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("timestamp_range", "not-a-range")])
+        .header("x-request-id", "it-req-42")
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(resp.headers()["x-request-id"], "it-req-42");
+    let body: Value = resp.json().await?;
+    assert_eq!(body["request_id"], "it-req-42");
+
+    // Unusable client IDs are replaced, never echoed
+    let resp = client
+        .get(format!("{base}/health"))
+        .header("x-request-id", "bad id!")
+        .send()
+        .await?;
+    let generated = resp.headers()["x-request-id"].to_str()?;
+    assert_eq!(generated.len(), 32);
+    assert_ne!(generated, "bad id!");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn mesh_summary_stream_pushes_changed_aggregates() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    // Make sure a snapshot row exists before subscribing
+    client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut stream = client
+        .get(format!("{base}/sql/stream/mesh-summary"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let first = stream.chunk().await?.expect("snapshot event");
+    assert!(String::from_utf8_lossy(&first).contains("event: aggregate"));
+
+    client
+        .post(format!("{base}/sql/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": "mesh-stream-test",
+            "device_id": "device-stream-test",
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": 30.0,
+            "humidity": 60.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut seen = String::new();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !seen.contains("mesh-stream-test") {
+            match stream.chunk().await? {
+                Some(chunk) => seen.push_str(&String::from_utf8_lossy(&chunk)),
+                None => break,
+            }
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    assert!(seen.contains(r#""reading_count":1"#), "{seen}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn alert_events_export_as_csv_and_ndjson() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let resp = client
+        .get(format!("{base}/alerts/events/export"))
+        .query(&[("kind", "temperature")])
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("text/csv"));
+    let csv = resp.text().await?;
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("id,reading_id,kind,device_id,mesh_id,occurred_at")
+    );
+    assert!(lines.all(|l| l.split(',').nth(2) == Some("temperature")));
+
+    let ndjson = client
+        .get(format!("{base}/alerts/events/export"))
+        .query(&[("format", "ndjson"), ("until", "2000-01-01T00:00:00Z")])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    assert!(ndjson.is_empty());
+
+    let resp = client
+        .get(format!("{base}/alerts/events/export"))
+        .query(&[("format", "xlsx")])
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn legacy_readings_route_signals_deprecation() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let resp = client
+        .get(format!("{base}/sql/readings"))
+        .query(&[("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(resp.headers()["deprecation"].to_str()?.starts_with('@'));
+    assert!(resp.headers()["sunset"].to_str()?.ends_with("GMT"));
+    // Alongside the page links
+    assert!(resp
+        .headers()
+        .get_all("link")
+        .iter()
+        .any(|v| v == "</v1/readings>; rel=\"successor-version\""));
+
+    // The successor is clean unless a deprecated alias is used
+    let resp = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(resp.headers().get("deprecation").is_none());
+
+    let resp = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[
+            ("deviceId", "device-001"),
+            ("limit", "1"),
+            ("envelope", "true"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(resp.headers().get("deprecation").is_some());
+    assert!(resp.headers().get("sunset").is_none());
+    let body: Value = resp.json().await?;
+    let warnings = body["warnings"].as_array().expect("warnings in envelope");
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].as_str().unwrap().contains("'deviceId'"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pushed_copy_of_ingested_reading_is_a_conflict() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // An upstream (source `default`) reading, not one pushed by another test
+    let one: Vec<SensorReading> = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[("device_id", "device-001"), ("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let r = one.first().expect("need at least one reading");
+
+    // Same device and timestamp via a second source, with a different status
+    let batch = serde_json::json!([{
+        "mesh_id": r.mesh_id,
+        "device_id": r.device_id,
+        "timestamp": r.timestamp_utc,
+        "temperature_c": r.temperature_c,
+        "humidity": r.humidity,
+        "status": "reconcile-test"
+    }]);
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let conflicts: Vec<Value> = client
+        .get(format!("{base}/admin/source-conflicts"))
+        .query(&[("device_id", r.device_id.as_str()), ("differing", "true")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let conflict = conflicts
+        .iter()
+        .find(|c| c["timestamp_utc"].as_str().and_then(|t| t.parse().ok()) == Some(r.timestamp_utc))
+        .expect("conflict recorded for the pushed copy");
+    // Without SOURCE_PRIORITY the copy stored first is preferred
+    assert_eq!(conflict["preferred_source"], "default");
+    assert!(conflict["source"].as_str().unwrap().starts_with("push:"));
+    assert_eq!(conflict["values_differ"], true);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metrics_are_served_in_prometheus_format() -> Result<()> {
+    // ---
+    let base = base_url();
+    let resp = Client::new()
+        .get(format!("{base}/metrics"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let content_type = resp.headers()[reqwest::header::CONTENT_TYPE].to_str()?;
+    assert!(content_type.starts_with("text/plain"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pool_stats_report_the_configured_pool() -> Result<()> {
+    // ---
+    let base = base_url();
+    let body: Value = Client::new()
+        .get(format!("{base}/admin/pool"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(body["interval_secs"].as_u64().unwrap() > 0);
+    assert!(body["recent"]["samples"].is_u64());
+
+    // The first sample is taken at startup
+    let current = &body["current"];
+    assert!(current["max"].as_u64().unwrap() >= current["size"].as_u64().unwrap());
+    assert!(current["in_use"].is_u64());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn aggregates_pick_the_coarsest_source() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // Make sure data exist
+    client
+        .get(format!("{base}/sql/readings?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    for (bucket, source) in [("15m", "raw"), ("6h", "hourly"), ("1d", "daily")] {
+        let body: Value = client
+            .get(format!("{base}/v1/aggregates?bucket={bucket}"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(body["source"], source, "bucket={bucket}");
+    }
+
+    // Raw buckets need no refresh, so they are never empty
+    let body: Value = client
+        .get(format!("{base}/v1/aggregates?bucket=15m&limit=5"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let data = body["data"].as_array().expect("data array");
+    assert!(!data.is_empty());
+    for b in data {
+        let t = &b["temperature_c"];
+        assert!(b["reading_count"].as_i64().unwrap() > 0);
+        assert!(t["min"].as_f64() <= t["avg"].as_f64() && t["avg"].as_f64() <= t["max"].as_f64());
+    }
+
+    let resp = client
+        .get(format!("{base}/v1/aggregates?bucket=soon"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = client
+        .get(format!("{base}/v1/aggregates?fill=zero"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn aggregates_fill_empty_buckets() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [("12:00", 20.0), ("12:30", 23.0)]
+        .iter()
+        .map(|(hm, temperature_c)| {
+            serde_json::json!({
+                "mesh_id": "mesh-fill-test",
+                "device_id": "device-fill-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let body: Value = client
+        .get(format!(
+            "{base}/v1/aggregates?bucket=10m&device_id=device-fill-test&fill=linear"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let data = body["data"].as_array().expect("data array");
+    let avgs: Vec<f64> = data
+        .iter()
+        .map(|b| b["temperature_c"]["avg"].as_f64().unwrap_or(f64::NAN))
+        .collect();
+    assert_eq!(avgs, [20.0, 21.0, 22.0, 23.0]);
+    assert_eq!(data[1]["filled"], true);
+    assert_eq!(data[1]["reading_count"], 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn device_registry_entries_join_into_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    let stored: Value = client
+        .put(format!("{base}/sql/devices/device-002"))
+        .json(&serde_json::json!({
+            "mesh_id": "mesh-001",
+            "display_name": "Freezer 2",
+            "location": "Plant A, aisle 4",
+            "installed_on": "2024-05-01"
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(stored["display_name"], "Freezer 2");
+
+    let fetched: Value = client
+        .get(format!("{base}/sql/devices/device-002"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(fetched["installed_on"], "2024-05-01");
+
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-002&limit=3&include=device"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(!readings.is_empty());
+    assert!(readings
+        .iter()
+        .all(|r| r["device"]["display_name"] == "Freezer 2"));
+
+    let resp = client
+        .get(format!("{base}/sql/devices/device-not-registered"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client
+        .get(format!("{base}/v1/readings?include=owner"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn mesh_registry_names_alerts() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // Make sure data (and so alert events) exist
+    client
+        .get(format!("{base}/sql/readings?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let resp = client
+        .put(format!("{base}/sql/meshes/mesh-003"))
+        .json(&serde_json::json!({ "site_name": "Plant C", "timezone": "Mars/Olympus" }))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    client
+        .put(format!("{base}/sql/meshes/mesh-003"))
+        .json(&serde_json::json!({ "site_name": "Plant C", "timezone": "Europe/Berlin" }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let meshes: Vec<Value> = client
+        .get(format!("{base}/sql/meshes"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(meshes.iter().any(|m| m["mesh_id"] == "mesh-003"));
+
+    let alerts: Vec<Value> = client
+        .get(format!("{base}/alerts/events?mesh_id=mesh-003&limit=5"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(!alerts.is_empty());
+    assert!(alerts
+        .iter()
+        .all(|a| a["site_name"] == "Plant C" && a["timezone"] == "Europe/Berlin"));
+
+    let resp = client
+        .delete(format!("{base}/sql/meshes/mesh-003"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = client
+        .get(format!("{base}/sql/meshes/mesh-003"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn bbox_uses_reading_or_device_positions() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let reading = |device: &str, position: Option<(f64, f64)>| {
+        let mut r = serde_json::json!({
+            "mesh_id": "mesh-geo-test",
+            "device_id": device,
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": 21.5,
+            "humidity": 40.0,
+            "status": "ok"
+        });
+        if let Some((lat, lon)) = position {
+            r["latitude"] = lat.into();
+            r["longitude"] = lon.into();
+        }
+        r
+    };
+
+    let mut partial = reading("device-geo-mobile", None);
+    partial["latitude"] = 52.52.into();
+    let resp = client
+        .post(format!("{base}/v1/readings"))
+        .json(&[partial])
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&[
+            reading("device-geo-mobile", Some((52.52, 13.40))), // Berlin
+            reading("device-geo-fixed", None),
+            reading("device-geo-unplaced", None),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    client
+        .put(format!("{base}/sql/devices/device-geo-fixed"))
+        .json(&serde_json::json!({ "latitude": 48.14, "longitude": 11.58 })) // Munich
+        .send()
+        .await?
+        .error_for_status()?;
+
+    async fn devices_in(client: &Client, base: &str, bbox: &str) -> Result<Vec<String>> {
+        // ---
+        let readings: Vec<Value> = client
+            .get(format!(
+                "{base}/v1/readings?mesh_id=mesh-geo-test&bbox={bbox}"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut ids: Vec<String> = readings
+            .iter()
+            .map(|r| r["device_id"].as_str().unwrap_or_default().to_string())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+    assert_eq!(
+        devices_in(&client, &base, "13.0,52.3,13.8,52.7").await?,
+        ["device-geo-mobile"]
+    );
+    assert_eq!(
+        devices_in(&client, &base, "5.0,47.0,15.0,55.0").await?,
+        ["device-geo-fixed", "device-geo-mobile"]
+    );
+
+    let resp = client
+        .get(format!("{base}/v1/readings?bbox=13.0,52.7,13.8,52.3"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn extra_measurements_are_kept_and_filterable() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch = serde_json::json!([
+        {
+            "mesh_id": "mesh-extra-test",
+            "device_id": "device-barometer",
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": 21.5,
+            "humidity": 40.0,
+            "status": "ok",
+            "pressure": 1013.2,
+            "firmware": "1.4.2"
+        },
+        {
+            "mesh_id": "mesh-extra-test",
+            "device_id": "device-plain",
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": 21.5,
+            "humidity": 40.0,
+            "status": "ok"
+        }
+    ]);
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?mesh_id=mesh-extra-test&extra_key=pressure"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["device_id"], "device-barometer");
+    assert_eq!(
+        readings[0]["extra"],
+        serde_json::json!({ "pressure": 1013.2 })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn calibration_offsets_apply_to_new_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    client
+        .put(format!("{base}/admin/calibration/device-calib-test"))
+        .json(&serde_json::json!({ "temperature_offset": -1.5 }))
+        .send()
+        .await?
+        .error_for_status()?;
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": "mesh-calib-test",
+            "device_id": "device-calib-test",
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": 61.0,
+            "humidity": 40.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let readings: Vec<Value> = client
+        .get(format!("{base}/v1/readings?device_id=device-calib-test"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["temperature_c"], 59.5);
+    assert_eq!(readings[0]["raw_temperature_c"], 61.0);
+    assert_eq!(readings[0]["temperature_alert"], false);
+
+    let url = format!("{base}/admin/calibration/device-calib-test");
+    let resp = client.delete(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = client.delete(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readings_filter_by_quality_score() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let reading = |device: &str, temperature_c: f32| {
+        serde_json::json!({
+            "mesh_id": "mesh-quality-test",
+            "device_id": device,
+            "timestamp": "2025-06-01T12:00:00Z",
+            "temperature_c": temperature_c,
+            "humidity": 40.0,
+            "status": "ok"
+        })
+    };
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&[
+            reading("device-sane", 21.5),
+            reading("device-broken", 150.0),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let readings: Vec<Value> = client
+        .get(format!("{base}/v1/readings?mesh_id=mesh-quality-test"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let broken = readings
+        .iter()
+        .find(|r| r["device_id"] == "device-broken")
+        .expect("broken reading stored");
+    assert_eq!(broken["quality"], 0.5);
+    assert_eq!(broken["quality_flags"], serde_json::json!(["out_of_range"]));
+
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?mesh_id=mesh-quality-test&min_quality=0.75"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["device_id"], "device-sane");
+    assert_eq!(readings[0]["quality"], 1.0);
+
+    let resp = client
+        .get(format!("{base}/v1/readings?min_quality=1.5"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn gaps_report_intervals_without_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = ["12:00", "12:05", "12:30"]
+        .iter()
+        .map(|hm| {
+            serde_json::json!({
+                "mesh_id": "mesh-gap-test",
+                "device_id": "device-gap-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": 21.5,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let url = format!("{base}/sql/devices/device-gap-test/gaps");
+    let report: Value = client
+        .get(format!("{url}?min_gap=10m"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(report["gap_count"], 1);
+    assert_eq!(report["gaps"][0]["start"], "2025-06-01T12:05:00Z");
+    assert_eq!(report["gaps"][0]["duration_secs"], 1500.0);
+
+    // Range bounds count too: 11:00-12:00, 12:05-12:30 and 12:30-13:00
+    let report: Value = client
+        .get(format!(
+            "{url}?min_gap=10m&timestamp_range=2025-06-01T11:00:00Z,2025-06-01T13:00:00Z"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(report["gap_count"], 3);
+    assert_eq!(report["total_gap_secs"], 6900.0);
+
+    let resp = client.get(format!("{url}?min_gap=soon")).send().await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn anomalies_flag_outliers_against_the_window() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = (0..13)
+        .map(|minute| {
+            let temperature_c = match minute {
+                12 => 30.0,
+                m if m % 2 == 0 => 20.0,
+                _ => 20.4,
+            };
+            serde_json::json!({
+                "mesh_id": "mesh-anomaly-test",
+                "device_id": "device-anomaly-test",
+                "timestamp": format!("2025-06-01T12:{minute:02}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0 + (minute % 3) as f64,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let body: Value = client
+        .get(format!(
+            "{base}/sql/anomalies?device_id=device-anomaly-test&window=1h&zscore=3"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let anomalies = body["anomalies"].as_array().expect("anomalies array");
+    assert_eq!(anomalies.len(), 1);
+    assert_eq!(anomalies[0]["timestamp_utc"], "2025-06-01T12:12:00Z");
+    assert_eq!(
+        anomalies[0]["flagged"],
+        serde_json::json!(["temperature_c"])
+    );
+    assert!(anomalies[0]["temperature_c"]["zscore"].as_f64().unwrap() > 3.0);
+
+    let resp = client
+        .get(format!("{base}/sql/anomalies?zscore=-1"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn smoothing_adds_ewma_alongside_raw_values() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [("12:00", 20.0), ("12:10", 30.0), ("12:20", 30.0)]
+        .iter()
+        .map(|(hm, temperature_c)| {
+            serde_json::json!({
+                "mesh_id": "mesh-smooth-test",
+                "device_id": "device-smooth-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // Newest first: 20 -> 25 -> 27.5 with alpha 0.5
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-smooth-test&smooth=ewma&alpha=0.5"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let smoothed: Vec<f64> = readings
+        .iter()
+        .map(|r| r["smoothed"]["temperature_c"].as_f64().unwrap_or(f64::NAN))
+        .collect();
+    assert_eq!(smoothed, [27.5, 25.0, 20.0]);
+    assert_eq!(readings[0]["temperature_c"], 30.0);
+
+    let body: Value = client
+        .get(format!(
+            "{base}/v1/aggregates?bucket=10m&device_id=device-smooth-test&smooth=ewma&alpha=0.5"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["data"][2]["smoothed"]["temperature_c"], 27.5);
+
+    let status = client
+        .get(format!("{base}/v1/readings?smooth=ewma&alpha=1.5"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rolling_averages_span_page_boundaries() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [("12:00", 10.0), ("12:10", 20.0), ("12:20", 30.0)]
+        .iter()
+        .map(|(hm, temperature_c)| {
+            serde_json::json!({
+                "mesh_id": "mesh-rolling-test",
+                "device_id": "device-rolling-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // The window reaches back past the one row returned
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-rolling-test&rolling_avg=2&limit=1"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["rolling_avg"]["temperature_c"], 25.0);
+    assert_eq!(readings[0]["rolling_avg"]["humidity"], 40.0);
+
+    let status = client
+        .get(format!("{base}/v1/readings?rolling_avg=0"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stats_describe_the_distribution() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [
+        ("12:00", 10.0),
+        ("12:10", 11.0),
+        ("12:20", 14.0),
+        ("12:30", 20.0),
+    ]
+    .iter()
+    .map(|(hm, temperature_c)| {
+        serde_json::json!({
+            "mesh_id": "mesh-histogram-test",
+            "device_id": "device-histogram-test",
+            "timestamp": format!("2025-06-01T{hm}:00Z"),
+            "temperature_c": temperature_c,
+            "humidity": 40.0,
+            "status": "ok"
+        })
+    })
+    .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let body: Value = client
+        .get(format!(
+            "{base}/sql/stats/histogram?metric=temperature_c&buckets=2&device_id=device-histogram-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["count"], 4);
+    assert_eq!(body["min"], 10.0);
+    assert_eq!(body["max"], 20.0);
+    let counts: Vec<i64> = body["histogram"]
+        .as_array()
+        .expect("histogram array")
+        .iter()
+        .map(|b| b["count"].as_i64().unwrap_or(-1))
+        .collect();
+    assert_eq!(counts, [3, 1]);
+
+    // 10, 11, 14, 20: the median interpolates between 11 and 14
+    let body: Value = client
+        .get(format!(
+            "{base}/sql/stats/percentiles?metric=temperature_c&p=0,50,100&device_id=device-histogram-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["count"], 4);
+    let values: Vec<f64> = body["percentiles"]
+        .as_array()
+        .expect("percentiles array")
+        .iter()
+        .map(|p| p["value"].as_f64().unwrap_or(f64::NAN))
+        .collect();
+    assert_eq!(values, [10.0, 12.5, 20.0]);
+
+    let body: Value = client
+        .get(format!(
+            "{base}/sql/stats?group_by=device&device_id=device-histogram-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let group = &body["groups"][0];
+    assert_eq!(group["device_id"], "device-histogram-test");
+    assert_eq!(group["count"], 4);
+    assert_eq!(group["temperature_c"]["min"], 10.0);
+    assert_eq!(group["temperature_c"]["max"], 20.0);
+    assert_eq!(group["temperature_c"]["avg"], 13.75);
+    assert_eq!(group["humidity"]["stddev"], 0.0);
+
+    let status = client
+        .get(format!("{base}/sql/stats/histogram?metric=pressure"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn count_matches_the_filtered_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = ["12:00", "12:10", "12:20"]
+        .iter()
+        .map(|hm| {
+            serde_json::json!({
+                "mesh_id": "mesh-count-test",
+                "device_id": "device-count-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": 21.0,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let body: Value = client
+        .get(format!(
+            "{base}/v1/readings/count?device_id=device-count-test&timestamp_range=2025-06-01T12:05:00Z,"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["earliest"], "2025-06-01T12:10:00Z");
+    assert_eq!(body["latest"], "2025-06-01T12:20:00Z");
+
+    let none: Value = client
+        .get(format!(
+            "{base}/v1/readings/count?device_id=device-count-none"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(none["count"], 0);
+    assert!(none["earliest"].is_null());
+
+    let legacy = client
+        .get(format!(
+            "{base}/sql/readings/count?device_id=device-count-test"
+        ))
+        .send()
+        .await?;
+    assert!(legacy.headers().contains_key("deprecation"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn top_alerting_devices_are_ranked() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let now = chrono::Utc::now();
+    let batch: Vec<Value> = [
+        ("device-top-a", 1, 70.0, 95.0),
+        ("device-top-a", 2, 70.0, 50.0),
+        ("device-top-b", 1, 21.0, 95.0),
+    ]
+    .iter()
+    .map(|(device_id, minutes_ago, temperature_c, humidity)| {
+        serde_json::json!({
+            "mesh_id": "mesh-top-test",
+            "device_id": device_id,
+            "timestamp": now - chrono::Duration::minutes(*minutes_ago),
+            "temperature_c": temperature_c,
+            "humidity": humidity,
+            "status": "ok"
+        })
+    })
+    .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let body: Value = client
+        .get(format!(
+            "{base}/sql/alerts/top?window=1h&n=2&mesh_id=mesh-top-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let devices = body["devices"].as_array().expect("devices array");
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0]["device_id"], "device-top-a");
+    assert_eq!(devices[0]["total"], 3);
+    assert_eq!(devices[0]["temperature"], 2);
+    assert_eq!(devices[0]["humidity"], 1);
+    assert_eq!(devices[1]["device_id"], "device-top-b");
+
+    let status = client
+        .get(format!("{base}/sql/alerts/top?n=0"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sort_orders_and_pages_by_the_requested_key() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [("12:00", 25.0), ("12:10", 21.0), ("12:20", 23.0)]
+        .iter()
+        .map(|(hm, temperature_c)| {
+            serde_json::json!({
+                "mesh_id": "mesh-sort-test",
+                "device_id": "device-sort-test",
+                "timestamp": format!("2025-06-01T{hm}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // Page through one row at a time, hottest first
+    let url =
+        format!("{base}/v1/readings?device_id=device-sort-test&sort=temperature_desc&limit=1");
+    let mut temperatures = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut req = client.get(&url);
+        if let Some(c) = &cursor {
+            req = req.query(&[("cursor", c)]);
+        }
+        let resp = req.send().await?.error_for_status()?;
+        cursor = resp
+            .headers()
+            .get("x-next-cursor")
+            .map(|v| v.to_str().map(str::to_string))
+            .transpose()?;
+        let page: Vec<Value> = resp.json().await?;
+        temperatures.extend(
+            page.iter()
+                .map(|r| r["temperature_c"].as_f64().unwrap_or(f64::NAN)),
+        );
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(temperatures, [25.0, 23.0, 21.0]);
+
+    // A cursor only continues the order it was issued for
+    let resp = client.get(&url).send().await?;
+    let token = resp.headers()["x-next-cursor"].to_str()?.to_string();
+    let status = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-sort-test&limit=1"
+        ))
+        .query(&[("cursor", token)])
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let status = client
+        .get(format!("{base}/v1/readings?sort=random"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fields_select_a_subset_of_each_reading() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-001&limit=3&fields=device_id,temperature_c"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(!readings.is_empty());
+    for r in &readings {
+        let mut keys: Vec<&str> = r
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["device_id", "temperature_c"]);
+        assert_eq!(r["device_id"], "device-001");
+    }
+
+    let body: Value = client
+        .get(format!(
+            "{base}/v1/readings?limit=2&envelope=true&fields=timestamp_utc&sort=humidity_desc"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(body["data"][0]["timestamp_utc"].is_string());
+    assert!(body["data"][0].get("humidity").is_none());
+
+    let status = client
+        .get(format!("{base}/v1/readings?fields=device_id,password"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn device_filter_accepts_several_values() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    for query in [
+        "device_id=device-001&device_id=device-002",
+        "device_id=device-001,device-002",
+    ] {
+        let readings: Vec<Value> = client
+            .get(format!("{base}/v1/readings?{query}&limit=1000"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut devices: Vec<&str> = readings
+            .iter()
+            .filter_map(|r| r["device_id"].as_str())
+            .collect();
+        devices.sort_unstable();
+        devices.dedup();
+        assert_eq!(devices, ["device-001", "device-002"], "{query}");
+    }
+
+    let many = vec!["d"; 101].join(",");
+    let status = client
+        .get(format!("{base}/v1/readings?device_id={many}"))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn prefix_filters_match_literally() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let readings: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id_prefix=device-00&limit=50"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(!readings.is_empty());
+    assert!(readings.iter().all(|r| r["device_id"]
+        .as_str()
+        .unwrap_or_default()
+        .starts_with("device-00")));
+
+    // `%` is no wildcard
+    let readings: Vec<Value> = client
+        .get(format!("{base}/v1/readings?device_id_prefix=%25"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(readings.is_empty());
+
+    let status = client
+        .get(format!("{base}/v1/readings?mesh_id_prefix="))
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn status_filters_pick_degraded_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = ["ok", "degraded", "faulty"]
+        .iter()
+        .enumerate()
+        .map(|(i, status)| {
+            serde_json::json!({
+                "mesh_id": "mesh-status-test",
+                "device_id": "device-status-test",
+                "timestamp": format!("2025-06-03T12:0{i}:00Z"),
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": status
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    for (query, expected) in [
+        ("status=degraded", vec!["degraded"]),
+        ("status=degraded&status=faulty", vec!["faulty", "degraded"]),
+        ("status_not=ok", vec!["faulty", "degraded"]),
+    ] {
+        let readings: Vec<Value> = client
+            .get(format!(
+                "{base}/v1/readings?device_id=device-status-test&{query}"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let statuses: Vec<&str> = readings
+            .iter()
+            .filter_map(|r| r["status"].as_str())
+            .collect();
+        assert_eq!(statuses, expected, "{query}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn alerts_only_keeps_flagged_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = [(20.0, 40.0), (70.0, 40.0), (20.0, 95.0)]
+        .iter()
+        .enumerate()
+        .map(|(i, (temperature_c, humidity))| {
+            serde_json::json!({
+                "mesh_id": "mesh-alerts-only-test",
+                "device_id": "device-alerts-only-test",
+                "timestamp": format!("2025-06-04T12:0{i}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": humidity,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let url = format!("{base}/v1/readings?device_id=device-alerts-only-test");
+    for (query, expected) in [
+        ("alerts_only=true", 2),
+        ("alert_type=temperature", 1),
+        ("alerts_only=true&alert_type=humidity", 1),
+        ("alerts_only=false", 3),
+    ] {
+        let readings: Vec<Value> = client
+            .get(format!("{url}&{query}"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(readings.len(), expected, "{query}");
+    }
+
+    let readings: Vec<Value> = client
+        .get(format!("{url}&temp_min=15&temp_max=25"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 2);
+
+    let readings: Vec<Value> = client
+        .get(format!("{url}&humidity_min=85"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["humidity"], 95.0);
+
+    for query in [
+        "alert_type=pressure",
+        "alerts_only=false&alert_type=humidity",
+        "temp_min=30&temp_max=20",
+        "humidity_min=90&humidity_max=10",
+    ] {
+        let status = client.get(format!("{url}&{query}")).send().await?.status();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{query}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn relative_time_ranges_resolve_against_now() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let now = Utc::now();
+    let recent = [
+        (now - chrono::Duration::minutes(5)).to_rfc3339(),
+        (now - chrono::Duration::hours(2)).to_rfc3339(),
+    ];
+    let batch: Vec<Value> = recent
+        .iter()
+        .map(|timestamp| {
+            serde_json::json!({
+                "mesh_id": "mesh-since-test",
+                "device_id": "device-since-test",
+                "timestamp": timestamp,
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let url = format!("{base}/v1/readings?device_id=device-since-test");
+    for (query, expected) in [("since=15m", 1), ("timestamp_range=last_24h", 2)] {
+        let readings: Vec<Value> = client
+            .get(format!("{url}&{query}"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(readings.len(), expected, "{query}");
+    }
+
+    for query in ["since=soon", "since=15m&timestamp_range=last_1h"] {
+        let status = client.get(format!("{url}&{query}")).send().await?.status();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{query}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readings_localize_times_and_units() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": "mesh-tz-test",
+            "device_id": "device-tz-test",
+            "timestamp": "2025-06-05T12:00:00Z",
+            "temperature_c": 20.0,
+            "humidity": 40.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+    client
+        .put(format!("{base}/sql/meshes/mesh-tz-test"))
+        .json(&serde_json::json!({ "timezone": "Asia/Kolkata" }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let url = format!("{base}/v1/readings?device_id=device-tz-test");
+    for (query, expected) in [
+        ("", "2025-06-05T17:30:00+05:30"),
+        ("&tz=America/New_York", "2025-06-05T08:00:00-04:00"),
+        ("&fields=timestamp_local", "2025-06-05T17:30:00+05:30"),
+    ] {
+        let readings: Vec<Value> = client
+            .get(format!("{url}{query}"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(readings[0]["timestamp_local"], expected, "{query}");
+    }
+
+    let body: Value = client
+        .get(format!("{url}&units=imperial&envelope=true"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["units"]["temperature"], "fahrenheit");
+    assert_eq!(body["data"][0]["temperature_f"], 68.0);
+    assert!(body["data"][0].get("temperature_c").is_none());
+
+    for query in ["tz=Mars/Olympus", "units=kelvin"] {
+        let status = client.get(format!("{url}&{query}")).send().await?.status();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{query}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn envelope_reports_total_and_limit() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch: Vec<Value> = (0..3)
+        .map(|i| {
+            serde_json::json!({
+                "mesh_id": "mesh-total-test",
+                "device_id": "device-total-test",
+                "timestamp": format!("2025-06-06T12:0{i}:00Z"),
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let resp = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-total-test&limit=2&envelope=true"
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["x-total-count"], "3");
+    let body: Value = resp.json().await?;
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["limit"], 2);
+    assert_eq!(body["data"].as_array().map(Vec::len), Some(2));
+    assert!(body["next_cursor"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn meta_echoes_the_applied_filters() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let body: Value = client
+        .get(format!(
+            "{base}/v1/readings?device=device-001&device_id=device-002&since=1h&alerts_only=true&meta=true"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(body["data"].is_array());
+    let meta = &body["meta"];
+    assert_eq!(
+        meta["filters"]["device_id"],
+        serde_json::json!(["device-001", "device-002"])
+    );
+    assert_eq!(meta["filters"]["alert"], "any");
+    assert!(meta["filters"]["start"].is_string());
+    assert!(meta["filters"].get("end").is_none());
+    assert_eq!(meta["sort"], "timestamp_desc");
+    assert!(meta["took_ms"].is_u64());
+    assert!(meta["latest_reading"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unchanged_readings_answer_304() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let push = |minute: u32| {
+        client
+            .post(format!("{base}/v1/readings"))
+            .json(&serde_json::json!([{
+                "mesh_id": "mesh-etag-test",
+                "device_id": "device-etag-test",
+                "timestamp": format!("2025-06-07T12:{minute:02}:00Z"),
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": "ok"
+            }]))
+            .send()
+    };
+    push(0).await?.error_for_status()?;
+
+    let url = format!("{base}/v1/readings?device_id=device-etag-test");
+    let resp = client.get(&url).send().await?.error_for_status()?;
+    let etag = resp.headers()["etag"].to_str()?.to_string();
+    assert!(etag.starts_with("W/\""), "{etag}");
+
+    let resp = client
+        .get(&url)
+        .header("if-none-match", &etag)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    // A new reading changes the tag
+    push(1).await?.error_for_status()?;
+    let resp = client
+        .get(&url)
+        .header("if-none-match", &etag)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_ne!(resp.headers()["etag"], etag.as_str());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn up_to_date_if_modified_since_answers_304() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let push = |minute: u32| {
+        client
+            .post(format!("{base}/v1/readings"))
+            .json(&serde_json::json!([{
+                "mesh_id": "mesh-ims-test",
+                "device_id": "device-ims-test",
+                "timestamp": format!("2025-06-08T12:{minute:02}:00Z"),
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": "ok"
+            }]))
+            .send()
+    };
+    push(0).await?.error_for_status()?;
+
+    for url in [
+        format!("{base}/v1/readings?mesh_id=mesh-ims-test"),
+        format!("{base}/sql/stats?mesh_id=mesh-ims-test"),
+    ] {
+        let resp = client.get(&url).send().await?.error_for_status()?;
+        let last_modified = resp.headers()["last-modified"].to_str()?.to_string();
+        assert!(last_modified.ends_with(" GMT"), "{last_modified}");
+
+        let resp = client
+            .get(&url)
+            .header("if-modified-since", &last_modified)
+            .send()
+            .await?;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED, "{url}");
+        assert_eq!(resp.headers()["last-modified"], last_modified.as_str());
+    }
+
+    // An ingest in a later second moves it on
+    let url = format!("{base}/v1/readings?mesh_id=mesh-ims-test");
+    let last_modified = client.get(&url).send().await?.headers()["last-modified"]
+        .to_str()?
+        .to_string();
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    push(1).await?.error_for_status()?;
+    let resp = client
+        .get(&url)
+        .header("if-modified-since", &last_modified)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_ne!(resp.headers()["last-modified"], last_modified.as_str());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_removes_matching_readings() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let readings: Vec<Value> = (0..3)
+        .map(|hour| {
+            serde_json::json!({
+                "mesh_id": "mesh-delete-test",
+                "device_id": "device-delete-test",
+                "timestamp": format!("2025-06-09T{hour:02}:00:00Z"),
+                "temperature_c": 20.0,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&readings)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let resp = client.delete(format!("{base}/v1/readings")).send().await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = client
+        .delete(format!(
+            "{base}/v1/readings?device_id=device-delete-test&timestamp_range=2025-06-09T01:00:00Z,"
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let body: Value = resp.json().await?;
+    assert_eq!(body["deleted"], 2);
+
+    let count: Value = client
+        .get(format!(
+            "{base}/v1/readings/count?device_id=device-delete-test"
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(count["count"], 1);
+    assert_eq!(count["latest"], "2025-06-09T00:00:00Z");
+
+    // Re-pushing a deleted reading doesn't revive it; restoring does
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&readings)
+        .send()
+        .await?
+        .error_for_status()?;
+    let body: Value = client
+        .post(format!(
+            "{base}/admin/readings/restore?device_id=device-delete-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["restored"], 2);
+
+    let count: Value = client
+        .get(format!(
+            "{base}/v1/readings/count?device_id=device-delete-test"
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(count["count"], 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reading_is_fetched_by_id() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let listed: Vec<Value> = client
+        .get(format!("{base}/v1/readings?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let id = listed[0]["id"].as_i64().expect("readings carry their id");
+
+    let resp = client
+        .get(format!("{base}/v1/readings/{id}"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let reading: Value = resp.json().await?;
+    assert_eq!(reading["id"], id);
+    assert_eq!(reading["device_id"], listed[0]["device_id"]);
+    assert_eq!(reading["timestamp_utc"], listed[0]["timestamp_utc"]);
+
+    let resp = client
+        .get(format!("{base}/v1/readings/999999999"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = client
+        .get(format!("{base}/v1/readings/latest"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrections_fix_values_and_keep_the_originals() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": "mesh-correction-test",
+            "device_id": "device-correction-test",
+            "timestamp": "2025-06-10T00:00:00Z",
+            "temperature_c": 20.0,
+            "humidity": 40.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+    let listed: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-correction-test"
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let url = format!("{base}/v1/readings/{}", listed[0]["id"]);
+    let patch = |body: Value| client.patch(&url).json(&body).send();
+    let alerts = || async {
+        let events: Vec<Value> = client
+            .get(format!(
+                "{base}/alerts/events?device_id=device-correction-test"
+            ))
+            .send()
+            .await?
+            .json()
+            .await?;
+        anyhow::Ok(events.len())
+    };
+
+    let resp = patch(serde_json::json!({})).await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let reading: Value = patch(serde_json::json!({ "temperature_c": 99.0 }))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(reading["temperature_alert"], true);
+    assert_eq!(alerts().await?, 1);
+
+    let reading: Value = patch(serde_json::json!({ "temperature_c": 21.0, "reason": "seized" }))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(reading["temperature_c"], 21.0);
+    assert_eq!(reading["temperature_alert"], false);
+    let audit = reading["corrections"].as_array().unwrap().last().unwrap();
+    assert_eq!(audit["original"]["temperature_c"], 99.0);
+    assert_eq!(audit["reason"], "seized");
+    assert_eq!(alerts().await?, 0);
+
+    let reading: Value = patch(serde_json::json!({ "invalid": true }))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(reading["invalid"], true);
+    patch(serde_json::json!({ "invalid": false }))
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn idempotency_keys_replay_the_first_response() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let key = format!(
+        "test-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    );
+    let batch = serde_json::json!([{
+        "mesh_id": "mesh-idempotency-test",
+        "device_id": "device-idempotency-test",
+        "timestamp": Utc::now().to_rfc3339(),
+        "temperature_c": 20.0,
+        "humidity": 40.0,
+        "status": "ok"
+    }]);
+    let push = |body: &Value| {
+        client
+            .post(format!("{base}/v1/readings"))
+            .header("idempotency-key", &key)
+            .json(body)
+            .send()
+    };
+
+    let first = push(&batch).await?.error_for_status()?;
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first: Value = first.json().await?;
+    assert_eq!(first["inserted"], 1);
+
+    let retry = push(&batch).await?.error_for_status()?;
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    let retry: Value = retry.json().await?;
+    assert_eq!(retry, first);
+
+    let resp = push(&serde_json::json!([])).await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readings_trace_back_to_their_ingest_batch() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch = serde_json::json!([{
+        "mesh_id": "mesh-lineage-test",
+        "device_id": "device-lineage-test",
+        "timestamp": Utc::now().to_rfc3339(),
+        "temperature_c": 20.0,
+        "humidity": 40.0,
+        "status": "ok"
+    }]);
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let listed: Vec<Value> = client
+        .get(format!(
+            "{base}/v1/readings?device_id=device-lineage-test&limit=1"
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let id = listed[0]["ingest_batch_id"]
+        .as_i64()
+        .expect("stored readings carry their batch");
+
+    let batch: Value = client
+        .get(format!("{base}/admin/ingest/batches/{id}"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(batch["source"].as_str().unwrap().starts_with("push:"));
+    assert_eq!(batch["rows"], 1);
+    assert!(batch["finished_at"].is_string());
+
+    let batches: Vec<Value> = client
+        .get(format!(
+            "{base}/admin/ingest/batches?source=default&limit=1"
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert!(batches[0]["pages"].as_i64().unwrap() > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ingest_status_reports_running_and_finished_batches() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let batch = serde_json::json!([{
+        "mesh_id": "mesh-status-test",
+        "device_id": "device-status-test",
+        "timestamp": Utc::now().to_rfc3339(),
+        "temperature_c": 20.0,
+        "humidity": 40.0,
+        "status": "ok"
+    }]);
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let status: Value = client
+        .get(format!("{base}/admin/ingest/status"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    for running in status["running"].as_array().unwrap() {
+        assert!(running["finished_at"].is_null());
+        assert!(running["stalled"].is_boolean());
+        assert!(running["idle_secs"].as_f64().unwrap() >= 0.0);
+    }
+    let finished = status["last_finished"].as_array().unwrap();
+    let pushed = finished
+        .iter()
+        .find(|b| b["source"].as_str().unwrap().starts_with("push:"))
+        .expect("the push is a finished batch");
+    assert_eq!(pushed["trigger"], "push");
+    let upstream = finished
+        .iter()
+        .find(|b| b["source"] == "default")
+        .expect("the upstream source was ingested");
+    assert!(upstream["pages"].as_i64().unwrap() > 0);
+    assert!(upstream["progressed_at"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn maintenance_mode_is_toggled_by_admins() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let toggle = |body: Value| {
+        client
+            .put(format!("{base}/admin/maintenance"))
+            .json(&body)
+            .send()
+    };
+
+    let on: Value = toggle(serde_json::json!({
+        "enabled": true,
+        "message": "integration test",
+        "retry_after_secs": 5
+    }))
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    assert_eq!(on["enabled"], true);
+    assert_eq!(on["message"], "integration test");
+    assert!(on["since"].is_string());
+
+    // Admins keep full access while it's on
+    let status: Value = client
+        .get(format!("{base}/admin/maintenance"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(status["retry_after_secs"], 5);
+
+    let off: Value = toggle(serde_json::json!({ "enabled": false }))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(off, serde_json::json!({ "enabled": false }));
+
+    let resp = toggle(serde_json::json!({ "enabled": "yes" })).await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_reports_the_settings_in_effect() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // The test server's settings all come from its environment, which a
+    // reload leaves as it was
+    let reloaded: Value = client
+        .post(format!("{base}/admin/reload"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(reloaded["changed"], serde_json::json!([]));
+    assert!(reloaded["sources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|s| s == "default"));
+    assert!(reloaded.get("rate_limit").is_some());
+
+    let events: Vec<Value> = client
+        .get(format!("{base}/admin/events?kind=config_reloaded"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert!(events.iter().any(|e| e["detail"]["trigger"] == "api"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn version_reports_the_build() -> Result<()> {
+    // ---
+    let base = base_url();
+    let version: Value = Client::new()
+        .get(format!("{base}/version"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!version["git_sha"].as_str().unwrap().is_empty());
+    assert!(version["build_timestamp"].is_string());
+    assert!(version["features"].is_array());
+    assert_eq!(version["environment"], "dev");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_headers_walk_the_pages() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let links = |resp: &reqwest::Response| -> Vec<String> {
+        resp.headers()
+            .get_all("link")
+            .iter()
+            .filter_map(|v| v.to_str().ok().map(str::to_string))
+            .collect()
+    };
+    let link_to = |links: &[String], rel: &str| {
+        links
+            .iter()
+            .find(|l| l.ends_with(&format!("rel=\"{rel}\"")))
+            .and_then(|l| l.strip_prefix('<')?.split_once('>'))
+            .map(|(url, _)| url.to_string())
+    };
+
+    let resp = client
+        .get(format!("{base}/v1/readings?device_id=device-001&limit=1"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let first = links(&resp);
+    let next = link_to(&first, "next").expect("next link");
+    assert!(next.starts_with(&format!("{base}/v1/readings?")), "{next}");
+    assert!(link_to(&first, "first").is_none());
+
+    let resp = client.get(&next).send().await?.error_for_status()?;
+    let page: Vec<Value> = {
+        let second = links(&resp);
+        assert_eq!(
+            link_to(&second, "first").as_deref(),
+            Some(format!("{base}/v1/readings?device_id=device-001&limit=1").as_str())
+        );
+        resp.json().await?
+    };
+    assert_eq!(page.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_are_compressed_when_accepted() -> Result<()> {
+    // ---
+    let base = base_url();
+    // Without automatic decompression, so the encoding stays visible
+    let client = Client::builder().no_gzip().no_brotli().build()?;
+    let url = format!("{base}/v1/readings?limit=100");
+
+    let resp = client
+        .get(&url)
+        .header("accept-encoding", "gzip")
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+
+    let resp = client
+        .get(&url)
+        .header("accept-encoding", "br")
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "br");
+
+    let resp = client.get(&url).send().await?.error_for_status()?;
+    assert!(resp.headers().get("content-encoding").is_none());
+    let readings: Vec<Value> = resp.json().await?;
+    assert!(!readings.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ready_reports_ok_once_serving() -> Result<()> {
+    // ---
+    let base = base_url();
+    let resp = Client::new().get(format!("{base}/ready")).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await?;
+    assert_eq!(body["status"], "ready");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn alert_context_includes_triggering_reading() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+
+    // Make sure data (and so alert events) exist
+    client
+        .get(format!("{base}/sql/readings?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let alerts: Vec<Value> = client
+        .get(format!("{base}/alerts/events?limit=1"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let alert = alerts.first().expect("dataset contains alerts");
+    let id = alert["id"].as_i64().expect("alert id");
+
+    let ctx: Value = client
+        .get(format!("{base}/alerts/events/{id}/context?window=2h"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let readings = ctx["readings"].as_array().expect("readings array");
+    let trigger: Vec<&Value> = readings
+        .iter()
+        .filter(|r| r["is_trigger"] == true)
+        .collect();
+    assert_eq!(trigger.len(), 1);
+    assert_eq!(trigger[0]["id"], alert["reading_id"]);
+    assert!(readings
+        .iter()
+        .all(|r| r["device_id"] == alert["device_id"]));
+
+    let resp = client
+        .get(format!("{base}/alerts/events/{id}/context?window=1w"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = client
+        .get(format!("{base}/alerts/events/-1/context"))
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+/// `(reading_count, avg_temperature_c, avg_humidity)` of `mesh` in the
+/// snapshot the mesh summary stream opens with.
+async fn streamed_summary(client: &Client, base: &str, mesh: &str) -> Result<(i64, f64, f64)> {
+    // ---
+    let mut stream = client
+        .get(format!("{base}/sql/stream/mesh-summary"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let needle = format!(r#""mesh_id":"{mesh}""#);
+    let mut seen = String::new();
+    let line = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let complete = seen.split_inclusive('\n').filter(|l| l.ends_with('\n'));
+            if let Some(line) = complete.into_iter().find(|l| l.contains(&needle)) {
+                return anyhow::Ok(line.to_string());
+            }
+            let chunk = stream.chunk().await?.expect("summary event for the mesh");
+            seen.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    })
+    .await??;
+    let row: Value = serde_json::from_str(line.trim().trim_start_matches("data:").trim())?;
+    Ok((
+        row["reading_count"].as_i64().unwrap_or(-1),
+        row["avg_temperature_c"].as_f64().unwrap_or(f64::NAN),
+        row["avg_humidity"].as_f64().unwrap_or(f64::NAN),
+    ))
+}
+
+/// The same figures computed over every stored reading of `mesh`.
+async fn recomputed_summary(client: &Client, base: &str, mesh: &str) -> Result<(i64, f64, f64)> {
+    // ---
+    let body: Value = client
+        .get(format!("{base}/sql/stats"))
+        .query(&[("mesh_id", mesh)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let group = &body["groups"][0];
+    Ok((
+        group["count"].as_i64().unwrap_or(-1),
+        group["temperature_c"]["avg"].as_f64().unwrap_or(f64::NAN),
+        group["humidity"]["avg"].as_f64().unwrap_or(f64::NAN),
+    ))
+}
+
+#[tokio::test]
+async fn mesh_summary_matches_a_full_recompute() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let mesh = "mesh-totals-test";
+    let assert_consistent = |step: &'static str| {
+        let (client, base) = (&client, &base);
+        async move {
+            let (count, temperature, humidity) = streamed_summary(client, base, mesh).await?;
+            let expected = recomputed_summary(client, base, mesh).await?;
+            assert_eq!(count, expected.0, "after {step}");
+            assert!((temperature - expected.1).abs() < 1e-3, "after {step}");
+            assert!((humidity - expected.2).abs() < 1e-3, "after {step}");
+            anyhow::Ok(count)
+        }
+    };
+
+    let batch: Vec<Value> = [("10:00", 18.0), ("10:30", 20.0), ("11:00", 25.0)]
+        .iter()
+        .map(|(hm, temperature_c)| {
+            serde_json::json!({
+                "mesh_id": mesh,
+                "device_id": "device-totals-test",
+                "timestamp": format!("2025-06-12T{hm}:00Z"),
+                "temperature_c": temperature_c,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(assert_consistent("ingest").await?, 3);
+
+    let range = [
+        ("device_id", "device-totals-test"),
+        (
+            "timestamp_range",
+            "2025-06-12T10:30:00Z,2025-06-12T10:30:00Z",
+        ),
+    ];
+    client
+        .delete(format!("{base}/v1/readings"))
+        .query(&range)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(assert_consistent("soft delete").await?, 2);
+
+    client
+        .post(format!("{base}/admin/readings/restore"))
+        .query(&range)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(assert_consistent("restore").await?, 3);
+
+    let listed: Vec<Value> = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[("device_id", "device-totals-test"), ("limit", "1")])
+        .send()
+        .await?
+        .json()
+        .await?;
+    client
+        .patch(format!("{base}/v1/readings/{}", listed[0]["id"]))
+        .json(&serde_json::json!({ "temperature_c": 30.0 }))
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(assert_consistent("correction").await?, 3);
+
+    // A copy of an upstream reading, reported from this mesh: a duplicate
+    // until the upstream copy is deleted, then the preferred one
+    let upstream: Vec<Value> = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[
+            ("device_id", "device-004"),
+            ("sort", "temperature_desc"),
+            ("limit", "1"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let original = upstream.first().expect("need an upstream reading");
+    let timestamp = original["timestamp_utc"].as_str().unwrap();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": mesh,
+            "device_id": "device-004",
+            "timestamp": timestamp,
+            "temperature_c": 35.0,
+            "humidity": 80.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(assert_consistent("duplicate push").await?, 3);
+
+    let exact = format!("{timestamp},{timestamp}");
+    let original_copy = [
+        ("device_id", "device-004"),
+        ("mesh_id", original["mesh_id"].as_str().unwrap()),
+        ("timestamp_range", exact.as_str()),
+    ];
+    client
+        .delete(format!("{base}/v1/readings"))
+        .query(&original_copy)
+        .send()
+        .await?
+        .error_for_status()?;
+    let handed_over = assert_consistent("preferred copy deleted").await;
+    client
+        .post(format!("{base}/admin/readings/restore"))
+        .query(&original_copy)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(handed_over?, 4);
+    assert_eq!(assert_consistent("preferred copy restored").await?, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn deleted_readings_leave_every_read_path_until_restored() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    let device = ("device_id", "device-tombstone-test");
+    let batch: Vec<Value> = (0..3)
+        .map(|hour| {
+            serde_json::json!({
+                "mesh_id": "mesh-tombstone-test",
+                "device_id": device.1,
+                "timestamp": format!("2025-06-13T{hour:02}:00:00Z"),
+                "temperature_c": 10.0 + 5.0 * hour as f64,
+                "humidity": 40.0,
+                "status": "ok"
+            })
+        })
+        .collect();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&batch)
+        .send()
+        .await?
+        .error_for_status()?;
+    let listed: Vec<Value> = client
+        .get(format!("{base}/v1/readings"))
+        .query(&[device, ("sort", "timestamp_asc")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let last_id = listed.last().expect("pushed readings are listed")["id"].clone();
+
+    // Counts and temperature averages from each read path
+    let observe = || async {
+        let readings: Vec<Value> = client
+            .get(format!("{base}/v1/readings"))
+            .query(&[device])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let count: Value = client
+            .get(format!("{base}/v1/readings/count"))
+            .query(&[device])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let stats: Value = client
+            .get(format!("{base}/sql/stats"))
+            .query(&[device, ("group_by", "device")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let by_id = client
+            .get(format!("{base}/v1/readings/{last_id}"))
+            .send()
+            .await?
+            .status();
+        let mut rollups = Vec::new();
+        for bucket in ["1h", "1d"] {
+            let body: Value = client
+                .get(format!("{base}/v1/aggregates"))
+                .query(&[device, ("bucket", bucket)])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let data = body["data"].as_array().expect("data array");
+            rollups.push(
+                data.iter()
+                    .map(|b| b["reading_count"].as_i64().unwrap_or(0))
+                    .sum::<i64>(),
+            );
+        }
+        anyhow::Ok((
+            readings.len() as i64,
+            count["count"].as_i64().unwrap_or(-1),
+            stats["groups"][0]["count"].as_i64().unwrap_or(0),
+            stats["groups"][0]["temperature_c"]["avg"].as_f64(),
+            by_id,
+            rollups,
+        ))
+    };
+    assert_eq!(
+        observe().await?,
+        (3, 3, 3, Some(15.0), StatusCode::OK, vec![3, 3])
+    );
+
+    let range = [device, ("timestamp_range", "2025-06-13T01:00:00Z,")];
+    let body: Value = client
+        .delete(format!("{base}/v1/readings"))
+        .query(&range)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["deleted"], 2);
+    assert_eq!(
+        observe().await?,
+        (1, 1, 1, Some(10.0), StatusCode::NOT_FOUND, vec![1, 1])
+    );
+
+    let body: Value = client
+        .post(format!("{base}/admin/readings/restore"))
+        .query(&range)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(body["restored"], 2);
+    assert_eq!(
+        observe().await?,
+        (3, 3, 3, Some(15.0), StatusCode::OK, vec![3, 3])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_rejects_ranges_that_filter_nothing() -> Result<()> {
+    // ---
+    let base = base_url();
+    let client = Client::new();
+    client
+        .post(format!("{base}/v1/readings"))
+        .json(&serde_json::json!([{
+            "mesh_id": "mesh-delete-range-test",
+            "device_id": "device-delete-range-test",
+            "timestamp": "2025-06-14T00:00:00Z",
+            "temperature_c": 20.0,
+            "humidity": 40.0,
+            "status": "ok"
+        }]))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    for range in [",", " , ", "typo,typo", "2025-06-14T00:00:00Z,typo"] {
+        let resp = client
+            .delete(format!("{base}/v1/readings"))
+            .query(&[("timestamp_range", range)])
+            .send()
+            .await?;
+        assert_eq!(
+            resp.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "range={range}"
+        );
+    }
+
+    let count: Value = client
+        .get(format!(
+            "{base}/v1/readings/count?device_id=device-delete-range-test"
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(count["count"], 1);
+
+    Ok(())
+}